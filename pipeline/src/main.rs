@@ -0,0 +1,255 @@
+//! Full TD architecture in one process: the TD 1 fetcher pushes every price
+//! it gets into the TD 2 broadcaster through an in-memory channel (no DB
+//! polling), optionally persisting to Postgres on the way.
+
+use clap::Parser;
+use rust_td::{http_client, init_tracing, save_price, save_symbol_info, Fetcher, HookSpec, Route, StockPrice};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use td_common::portfolio::Portfolio;
+use td_common::{Context, Result};
+use tokio::sync::{broadcast, watch, Mutex};
+use tracing::{error, info, warn, Level};
+use ws_price_feed::{
+    report_channel, ChannelMetrics, FeedEvent, FeedMode, FeedServer, PriceUpdate, ServerConfig, SymbolMeta, SymbolMetadata,
+};
+
+/// Settings come from the CLI, the environment or a `.env` file.
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Fetch -> store -> broadcast in a single service", long_about = None)]
+struct Config {
+    /// Address of the WebSocket listener
+    #[arg(long, env = "PIPELINE_BIND", default_value = "127.0.0.1:8080")]
+    bind: String,
+
+    /// Symbols to fetch, comma separated
+    #[arg(long, env = "PIPELINE_SYMBOLS", value_delimiter = ',', default_value = "AAPL,GOOG,AMZN")]
+    symbols: Vec<String>,
+
+    /// Seconds between two fetch cycles
+    #[arg(long, env = "PIPELINE_INTERVAL_SECS", default_value_t = 60)]
+    interval_secs: u64,
+
+    /// Optional Postgres URL; prices are also stored when set
+    #[arg(long, env = "DATABASE_URL")]
+    database_url: Option<String>,
+
+    /// Capacity of the in-memory broadcast channel
+    #[arg(long, env = "PIPELINE_CHANNEL_CAPACITY", default_value_t = 100)]
+    channel_capacity: usize,
+
+    /// Log the channel's lagged drops and max queue depth every N seconds (0 = off)
+    #[arg(long, env = "PIPELINE_CHANNEL_REPORT_SECS", default_value_t = 60)]
+    channel_report_secs: u64,
+
+    /// Drop a price when its provider fails instead of broadcasting a mock one
+    #[arg(long, env = "PIPELINE_NO_MOCK_FALLBACK")]
+    no_mock_fallback: bool,
+
+    /// Restate every price in this currency (e.g. USD) before broadcasting it
+    #[arg(long, env = "PIPELINE_CONVERT_TO")]
+    convert_to: Option<String>,
+
+    /// Price hooks applied before broadcasting, e.g. remap=BRK-B:BRK.B,round=2
+    #[arg(long, env = "PIPELINE_HOOKS", value_delimiter = ',')]
+    hooks: Vec<HookSpec>,
+
+    /// Per-symbol providers, tried in order, e.g. `*.PA=yahoo,alpha;BRK.B=finnhub`
+    #[arg(long, env = "PIPELINE_ROUTES", value_delimiter = ';')]
+    routes: Vec<Route>,
+
+    /// Refresh the symbols' name, exchange and currency every N hours (0 = off)
+    #[arg(long, env = "PIPELINE_ENRICH_HOURS", default_value_t = 24)]
+    enrich_hours: u64,
+
+    /// Paper-trading positions (TOML) streamed as `portfolio_update` to the
+    /// clients that send `PORTFOLIO ON`
+    #[arg(long, env = "PIPELINE_PORTFOLIO")]
+    portfolio: Option<PathBuf>,
+}
+
+fn to_event(price: StockPrice) -> FeedEvent {
+    FeedEvent::Quote(PriceUpdate {
+        symbol: price.symbol,
+        price: price.price,
+        source: price.source,
+        timestamp: price.timestamp,
+        is_mock: price.is_mock,
+        // Right after the fetch cycle that produced it.
+        produced_at: Some(chrono::Utc::now().timestamp_millis()),
+        broadcast_at: None,
+    })
+}
+
+async fn fetch_loop(
+    fetcher: Fetcher,
+    symbols: Vec<String>,
+    every: Duration,
+    pool: Option<PgPool>,
+    tx: broadcast::Sender<FeedEvent>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut timer = tokio::time::interval(every);
+
+    loop {
+        tokio::select! {
+            _ = timer.tick() => {
+                let prices = fetcher.fetch_cycle(&symbols).await;
+                info!(count = prices.len(), "Fetch cycle done, broadcasting");
+                for price in prices {
+                    if let Some(pool) = &pool {
+                        if let Err(e) = save_price(pool, &price).await {
+                            error!("{}", e);
+                        }
+                    }
+                    // Err only means no client is connected right now.
+                    let _ = tx.send(to_event(price));
+                }
+            }
+            _ = shutdown.changed() => break,
+        }
+    }
+}
+
+/// Fetches the symbols' metadata at startup and then every `every`, for
+/// `list_symbols` and, when storing, the `symbols` table.
+async fn enrich_loop(
+    fetcher: Fetcher,
+    symbols: Vec<String>,
+    every: Duration,
+    pool: Option<PgPool>,
+    metadata: SymbolMetadata,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut timer = tokio::time::interval(every);
+
+    loop {
+        tokio::select! {
+            _ = timer.tick() => {
+                for symbol in &symbols {
+                    let info = match fetcher.fetch_symbol_info(symbol).await {
+                        Ok(info) => info,
+                        Err(e) => {
+                            warn!(symbol = %symbol, "Symbol metadata unavailable: {}", e);
+                            continue;
+                        }
+                    };
+                    if let Some(pool) = &pool {
+                        if let Err(e) = save_symbol_info(pool, &info).await {
+                            error!("{}", e);
+                        }
+                    }
+                    let meta = SymbolMeta {
+                        name: info.name,
+                        exchange: info.exchange,
+                        currency: info.currency,
+                    };
+                    metadata.set(symbol, meta);
+                }
+            }
+            _ = shutdown.changed() => break,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+    // Also collects the `log` records of the WS server.
+    let _telemetry = init_tracing("pipeline", Level::INFO)?;
+
+    let config = Config::parse();
+    let portfolio = config.portfolio.as_deref().map(Portfolio::load).transpose()?;
+
+    let pool = match &config.database_url {
+        Some(url) => Some(
+            PgPoolOptions::new()
+                .max_connections(5)
+                .connect(url)
+                .await
+                .context("connecting to DATABASE_URL")?,
+        ),
+        None => {
+            warn!("DATABASE_URL not set: prices are broadcast but not stored");
+            None
+        }
+    };
+
+    let (tx, _rx) = broadcast::channel::<FeedEvent>(config.channel_capacity);
+    let clients = Arc::new(Mutex::new(0u32));
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    let channel = ChannelMetrics::default();
+    if config.channel_report_secs > 0 {
+        let every = Duration::from_secs(config.channel_report_secs);
+        tokio::spawn(report_channel(channel.clone(), config.channel_capacity, every, 3));
+    }
+
+    let price_fetcher = Fetcher::with_client(http_client()?)
+        .mock_fallback(!config.no_mock_fallback)
+        .convert_to(config.convert_to.map(|c| c.to_uppercase()))
+        .hooks(config.hooks.iter().map(HookSpec::build).collect())
+        .routes(config.routes.clone());
+
+    let metadata = SymbolMetadata::default();
+    if config.enrich_hours > 0 {
+        tokio::spawn(enrich_loop(
+            price_fetcher.clone(),
+            config.symbols.clone(),
+            Duration::from_secs(config.enrich_hours * 3600),
+            pool.clone(),
+            metadata.clone(),
+            shutdown_rx.clone(),
+        ));
+    }
+
+    let fetcher = tokio::spawn(fetch_loop(
+        price_fetcher,
+        config.symbols.clone(),
+        Duration::from_secs(config.interval_secs.max(1)),
+        pool.clone(),
+        tx.clone(),
+        shutdown_rx,
+    ));
+
+    let server_config = ServerConfig {
+        known_symbols: config.symbols.clone(),
+        channel,
+        metadata,
+        portfolio,
+        db: pool.clone(),
+        feed: FeedMode::Pipeline,
+        ..ServerConfig::default()
+    };
+
+    let server = FeedServer::builder()
+        .bind(&config.bind)
+        .feed(tx)
+        .clients(clients)
+        .config(server_config)
+        .build()
+        .await?;
+    info!(
+        "Pipeline up: fetching {:?} every {}s, broadcasting on ws://{}",
+        config.symbols, config.interval_secs, config.bind
+    );
+
+    tokio::select! {
+        _ = server.run() => {}
+        _ = tokio::signal::ctrl_c() => info!("Shutdown requested via ctrl-c"),
+    }
+
+    // Let an in-flight fetch cycle finish its DB writes before closing.
+    let _ = shutdown_tx.send(true);
+    if let Err(e) = fetcher.await {
+        error!("Fetcher task failed: {}", e);
+    }
+    if let Some(pool) = pool {
+        pool.close().await;
+    }
+
+    info!("Shutdown complete");
+    Ok(())
+}
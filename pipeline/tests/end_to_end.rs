@@ -0,0 +1,135 @@
+//! Cross-project contract: what TD 1 fetches reaches a subscribed TD 2
+//! client as quotes, through the pipeline's in-memory channel or stored in
+//! Postgres and picked up by the DB feed.
+//!
+//! The Postgres one needs Docker (a throwaway container) or an existing
+//! database in `TEST_DATABASE_URL`, so it only runs on request:
+//! `cargo test -p pipeline -- --ignored`.
+
+use futures_util::{SinkExt, StreamExt};
+use rust_td::{check_schema, repair_schema, Fetcher, StockPrice};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::time::Duration;
+use testcontainers_modules::postgres::Postgres;
+use testcontainers_modules::testcontainers::runners::AsyncRunner;
+use testcontainers_modules::testcontainers::ContainerAsync;
+use tokio::sync::broadcast;
+use tokio::net::TcpStream;
+use tokio::time::{timeout, Instant};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+use ws_price_feed::feed::db_price_poller;
+use ws_price_feed::{Envelope, FeedEvent, FeedServer, PriceUpdate, ServerConfig, ServerMessage, SymbolMetadata};
+
+type Client = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+/// Unused by the real feeds, so rows left in a shared database are ours.
+const SYMBOL: &str = "E2ETEST";
+/// The DB feed polls every 5 seconds.
+const DELIVERY_BOUND: Duration = Duration::from_secs(15);
+
+/// The container is returned so it lives as long as the test.
+async fn database() -> (PgPool, Option<ContainerAsync<Postgres>>) {
+    let (url, container) = match std::env::var("TEST_DATABASE_URL") {
+        Ok(url) => (url, None),
+        Err(_) => {
+            let container = Postgres::default().start().await.expect("starting Postgres (is Docker running?)");
+            let host = container.get_host().await.unwrap();
+            let port = container.get_host_port_ipv4(5432).await.unwrap();
+            (format!("postgres://postgres:postgres@{}:{}/postgres", host, port), Some(container))
+        }
+    };
+    let pool = PgPoolOptions::new().max_connections(5).connect(&url).await.expect("connecting to Postgres");
+    let problems = check_schema(&pool).await.unwrap();
+    repair_schema(&pool, &problems).await.unwrap();
+    sqlx::query("DELETE FROM stock_prices WHERE symbol = $1").bind(SYMBOL).execute(&pool).await.unwrap();
+    (pool, container)
+}
+
+/// A TD 2 server reading `tx`, and a client subscribed to `SYMBOL`.
+async fn subscribed_client(tx: broadcast::Sender<FeedEvent>) -> Client {
+    let config = ServerConfig {
+        known_symbols: vec![SYMBOL.to_string()],
+        ..ServerConfig::default()
+    };
+    let server = FeedServer::builder().bind("127.0.0.1:0").feed(tx).config(config).build().await.unwrap();
+    let addr = server.local_addr().unwrap();
+    tokio::spawn(server.run());
+
+    let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr)).await.unwrap();
+    ws.send(Message::Text(format!("SUB {}", SYMBOL))).await.unwrap();
+    ws
+}
+
+/// One fetch cycle of mock prices, stored when there is a pool.
+async fn fetch(pool: Option<&PgPool>) -> Vec<StockPrice> {
+    // Providers answer with mock prices, no network or API key involved.
+    std::env::set_var("MOCK_FETCH", "1");
+    let stored = Fetcher::new().fetch_and_save_all(pool, &[SYMBOL.to_string()]).await.unwrap();
+    assert_eq!(stored.len(), 3, "one price per provider");
+    stored
+}
+
+/// Waits for a quote of each fetched price.
+async fn expect_quotes(ws: &mut Client, fetched: &[StockPrice]) {
+    let deadline = Instant::now() + DELIVERY_BOUND;
+    let mut pending = fetched.to_vec();
+    while !pending.is_empty() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let frame = match timeout(remaining, ws.next()).await {
+            Ok(Some(frame)) => frame.unwrap(),
+            _ => panic!("still waiting after {:?} for {:?}", DELIVERY_BOUND, pending),
+        };
+        let Message::Text(text) = frame else { continue };
+        let envelope: Envelope = serde_json::from_str(&text).unwrap();
+        let ServerMessage::Quote(quote) = envelope.message else { continue };
+        // Stored as NUMERIC(10,2).
+        pending.retain(|p| {
+            !(p.symbol == quote.symbol
+                && p.source == quote.source
+                && p.timestamp == quote.timestamp
+                && (p.price - quote.price).abs() < 0.006
+                && quote.is_mock)
+        });
+    }
+}
+
+#[tokio::test]
+async fn fetched_prices_reach_ws_clients() {
+    let (tx, _) = broadcast::channel(100);
+    let mut ws = subscribed_client(tx.clone()).await;
+    // the subscription is in once its reply is back
+    while !matches!(ws.next().await.unwrap().unwrap(), Message::Text(text) if text.contains("subscribed")) {}
+
+    let fetched = fetch(None).await;
+    // what the pipeline's fetch loop publishes
+    for price in &fetched {
+        let update = PriceUpdate {
+            symbol: price.symbol.clone(),
+            price: price.price,
+            source: price.source.clone(),
+            timestamp: price.timestamp,
+            is_mock: price.is_mock,
+            produced_at: None,
+            broadcast_at: None,
+        };
+        tx.send(FeedEvent::Quote(update)).unwrap();
+    }
+    expect_quotes(&mut ws, &fetched).await;
+}
+
+#[tokio::test]
+#[ignore = "needs Docker or TEST_DATABASE_URL"]
+async fn stored_prices_reach_ws_clients() {
+    let (pool, _container) = database().await;
+
+    let (tx, _) = broadcast::channel(100);
+    tokio::spawn(db_price_poller(pool.clone(), tx.clone(), None, SymbolMetadata::default()));
+    let mut ws = subscribed_client(tx).await;
+
+    let stored = fetch(Some(&pool)).await;
+    expect_quotes(&mut ws, &stored).await;
+
+    sqlx::query("DELETE FROM stock_prices WHERE symbol = $1").bind(SYMBOL).execute(&pool).await.unwrap();
+}
@@ -0,0 +1,2 @@
+-- Rows produced by the mock provider (MOCK_FETCH or fallback after a failure).
+ALTER TABLE stock_prices ADD COLUMN IF NOT EXISTS is_mock BOOLEAN NOT NULL DEFAULT FALSE;
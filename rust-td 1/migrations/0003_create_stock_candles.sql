@@ -0,0 +1,13 @@
+-- OHLC roll-up of raw ticks, filled by the retention job (--prune) before
+-- old rows of stock_prices are deleted.
+CREATE TABLE IF NOT EXISTS stock_candles (
+    symbol VARCHAR(10) NOT NULL,
+    interval_secs INTEGER NOT NULL,
+    bucket_start BIGINT NOT NULL,
+    open DECIMAL(10, 2) NOT NULL,
+    high DECIMAL(10, 2) NOT NULL,
+    low DECIMAL(10, 2) NOT NULL,
+    close DECIMAL(10, 2) NOT NULL,
+    samples INTEGER NOT NULL,
+    PRIMARY KEY (symbol, interval_secs, bucket_start)
+);
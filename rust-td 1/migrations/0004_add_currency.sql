@@ -0,0 +1,2 @@
+-- Currency of `price` (listing currency, or the --convert-to target).
+ALTER TABLE stock_prices ADD COLUMN IF NOT EXISTS currency VARCHAR(3) NOT NULL DEFAULT 'USD';
@@ -0,0 +1,15 @@
+-- Per-cycle divergence between the cheapest and dearest source of a symbol,
+-- written by the spread monitor (--spread-threshold-pct).
+CREATE TABLE IF NOT EXISTS source_spreads (
+    id SERIAL PRIMARY KEY,
+    symbol VARCHAR(10) NOT NULL,
+    timestamp BIGINT NOT NULL,
+    min_source VARCHAR(50) NOT NULL,
+    min_price NUMERIC(10,2) NOT NULL,
+    max_source VARCHAR(50) NOT NULL,
+    max_price NUMERIC(10,2) NOT NULL,
+    spread_pct DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_source_spreads_symbol_timestamp ON source_spreads(symbol, timestamp DESC);
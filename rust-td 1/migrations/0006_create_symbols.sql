@@ -0,0 +1,10 @@
+-- Company name, exchange and currency of each fetched symbol, refreshed by
+-- the enrichment task from Finnhub (profile) or Yahoo (quote).
+CREATE TABLE IF NOT EXISTS symbols (
+    symbol VARCHAR(10) PRIMARY KEY,
+    name VARCHAR(200),
+    exchange VARCHAR(100),
+    currency VARCHAR(3),
+    source VARCHAR(50) NOT NULL,
+    updated_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
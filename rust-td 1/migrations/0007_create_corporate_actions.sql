@@ -0,0 +1,13 @@
+-- Splits and dividends detected from the providers. `factor` multiplies the
+-- prices before `ex_date` to make them comparable with the later ones
+-- (0.25 for a 4:1 split, 1 - dividend / previous close for a dividend).
+CREATE TABLE IF NOT EXISTS corporate_actions (
+    symbol VARCHAR(10) NOT NULL,
+    kind VARCHAR(10) NOT NULL,
+    ex_date BIGINT NOT NULL,
+    value DOUBLE PRECISION NOT NULL,
+    factor DOUBLE PRECISION NOT NULL,
+    source VARCHAR(50) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (symbol, kind, ex_date)
+);
@@ -0,0 +1,6 @@
+-- Paper-trading positions valued by --portfolio-value when no TOML file is given.
+CREATE TABLE IF NOT EXISTS portfolio_positions (
+    symbol VARCHAR(10) PRIMARY KEY,
+    quantity DOUBLE PRECISION NOT NULL,
+    cost_basis NUMERIC(10,2) NOT NULL
+);
@@ -0,0 +1,16 @@
+-- Movement history: one row per (symbol, source) each time a real quote
+-- moves more than --change-epsilon away from the last recorded price.
+CREATE TABLE IF NOT EXISTS price_changes (
+    id SERIAL PRIMARY KEY,
+    symbol VARCHAR(10) NOT NULL,
+    source VARCHAR(50) NOT NULL,
+    timestamp BIGINT NOT NULL,
+    previous_price NUMERIC(10,2) NOT NULL,
+    price NUMERIC(10,2) NOT NULL,
+    change DOUBLE PRECISION NOT NULL,
+    change_pct DOUBLE PRECISION NOT NULL,
+    direction VARCHAR(4) NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS idx_price_changes_symbol_timestamp ON price_changes(symbol, timestamp DESC);
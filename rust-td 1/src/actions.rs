@@ -0,0 +1,268 @@
+use crate::Fetcher;
+use crate::providers::{parse_strict, should_mock_fetch};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::fmt;
+use td_common::{Context, Error, Result};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActionKind {
+    Split,
+    Dividend,
+}
+
+impl fmt::Display for ActionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ActionKind::Split => "split",
+            ActionKind::Dividend => "dividend",
+        })
+    }
+}
+
+/// A split or a dividend, with the factor applied to the prices before its
+/// ex-date so they line up with the later ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorporateAction {
+    pub symbol: String,
+    pub kind: ActionKind,
+    /// Unix seconds.
+    pub ex_date: i64,
+    /// New shares per old share for a split, amount per share for a dividend.
+    pub value: f64,
+    pub factor: f64,
+    pub source: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooSplit {
+    date: i64,
+    numerator: f64,
+    denominator: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooDividend {
+    date: i64,
+    amount: f64,
+}
+
+#[derive(Deserialize, Debug, Default)]
+struct YahooEvents {
+    #[serde(default)]
+    splits: HashMap<String, YahooSplit>,
+    #[serde(default)]
+    dividends: HashMap<String, YahooDividend>,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooCloses {
+    close: Vec<Option<f64>>,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooIndicators {
+    quote: Vec<YahooCloses>,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooChart {
+    #[serde(default)]
+    timestamp: Vec<i64>,
+    indicators: YahooIndicators,
+    #[serde(default)]
+    events: YahooEvents,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooChartResult {
+    result: Option<Vec<YahooChart>>,
+    error: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooChartResponse {
+    chart: YahooChartResult,
+}
+
+/// Splits and dividends of a daily Yahoo chart. A dividend's factor needs
+/// the close before its ex-date; one without is left out.
+fn parse_yahoo_actions(symbol: &str, body: &str) -> Result<Vec<CorporateAction>> {
+    let data: YahooChartResponse = parse_strict("Yahoo", body)?;
+    if let Some(error) = data.chart.error {
+        return Err(Error::http(error.to_string()).context(format!("Yahoo chart of {}", symbol)));
+    }
+    let Some(chart) = data.chart.result.and_then(|r| r.into_iter().next()) else {
+        return Ok(Vec::new());
+    };
+
+    let closes: Vec<(i64, f64)> = match chart.indicators.quote.first() {
+        Some(quote) => chart
+            .timestamp
+            .iter()
+            .zip(&quote.close)
+            .filter_map(|(&ts, close)| close.map(|c| (ts, c)))
+            .collect(),
+        None => Vec::new(),
+    };
+    let close_before = |ex_date: i64| closes.iter().rev().find(|(ts, _)| *ts < ex_date).map(|(_, c)| *c);
+
+    let mut actions: Vec<CorporateAction> = chart
+        .events
+        .splits
+        .values()
+        .filter(|s| s.numerator > 0.0 && s.denominator > 0.0)
+        .map(|s| CorporateAction {
+            symbol: symbol.to_string(),
+            kind: ActionKind::Split,
+            ex_date: s.date,
+            value: s.numerator / s.denominator,
+            factor: s.denominator / s.numerator,
+            source: "Yahoo".to_string(),
+        })
+        .collect();
+
+    for dividend in chart.events.dividends.values() {
+        match close_before(dividend.date) {
+            Some(close) if close > dividend.amount => actions.push(CorporateAction {
+                symbol: symbol.to_string(),
+                kind: ActionKind::Dividend,
+                ex_date: dividend.date,
+                value: dividend.amount,
+                factor: 1.0 - dividend.amount / close,
+                source: "Yahoo".to_string(),
+            }),
+            _ => warn!(symbol = %symbol, ex_date = dividend.date, "No close before dividend, skipped"),
+        }
+    }
+
+    actions.sort_by_key(|a| a.ex_date);
+    Ok(actions)
+}
+
+impl Fetcher {
+    /// Splits and dividends of the last ten years, from Yahoo's daily chart.
+    pub async fn fetch_corporate_actions(&self, symbol: &str) -> Result<Vec<CorporateAction>> {
+        if cfg!(test) || should_mock_fetch() {
+            return Ok(Vec::new());
+        }
+
+        let url = format!(
+            "https://query1.finance.yahoo.com/v8/finance/chart/{}?range=10y&interval=1d&events=div%2Csplits",
+            symbol
+        );
+        let body = self.client.get(&url).send().await?.text().await?;
+        parse_yahoo_actions(symbol, &body).with_context(|| format!("corporate actions of {}", symbol))
+    }
+}
+
+/// Stores the actions not known yet; returns those that were new.
+pub async fn save_actions(pool: &PgPool, actions: &[CorporateAction]) -> Result<Vec<CorporateAction>> {
+    let mut new = Vec::new();
+    for action in actions {
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO corporate_actions (symbol, kind, ex_date, value, factor, source)
+            VALUES ($1, $2, $3, $4, $5, $6)
+            ON CONFLICT (symbol, kind, ex_date) DO NOTHING
+            "#,
+        )
+        .bind(&action.symbol)
+        .bind(action.kind.to_string())
+        .bind(action.ex_date)
+        .bind(action.value)
+        .bind(action.factor)
+        .bind(&action.source)
+        .execute(pool)
+        .await
+        .with_context(|| format!("saving {} of {}", action.kind, action.symbol))?
+        .rows_affected();
+        if inserted > 0 {
+            new.push(action.clone());
+        }
+    }
+    Ok(new)
+}
+
+/// Fetches and stores the corporate actions of `symbols`, logging the new
+/// ones; a symbol whose provider fails is retried on the next run.
+pub async fn sync_corporate_actions(fetcher: &Fetcher, pool: &PgPool, symbols: &[String]) -> Result<usize> {
+    let mut count = 0;
+    for symbol in symbols {
+        let actions = match fetcher.fetch_corporate_actions(symbol).await {
+            Ok(actions) => actions,
+            Err(e) => {
+                warn!(symbol = %symbol, "Corporate actions unavailable: {}", e);
+                continue;
+            }
+        };
+        for action in save_actions(pool, &actions).await? {
+            info!(
+                symbol = %action.symbol,
+                ex_date = action.ex_date,
+                "New {} ({}), adjustment factor {:.6}",
+                action.kind,
+                action.value,
+                action.factor
+            );
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Factor turning a price at `timestamp` into today's terms: the product of
+/// the factors of the later actions.
+pub fn adjustment_factor(actions: &[CorporateAction], timestamp: i64) -> f64 {
+    actions
+        .iter()
+        .filter(|a| a.ex_date > timestamp)
+        .map(|a| a.factor)
+        .product()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHART: &str = r#"{"chart":{"result":[{
+        "timestamp":[1598533200,1598619600,1598880600,1604673000],
+        "indicators":{"quote":[{"close":[500.04,499.23,129.04,null]}]},
+        "events":{
+            "splits":{"1598880600":{"date":1598880600,"numerator":4,"denominator":1,"splitRatio":"4:1"}},
+            "dividends":{"1604673000":{"amount":0.205,"date":1604673000},"1500000000":{"amount":0.63,"date":1500000000}}
+        }}],"error":null}}"#;
+
+    #[test]
+    fn splits_and_dividends_get_their_factor() {
+        let actions = parse_yahoo_actions("AAPL", CHART).unwrap();
+        // The 2017 dividend has no close before it in the chart.
+        assert_eq!(actions.len(), 2);
+
+        assert_eq!(actions[0].kind, ActionKind::Split);
+        assert_eq!(actions[0].value, 4.0);
+        assert_eq!(actions[0].factor, 0.25);
+
+        assert_eq!(actions[1].kind, ActionKind::Dividend);
+        assert!((actions[1].factor - (1.0 - 0.205 / 129.04)).abs() < 1e-12);
+    }
+
+    #[test]
+    fn older_prices_take_every_later_factor() {
+        let actions = parse_yahoo_actions("AAPL", CHART).unwrap();
+        let dividend = actions[1].factor;
+        assert_eq!(adjustment_factor(&actions, 1598533200), 0.25 * dividend);
+        assert_eq!(adjustment_factor(&actions, 1598880600), dividend);
+        assert_eq!(adjustment_factor(&actions, 1604673000), 1.0);
+    }
+
+    #[test]
+    fn chart_errors_and_unknown_symbols() {
+        let unknown = r#"{"chart":{"result":null,"error":{"code":"Not Found","description":"No data found"}}}"#;
+        assert!(matches!(parse_yahoo_actions("ZZZZ", unknown).unwrap_err().root(), Error::Http(_)));
+        let quiet = r#"{"chart":{"result":[{"timestamp":[1],"indicators":{"quote":[{"close":[1.0]}]}}],"error":null}}"#;
+        assert!(parse_yahoo_actions("AAPL", quiet).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,413 @@
+use sqlx::{PgPool, Row};
+use std::fmt;
+use std::str::FromStr;
+use td_common::indicators::{Indicator, IndicatorSpec};
+use td_common::{Context, Result};
+
+/// One OHLC candle, split/dividend adjusted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bar {
+    /// Start of the candle, unix seconds.
+    pub timestamp: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
+/// What a strategy wants after a closed bar; it is executed at the next
+/// bar's open.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Signal {
+    Hold,
+    Buy,
+    Sell,
+}
+
+/// A long-only trading rule fed the bars one by one.
+pub trait Strategy {
+    fn name(&self) -> String;
+
+    /// Called after each bar; `long` tells whether a position is open.
+    fn on_bar(&mut self, bar: &Bar, long: bool) -> Signal;
+}
+
+/// Buys when the fast SMA crosses above the slow one, sells on the way down.
+#[derive(Debug, Clone)]
+pub struct SmaCrossover {
+    periods: (usize, usize),
+    fast: Indicator,
+    slow: Indicator,
+    fast_above: Option<bool>,
+}
+
+impl SmaCrossover {
+    pub fn new(fast: usize, slow: usize) -> Self {
+        SmaCrossover {
+            periods: (fast, slow),
+            fast: Indicator::new(IndicatorSpec::Sma(fast)),
+            slow: Indicator::new(IndicatorSpec::Sma(slow)),
+            fast_above: None,
+        }
+    }
+}
+
+impl Strategy for SmaCrossover {
+    fn name(&self) -> String {
+        format!("sma-cross:{}:{}", self.periods.0, self.periods.1)
+    }
+
+    fn on_bar(&mut self, bar: &Bar, long: bool) -> Signal {
+        let (Some(fast), Some(slow)) = (self.fast.update(bar.close), self.slow.update(bar.close)) else {
+            return Signal::Hold;
+        };
+        let above = fast > slow;
+        let crossed = self.fast_above.replace(above).is_some_and(|before| before != above);
+        match (crossed, above, long) {
+            (true, true, false) => Signal::Buy,
+            (true, false, true) => Signal::Sell,
+            _ => Signal::Hold,
+        }
+    }
+}
+
+/// The strategies `--backtest` knows: `sma-cross:FAST:SLOW`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrategySpec {
+    SmaCross { fast: usize, slow: usize },
+}
+
+impl StrategySpec {
+    pub fn build(&self) -> Box<dyn Strategy> {
+        match *self {
+            StrategySpec::SmaCross { fast, slow } => Box::new(SmaCrossover::new(fast, slow)),
+        }
+    }
+}
+
+impl FromStr for StrategySpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split(':').collect();
+        match parts.as_slice() {
+            [name, fast, slow] if name.eq_ignore_ascii_case("sma-cross") => {
+                let period = |p: &str| p.parse::<usize>().ok().filter(|&p| p > 0);
+                match (period(fast), period(slow)) {
+                    (Some(fast), Some(slow)) if fast < slow => Ok(StrategySpec::SmaCross { fast, slow }),
+                    _ => Err(format!("`{}`: expected sma-cross:FAST:SLOW with 0 < FAST < SLOW", s)),
+                }
+            }
+            _ => Err(format!("unknown strategy `{}` (sma-cross:FAST:SLOW)", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+impl fmt::Display for TradeSide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TradeSide::Buy => "BUY",
+            TradeSide::Sell => "SELL",
+        })
+    }
+}
+
+/// Turns an order into an execution price.
+pub trait FillModel {
+    /// Price paid (buy) or received (sell) for `quantity` when the market is
+    /// at `reference`; `None` when the order can't be filled.
+    fn fill(&mut self, side: TradeSide, quantity: f64, reference: f64) -> Option<f64>;
+}
+
+/// Fills at the reference price moved against the order by `bps` basis
+/// points.
+#[derive(Debug, Clone, Copy)]
+pub struct Slippage {
+    pub bps: f64,
+}
+
+impl FillModel for Slippage {
+    fn fill(&mut self, side: TradeSide, _quantity: f64, reference: f64) -> Option<f64> {
+        let slip = reference * self.bps / 10_000.0;
+        Some(match side {
+            TradeSide::Buy => reference + slip,
+            TradeSide::Sell => reference - slip,
+        })
+    }
+}
+
+/// Fills by walking a synthetic L2 book of the rust-td 4 engine, rebuilt
+/// around each reference price: `levels` levels of `level_size` shares per
+/// side, one cent apart, a cent from the reference. An order larger than
+/// the book isn't filled.
+#[cfg(feature = "orderbook")]
+#[derive(Debug, Clone, Copy)]
+pub struct BookFills {
+    pub levels: usize,
+    pub level_size: u64,
+}
+
+#[cfg(feature = "orderbook")]
+impl FillModel for BookFills {
+    fn fill(&mut self, side: TradeSide, quantity: f64, reference: f64) -> Option<f64> {
+        use rust_3::interfaces::{OrderBook, Side, Update};
+        use rust_3::orderbook::OrderBookImpl;
+
+        // rust-td 4 prices are in 1/10_000.
+        const CENT: i64 = 100;
+        let mid = (reference * 10_000.0).round() as i64;
+        let mut book = OrderBookImpl::new();
+        for level in 1..=self.levels as i64 {
+            for (side, price) in [(Side::Bid, mid - level * CENT), (Side::Ask, mid + level * CENT)] {
+                if price > 0 {
+                    book.apply_update(Update::Set { price, quantity: self.level_size, side });
+                }
+            }
+        }
+
+        let taken = match side {
+            TradeSide::Buy => Side::Ask,
+            TradeSide::Sell => Side::Bid,
+        };
+        let shares = quantity.ceil() as u64;
+        let sweep = book.sweep_cost_curve(taken, shares, 1).pop()?;
+        (sweep.quantity == shares).then(|| sweep.average_price / 10_000.0)
+    }
+}
+
+/// `close` (no cost), `slippage:BPS` or, with the `orderbook` feature,
+/// `book` (see [`BookFills`]).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FillSpec {
+    Slippage(f64),
+    Book,
+}
+
+impl FillSpec {
+    pub fn build(&self) -> Box<dyn FillModel> {
+        match *self {
+            FillSpec::Slippage(bps) => Box::new(Slippage { bps }),
+            #[cfg(feature = "orderbook")]
+            FillSpec::Book => Box::new(BookFills { levels: 50, level_size: 100 }),
+            #[cfg(not(feature = "orderbook"))]
+            FillSpec::Book => unreachable!("rejected by FromStr without the orderbook feature"),
+        }
+    }
+}
+
+impl FromStr for FillSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        if s == "close" {
+            return Ok(FillSpec::Slippage(0.0));
+        }
+        if s == "book" {
+            return if cfg!(feature = "orderbook") {
+                Ok(FillSpec::Book)
+            } else {
+                Err("`book` fills need the orderbook feature".to_string())
+            };
+        }
+        match s.strip_prefix("slippage:").map(str::parse::<f64>) {
+            Some(Ok(bps)) if bps >= 0.0 => Ok(FillSpec::Slippage(bps)),
+            _ => Err(format!("unknown fill model `{}` (close, slippage:BPS or book)", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trade {
+    pub side: TradeSide,
+    pub timestamp: i64,
+    pub price: f64,
+    pub quantity: f64,
+    /// Realized by a sell.
+    pub pnl: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BacktestReport {
+    pub bars: usize,
+    pub trades: Vec<Trade>,
+    pub realized: f64,
+    /// Position still open after the last bar, at its close.
+    pub unrealized: f64,
+    pub pnl: f64,
+    /// Largest fall of the equity (P&L marked at each close) from its peak.
+    pub max_drawdown: f64,
+}
+
+/// Replays `bars` through `strategy`, trading `quantity` shares filled by
+/// `fills` at the open of the bar after each signal.
+pub fn run_backtest(bars: &[Bar], strategy: &mut dyn Strategy, fills: &mut dyn FillModel, quantity: f64) -> BacktestReport {
+    let mut trades = Vec::new();
+    let mut entry: Option<f64> = None;
+    let mut pending = Signal::Hold;
+    let mut realized = 0.0;
+    let (mut peak, mut max_drawdown) = (0.0_f64, 0.0_f64);
+
+    for bar in bars {
+        match (pending, entry) {
+            (Signal::Buy, None) => {
+                if let Some(price) = fills.fill(TradeSide::Buy, quantity, bar.open) {
+                    entry = Some(price);
+                    trades.push(Trade { side: TradeSide::Buy, timestamp: bar.timestamp, price, quantity, pnl: None });
+                }
+            }
+            (Signal::Sell, Some(cost)) => {
+                if let Some(price) = fills.fill(TradeSide::Sell, quantity, bar.open) {
+                    let pnl = (price - cost) * quantity;
+                    realized += pnl;
+                    entry = None;
+                    trades.push(Trade { side: TradeSide::Sell, timestamp: bar.timestamp, price, quantity, pnl: Some(pnl) });
+                }
+            }
+            _ => {}
+        }
+
+        let equity = realized + entry.map_or(0.0, |cost| (bar.close - cost) * quantity);
+        peak = peak.max(equity);
+        max_drawdown = max_drawdown.max(peak - equity);
+        pending = strategy.on_bar(bar, entry.is_some());
+    }
+
+    let unrealized = match (entry, bars.last()) {
+        (Some(cost), Some(last)) => (last.close - cost) * quantity,
+        _ => 0.0,
+    };
+    BacktestReport {
+        bars: bars.len(),
+        trades,
+        realized,
+        unrealized,
+        pnl: realized + unrealized,
+        max_drawdown,
+    }
+}
+
+/// `interval_secs` candles of `symbol`, oldest first: the ones the retention
+/// job stored plus the roll-up of the ticks still in `stock_prices` (mock
+/// ticks only when the symbol has no real one).
+pub async fn load_bars(pool: &PgPool, symbol: &str, interval_secs: i64, since_ts: Option<i64>) -> Result<Vec<Bar>> {
+    let rows = sqlx::query(
+        r#"
+        WITH bars AS (
+            SELECT bucket_start, open::float8 AS open, high::float8 AS high, low::float8 AS low,
+                   close::float8 AS close, 0 AS part
+            FROM stock_candles
+            WHERE symbol = $1 AND interval_secs = $2
+            UNION ALL
+            SELECT (timestamp / $2) * $2,
+                   (ARRAY_AGG(price::float8 ORDER BY timestamp))[1],
+                   MAX(price::float8),
+                   MIN(price::float8),
+                   (ARRAY_AGG(price::float8 ORDER BY timestamp DESC))[1],
+                   1
+            FROM stock_prices
+            WHERE symbol = $1
+              AND is_mock = NOT EXISTS (SELECT 1 FROM stock_prices WHERE symbol = $1 AND NOT is_mock)
+            GROUP BY 1
+        )
+        SELECT bucket_start, open, high, low, close,
+               COALESCE((
+                   SELECT EXP(SUM(LN(a.factor))) FROM corporate_actions a
+                   WHERE a.symbol = $1 AND a.ex_date > bars.bucket_start
+               ), 1) AS factor
+        FROM bars
+        WHERE $3::bigint IS NULL OR bucket_start >= $3
+        ORDER BY bucket_start, part
+        "#,
+    )
+    .bind(symbol)
+    .bind(interval_secs)
+    .bind(since_ts)
+    .fetch_all(pool)
+    .await
+    .with_context(|| format!("reading candles of {}", symbol))?;
+
+    let mut bars: Vec<Bar> = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let factor: f64 = row.try_get("factor")?;
+        let bar = Bar {
+            timestamp: row.try_get("bucket_start")?,
+            open: row.try_get::<f64, _>("open")? * factor,
+            high: row.try_get::<f64, _>("high")? * factor,
+            low: row.try_get::<f64, _>("low")? * factor,
+            close: row.try_get::<f64, _>("close")? * factor,
+        };
+        // The bucket being pruned has both a stored candle and ticks.
+        match bars.last_mut() {
+            Some(last) if last.timestamp == bar.timestamp => {
+                last.high = last.high.max(bar.high);
+                last.low = last.low.min(bar.low);
+                last.close = bar.close;
+            }
+            _ => bars.push(bar),
+        }
+    }
+    Ok(bars)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bars(closes: &[f64]) -> Vec<Bar> {
+        closes
+            .iter()
+            .enumerate()
+            .map(|(i, &close)| Bar { timestamp: i as i64, open: close, high: close, low: close, close })
+            .collect()
+    }
+
+    #[test]
+    fn specs_parse() {
+        assert_eq!("sma-cross:10:30".parse(), Ok(StrategySpec::SmaCross { fast: 10, slow: 30 }));
+        assert!("sma-cross:30:10".parse::<StrategySpec>().is_err());
+        assert!("rsi:14".parse::<StrategySpec>().is_err());
+        assert_eq!("slippage:5".parse(), Ok(FillSpec::Slippage(5.0)));
+        assert_eq!("close".parse(), Ok(FillSpec::Slippage(0.0)));
+        assert!("slippage:-1".parse::<FillSpec>().is_err());
+    }
+
+    #[test]
+    fn sma_crossover_trades_the_next_open() {
+        // The 1/2 SMAs cross up at bar 2 and down at bar 5.
+        let bars = bars(&[10.0, 9.0, 11.0, 12.0, 13.0, 8.0, 7.0]);
+        let mut strategy = SmaCrossover::new(1, 2);
+        let report = run_backtest(&bars, &mut strategy, &mut Slippage { bps: 0.0 }, 10.0);
+
+        let sides: Vec<(TradeSide, i64)> = report.trades.iter().map(|t| (t.side, t.timestamp)).collect();
+        assert_eq!(sides, vec![(TradeSide::Buy, 3), (TradeSide::Sell, 6)]);
+        assert_eq!(report.realized, (7.0 - 12.0) * 10.0);
+        assert_eq!(report.unrealized, 0.0);
+        // Peak at bar 4 (+10), trough at bar 6 (-50).
+        assert_eq!(report.max_drawdown, 60.0);
+    }
+
+    #[test]
+    fn slippage_moves_against_the_order() {
+        let mut fills = Slippage { bps: 10.0 };
+        assert_eq!(fills.fill(TradeSide::Buy, 1.0, 100.0), Some(100.1));
+        assert_eq!(fills.fill(TradeSide::Sell, 1.0, 100.0), Some(99.9));
+    }
+
+    #[cfg(feature = "orderbook")]
+    #[test]
+    fn book_fills_walk_the_levels() {
+        let mut fills = BookFills { levels: 3, level_size: 100 };
+        assert_eq!(fills.fill(TradeSide::Buy, 100.0, 50.0), Some(50.01));
+        // 100 at 50.01 and 100 at 50.02.
+        assert!((fills.fill(TradeSide::Buy, 200.0, 50.0).unwrap() - 50.015).abs() < 1e-9);
+        assert_eq!(fills.fill(TradeSide::Sell, 100.0, 50.0), Some(49.99));
+        assert_eq!(fills.fill(TradeSide::Buy, 301.0, 50.0), None);
+    }
+}
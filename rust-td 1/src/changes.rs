@@ -0,0 +1,201 @@
+use crate::StockPrice;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::fmt;
+use td_common::{Context, Result};
+use tracing::info;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Direction::Up => "up",
+            Direction::Down => "down",
+        })
+    }
+}
+
+/// A quote that moved away from the last recorded price of its symbol and
+/// source; one row of `price_changes`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceChange {
+    pub symbol: String,
+    pub source: String,
+    pub timestamp: i64,
+    pub previous_price: f64,
+    pub price: f64,
+    pub direction: Direction,
+}
+
+impl PriceChange {
+    /// Size of the move, always positive.
+    pub fn change(&self) -> f64 {
+        (self.price - self.previous_price).abs()
+    }
+
+    pub fn change_pct(&self) -> f64 {
+        self.change() / self.previous_price * 100.0
+    }
+}
+
+/// Last recorded price per (symbol, source). A quote becomes a change when
+/// it is more than `epsilon` away from it, and then the new reference, so
+/// slow drifts are recorded once they add up. The first quote of a pair
+/// only sets its reference.
+#[derive(Debug, Clone)]
+pub struct ChangeTracker {
+    epsilon: f64,
+    last: HashMap<(String, String), f64>,
+}
+
+impl ChangeTracker {
+    pub fn new(epsilon: f64) -> Self {
+        ChangeTracker {
+            epsilon: epsilon.max(0.0),
+            last: HashMap::new(),
+        }
+    }
+
+    pub fn knows(&self, symbol: &str, source: &str) -> bool {
+        self.last.contains_key(&(symbol.to_string(), source.to_string()))
+    }
+
+    /// Sets the reference of a pair, e.g. from the database at startup.
+    pub fn seed(&mut self, symbol: &str, source: &str, price: f64) {
+        self.last.insert((symbol.to_string(), source.to_string()), price);
+    }
+
+    /// Mock prices are random and ignored.
+    pub fn observe(&mut self, price: &StockPrice) -> Option<PriceChange> {
+        if price.is_mock || !price.price.is_finite() || price.price <= 0.0 {
+            return None;
+        }
+        let key = (price.symbol.clone(), price.source.clone());
+        let Some(&previous) = self.last.get(&key) else {
+            self.last.insert(key, price.price);
+            return None;
+        };
+        if (price.price - previous).abs() <= self.epsilon {
+            return None;
+        }
+        self.last.insert(key, price.price);
+        Some(PriceChange {
+            symbol: price.symbol.clone(),
+            source: price.source.clone(),
+            timestamp: price.timestamp,
+            previous_price: previous,
+            price: price.price,
+            direction: if price.price > previous { Direction::Up } else { Direction::Down },
+        })
+    }
+}
+
+async fn last_recorded(pool: &PgPool, symbol: &str, source: &str) -> Result<Option<f64>> {
+    let price = sqlx::query_scalar(
+        r#"SELECT price::float8 FROM price_changes WHERE symbol = $1 AND source = $2 ORDER BY timestamp DESC, id DESC LIMIT 1"#,
+    )
+    .bind(symbol)
+    .bind(source)
+    .fetch_optional(pool)
+    .await
+    .with_context(|| format!("reading last {} change for {}", source, symbol))?;
+    Ok(price)
+}
+
+pub async fn save_change(pool: &PgPool, change: &PriceChange) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO price_changes (symbol, source, timestamp, previous_price, price, change, change_pct, direction)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+        "#,
+    )
+    .bind(&change.symbol)
+    .bind(&change.source)
+    .bind(change.timestamp)
+    .bind(change.previous_price)
+    .bind(change.price)
+    .bind(change.change())
+    .bind(change.change_pct())
+    .bind(change.direction.to_string())
+    .execute(pool)
+    .await
+    .with_context(|| format!("saving {} price change for {}", change.source, change.symbol))?;
+    Ok(())
+}
+
+/// Feeds the cycle's prices to `tracker` and stores the changes when a pool
+/// is given. A pair seen for the first time since startup takes its last
+/// recorded price from the table, so restarts don't lose the reference.
+pub async fn record_changes(
+    pool: Option<&PgPool>,
+    tracker: &mut ChangeTracker,
+    prices: &[StockPrice],
+) -> Result<Vec<PriceChange>> {
+    let mut changes = Vec::new();
+    for price in prices {
+        if let Some(pool) = pool
+            && !price.is_mock
+            && !tracker.knows(&price.symbol, &price.source)
+            && let Some(last) = last_recorded(pool, &price.symbol, &price.source).await?
+        {
+            tracker.seed(&price.symbol, &price.source, last);
+        }
+        let Some(change) = tracker.observe(price) else {
+            continue;
+        };
+        if let Some(pool) = pool {
+            save_change(pool, &change).await?;
+        }
+        info!(
+            symbol = %change.symbol,
+            source = %change.source,
+            "Price {} {:.4} ({:.2}%) to {}",
+            change.direction,
+            change.change(),
+            change.change_pct(),
+            change.price
+        );
+        changes.push(change);
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(source: &str, price: f64) -> StockPrice {
+        StockPrice {
+            symbol: "AAPL".to_string(),
+            price,
+            source: source.to_string(),
+            timestamp: 0,
+            currency: "USD".to_string(),
+            is_mock: false,
+        }
+    }
+
+    #[test]
+    fn only_moves_beyond_epsilon_are_changes() {
+        let mut tracker = ChangeTracker::new(0.05);
+        assert!(tracker.observe(&quote("Yahoo", 100.0)).is_none());
+        assert!(tracker.observe(&quote("Yahoo", 100.03)).is_none());
+        // Drift measured from the last recorded price, not the last quote.
+        let up = tracker.observe(&quote("Yahoo", 100.06)).unwrap();
+        assert_eq!((up.previous_price, up.direction), (100.0, Direction::Up));
+        assert!((up.change_pct() - 0.06).abs() < 1e-9);
+
+        let down = tracker.observe(&quote("Yahoo", 99.0)).unwrap();
+        assert_eq!((down.previous_price, down.direction), (100.06, Direction::Down));
+        // Each source has its own reference; mocks never count.
+        assert!(tracker.observe(&quote("Finnhub", 50.0)).is_none());
+        let mut mock = quote("Finnhub", 80.0);
+        mock.is_mock = true;
+        assert!(tracker.observe(&mock).is_none());
+    }
+}
@@ -0,0 +1,178 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use td_common::Error;
+use tracing::warn;
+
+/// Body handed to a provider's parser in place of its real answer.
+pub const MALFORMED_BODY: &str = r#"{"chaos": "malformed", "price": "#;
+
+/// Fault probabilities of `--chaos`, each in `[0, 1]`:
+/// `timeout=0.1,db=0.05,malformed=0.2,seed=42`. Omitted = 0; the seed makes
+/// a run reproducible.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ChaosConfig {
+    pub timeout: f64,
+    pub db_failure: f64,
+    pub malformed: f64,
+    pub seed: Option<u64>,
+}
+
+impl FromStr for ChaosConfig {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut config = ChaosConfig::default();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("expected KEY=VALUE, got `{}`", part))?;
+            if key == "seed" {
+                config.seed = Some(value.parse().map_err(|_| format!("invalid seed `{}`", value))?);
+                continue;
+            }
+            let probability: f64 = value
+                .parse()
+                .ok()
+                .filter(|p| (0.0..=1.0).contains(p))
+                .ok_or_else(|| format!("`{}`: probability must be between 0 and 1", part))?;
+            match key {
+                "timeout" => config.timeout = probability,
+                "db" => config.db_failure = probability,
+                "malformed" => config.malformed = probability,
+                other => return Err(format!("unknown chaos fault `{}` (timeout, db, malformed, seed)", other)),
+            }
+        }
+        Ok(config)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    Timeout,
+    Malformed,
+}
+
+/// How many faults were injected since startup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChaosCounts {
+    pub timeouts: u64,
+    pub db_failures: u64,
+    pub malformed: u64,
+}
+
+#[derive(Debug)]
+struct Inner {
+    config: ChaosConfig,
+    rng: Mutex<StdRng>,
+    timeouts: AtomicU64,
+    db_failures: AtomicU64,
+    malformed: AtomicU64,
+}
+
+/// Fault injector shared by the clones of a `Fetcher`. Draws come from one
+/// seeded generator, so the same seed and calls give the same faults.
+#[derive(Debug, Clone)]
+pub struct Chaos(Arc<Inner>);
+
+impl Chaos {
+    pub fn new(config: ChaosConfig) -> Self {
+        let rng = match config.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        Chaos(Arc::new(Inner {
+            config,
+            rng: Mutex::new(rng),
+            timeouts: AtomicU64::new(0),
+            db_failures: AtomicU64::new(0),
+            malformed: AtomicU64::new(0),
+        }))
+    }
+
+    pub fn config(&self) -> ChaosConfig {
+        self.0.config
+    }
+
+    fn roll(&self, probability: f64) -> bool {
+        probability > 0.0 && self.0.rng.lock().unwrap().gen_bool(probability)
+    }
+
+    /// The fault to inject in the next call to `provider`, if any.
+    pub fn provider_fault(&self, provider: &str) -> Option<Fault> {
+        let fault = if self.roll(self.0.config.timeout) {
+            self.0.timeouts.fetch_add(1, Ordering::Relaxed);
+            Fault::Timeout
+        } else if self.roll(self.0.config.malformed) {
+            self.0.malformed.fetch_add(1, Ordering::Relaxed);
+            Fault::Malformed
+        } else {
+            return None;
+        };
+        warn!(provider, chaos = ?fault, "Chaos: injecting fault");
+        Some(fault)
+    }
+
+    /// `Some` error when the next database write must fail.
+    pub fn db_failure(&self) -> Option<Error> {
+        if !self.roll(self.0.config.db_failure) {
+            return None;
+        }
+        self.0.db_failures.fetch_add(1, Ordering::Relaxed);
+        warn!("Chaos: injecting database failure");
+        Some(Error::db("simulated database failure (chaos)"))
+    }
+
+    pub fn counts(&self) -> ChaosCounts {
+        ChaosCounts {
+            timeouts: self.0.timeouts.load(Ordering::Relaxed),
+            db_failures: self.0.db_failures.load(Ordering::Relaxed),
+            malformed: self.0.malformed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// What a provider call that timed out returns.
+pub fn timeout_error(provider: &str) -> Error {
+    Error::http(format!("{} request timed out (chaos)", provider))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_parses_probabilities_and_seed() {
+        let config: ChaosConfig = "timeout=0.1, db=0.05,seed=42".parse().unwrap();
+        assert_eq!(config, ChaosConfig { timeout: 0.1, db_failure: 0.05, malformed: 0.0, seed: Some(42) });
+        assert!("timeout=1.5".parse::<ChaosConfig>().is_err());
+        assert!("latency=0.1".parse::<ChaosConfig>().is_err());
+        assert!("timeout".parse::<ChaosConfig>().is_err());
+    }
+
+    #[test]
+    fn the_same_seed_injects_the_same_faults() {
+        let config: ChaosConfig = "timeout=0.3,malformed=0.3,db=0.3,seed=7".parse().unwrap();
+        let run = || {
+            let chaos = Chaos::new(config);
+            let faults: Vec<_> = (0..50).map(|_| (chaos.provider_fault("Yahoo"), chaos.db_failure().is_some())).collect();
+            (faults, chaos.counts())
+        };
+        let (first, counts) = run();
+        assert_eq!(first, run().0);
+        assert!(counts.timeouts > 0 && counts.malformed > 0 && counts.db_failures > 0);
+    }
+
+    #[test]
+    fn certain_and_impossible_faults() {
+        let always = Chaos::new(ChaosConfig { timeout: 1.0, db_failure: 1.0, ..Default::default() });
+        assert_eq!(always.provider_fault("Finnhub"), Some(Fault::Timeout));
+        assert!(always.db_failure().is_some());
+
+        let never = Chaos::new(ChaosConfig::default());
+        assert_eq!(never.provider_fault("Finnhub"), None);
+        assert!(never.db_failure().is_none());
+    }
+}
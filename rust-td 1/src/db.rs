@@ -0,0 +1,49 @@
+use crate::StockPrice;
+use sqlx::{PgPool, Row};
+use td_common::{Context, Result};
+use tracing::instrument;
+
+#[instrument(skip_all, fields(symbol = %price.symbol, source = %price.source))]
+pub async fn save_price(pool: &PgPool, price: &StockPrice) -> Result<()> {
+    sqlx::query(
+        r#"INSERT INTO stock_prices (symbol, price, source, timestamp, currency, is_mock) VALUES ($1, $2, $3, $4, $5, $6)"#,
+    )
+    .bind(&price.symbol)
+    .bind(price.price)
+    .bind(&price.source)
+    .bind(price.timestamp)
+    .bind(&price.currency)
+    .bind(price.is_mock)
+    .execute(pool)
+    .await
+    .with_context(|| format!("saving {} price for {}", price.source, price.symbol))?;
+
+    Ok(())
+}
+
+pub async fn query_latest(pool: &PgPool, symbols: &[&str]) -> Result<()> {
+    for &sym in symbols {
+        let res = sqlx::query(
+            r#"SELECT symbol, price, source, timestamp, currency, is_mock, created_at FROM stock_prices WHERE symbol = $1 ORDER BY timestamp DESC LIMIT 1"#,
+        )
+        .bind(sym)
+        .fetch_optional(pool)
+        .await
+        .with_context(|| format!("querying latest price for {}", sym))?;
+
+        if let Some(row) = res {
+            let symbol: String = row.try_get("symbol")?;
+            let price: f64 = row.try_get("price")?;
+            let source: String = row.try_get("source")?;
+            let timestamp: i64 = row.try_get("timestamp")?;
+            let currency: String = row.try_get("currency")?;
+            let is_mock: bool = row.try_get("is_mock")?;
+            let marker = if is_mock { " [MOCK]" } else { "" };
+            println!("Latest {}: {} {} (source={}, ts={}){}", symbol, price, currency, source, timestamp, marker);
+        } else {
+            println!("No data for {}", sym);
+        }
+    }
+
+    Ok(())
+}
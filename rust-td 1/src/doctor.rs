@@ -0,0 +1,387 @@
+use sqlx::{PgPool, Row};
+use std::fmt;
+use td_common::{Context, Result};
+
+struct ExpectedColumn {
+    name: &'static str,
+    /// Accepted `information_schema.columns.data_type` values.
+    data_types: &'static [&'static str],
+    /// Type used to convert a column of another type.
+    sql_type: &'static str,
+    /// Definition used to add the column when it is missing.
+    definition: &'static str,
+}
+
+struct ExpectedIndex {
+    name: &'static str,
+    /// Leading columns; any index starting with them will do.
+    columns: &'static [&'static str],
+}
+
+struct ExpectedTable {
+    name: &'static str,
+    columns: &'static [ExpectedColumn],
+    indexes: &'static [ExpectedIndex],
+    /// Migrations creating the table as the current version expects it.
+    migrations: &'static [&'static str],
+}
+
+const fn column(
+    name: &'static str,
+    data_types: &'static [&'static str],
+    sql_type: &'static str,
+    definition: &'static str,
+) -> ExpectedColumn {
+    ExpectedColumn {
+        name,
+        data_types,
+        sql_type,
+        definition,
+    }
+}
+
+const VARCHAR: &[&str] = &["character varying"];
+const NUMERIC: &[&str] = &["numeric"];
+const BIGINT: &[&str] = &["bigint"];
+const INTEGER: &[&str] = &["integer"];
+const DOUBLE: &[&str] = &["double precision"];
+// Databases created from the TD 2 schema.sql have a naive created_at.
+const TIMESTAMP: &[&str] = &["timestamp with time zone", "timestamp without time zone"];
+
+const SCHEMA: &[ExpectedTable] = &[
+    ExpectedTable {
+        name: "stock_prices",
+        columns: &[
+            column("id", INTEGER, "INTEGER", "SERIAL PRIMARY KEY"),
+            column("symbol", VARCHAR, "VARCHAR(10)", "VARCHAR(10) NOT NULL"),
+            column("price", NUMERIC, "NUMERIC(10,2)", "NUMERIC(10,2) NOT NULL"),
+            column("source", VARCHAR, "VARCHAR(50)", "VARCHAR(50) NOT NULL"),
+            column("timestamp", BIGINT, "BIGINT", "BIGINT NOT NULL"),
+            column("is_mock", &["boolean"], "BOOLEAN", "BOOLEAN NOT NULL DEFAULT FALSE"),
+            column("currency", VARCHAR, "VARCHAR(3)", "VARCHAR(3) NOT NULL DEFAULT 'USD'"),
+            column("created_at", TIMESTAMP, "TIMESTAMP WITH TIME ZONE", "TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP"),
+        ],
+        indexes: &[ExpectedIndex {
+            name: "idx_symbol_timestamp",
+            columns: &["symbol", "timestamp"],
+        }],
+        migrations: &[
+            include_str!("../migrations/0001_create_stock_prices.sql"),
+            include_str!("../migrations/0002_add_is_mock.sql"),
+            include_str!("../migrations/0004_add_currency.sql"),
+        ],
+    },
+    ExpectedTable {
+        name: "stock_candles",
+        columns: &[
+            column("symbol", VARCHAR, "VARCHAR(10)", "VARCHAR(10) NOT NULL"),
+            column("interval_secs", INTEGER, "INTEGER", "INTEGER NOT NULL"),
+            column("bucket_start", BIGINT, "BIGINT", "BIGINT NOT NULL"),
+            column("open", NUMERIC, "NUMERIC(10,2)", "NUMERIC(10,2) NOT NULL"),
+            column("high", NUMERIC, "NUMERIC(10,2)", "NUMERIC(10,2) NOT NULL"),
+            column("low", NUMERIC, "NUMERIC(10,2)", "NUMERIC(10,2) NOT NULL"),
+            column("close", NUMERIC, "NUMERIC(10,2)", "NUMERIC(10,2) NOT NULL"),
+            column("samples", INTEGER, "INTEGER", "INTEGER NOT NULL"),
+        ],
+        indexes: &[ExpectedIndex {
+            name: "stock_candles_pkey",
+            columns: &["symbol", "interval_secs", "bucket_start"],
+        }],
+        migrations: &[include_str!("../migrations/0003_create_stock_candles.sql")],
+    },
+    ExpectedTable {
+        name: "source_spreads",
+        columns: &[
+            column("symbol", VARCHAR, "VARCHAR(10)", "VARCHAR(10) NOT NULL"),
+            column("timestamp", BIGINT, "BIGINT", "BIGINT NOT NULL"),
+            column("min_source", VARCHAR, "VARCHAR(50)", "VARCHAR(50) NOT NULL"),
+            column("min_price", NUMERIC, "NUMERIC(10,2)", "NUMERIC(10,2) NOT NULL"),
+            column("max_source", VARCHAR, "VARCHAR(50)", "VARCHAR(50) NOT NULL"),
+            column("max_price", NUMERIC, "NUMERIC(10,2)", "NUMERIC(10,2) NOT NULL"),
+            column("spread_pct", DOUBLE, "DOUBLE PRECISION", "DOUBLE PRECISION NOT NULL"),
+        ],
+        indexes: &[ExpectedIndex {
+            name: "idx_source_spreads_symbol_timestamp",
+            columns: &["symbol", "timestamp"],
+        }],
+        migrations: &[include_str!("../migrations/0005_create_source_spreads.sql")],
+    },
+    ExpectedTable {
+        name: "symbols",
+        columns: &[
+            column("symbol", VARCHAR, "VARCHAR(10)", "VARCHAR(10) NOT NULL"),
+            column("name", VARCHAR, "VARCHAR(200)", "VARCHAR(200)"),
+            column("exchange", VARCHAR, "VARCHAR(100)", "VARCHAR(100)"),
+            column("currency", VARCHAR, "VARCHAR(3)", "VARCHAR(3)"),
+            column("source", VARCHAR, "VARCHAR(50)", "VARCHAR(50) NOT NULL"),
+            column("updated_at", TIMESTAMP, "TIMESTAMP WITH TIME ZONE", "TIMESTAMP WITH TIME ZONE DEFAULT CURRENT_TIMESTAMP"),
+        ],
+        indexes: &[ExpectedIndex {
+            name: "symbols_pkey",
+            columns: &["symbol"],
+        }],
+        migrations: &[include_str!("../migrations/0006_create_symbols.sql")],
+    },
+    ExpectedTable {
+        name: "corporate_actions",
+        columns: &[
+            column("symbol", VARCHAR, "VARCHAR(10)", "VARCHAR(10) NOT NULL"),
+            column("kind", VARCHAR, "VARCHAR(10)", "VARCHAR(10) NOT NULL"),
+            column("ex_date", BIGINT, "BIGINT", "BIGINT NOT NULL"),
+            column("value", DOUBLE, "DOUBLE PRECISION", "DOUBLE PRECISION NOT NULL"),
+            column("factor", DOUBLE, "DOUBLE PRECISION", "DOUBLE PRECISION NOT NULL"),
+            column("source", VARCHAR, "VARCHAR(50)", "VARCHAR(50) NOT NULL"),
+        ],
+        indexes: &[ExpectedIndex {
+            name: "corporate_actions_pkey",
+            columns: &["symbol", "kind", "ex_date"],
+        }],
+        migrations: &[include_str!("../migrations/0007_create_corporate_actions.sql")],
+    },
+    ExpectedTable {
+        name: "portfolio_positions",
+        columns: &[
+            column("symbol", VARCHAR, "VARCHAR(10)", "VARCHAR(10) NOT NULL"),
+            column("quantity", DOUBLE, "DOUBLE PRECISION", "DOUBLE PRECISION NOT NULL"),
+            column("cost_basis", NUMERIC, "NUMERIC(10,2)", "NUMERIC(10,2) NOT NULL"),
+        ],
+        indexes: &[ExpectedIndex {
+            name: "portfolio_positions_pkey",
+            columns: &["symbol"],
+        }],
+        migrations: &[include_str!("../migrations/0008_create_portfolio_positions.sql")],
+    },
+    ExpectedTable {
+        name: "price_changes",
+        columns: &[
+            column("symbol", VARCHAR, "VARCHAR(10)", "VARCHAR(10) NOT NULL"),
+            column("source", VARCHAR, "VARCHAR(50)", "VARCHAR(50) NOT NULL"),
+            column("timestamp", BIGINT, "BIGINT", "BIGINT NOT NULL"),
+            column("previous_price", NUMERIC, "NUMERIC(10,2)", "NUMERIC(10,2) NOT NULL"),
+            column("price", NUMERIC, "NUMERIC(10,2)", "NUMERIC(10,2) NOT NULL"),
+            column("change", DOUBLE, "DOUBLE PRECISION", "DOUBLE PRECISION NOT NULL"),
+            column("change_pct", DOUBLE, "DOUBLE PRECISION", "DOUBLE PRECISION NOT NULL"),
+            column("direction", VARCHAR, "VARCHAR(4)", "VARCHAR(4) NOT NULL"),
+        ],
+        indexes: &[ExpectedIndex {
+            name: "idx_price_changes_symbol_timestamp",
+            columns: &["symbol", "timestamp"],
+        }],
+        migrations: &[include_str!("../migrations/0009_create_price_changes.sql")],
+    },
+];
+
+/// Something the current version needs and the database lacks.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaProblem {
+    MissingTable {
+        table: String,
+    },
+    MissingColumn {
+        table: String,
+        column: String,
+    },
+    WrongType {
+        table: String,
+        column: String,
+        found: String,
+        expected: String,
+    },
+    MissingIndex {
+        table: String,
+        columns: Vec<String>,
+    },
+}
+
+impl fmt::Display for SchemaProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaProblem::MissingTable { table } => write!(f, "missing table {}", table),
+            SchemaProblem::MissingColumn { table, column } => write!(f, "missing column {}.{}", table, column),
+            SchemaProblem::WrongType {
+                table,
+                column,
+                found,
+                expected,
+            } => write!(f, "column {}.{} is {}, expected {}", table, column, found, expected),
+            SchemaProblem::MissingIndex { table, columns } => {
+                write!(f, "no index on {}({})", table, columns.join(", "))
+            }
+        }
+    }
+}
+
+fn expected_table(name: &str) -> &'static ExpectedTable {
+    SCHEMA.iter().find(|t| t.name == name).expect("table from SCHEMA")
+}
+
+impl SchemaProblem {
+    /// SQL repairing the problem. Converting a column keeps its data only
+    /// when the cast succeeds, otherwise the statement fails.
+    pub fn fix_sql(&self) -> String {
+        match self {
+            SchemaProblem::MissingTable { table } => expected_table(table).migrations.concat(),
+            SchemaProblem::MissingColumn { table, column } => {
+                let expected = expected_table(table).columns.iter().find(|c| c.name == column).unwrap();
+                format!(
+                    r#"ALTER TABLE {} ADD COLUMN IF NOT EXISTS "{}" {};"#,
+                    table, column, expected.definition
+                )
+            }
+            SchemaProblem::WrongType {
+                table,
+                column,
+                expected,
+                ..
+            } => format!(
+                r#"ALTER TABLE {table} ALTER COLUMN "{column}" TYPE {expected} USING "{column}"::{expected};"#
+            ),
+            SchemaProblem::MissingIndex { table, columns } => {
+                let index = expected_table(table)
+                    .indexes
+                    .iter()
+                    .find(|i| i.columns.iter().eq(columns.iter()))
+                    .unwrap();
+                let columns: Vec<String> = columns.iter().map(|c| format!(r#""{}""#, c)).collect();
+                format!(
+                    "CREATE INDEX IF NOT EXISTS {} ON {}({});",
+                    index.name,
+                    table,
+                    columns.join(", ")
+                )
+            }
+        }
+    }
+}
+
+/// Compares the database with the schema of this version.
+pub async fn check_schema(pool: &PgPool) -> Result<Vec<SchemaProblem>> {
+    let mut problems = Vec::new();
+    for table in SCHEMA {
+        let rows = sqlx::query(
+            r#"
+            SELECT column_name::text AS name, data_type::text AS data_type
+            FROM information_schema.columns
+            WHERE table_schema = current_schema() AND table_name = $1
+            "#,
+        )
+        .bind(table.name)
+        .fetch_all(pool)
+        .await
+        .with_context(|| format!("reading columns of {}", table.name))?;
+
+        if rows.is_empty() {
+            problems.push(SchemaProblem::MissingTable {
+                table: table.name.to_string(),
+            });
+            continue;
+        }
+
+        for expected in table.columns {
+            let found = rows.iter().find(|r| r.get::<String, _>("name") == expected.name);
+            match found {
+                None => problems.push(SchemaProblem::MissingColumn {
+                    table: table.name.to_string(),
+                    column: expected.name.to_string(),
+                }),
+                Some(row) => {
+                    let data_type: String = row.get("data_type");
+                    if !expected.data_types.contains(&data_type.as_str()) {
+                        problems.push(SchemaProblem::WrongType {
+                            table: table.name.to_string(),
+                            column: expected.name.to_string(),
+                            found: data_type,
+                            expected: expected.sql_type.to_string(),
+                        });
+                    }
+                }
+            }
+        }
+
+        let indexes: Vec<Vec<String>> = sqlx::query_scalar(
+            r#"
+            SELECT ARRAY(
+                SELECT a.attname::text
+                FROM unnest(x.indkey) WITH ORDINALITY AS k(attnum, ord)
+                JOIN pg_attribute a ON a.attrelid = x.indrelid AND a.attnum = k.attnum
+                ORDER BY k.ord
+            )
+            FROM pg_index x
+            JOIN pg_class t ON t.oid = x.indrelid
+            WHERE t.relname = $1 AND t.relnamespace = current_schema()::regnamespace
+            "#,
+        )
+        .bind(table.name)
+        .fetch_all(pool)
+        .await
+        .with_context(|| format!("reading indexes of {}", table.name))?;
+
+        for expected in table.indexes {
+            let covered = indexes.iter().any(|cols| cols.iter().take(expected.columns.len()).eq(expected.columns.iter()));
+            if !covered {
+                problems.push(SchemaProblem::MissingIndex {
+                    table: table.name.to_string(),
+                    columns: expected.columns.iter().map(|c| c.to_string()).collect(),
+                });
+            }
+        }
+    }
+    Ok(problems)
+}
+
+/// Applies the fixes of `problems` in one transaction.
+pub async fn repair_schema(pool: &PgPool, problems: &[SchemaProblem]) -> Result<()> {
+    let mut tx = pool.begin().await.context("starting schema repair")?;
+    for problem in problems {
+        sqlx::raw_sql(&problem.fix_sql())
+            .execute(&mut *tx)
+            .await
+            .with_context(|| format!("fixing {}", problem))?;
+    }
+    tx.commit().await.context("committing schema repair")?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fixes_are_idempotent_sql() {
+        let column = SchemaProblem::MissingColumn {
+            table: "stock_prices".into(),
+            column: "is_mock".into(),
+        };
+        assert_eq!(
+            column.fix_sql(),
+            r#"ALTER TABLE stock_prices ADD COLUMN IF NOT EXISTS "is_mock" BOOLEAN NOT NULL DEFAULT FALSE;"#
+        );
+
+        let index = SchemaProblem::MissingIndex {
+            table: "stock_prices".into(),
+            columns: vec!["symbol".into(), "timestamp".into()],
+        };
+        assert_eq!(
+            index.fix_sql(),
+            r#"CREATE INDEX IF NOT EXISTS idx_symbol_timestamp ON stock_prices("symbol", "timestamp");"#
+        );
+        assert_eq!(index.to_string(), "no index on stock_prices(symbol, timestamp)");
+
+        let table = SchemaProblem::MissingTable {
+            table: "stock_prices".into(),
+        };
+        assert!(table.fix_sql().contains("CREATE TABLE IF NOT EXISTS stock_prices"));
+        assert!(table.fix_sql().contains("ADD COLUMN IF NOT EXISTS currency"));
+    }
+
+    #[test]
+    fn every_expected_index_uses_known_columns() {
+        for table in SCHEMA {
+            for index in table.indexes {
+                for column in index.columns {
+                    assert!(table.columns.iter().any(|c| c.name == *column), "{}.{}", table.name, column);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,278 @@
+use futures_util::TryStreamExt;
+use sqlx::{PgPool, Row};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+use td_common::{Context, Error, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Parquet,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "parquet" => Ok(ExportFormat::Parquet),
+            other => Err(format!("unknown export format `{}` (csv or parquet)", other)),
+        }
+    }
+}
+
+/// `90s`, `15m`, `12h`, `7d` or `2w`.
+pub fn parse_since(s: &str) -> std::result::Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (amount, unit) = s.split_at(split);
+    let amount: u64 = amount.parse().map_err(|_| format!("invalid duration `{}`", s))?;
+    let secs = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        "w" => 7 * 86_400,
+        _ => return Err(format!("invalid duration unit in `{}` (s, m, h, d or w)", s)),
+    };
+    Ok(Duration::from_secs(amount * secs))
+}
+
+/// One exported row; the column order of both formats.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportRow {
+    pub symbol: String,
+    pub price: f64,
+    pub currency: String,
+    pub source: String,
+    pub timestamp: i64,
+    pub is_mock: bool,
+}
+
+pub const EXPORT_COLUMNS: [&str; 6] = ["symbol", "price", "currency", "source", "timestamp", "is_mock"];
+
+/// Price of a row restated after the later splits and dividends.
+pub(crate) const ADJUSTED_PRICE: &str = r#"price::float8 * COALESCE((
+            SELECT EXP(SUM(LN(a.factor))) FROM corporate_actions a
+            WHERE a.symbol = stock_prices.symbol AND a.ex_date > stock_prices.timestamp
+        ), 1)"#;
+
+/// Streams `stock_prices` (optionally one symbol, optionally only rows newer
+/// than `since_ts`) into `output`, oldest first. With `adjusted`, prices are
+/// multiplied by the factors of the later corporate actions so a split
+/// doesn't show as a cliff. Returns the row count.
+pub async fn export_prices(
+    pool: &PgPool,
+    symbol: Option<&str>,
+    since_ts: Option<i64>,
+    adjusted: bool,
+    format: ExportFormat,
+    output: &Path,
+) -> Result<u64> {
+    let price = if adjusted { ADJUSTED_PRICE } else { "price::float8" };
+    let sql = format!(
+        r#"
+        SELECT symbol, {} AS price, currency, source, timestamp, is_mock
+        FROM stock_prices
+        WHERE ($1::text IS NULL OR symbol = $1) AND ($2::bigint IS NULL OR timestamp >= $2)
+        ORDER BY timestamp
+        "#,
+        price
+    );
+    let mut rows = sqlx::query(&sql).bind(symbol).bind(since_ts).fetch(pool);
+
+    let mut sink = RowSink::create(format, output)?;
+    let mut count = 0;
+    while let Some(row) = rows.try_next().await.context("reading stock_prices")? {
+        sink.write(ExportRow {
+            symbol: row.try_get("symbol")?,
+            price: row.try_get("price")?,
+            currency: row.try_get("currency")?,
+            source: row.try_get("source")?,
+            timestamp: row.try_get("timestamp")?,
+            is_mock: row.try_get("is_mock")?,
+        })?;
+        count += 1;
+    }
+    sink.finish()?;
+    Ok(count)
+}
+
+enum RowSink {
+    Csv(csv::Writer<std::fs::File>),
+    #[cfg(feature = "parquet")]
+    Parquet(parquet_sink::ParquetSink),
+}
+
+impl RowSink {
+    fn create(format: ExportFormat, output: &Path) -> Result<Self> {
+        match format {
+            ExportFormat::Csv => {
+                let mut writer = csv::Writer::from_path(output)
+                    .map_err(Error::parse)
+                    .with_context(|| format!("creating {}", output.display()))?;
+                writer.write_record(EXPORT_COLUMNS).map_err(Error::parse)?;
+                Ok(RowSink::Csv(writer))
+            }
+            #[cfg(feature = "parquet")]
+            ExportFormat::Parquet => Ok(RowSink::Parquet(parquet_sink::ParquetSink::create(output)?)),
+            #[cfg(not(feature = "parquet"))]
+            ExportFormat::Parquet => Err(Error::parse("this build has no Parquet support (feature `parquet`)")),
+        }
+    }
+
+    fn write(&mut self, row: ExportRow) -> Result<()> {
+        match self {
+            RowSink::Csv(writer) => writer
+                .write_record([
+                    row.symbol,
+                    row.price.to_string(),
+                    row.currency,
+                    row.source,
+                    row.timestamp.to_string(),
+                    row.is_mock.to_string(),
+                ])
+                .map_err(Error::parse),
+            #[cfg(feature = "parquet")]
+            RowSink::Parquet(sink) => sink.write(row),
+        }
+    }
+
+    fn finish(self) -> Result<()> {
+        match self {
+            RowSink::Csv(mut writer) => Ok(writer.flush()?),
+            #[cfg(feature = "parquet")]
+            RowSink::Parquet(sink) => sink.finish(),
+        }
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_sink {
+    use super::{ExportRow, EXPORT_COLUMNS};
+    use arrow_array::builder::{BooleanBuilder, Float64Builder, Int64Builder, StringBuilder};
+    use arrow_array::{ArrayRef, RecordBatch};
+    use arrow_schema::{DataType, Field, Schema, SchemaRef};
+    use parquet::arrow::ArrowWriter;
+    use std::fs::File;
+    use std::path::Path;
+    use std::sync::Arc;
+    use td_common::{Context, Error, Result};
+
+    /// Rows are buffered into record batches of this size.
+    const BATCH_ROWS: usize = 8192;
+
+    pub struct ParquetSink {
+        writer: ArrowWriter<File>,
+        schema: SchemaRef,
+        rows: Vec<ExportRow>,
+    }
+
+    impl ParquetSink {
+        pub fn create(output: &Path) -> Result<Self> {
+            let [symbol, price, currency, source, timestamp, is_mock] = EXPORT_COLUMNS;
+            let schema = Arc::new(Schema::new(vec![
+                Field::new(symbol, DataType::Utf8, false),
+                Field::new(price, DataType::Float64, false),
+                Field::new(currency, DataType::Utf8, false),
+                Field::new(source, DataType::Utf8, false),
+                Field::new(timestamp, DataType::Int64, false),
+                Field::new(is_mock, DataType::Boolean, false),
+            ]));
+            let file = File::create(output).with_context(|| format!("creating {}", output.display()))?;
+            let writer = ArrowWriter::try_new(file, schema.clone(), None).map_err(Error::parse)?;
+            Ok(ParquetSink {
+                writer,
+                schema,
+                rows: Vec::with_capacity(BATCH_ROWS),
+            })
+        }
+
+        pub fn write(&mut self, row: ExportRow) -> Result<()> {
+            self.rows.push(row);
+            if self.rows.len() >= BATCH_ROWS {
+                self.flush_batch()?;
+            }
+            Ok(())
+        }
+
+        fn flush_batch(&mut self) -> Result<()> {
+            let mut symbol = StringBuilder::new();
+            let mut price = Float64Builder::new();
+            let mut currency = StringBuilder::new();
+            let mut source = StringBuilder::new();
+            let mut timestamp = Int64Builder::new();
+            let mut is_mock = BooleanBuilder::new();
+            for row in self.rows.drain(..) {
+                symbol.append_value(row.symbol);
+                price.append_value(row.price);
+                currency.append_value(row.currency);
+                source.append_value(row.source);
+                timestamp.append_value(row.timestamp);
+                is_mock.append_value(row.is_mock);
+            }
+            let columns: Vec<ArrayRef> = vec![
+                Arc::new(symbol.finish()),
+                Arc::new(price.finish()),
+                Arc::new(currency.finish()),
+                Arc::new(source.finish()),
+                Arc::new(timestamp.finish()),
+                Arc::new(is_mock.finish()),
+            ];
+            let batch = RecordBatch::try_new(self.schema.clone(), columns).map_err(Error::parse)?;
+            self.writer.write(&batch).map_err(Error::parse)
+        }
+
+        pub fn finish(mut self) -> Result<()> {
+            if !self.rows.is_empty() {
+                self.flush_batch()?;
+            }
+            self.writer.close().map_err(Error::parse)?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn since_accepts_common_units() {
+        assert_eq!(parse_since("7d"), Ok(Duration::from_secs(7 * 86_400)));
+        assert_eq!(parse_since("90s"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_since("2w"), Ok(Duration::from_secs(14 * 86_400)));
+        assert!(parse_since("7").is_err());
+        assert!(parse_since("d").is_err());
+        assert!(parse_since("7y").is_err());
+    }
+
+    #[test]
+    fn format_names() {
+        assert_eq!("CSV".parse(), Ok(ExportFormat::Csv));
+        assert_eq!("parquet".parse(), Ok(ExportFormat::Parquet));
+        assert!("xlsx".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn csv_sink_writes_header_and_rows() {
+        let path = std::env::temp_dir().join(format!("rust-td-export-{}.csv", std::process::id()));
+        let mut sink = RowSink::create(ExportFormat::Csv, &path).unwrap();
+        sink.write(ExportRow {
+            symbol: "AAPL".into(),
+            price: 187.25,
+            currency: "USD".into(),
+            source: "Finnhub".into(),
+            timestamp: 1_700_000_000,
+            is_mock: false,
+        })
+        .unwrap();
+        sink.finish().unwrap();
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(text, "symbol,price,currency,source,timestamp,is_mock\nAAPL,187.25,USD,Finnhub,1700000000,false\n");
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{Fetcher, StockPrice};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use td_common::{Context, Result};
+use tracing::error;
+
+/// ECB reference rates only change once a day.
+pub const FX_CACHE_TTL: Duration = Duration::from_secs(3600);
+
+/// Listing currency guessed from the exchange suffix of a Yahoo-style
+/// ticker (`MC.PA`, `VOD.L`...); no suffix means a US listing.
+pub fn listing_currency(symbol: &str) -> &'static str {
+    let Some((_, suffix)) = symbol.rsplit_once('.') else {
+        return "USD";
+    };
+    match suffix.to_ascii_uppercase().as_str() {
+        "PA" | "DE" | "F" | "AS" | "BR" | "MI" | "MC" | "LS" | "VI" | "HE" | "IR" => "EUR",
+        // London quotes are in pence.
+        "L" => "GBp",
+        "SW" => "CHF",
+        "TO" | "V" => "CAD",
+        "T" => "JPY",
+        "HK" => "HKD",
+        "AX" => "AUD",
+        "ST" => "SEK",
+        "CO" => "DKK",
+        "OL" => "NOK",
+        _ => "USD",
+    }
+}
+
+/// Minor-unit currencies some exchanges quote in, with their major unit.
+pub(crate) fn major_unit(currency: &str) -> (&str, f64) {
+    match currency {
+        "GBp" | "GBX" => ("GBP", 100.0),
+        "ZAc" | "ZAC" => ("ZAR", 100.0),
+        "ILA" => ("ILS", 100.0),
+        other => (other, 1.0),
+    }
+}
+
+/// Units of each currency for one unit of `base`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FxRates {
+    pub base: String,
+    pub rates: HashMap<String, f64>,
+}
+
+impl FxRates {
+    /// `amount` of `from` expressed in `to`; `None` for an unknown currency.
+    pub fn convert(&self, amount: f64, from: &str, to: &str) -> Option<f64> {
+        let (from, from_div) = major_unit(from);
+        let (to, to_div) = major_unit(to);
+        let rate = |currency: &str| {
+            if currency.eq_ignore_ascii_case(&self.base) {
+                Some(1.0)
+            } else {
+                self.rates.get(&currency.to_ascii_uppercase()).copied()
+            }
+        };
+        Some(amount / from_div / rate(from)? * rate(to)? * to_div)
+    }
+
+    /// The price restated in `to`.
+    pub fn convert_price(&self, price: &StockPrice, to: &str) -> Option<StockPrice> {
+        if price.currency == to {
+            return Some(price.clone());
+        }
+        Some(StockPrice {
+            price: self.convert(price.price, &price.currency, to)?,
+            currency: to.to_string(),
+            ..price.clone()
+        })
+    }
+}
+
+/// `https://api.frankfurter.app/latest?from=USD`
+#[derive(Deserialize, Debug)]
+struct FrankfurterResponse {
+    base: String,
+    rates: HashMap<String, f64>,
+}
+
+fn parse_frankfurter(body: &str) -> Result<FxRates> {
+    let data: FrankfurterResponse = crate::providers::parse_strict("Frankfurter", body)?;
+    Ok(FxRates {
+        base: data.base,
+        rates: data.rates,
+    })
+}
+
+/// Fixed rates for tests and `MOCK_FETCH`.
+fn mock_rates(base: &str) -> FxRates {
+    let usd = FxRates {
+        base: "USD".to_string(),
+        rates: [("EUR", 0.92), ("GBP", 0.79), ("CHF", 0.88), ("CAD", 1.36), ("JPY", 150.0)]
+            .into_iter()
+            .map(|(c, r)| (c.to_string(), r))
+            .collect(),
+    };
+    let rates = ["USD", "EUR", "GBP", "CHF", "CAD", "JPY"]
+        .into_iter()
+        .filter(|c| *c != base)
+        .filter_map(|c| Some((c.to_string(), usd.convert(1.0, base, c)?)))
+        .collect();
+    FxRates {
+        base: base.to_string(),
+        rates,
+    }
+}
+
+impl Fetcher {
+    /// Current reference rates against `base` (European Central Bank, through
+    /// frankfurter.app, no key needed), cached for [`FX_CACHE_TTL`].
+    pub async fn fetch_fx_rates(&self, base: &str) -> Result<FxRates> {
+        if let Some((at, rates)) = &*self.fx_cache.lock().unwrap()
+            && rates.base == base
+            && at.elapsed() < FX_CACHE_TTL
+        {
+            return Ok(rates.clone());
+        }
+
+        let rates = if cfg!(test) || std::env::var("MOCK_FETCH").is_ok() {
+            mock_rates(base)
+        } else {
+            let url = format!("https://api.frankfurter.app/latest?from={}", base);
+            let body = self.client.get(&url).send().await?.error_for_status()?.text().await?;
+            parse_frankfurter(&body).with_context(|| format!("FX rates for {}", base))?
+        };
+        *self.fx_cache.lock().unwrap() = Some((Instant::now(), rates.clone()));
+        Ok(rates)
+    }
+
+    /// Restates `prices` in `to`. A price that cannot be converted (no rate
+    /// for its currency, rates unavailable) is dropped rather than mixed in.
+    pub async fn convert_prices(&self, prices: Vec<StockPrice>, to: &str) -> Vec<StockPrice> {
+        if prices.iter().all(|p| p.currency == to) {
+            return prices;
+        }
+        let rates = match self.fetch_fx_rates(to).await {
+            Ok(rates) => Some(rates),
+            Err(e) => {
+                error!("FX rates unavailable: {}", e);
+                None
+            }
+        };
+        prices
+            .into_iter()
+            .filter_map(|price| {
+                let converted = rates.as_ref().and_then(|rates| rates.convert_price(&price, to));
+                if converted.is_none() {
+                    error!(symbol = %price.symbol, source = %price.source, "Dropping price: no {}/{} rate", price.currency, to);
+                }
+                converted
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suffix_gives_the_listing_currency() {
+        assert_eq!(listing_currency("AAPL"), "USD");
+        assert_eq!(listing_currency("MC.PA"), "EUR");
+        assert_eq!(listing_currency("VOD.L"), "GBp");
+        assert_eq!(listing_currency("BRK.B"), "USD");
+    }
+
+    #[test]
+    fn conversion_goes_through_the_base_and_minor_units() {
+        let rates = parse_frankfurter(r#"{"amount":1.0,"base":"USD","date":"2024-03-01","rates":{"EUR":0.8,"GBP":0.5}}"#)
+            .unwrap();
+        assert_eq!(rates.convert(8.0, "EUR", "USD"), Some(10.0));
+        assert_eq!(rates.convert(10.0, "USD", "EUR"), Some(8.0));
+        assert_eq!(rates.convert(8.0, "EUR", "GBP"), Some(5.0));
+        assert_eq!(rates.convert(250.0, "GBp", "USD"), Some(5.0));
+        assert_eq!(rates.convert(1.0, "XYZ", "USD"), None);
+    }
+
+    #[tokio::test]
+    async fn prices_are_restated_or_dropped() {
+        let mut eur = crate::fetch_mock_price("MC.PA", "Yahoo");
+        eur.price = 92.0;
+        let mut odd = crate::fetch_mock_price("AAPL", "Yahoo");
+        odd.currency = "XYZ".to_string();
+        let usd = crate::fetch_mock_price("AAPL", "Yahoo");
+
+        let prices = Fetcher::new().convert_prices(vec![eur, odd, usd], "USD").await;
+        assert_eq!(prices.len(), 2);
+        assert!(prices.iter().all(|p| p.currency == "USD"));
+        assert!((prices[0].price - 100.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,123 @@
+use crate::{save_price, Fetcher, StockPrice};
+use sqlx::{PgPool, Row};
+use td_common::{Context, Result};
+use tracing::{info, warn};
+
+/// A stretch with no stored price for `symbol`, between two consecutive rows.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gap {
+    pub symbol: String,
+    pub from: i64,
+    pub to: i64,
+}
+
+impl Gap {
+    pub fn secs(&self) -> i64 {
+        self.to - self.from
+    }
+}
+
+/// Consecutive rows of a symbol (all sources together) more than
+/// `max_interval_secs` apart.
+pub async fn find_gaps(pool: &PgPool, max_interval_secs: i64) -> Result<Vec<Gap>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT symbol, prev_ts, ts FROM (
+            SELECT symbol,
+                   timestamp AS ts,
+                   LAG(timestamp) OVER (PARTITION BY symbol ORDER BY timestamp) AS prev_ts
+            FROM stock_prices
+        ) t
+        WHERE ts - prev_ts > $1
+        ORDER BY symbol, prev_ts
+        "#,
+    )
+    .bind(max_interval_secs)
+    .fetch_all(pool)
+    .await
+    .context("scanning stock_prices for gaps")?;
+
+    let mut gaps = Vec::with_capacity(rows.len());
+    for row in rows {
+        gaps.push(Gap {
+            symbol: row.try_get("symbol")?,
+            from: row.try_get("prev_ts")?,
+            to: row.try_get("ts")?,
+        });
+    }
+    Ok(gaps)
+}
+
+/// History points strictly inside the gap (its bounds are already stored).
+pub fn points_in_gap(points: &[StockPrice], gap: &Gap) -> Vec<StockPrice> {
+    points
+        .iter()
+        .filter(|p| p.symbol == gap.symbol && p.timestamp > gap.from && p.timestamp < gap.to)
+        .cloned()
+        .collect()
+}
+
+/// Daily candles for gaps longer than a week, 1-minute candles otherwise.
+fn resolution_for(gap: &Gap) -> &'static str {
+    if gap.secs() > 7 * 24 * 3600 { "D" } else { "1" }
+}
+
+/// Fills each gap from the Finnhub candle history; returns the rows inserted.
+pub async fn backfill(fetcher: &Fetcher, pool: &PgPool, gaps: &[Gap]) -> Result<usize> {
+    let mut inserted = 0;
+    for gap in gaps {
+        let history = match fetcher
+            .fetch_finnhub_candles(&gap.symbol, gap.from, gap.to, resolution_for(gap))
+            .await
+        {
+            Ok(history) => history,
+            Err(e) => {
+                warn!(symbol = %gap.symbol, "Backfill of {}..{} failed: {}", gap.from, gap.to, e);
+                continue;
+            }
+        };
+        for price in points_in_gap(&history, gap) {
+            save_price(pool, &price).await?;
+            inserted += 1;
+        }
+        info!(symbol = %gap.symbol, "Backfilled {}..{}", gap.from, gap.to);
+    }
+    Ok(inserted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(symbol: &str, timestamp: i64) -> StockPrice {
+        StockPrice {
+            symbol: symbol.into(),
+            price: 100.0,
+            source: "Finnhub".into(),
+            timestamp,
+            currency: "USD".into(),
+            is_mock: false,
+        }
+    }
+
+    #[test]
+    fn only_points_strictly_inside_the_gap_are_kept() {
+        let gap = Gap {
+            symbol: "AAPL".into(),
+            from: 100,
+            to: 400,
+        };
+        let points = [price("AAPL", 100), price("AAPL", 200), price("GOOG", 250), price("AAPL", 400)];
+        let kept = points_in_gap(&points, &gap);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].timestamp, 200);
+    }
+
+    #[test]
+    fn long_gaps_use_daily_candles() {
+        let short = Gap { symbol: "AAPL".into(), from: 0, to: 3600 };
+        let long = Gap { symbol: "AAPL".into(), from: 0, to: 30 * 24 * 3600 };
+        assert_eq!(resolution_for(&short), "1");
+        assert_eq!(resolution_for(&long), "D");
+    }
+}
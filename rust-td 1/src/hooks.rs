@@ -0,0 +1,165 @@
+use crate::StockPrice;
+use crate::fx::major_unit;
+use std::fmt::Debug;
+use std::str::FromStr;
+
+/// Transformation applied to every fetched price before it is stored;
+/// returning `None` drops the price.
+pub trait PriceHook: Debug + Send {
+    fn on_price(&mut self, price: StockPrice) -> Option<StockPrice>;
+}
+
+/// Rounds the price to `decimals` places; a NaN or infinite price is dropped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Round {
+    pub decimals: u32,
+}
+
+impl PriceHook for Round {
+    fn on_price(&mut self, mut price: StockPrice) -> Option<StockPrice> {
+        if !price.price.is_finite() {
+            return None;
+        }
+        let scale = 10f64.powi(self.decimals as i32);
+        price.price = (price.price * scale).round() / scale;
+        Some(price)
+    }
+}
+
+/// Restates the prices quoted in `from` in `to` (`GBp` pence to `GBP`),
+/// multiplying by `factor`. Other currencies pass through.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitConversion {
+    pub from: String,
+    pub to: String,
+    pub factor: f64,
+}
+
+impl PriceHook for UnitConversion {
+    fn on_price(&mut self, mut price: StockPrice) -> Option<StockPrice> {
+        if price.currency == self.from {
+            price.price *= self.factor;
+            price.currency = self.to.clone();
+        }
+        Some(price)
+    }
+}
+
+/// Renames a symbol spelled differently by some provider (`BRK-B` for `BRK.B`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolRemap {
+    pub from: String,
+    pub to: String,
+}
+
+impl PriceHook for SymbolRemap {
+    fn on_price(&mut self, mut price: StockPrice) -> Option<StockPrice> {
+        if price.symbol.eq_ignore_ascii_case(&self.from) {
+            price.symbol = self.to.clone();
+        }
+        Some(price)
+    }
+}
+
+/// A built-in hook by name, as given to `--hooks`: `round[=DECIMALS]`,
+/// `unit=FROM:TO[:FACTOR]` (the factor is known for minor units such as
+/// `GBp`) or `remap=FROM:TO`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum HookSpec {
+    Round(Round),
+    Unit(UnitConversion),
+    Remap(SymbolRemap),
+}
+
+impl HookSpec {
+    pub fn build(&self) -> Box<dyn PriceHook> {
+        match self {
+            HookSpec::Round(hook) => Box::new(*hook),
+            HookSpec::Unit(hook) => Box::new(hook.clone()),
+            HookSpec::Remap(hook) => Box::new(hook.clone()),
+        }
+    }
+}
+
+impl FromStr for HookSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (name, args) = match s.trim().split_once('=') {
+            Some((name, args)) => (name, Some(args)),
+            None => (s.trim(), None),
+        };
+        let parts: Vec<&str> = args.map(|a| a.split(':').map(str::trim).collect()).unwrap_or_default();
+        match (name, parts.as_slice()) {
+            ("round", []) => Ok(HookSpec::Round(Round { decimals: 2 })),
+            ("round", [decimals]) => decimals
+                .parse()
+                .map(|decimals| HookSpec::Round(Round { decimals }))
+                .map_err(|_| format!("round: invalid number of decimals `{}`", decimals)),
+            ("unit", [from, to, rest @ ..]) if rest.len() <= 1 && !from.is_empty() && !to.is_empty() => {
+                let factor = match rest {
+                    [factor] => factor
+                        .parse::<f64>()
+                        .ok()
+                        .filter(|f| f.is_finite() && *f > 0.0)
+                        .ok_or_else(|| format!("unit: invalid factor `{}`", factor))?,
+                    _ => match major_unit(from) {
+                        (major, per_major) if major == *to && per_major != 1.0 => 1.0 / per_major,
+                        _ => return Err(format!("unit: no known factor from {} to {}, give FROM:TO:FACTOR", from, to)),
+                    },
+                };
+                Ok(HookSpec::Unit(UnitConversion { from: from.to_string(), to: to.to_string(), factor }))
+            }
+            ("remap", [from, to]) if !from.is_empty() && !to.is_empty() => {
+                Ok(HookSpec::Remap(SymbolRemap { from: from.to_string(), to: to.to_string() }))
+            }
+            ("round" | "unit" | "remap", _) => Err(format!(
+                "invalid hook `{}` (round[=DECIMALS], unit=FROM:TO[:FACTOR], remap=FROM:TO)",
+                s
+            )),
+            (other, _) => Err(format!("unknown hook `{}` (round, unit, remap)", other)),
+        }
+    }
+}
+
+/// Runs `price` through `hooks` in order; `None` as soon as one drops it.
+pub fn apply_hooks(hooks: &mut [Box<dyn PriceHook>], price: StockPrice) -> Option<StockPrice> {
+    hooks.iter_mut().try_fold(price, |price, hook| hook.on_price(price))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn build(specs: &[&str]) -> Vec<Box<dyn PriceHook>> {
+        specs.iter().map(|s| s.parse::<HookSpec>().unwrap().build()).collect()
+    }
+
+    #[test]
+    fn specs_parse_by_name() {
+        assert_eq!("round".parse(), Ok(HookSpec::Round(Round { decimals: 2 })));
+        assert_eq!("round=4".parse(), Ok(HookSpec::Round(Round { decimals: 4 })));
+        assert_eq!(
+            "unit=GBp:GBP".parse(),
+            Ok(HookSpec::Unit(UnitConversion { from: "GBp".into(), to: "GBP".into(), factor: 0.01 }))
+        );
+        assert!("unit=USD:EUR".parse::<HookSpec>().is_err());
+        assert!("unit=USD:EUR:0.92".parse::<HookSpec>().is_ok());
+        assert!("remap=BRK-B".parse::<HookSpec>().is_err());
+        assert!("upper".parse::<HookSpec>().is_err());
+    }
+
+    #[test]
+    fn hooks_apply_in_order_and_can_drop() {
+        let mut hooks = build(&["remap=BRK-B:BRK.B", "unit=GBp:GBP", "round=2"]);
+        let mut price = crate::fetch_mock_price("brk-b", "Yahoo");
+        price.price = 41_234.567;
+        price.currency = "GBp".to_string();
+
+        let out = apply_hooks(&mut hooks, price.clone()).unwrap();
+        assert_eq!((out.symbol.as_str(), out.currency.as_str(), out.price), ("BRK.B", "GBP", 412.35));
+
+        price.price = f64::NAN;
+        assert!(apply_hooks(&mut hooks, price).is_none());
+    }
+}
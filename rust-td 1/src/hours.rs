@@ -0,0 +1,174 @@
+use crate::Exchange;
+use chrono::{DateTime, Days, NaiveTime, TimeZone, Utc};
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::info;
+
+/// Regular session of an exchange, in its local time. Early closes (the day
+/// after Thanksgiving, Christmas Eve) are not known and count as full days.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TradingHours {
+    pub exchange: Exchange,
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+}
+
+/// `HH:MM-HH:MM` session override, in the exchange's local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Session {
+    pub open: NaiveTime,
+    pub close: NaiveTime,
+}
+
+impl FromStr for Session {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (open, close) = s
+            .split_once('-')
+            .ok_or_else(|| format!("expected HH:MM-HH:MM, got `{}`", s))?;
+        let time = |t: &str| NaiveTime::parse_from_str(t.trim(), "%H:%M").map_err(|_| format!("invalid time `{}`", t));
+        let (open, close) = (time(open)?, time(close)?);
+        if open >= close {
+            return Err(format!("the session must open before it closes: `{}`", s));
+        }
+        Ok(Session { open, close })
+    }
+}
+
+fn hm(hour: u32, minute: u32) -> NaiveTime {
+    NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+}
+
+impl TradingHours {
+    pub fn regular(exchange: Exchange) -> Self {
+        let (open, close) = match exchange {
+            Exchange::Nyse => (hm(9, 30), hm(16, 0)),
+            Exchange::Euronext => (hm(9, 0), hm(17, 30)),
+            Exchange::Lse => (hm(8, 0), hm(16, 30)),
+        };
+        TradingHours { exchange, open, close }
+    }
+
+    pub fn with_session(exchange: Exchange, session: Option<Session>) -> Self {
+        match session {
+            Some(session) => TradingHours {
+                exchange,
+                open: session.open,
+                close: session.close,
+            },
+            None => TradingHours::regular(exchange),
+        }
+    }
+
+    pub fn is_open(&self, at: DateTime<Utc>) -> bool {
+        let local = at.with_timezone(&self.exchange.timezone());
+        self.exchange.is_trading_day(local.date_naive()) && (self.open..self.close).contains(&local.time())
+    }
+
+    /// Next time the session opens after `at` (`at` itself excluded), within
+    /// the coming month.
+    pub fn next_open(&self, at: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let tz = self.exchange.timezone();
+        let today = at.with_timezone(&tz).date_naive();
+        (0..31)
+            .filter_map(|d| today.checked_add_days(Days::new(d)))
+            .filter(|day| self.exchange.is_trading_day(*day))
+            .filter_map(|day| tz.from_local_datetime(&day.and_time(self.open)).earliest())
+            .map(|open| open.with_timezone(&Utc))
+            .find(|open| *open > at)
+    }
+}
+
+/// Decides, at each tick of the fetch loop, whether to fetch: always while
+/// the market is open; outside its hours every `closed_every` at most, never
+/// when it is `None`. Logs the transitions instead of storing frozen prices.
+#[derive(Debug, Clone)]
+pub struct MarketGate {
+    hours: TradingHours,
+    closed_every: Option<Duration>,
+    last_closed_fetch: Option<DateTime<Utc>>,
+    closed: bool,
+}
+
+impl MarketGate {
+    /// `closed_every` of zero pauses the fetches until the next open.
+    pub fn new(hours: TradingHours, closed_every: Duration) -> Self {
+        MarketGate {
+            hours,
+            closed_every: Some(closed_every).filter(|d| !d.is_zero()),
+            last_closed_fetch: None,
+            closed: false,
+        }
+    }
+
+    pub fn should_fetch(&mut self, now: DateTime<Utc>) -> bool {
+        if self.hours.is_open(now) {
+            if self.closed {
+                info!(exchange = ?self.hours.exchange, "Market open, resuming the fetch cycles");
+                self.closed = false;
+            }
+            self.last_closed_fetch = None;
+            return true;
+        }
+
+        if !self.closed {
+            self.closed = true;
+            let next = self.hours.next_open(now).map(|at| at.to_rfc3339()).unwrap_or_else(|| "unknown".to_string());
+            match self.closed_every {
+                Some(every) => info!(exchange = ?self.hours.exchange, next_open = %next, "Market closed, fetching every {}s", every.as_secs()),
+                None => info!(exchange = ?self.hours.exchange, next_open = %next, "Market closed, fetches paused"),
+            }
+        }
+        let Some(every) = self.closed_every else {
+            return false;
+        };
+        let due = self
+            .last_closed_fetch
+            .is_none_or(|last| (now - last).to_std().unwrap_or_default() >= every);
+        if due {
+            self.last_closed_fetch = Some(now);
+        }
+        due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn utc(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, min, 0).unwrap()
+    }
+
+    #[test]
+    fn sessions_follow_the_local_clock_and_calendar() {
+        let nyse = TradingHours::regular(Exchange::Nyse);
+        // 2024-03-11 is a Monday, New York on EDT (UTC-4).
+        assert!(!nyse.is_open(utc(2024, 3, 11, 13, 29)));
+        assert!(nyse.is_open(utc(2024, 3, 11, 13, 30)));
+        assert!(!nyse.is_open(utc(2024, 3, 11, 20, 0)));
+        // Good Friday, then the weekend: next open is Monday 09:30 EDT.
+        assert!(!nyse.is_open(utc(2024, 3, 29, 15, 0)));
+        assert_eq!(nyse.next_open(utc(2024, 3, 29, 15, 0)), Some(utc(2024, 4, 1, 13, 30)));
+
+        let late: Session = "10:00-12:00".parse().unwrap();
+        assert!(!TradingHours::with_session(Exchange::Nyse, Some(late)).is_open(utc(2024, 3, 11, 13, 30)));
+        assert!("16:00-09:30".parse::<Session>().is_err());
+    }
+
+    #[test]
+    fn closed_market_widens_or_pauses_the_cycles() {
+        let hours = TradingHours::regular(Exchange::Euronext);
+        // Saturday 2024-06-01, Paris on CEST (UTC+2).
+        let saturday = utc(2024, 6, 1, 10, 0);
+        let mut widened = MarketGate::new(hours, Duration::from_secs(3600));
+        assert!(widened.should_fetch(saturday));
+        assert!(!widened.should_fetch(saturday + chrono::Duration::minutes(59)));
+        assert!(widened.should_fetch(saturday + chrono::Duration::minutes(60)));
+
+        let mut paused = MarketGate::new(hours, Duration::ZERO);
+        assert!(!paused.should_fetch(saturday));
+        assert!(paused.should_fetch(utc(2024, 6, 3, 7, 0)));
+    }
+}
@@ -0,0 +1,384 @@
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::path::Path;
+use td_common::{Context, Error, Result};
+
+/// Largest value of the `NUMERIC(10,2)` price columns.
+const MAX_PRICE: f64 = 99_999_999.99;
+/// Rows sent per INSERT.
+const CHUNK_ROWS: usize = 1000;
+/// Invalid rows listed in the error before it is truncated.
+const MAX_REPORTED: usize = 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TickRow {
+    pub symbol: String,
+    pub price: f64,
+    pub currency: String,
+    pub source: String,
+    pub timestamp: i64,
+    pub is_mock: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CandleRow {
+    pub symbol: String,
+    pub interval_secs: i32,
+    pub bucket_start: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub samples: i32,
+}
+
+/// Content of an import file: ticks
+/// (`symbol,price,timestamp[,currency][,source][,is_mock]`,
+/// the `--export` layout) or candles
+/// (`symbol,interval_secs,bucket_start,open,high,low,close[,samples]`),
+/// told apart by the header. Rows repeated inside the file are already
+/// dropped and counted in `duplicates`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ImportBatch {
+    Ticks { rows: Vec<TickRow>, duplicates: u64 },
+    Candles { rows: Vec<CandleRow>, duplicates: u64 },
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ImportReport {
+    pub inserted: u64,
+    /// Already in the database or repeated in the file.
+    pub duplicates: u64,
+}
+
+/// Column positions, looked up by header name.
+struct Columns<'a> {
+    headers: &'a csv::StringRecord,
+}
+
+impl Columns<'_> {
+    fn index(&self, name: &str) -> Option<usize> {
+        self.headers.iter().position(|h| h.trim().eq_ignore_ascii_case(name))
+    }
+
+    fn require(&self, name: &str) -> Result<usize> {
+        self.index(name)
+            .ok_or_else(|| Error::parse(format!("missing column `{}`", name)))
+    }
+}
+
+fn field<'r>(record: &'r csv::StringRecord, index: usize, name: &str) -> std::result::Result<&'r str, String> {
+    match record.get(index).map(str::trim) {
+        Some(value) if !value.is_empty() => Ok(value),
+        _ => Err(format!("empty {}", name)),
+    }
+}
+
+fn parse_symbol(value: &str) -> std::result::Result<String, String> {
+    let symbol = value.to_uppercase();
+    let valid = symbol.len() <= 10
+        && symbol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    if valid {
+        Ok(symbol)
+    } else {
+        Err(format!("invalid symbol `{}`", value))
+    }
+}
+
+fn parse_price(value: &str, name: &str) -> std::result::Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(price) if price > 0.0 && price <= MAX_PRICE => Ok(price),
+        _ => Err(format!("invalid {} `{}`", name, value)),
+    }
+}
+
+/// Unix seconds, or an RFC 3339 date (`2024-03-01T14:30:00Z`).
+fn parse_timestamp(value: &str) -> std::result::Result<i64, String> {
+    let ts = match value.parse::<i64>() {
+        Ok(ts) => ts,
+        Err(_) => chrono::DateTime::parse_from_rfc3339(value)
+            .map(|dt| dt.timestamp())
+            .map_err(|_| format!("invalid timestamp `{}`", value))?,
+    };
+    if ts <= 0 {
+        return Err(format!("invalid timestamp `{}`", value));
+    }
+    Ok(ts)
+}
+
+/// A 3-letter code, case kept for minor units such as `GBp`.
+fn parse_currency(value: &str) -> std::result::Result<String, String> {
+    if value.len() == 3 && value.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(value.to_string())
+    } else {
+        Err(format!("invalid currency `{}`", value))
+    }
+}
+
+fn parse_bool(value: &str) -> std::result::Result<bool, String> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "t" | "1" | "yes" => Ok(true),
+        "false" | "f" | "0" | "no" => Ok(false),
+        _ => Err(format!("invalid is_mock `{}`", value)),
+    }
+}
+
+fn parse_positive_int(value: &str, name: &str) -> std::result::Result<i32, String> {
+    match value.parse::<i32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("invalid {} `{}`", name, value)),
+    }
+}
+
+/// Reads and validates a whole CSV before anything is written: one bad row
+/// rejects the file, with the offending line numbers in the error. `source`
+/// overrides the file's `source` column, and is required when it has none.
+pub fn read_import(input: impl std::io::Read, source: Option<&str>) -> Result<ImportBatch> {
+    let mut reader = csv::ReaderBuilder::new().flexible(true).from_reader(input);
+    let headers = reader.headers().map_err(Error::parse)?.clone();
+    let columns = Columns { headers: &headers };
+
+    let mut invalid = Vec::new();
+    let batch = if columns.index("open").is_some() {
+        let idx = [
+            columns.require("symbol")?,
+            columns.require("interval_secs")?,
+            columns.require("bucket_start")?,
+            columns.require("open")?,
+            columns.require("high")?,
+            columns.require("low")?,
+            columns.require("close")?,
+        ];
+        let samples_idx = columns.index("samples");
+        let mut seen = HashSet::new();
+        let mut rows = Vec::new();
+        let mut duplicates = 0;
+        for (line, record) in reader.records().enumerate() {
+            let record = record.map_err(Error::parse)?;
+            let row = (|| {
+                let candle = CandleRow {
+                    symbol: parse_symbol(field(&record, idx[0], "symbol")?)?,
+                    interval_secs: parse_positive_int(field(&record, idx[1], "interval_secs")?, "interval_secs")?,
+                    bucket_start: parse_timestamp(field(&record, idx[2], "bucket_start")?)?,
+                    open: parse_price(field(&record, idx[3], "open")?, "open")?,
+                    high: parse_price(field(&record, idx[4], "high")?, "high")?,
+                    low: parse_price(field(&record, idx[5], "low")?, "low")?,
+                    close: parse_price(field(&record, idx[6], "close")?, "close")?,
+                    samples: match samples_idx {
+                        Some(i) => parse_positive_int(field(&record, i, "samples")?, "samples")?,
+                        None => 1,
+                    },
+                };
+                let body_max = candle.open.max(candle.close);
+                let body_min = candle.open.min(candle.close);
+                if candle.high < body_max || candle.low > body_min {
+                    return Err("high/low do not contain open and close".to_string());
+                }
+                Ok(candle)
+            })();
+            match row {
+                Ok(row) => {
+                    if seen.insert((row.symbol.clone(), row.interval_secs, row.bucket_start)) {
+                        rows.push(row);
+                    } else {
+                        duplicates += 1;
+                    }
+                }
+                // +2: 1-based, after the header.
+                Err(e) => invalid.push(format!("line {}: {}", line + 2, e)),
+            }
+        }
+        ImportBatch::Candles { rows, duplicates }
+    } else {
+        let symbol_idx = columns.require("symbol")?;
+        let price_idx = columns.require("price")?;
+        let timestamp_idx = columns.require("timestamp")?;
+        let source_idx = columns.index("source");
+        let mock_idx = columns.index("is_mock");
+        let currency_idx = columns.index("currency");
+        if source.is_none() && source_idx.is_none() {
+            return Err(Error::parse("no `source` column: pass --source"));
+        }
+        let mut seen = HashSet::new();
+        let mut rows = Vec::new();
+        let mut duplicates = 0;
+        for (line, record) in reader.records().enumerate() {
+            let record = record.map_err(Error::parse)?;
+            let row = (|| {
+                Ok::<_, String>(TickRow {
+                    symbol: parse_symbol(field(&record, symbol_idx, "symbol")?)?,
+                    price: parse_price(field(&record, price_idx, "price")?, "price")?,
+                    currency: match currency_idx {
+                        Some(i) => parse_currency(field(&record, i, "currency")?)?,
+                        None => "USD".to_string(),
+                    },
+                    source: match (source, source_idx) {
+                        (Some(source), _) => source.to_string(),
+                        (None, Some(i)) => field(&record, i, "source")?.to_string(),
+                        (None, None) => unreachable!(),
+                    },
+                    timestamp: parse_timestamp(field(&record, timestamp_idx, "timestamp")?)?,
+                    is_mock: match mock_idx {
+                        Some(i) => parse_bool(field(&record, i, "is_mock")?)?,
+                        None => false,
+                    },
+                })
+            })();
+            match row {
+                Ok(row) => {
+                    if seen.insert((row.symbol.clone(), row.source.clone(), row.timestamp)) {
+                        rows.push(row);
+                    } else {
+                        duplicates += 1;
+                    }
+                }
+                Err(e) => invalid.push(format!("line {}: {}", line + 2, e)),
+            }
+        }
+        ImportBatch::Ticks { rows, duplicates }
+    };
+
+    if !invalid.is_empty() {
+        let mut message = format!("{} invalid row(s), nothing imported", invalid.len());
+        for line in invalid.iter().take(MAX_REPORTED) {
+            message.push_str("\n  ");
+            message.push_str(line);
+        }
+        if invalid.len() > MAX_REPORTED {
+            message.push_str("\n  ...");
+        }
+        return Err(Error::parse(message));
+    }
+    Ok(batch)
+}
+
+/// Validates `path` (see [`read_import`]) and loads it in one transaction.
+/// Ticks already stored with the same (symbol, source, timestamp), or
+/// candles with the same key, are skipped and counted as duplicates.
+pub async fn import_file(pool: &PgPool, path: &Path, source: Option<&str>) -> Result<ImportReport> {
+    let file = std::fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let batch = read_import(file, source).with_context(|| format!("reading {}", path.display()))?;
+
+    let mut tx = pool.begin().await.context("starting import transaction")?;
+    let mut report = ImportReport::default();
+    match batch {
+        ImportBatch::Ticks { rows, duplicates } => {
+            report.duplicates = duplicates;
+            for chunk in rows.chunks(CHUNK_ROWS) {
+                let inserted = sqlx::query(
+                    r#"
+                    INSERT INTO stock_prices (symbol, price, currency, source, timestamp, is_mock)
+                    SELECT t.symbol, t.price, t.currency, t.source, t.timestamp, t.is_mock
+                    FROM UNNEST($1::text[], $2::float8[], $3::text[], $4::text[], $5::bigint[], $6::bool[])
+                         AS t(symbol, price, currency, source, timestamp, is_mock)
+                    WHERE NOT EXISTS (
+                        SELECT 1 FROM stock_prices p
+                        WHERE p.symbol = t.symbol AND p.source = t.source AND p.timestamp = t.timestamp
+                    )
+                    "#,
+                )
+                .bind(chunk.iter().map(|r| r.symbol.clone()).collect::<Vec<_>>())
+                .bind(chunk.iter().map(|r| r.price).collect::<Vec<_>>())
+                .bind(chunk.iter().map(|r| r.currency.clone()).collect::<Vec<_>>())
+                .bind(chunk.iter().map(|r| r.source.clone()).collect::<Vec<_>>())
+                .bind(chunk.iter().map(|r| r.timestamp).collect::<Vec<_>>())
+                .bind(chunk.iter().map(|r| r.is_mock).collect::<Vec<_>>())
+                .execute(&mut *tx)
+                .await
+                .context("inserting imported prices")?
+                .rows_affected();
+                report.inserted += inserted;
+                report.duplicates += chunk.len() as u64 - inserted;
+            }
+        }
+        ImportBatch::Candles { rows, duplicates } => {
+            report.duplicates = duplicates;
+            for chunk in rows.chunks(CHUNK_ROWS) {
+                let inserted = sqlx::query(
+                    r#"
+                    INSERT INTO stock_candles (symbol, interval_secs, bucket_start, open, high, low, close, samples)
+                    SELECT * FROM UNNEST($1::text[], $2::int[], $3::bigint[], $4::float8[], $5::float8[],
+                                         $6::float8[], $7::float8[], $8::int[])
+                    ON CONFLICT (symbol, interval_secs, bucket_start) DO NOTHING
+                    "#,
+                )
+                .bind(chunk.iter().map(|r| r.symbol.clone()).collect::<Vec<_>>())
+                .bind(chunk.iter().map(|r| r.interval_secs).collect::<Vec<_>>())
+                .bind(chunk.iter().map(|r| r.bucket_start).collect::<Vec<_>>())
+                .bind(chunk.iter().map(|r| r.open).collect::<Vec<_>>())
+                .bind(chunk.iter().map(|r| r.high).collect::<Vec<_>>())
+                .bind(chunk.iter().map(|r| r.low).collect::<Vec<_>>())
+                .bind(chunk.iter().map(|r| r.close).collect::<Vec<_>>())
+                .bind(chunk.iter().map(|r| r.samples).collect::<Vec<_>>())
+                .execute(&mut *tx)
+                .await
+                .context("inserting imported candles")?
+                .rows_affected();
+                report.inserted += inserted;
+                report.duplicates += chunk.len() as u64 - inserted;
+            }
+        }
+    }
+    tx.commit().await.context("committing import")?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ticks_take_the_cli_source_and_drop_in_file_duplicates() {
+        let csv = "symbol,price,timestamp\naapl,187.25,1700000000\nAAPL,187.25,1700000000\nMSFT,402.1,2024-03-01T14:30:00Z\n";
+        let ImportBatch::Ticks { rows, duplicates } = read_import(csv.as_bytes(), Some("backfill")).unwrap() else {
+            panic!("expected ticks");
+        };
+        assert_eq!(duplicates, 1);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].symbol, "AAPL");
+        assert_eq!(rows[0].source, "backfill");
+        assert_eq!(rows[1].timestamp, 1_709_303_400);
+        assert!(!rows[1].is_mock);
+    }
+
+    #[test]
+    fn exported_files_import_as_is() {
+        let csv = "symbol,price,currency,source,timestamp,is_mock\nAAPL,187.25,USD,Finnhub,1700000000,false\nMC.PA,702.5,EUR,Yahoo,1700000000,true\n";
+        let ImportBatch::Ticks { rows, duplicates } = read_import(csv.as_bytes(), None).unwrap() else {
+            panic!("expected ticks");
+        };
+        assert_eq!(duplicates, 0);
+        assert_eq!(rows[1].source, "Yahoo");
+        assert_eq!(rows[1].currency, "EUR");
+        assert!(rows[1].is_mock);
+    }
+
+    #[test]
+    fn candles_are_detected_by_header_and_checked() {
+        let csv = "symbol,interval_secs,bucket_start,open,high,low,close\nAAPL,3600,1700000000,10,12,9,11\n";
+        let ImportBatch::Candles { rows, .. } = read_import(csv.as_bytes(), None).unwrap() else {
+            panic!("expected candles");
+        };
+        assert_eq!(rows[0].samples, 1);
+
+        let bad = "symbol,interval_secs,bucket_start,open,high,low,close\nAAPL,3600,1700000000,10,10.5,9,11\n";
+        assert!(read_import(bad.as_bytes(), None).is_err());
+    }
+
+    #[test]
+    fn one_invalid_row_rejects_the_file_with_line_numbers() {
+        let csv = "symbol,price,timestamp\nAAPL,187.25,1700000000\nAAPL,-3,1700000060\n,1,1700000120\n";
+        let err = read_import(csv.as_bytes(), Some("backfill")).unwrap_err().to_string();
+        assert!(err.contains("2 invalid row(s)"), "{}", err);
+        assert!(err.contains("line 3: invalid price `-3`"), "{}", err);
+        assert!(err.contains("line 4: empty symbol"), "{}", err);
+    }
+
+    #[test]
+    fn source_is_required_somewhere() {
+        let csv = "symbol,price,timestamp\nAAPL,187.25,1700000000\n";
+        assert!(read_import(csv.as_bytes(), None).is_err());
+    }
+}
@@ -0,0 +1,67 @@
+use crate::export::ADJUSTED_PRICE;
+use sqlx::{PgPool, Row};
+use td_common::indicators::{Indicator, IndicatorSpec};
+use td_common::{Context, Result};
+
+/// One price of the series with the value of each requested indicator,
+/// `None` while it warms up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndicatorRow {
+    pub timestamp: i64,
+    pub price: f64,
+    pub values: Vec<Option<f64>>,
+}
+
+/// Stored prices of `symbol`, oldest first, adjusted for splits and
+/// dividends and averaged over the sources of a timestamp. Mock prices are
+/// only used when the symbol has no real one.
+pub async fn price_series(pool: &PgPool, symbol: &str, since_ts: Option<i64>) -> Result<Vec<(i64, f64)>> {
+    let sql = format!(
+        r#"
+        SELECT timestamp, AVG({}) AS price
+        FROM stock_prices
+        WHERE symbol = $1
+          AND ($2::bigint IS NULL OR timestamp >= $2)
+          AND is_mock = NOT EXISTS (SELECT 1 FROM stock_prices WHERE symbol = $1 AND NOT is_mock)
+        GROUP BY symbol, timestamp
+        ORDER BY timestamp
+        "#,
+        ADJUSTED_PRICE
+    );
+    let rows = sqlx::query(&sql)
+        .bind(symbol)
+        .bind(since_ts)
+        .fetch_all(pool)
+        .await
+        .with_context(|| format!("reading prices of {}", symbol))?;
+    rows.iter()
+        .map(|row| Ok((row.try_get("timestamp")?, row.try_get("price")?)))
+        .collect()
+}
+
+/// Runs every indicator of `specs` over `series`.
+pub fn compute_indicators(series: &[(i64, f64)], specs: &[IndicatorSpec]) -> Vec<IndicatorRow> {
+    let mut indicators: Vec<Indicator> = specs.iter().map(|&spec| Indicator::new(spec)).collect();
+    series
+        .iter()
+        .map(|&(timestamp, price)| IndicatorRow {
+            timestamp,
+            price,
+            values: indicators.iter_mut().map(|i| i.update(price)).collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_indicator_sees_every_price() {
+        let series = [(1, 1.0), (2, 2.0), (3, 3.0)];
+        let rows = compute_indicators(&series, &[IndicatorSpec::Sma(2), IndicatorSpec::Rsi(2)]);
+        assert_eq!(rows[0].values, vec![None, None]);
+        assert_eq!(rows[1].values, vec![Some(1.5), None]);
+        assert_eq!(rows[2], IndicatorRow { timestamp: 3, price: 3.0, values: vec![Some(2.5), Some(100.0)] });
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::env;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use td_common::{mask_key, Error, Result};
+
+/// Free-tier calls per minute allowed for one key.
+pub const ALPHA_VANTAGE_PER_MINUTE: u32 = 5;
+pub const FINNHUB_PER_MINUTE: u32 = 60;
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+struct KeySlot {
+    key: String,
+    /// First call of the current minute.
+    window_start: Option<Instant>,
+    in_window: u32,
+    calls: u64,
+    throttled: u64,
+    cooldown_until: Option<Instant>,
+}
+
+impl KeySlot {
+    /// When the key can be used again; `None` when it can right now.
+    fn wait(&mut self, per_minute: u32, now: Instant) -> Option<Duration> {
+        if let Some(until) = self.cooldown_until {
+            if until > now {
+                return Some(until - now);
+            }
+            self.cooldown_until = None;
+        }
+        let start = match self.window_start {
+            Some(start) if now.duration_since(start) < WINDOW => start,
+            _ => {
+                self.window_start = None;
+                self.in_window = 0;
+                return None;
+            }
+        };
+        (self.in_window >= per_minute).then(|| WINDOW - now.duration_since(start))
+    }
+}
+
+/// The keys of one provider, each allowed `per_minute` calls per minute.
+pub struct KeyPool {
+    provider: String,
+    per_minute: u32,
+    slots: Vec<KeySlot>,
+}
+
+/// Calls made with one key since startup; the key itself is masked.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyUsage {
+    pub provider: String,
+    pub key: String,
+    pub calls: u64,
+    pub throttled: u64,
+}
+
+impl KeyPool {
+    pub fn new(provider: &str, keys: impl IntoIterator<Item = String>, per_minute: u32) -> Self {
+        KeyPool {
+            provider: provider.to_string(),
+            per_minute: per_minute.max(1),
+            slots: keys
+                .into_iter()
+                .map(|key| KeySlot {
+                    key,
+                    window_start: None,
+                    in_window: 0,
+                    calls: 0,
+                    throttled: 0,
+                    cooldown_until: None,
+                })
+                .collect(),
+        }
+    }
+
+    /// The comma-separated keys of `var`; `rate_var` overrides the calls per
+    /// minute of each key. `None` when no key is set.
+    pub fn from_env(provider: &str, var: &str, rate_var: &str, default_per_minute: u32) -> Option<Self> {
+        let keys: Vec<String> = env::var(var)
+            .ok()?
+            .split(',')
+            .map(|k| k.trim().to_string())
+            .filter(|k| !k.is_empty())
+            .collect();
+        if keys.is_empty() {
+            return None;
+        }
+        let per_minute = env::var(rate_var).ok().and_then(|r| r.trim().parse().ok()).unwrap_or(default_per_minute);
+        Some(KeyPool::new(provider, keys, per_minute))
+    }
+
+    /// The usable key with the fewest calls this minute, counted as used;
+    /// otherwise how long until one frees up.
+    pub fn acquire(&mut self, now: Instant) -> std::result::Result<String, Duration> {
+        let per_minute = self.per_minute;
+        let mut soonest = Duration::MAX;
+        let mut best: Option<usize> = None;
+        for i in 0..self.slots.len() {
+            match self.slots[i].wait(per_minute, now) {
+                Some(wait) => soonest = soonest.min(wait),
+                None if best.is_none_or(|b| self.slots[i].in_window < self.slots[b].in_window) => best = Some(i),
+                None => {}
+            }
+        }
+        let Some(i) = best else {
+            return Err(soonest);
+        };
+        let slot = &mut self.slots[i];
+        slot.window_start.get_or_insert(now);
+        slot.in_window += 1;
+        slot.calls += 1;
+        Ok(slot.key.clone())
+    }
+
+    /// The provider throttled `key`: it rests for `wait`, the others go on.
+    pub fn throttled(&mut self, key: &str, wait: Duration, now: Instant) {
+        if let Some(slot) = self.slots.iter_mut().find(|s| s.key == key) {
+            slot.throttled += 1;
+            slot.cooldown_until = Some(now + wait);
+        }
+    }
+
+    pub fn usage(&self) -> Vec<KeyUsage> {
+        self.slots
+            .iter()
+            .map(|s| KeyUsage {
+                provider: self.provider.clone(),
+                key: mask_key(&s.key),
+                calls: s.calls,
+                throttled: s.throttled,
+            })
+            .collect()
+    }
+}
+
+/// The key pools of every provider, shared by the clones of a `Fetcher`.
+#[derive(Clone, Default)]
+pub struct KeyRing(Arc<Mutex<HashMap<String, KeyPool>>>);
+
+impl fmt::Debug for KeyRing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("KeyRing").field(&self.usage()).finish()
+    }
+}
+
+impl KeyRing {
+    /// `ALPHA_VANTAGE_KEY` and `FINNHUB_KEY`, each a comma-separated list,
+    /// limited by `ALPHA_VANTAGE_RATE_PER_MIN` / `FINNHUB_RATE_PER_MIN`.
+    pub fn from_env() -> Self {
+        let ring = KeyRing::default();
+        let pools = [
+            KeyPool::from_env("AlphaVantage", "ALPHA_VANTAGE_KEY", "ALPHA_VANTAGE_RATE_PER_MIN", ALPHA_VANTAGE_PER_MINUTE),
+            KeyPool::from_env("Finnhub", "FINNHUB_KEY", "FINNHUB_RATE_PER_MIN", FINNHUB_PER_MINUTE),
+        ];
+        for pool in pools.into_iter().flatten() {
+            ring.insert(pool);
+        }
+        ring
+    }
+
+    pub fn insert(&self, pool: KeyPool) {
+        self.0.lock().unwrap().insert(pool.provider.clone(), pool);
+    }
+
+    /// A key of `provider` within its rate limit: [`Error::RateLimited`]
+    /// when they are all used up, a configuration ([`Error::Parse`]) error
+    /// when none is configured.
+    pub fn acquire(&self, provider: &str) -> Result<String> {
+        let mut pools = self.0.lock().unwrap();
+        let pool = pools
+            .get_mut(provider)
+            .ok_or_else(|| Error::parse(format!("no {} API key configured", provider)))?;
+        pool.acquire(Instant::now()).map_err(|retry_after| Error::RateLimited {
+            provider: provider.to_string(),
+            retry_after,
+        })
+    }
+
+    pub fn throttled(&self, provider: &str, key: &str, wait: Duration) {
+        if let Some(pool) = self.0.lock().unwrap().get_mut(provider) {
+            pool.throttled(key, wait, Instant::now());
+        }
+    }
+
+    /// Per-key usage, by provider.
+    pub fn usage(&self) -> Vec<KeyUsage> {
+        let pools = self.0.lock().unwrap();
+        let mut usage: Vec<KeyUsage> = pools.values().flat_map(KeyPool::usage).collect();
+        usage.sort_by(|a, b| a.provider.cmp(&b.provider));
+        usage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool(keys: &[&str], per_minute: u32) -> KeyPool {
+        KeyPool::new("Test", keys.iter().map(|k| k.to_string()), per_minute)
+    }
+
+    #[test]
+    fn keys_rotate_within_their_limit() {
+        let mut pool = pool(&["aaaa-1111", "bbbb-2222"], 2);
+        let now = Instant::now();
+        let used: Vec<String> = (0..4).map(|_| pool.acquire(now).unwrap()).collect();
+        assert_eq!(used, vec!["aaaa-1111", "bbbb-2222", "aaaa-1111", "bbbb-2222"]);
+
+        let wait = pool.acquire(now + Duration::from_secs(15)).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(45));
+        assert_eq!(pool.acquire(now + WINDOW).unwrap(), "aaaa-1111");
+        assert_eq!(pool.usage()[0].calls, 3);
+    }
+
+    #[test]
+    fn a_throttled_key_rests_while_the_others_work() {
+        let mut pool = pool(&["aaaa-1111", "bbbb-2222"], 5);
+        let now = Instant::now();
+        pool.throttled("aaaa-1111", Duration::from_secs(600), now);
+        assert_eq!(pool.acquire(now).unwrap(), "bbbb-2222");
+        assert_eq!(pool.acquire(now).unwrap(), "bbbb-2222");
+        assert_eq!(pool.acquire(now + Duration::from_secs(600)).unwrap(), "aaaa-1111");
+
+        let usage = pool.usage();
+        assert_eq!((usage[0].key.as_str(), usage[0].throttled), ("aaaa...", 1));
+    }
+
+    #[test]
+    fn ring_reports_missing_and_exhausted_providers() {
+        let ring = KeyRing::default();
+        assert!(matches!(ring.acquire("Finnhub"), Err(Error::Parse(_))));
+        ring.insert(KeyPool::new("Finnhub", ["key".to_string()], 1));
+        assert_eq!(ring.acquire("Finnhub").unwrap(), "key");
+        assert!(matches!(ring.acquire("Finnhub"), Err(Error::RateLimited { .. })));
+        assert_eq!(ring.usage()[0].key, "****");
+    }
+}
@@ -0,0 +1,352 @@
+//! Multi-source stock price fetcher (TD 1): providers, persistence and the
+//! fetch cycle. Used by the `rust-td` CLI and embedded by the `pipeline`
+//! service.
+
+use arc_swap::ArcSwap;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use td_common::Result;
+use tracing::{debug, error, info, instrument};
+
+pub mod actions;
+pub mod backtest;
+pub mod changes;
+pub mod chaos;
+pub mod db;
+pub mod doctor;
+pub mod export;
+pub mod fx;
+pub mod gaps;
+pub mod hooks;
+pub mod hours;
+pub mod import;
+pub mod indicators;
+pub mod keys;
+pub mod metadata;
+pub mod portfolio;
+pub mod providers;
+pub mod retention;
+pub mod routing;
+pub mod schedule;
+pub mod spreads;
+pub mod telemetry;
+
+pub use actions::{adjustment_factor, save_actions, sync_corporate_actions, ActionKind, CorporateAction};
+pub use backtest::{
+    load_bars, run_backtest, BacktestReport, Bar, FillModel, FillSpec, Signal, Slippage, SmaCrossover, Strategy, StrategySpec,
+    Trade, TradeSide,
+};
+pub use changes::{record_changes, ChangeTracker, Direction, PriceChange};
+pub use chaos::{Chaos, ChaosConfig, ChaosCounts, Fault};
+pub use db::{query_latest, save_price};
+pub use doctor::{check_schema, repair_schema, SchemaProblem};
+pub use export::{export_prices, parse_since, ExportFormat};
+pub use fx::{listing_currency, FxRates};
+pub use gaps::{backfill, find_gaps, Gap};
+pub use hooks::{apply_hooks, HookSpec, PriceHook, Round, SymbolRemap, UnitConversion};
+pub use hours::{MarketGate, Session, TradingHours};
+pub use import::{import_file, read_import, ImportBatch, ImportReport};
+pub use indicators::{compute_indicators, price_series, IndicatorRow};
+pub use keys::{KeyPool, KeyRing, KeyUsage};
+pub use metadata::{enrich_symbols, save_symbol_info, stale_symbols, SymbolInfo};
+pub use portfolio::{latest_prices, load_positions, portfolio_value};
+pub use retention::{cutoff_timestamp, prune, PruneReport};
+pub use routing::{route_for, Provider, Route};
+pub use schedule::{CronSchedule, Exchange};
+pub use spreads::{compute_spreads, record_spreads, SourceSpread, SpreadAlert, SpreadMonitor};
+pub use telemetry::{init_tracing, Telemetry};
+pub use providers::{fetch_mock_price, http_client, schema_mismatch_count};
+
+#[derive(Debug, Clone)]
+pub struct StockPrice {
+    pub symbol: String,
+    pub price: f64,
+    pub source: String,
+    pub timestamp: i64,
+    /// ISO code of `price`, or a minor unit such as `GBp` (London pence).
+    pub currency: String,
+    /// Simulated price (mock mode or fallback after a provider failure).
+    pub is_mock: bool,
+}
+
+/// Entry point of the providers. By default a failing provider yields a
+/// mock price (flagged `is_mock`); disable that for production databases.
+/// Cloning is cheap: the HTTP client and its connection pool are shared.
+#[derive(Debug, Clone)]
+pub struct Fetcher {
+    client: reqwest::Client,
+    mock_fallback: bool,
+    convert_to: Option<String>,
+    fx_cache: Arc<Mutex<Option<(Instant, FxRates)>>>,
+    keys: KeyRing,
+    chaos: Option<Chaos>,
+    hooks: Arc<Mutex<Vec<Box<dyn PriceHook>>>>,
+    routes: Vec<Route>,
+    latest: Arc<ArcSwap<HashMap<String, StockPrice>>>,
+}
+
+impl Default for Fetcher {
+    fn default() -> Self {
+        // Same contract as `reqwest::Client::new()`: only fails when the TLS
+        // backend cannot be initialised.
+        Fetcher::with_client(http_client().expect("HTTP client"))
+    }
+}
+
+impl Fetcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use a preconfigured client (proxy, other timeouts...). The API keys
+    /// are read from the environment ([`KeyRing::from_env`]).
+    pub fn with_client(client: reqwest::Client) -> Self {
+        Fetcher {
+            client,
+            mock_fallback: true,
+            convert_to: None,
+            fx_cache: Arc::default(),
+            keys: KeyRing::from_env(),
+            chaos: None,
+            hooks: Arc::default(),
+            routes: Vec::new(),
+            latest: Arc::default(),
+        }
+    }
+
+    pub fn keys(mut self, keys: KeyRing) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    /// Inject the faults of `--chaos` in the provider calls and the price
+    /// writes.
+    pub fn chaos(mut self, chaos: Option<Chaos>) -> Self {
+        self.chaos = chaos;
+        self
+    }
+
+    /// Calls and throttles of each API key since startup.
+    pub fn key_usage(&self) -> Vec<KeyUsage> {
+        self.keys.usage()
+    }
+
+    pub fn mock_fallback(mut self, enabled: bool) -> Self {
+        self.mock_fallback = enabled;
+        self
+    }
+
+    /// Restate every fetched price in this currency (e.g. `USD`), so prices
+    /// from different listings can be compared.
+    pub fn convert_to(mut self, currency: Option<String>) -> Self {
+        self.convert_to = currency;
+        self
+    }
+
+    /// Run every fetched price through `hooks`, in order, before it is
+    /// returned or stored. Clones of the fetcher share them.
+    pub fn hooks(mut self, hooks: Vec<Box<dyn PriceHook>>) -> Self {
+        self.hooks = Arc::new(Mutex::new(hooks));
+        self
+    }
+
+    /// Symbols matching a route are only asked to its providers, in order
+    /// (see [`Fetcher::fetch_routed`]); the others to every provider.
+    pub fn routes(mut self, routes: Vec<Route>) -> Self {
+        self.routes = routes;
+        self
+    }
+
+    /// Query all providers in parallel for each symbol (or the providers of
+    /// its route) and return what came back.
+    #[instrument(skip_all, fields(symbols = symbols.len()))]
+    pub async fn fetch_cycle(&self, symbols: &[String]) -> Vec<StockPrice> {
+        let mut prices = Vec::with_capacity(symbols.len() * 3);
+
+        for symbol in symbols {
+            if let Some(route) = route_for(&self.routes, symbol) {
+                prices.extend(self.fetch_routed(symbol, &route.providers).await);
+                continue;
+            }
+            let (a_res, f_res, y_res) = tokio::join!(
+                self.fetch_alpha_vantage(symbol),
+                self.fetch_finnhub(symbol),
+                self.fetch_yahoo(symbol)
+            );
+
+            match a_res {
+                Ok(a) => {
+                    info!(symbol = %a.symbol, source = %a.source, price = a.price, "Alpha result");
+                    prices.push(a);
+                }
+                Err(e) => error!(symbol = %symbol, "Alpha failed: {}", e),
+            }
+
+            match f_res {
+                Ok(f) => {
+                    info!(symbol = %f.symbol, source = %f.source, price = f.price, "Finnhub result");
+                    prices.push(f);
+                }
+                Err(e) => error!(symbol = %symbol, "Finnhub failed: {}", e),
+            }
+
+            match y_res {
+                Ok(y) => {
+                    info!(symbol = %y.symbol, source = %y.source, price = y.price, "Yahoo result");
+                    prices.push(y);
+                }
+                Err(e) => error!(symbol = %symbol, "Yahoo failed (unexpected): {}", e),
+            }
+        }
+
+        let prices = match &self.convert_to {
+            Some(to) => self.convert_prices(prices, to).await,
+            None => prices,
+        };
+        let prices = self.run_hooks(prices);
+        self.update_latest(&prices);
+        prices
+    }
+
+    fn run_hooks(&self, prices: Vec<StockPrice>) -> Vec<StockPrice> {
+        let mut hooks = self.hooks.lock().unwrap();
+        if hooks.is_empty() {
+            return prices;
+        }
+        prices
+            .into_iter()
+            .filter_map(|price| {
+                let (symbol, source) = (price.symbol.clone(), price.source.clone());
+                let kept = apply_hooks(&mut hooks, price);
+                if kept.is_none() {
+                    debug!(symbol = %symbol, source = %source, "Price dropped by a hook");
+                }
+                kept
+            })
+            .collect()
+    }
+
+    /// Keeps, per symbol, the most recent real quote (a mock one only while
+    /// there is none), published at once for the readers of `latest`.
+    fn update_latest(&self, prices: &[StockPrice]) {
+        if prices.is_empty() {
+            return;
+        }
+        self.latest.rcu(|current| {
+            let mut next = HashMap::clone(current);
+            for price in prices {
+                let replace = next
+                    .get(&price.symbol)
+                    .is_none_or(|old| (price.is_mock, -price.timestamp) <= (old.is_mock, -old.timestamp));
+                if replace {
+                    next.insert(price.symbol.clone(), price.clone());
+                }
+            }
+            next
+        });
+    }
+
+    /// Last price fetched for `symbol` by this fetcher (or a clone of it),
+    /// without locking nor touching the database.
+    pub fn latest(&self, symbol: &str) -> Option<StockPrice> {
+        self.latest.load().get(symbol).cloned()
+    }
+
+    /// Every symbol's last price, as one consistent snapshot.
+    pub fn latest_all(&self) -> Arc<HashMap<String, StockPrice>> {
+        self.latest.load_full()
+    }
+
+    /// One fetch cycle, stored when a pool is given; returns its prices.
+    #[instrument(skip(self, pool))]
+    pub async fn fetch_and_save_all(&self, pool: Option<&PgPool>, symbols: &[String]) -> Result<Vec<StockPrice>> {
+        info!(count = symbols.len(), "Starting fetch cycle");
+
+        let prices = self.fetch_cycle(symbols).await;
+        if let Some(pool) = pool {
+            for price in &prices {
+                if let Some(e) = self.chaos.as_ref().and_then(Chaos::db_failure) {
+                    return Err(e.context(format!("saving {} price for {}", price.source, price.symbol)));
+                }
+                save_price(pool, price).await?;
+            }
+        }
+
+        info!("Completed fetch cycle");
+        Ok(prices)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn fetchers_return_mock_when_mock_env_set() {
+        let fetcher = Fetcher::new();
+        let a = fetcher.fetch_alpha_vantage("TEST").await.unwrap();
+        let f = fetcher.fetch_finnhub("TEST").await.unwrap();
+        let y = fetcher.fetch_yahoo("TEST").await.unwrap();
+
+        assert!(a.is_mock && f.is_mock && y.is_mock);
+        assert_eq!(a.source, "AlphaVantage");
+        assert_eq!(f.source, "Finnhub");
+        assert_eq!(y.source, "Yahoo");
+    }
+
+    #[tokio::test]
+    async fn fetch_mock_price_has_expected_shape() {
+        let p = fetch_mock_price("TEST", "MockSource");
+        assert!(p.price >= 100.0 && p.price <= 200.0);
+        assert_eq!(p.symbol, "TEST");
+        assert_eq!(p.source, "MockSource");
+    }
+
+    #[tokio::test]
+    async fn fetch_and_save_all_runs_without_db_pool() {
+        let symbols = vec!["AAPL".to_string(), "GOOG".to_string()];
+        let res = Fetcher::new().fetch_and_save_all(None, &symbols).await;
+        assert!(res.is_ok());
+    }
+
+    #[tokio::test]
+    async fn chaos_faults_go_through_the_fallback() {
+        let symbols = vec!["AAPL".to_string()];
+        let timeouts = Chaos::new(ChaosConfig { timeout: 1.0, ..Default::default() });
+        let strict = Fetcher::new().mock_fallback(false).chaos(Some(timeouts.clone()));
+        assert!(strict.fetch_cycle(&symbols).await.is_empty());
+        assert_eq!(timeouts.counts().timeouts, 3);
+
+        let before = schema_mismatch_count("Yahoo");
+        let malformed = Chaos::new(ChaosConfig { malformed: 1.0, ..Default::default() });
+        let prices = Fetcher::new().chaos(Some(malformed)).fetch_cycle(&symbols).await;
+        assert!(prices.len() == 3 && prices.iter().all(|p| p.is_mock));
+        assert!(schema_mismatch_count("Yahoo") > before);
+    }
+
+    #[tokio::test]
+    async fn latest_prefers_real_and_recent_quotes() {
+        let fetcher = Fetcher::new();
+        assert!(fetcher.latest("AAPL").is_none());
+        fetcher.fetch_cycle(&["AAPL".to_string()]).await;
+        assert!(fetcher.latest("AAPL").is_some_and(|p| p.is_mock));
+
+        let real = StockPrice { is_mock: false, timestamp: 10, ..fetch_mock_price("AAPL", "Yahoo") };
+        let older = StockPrice { timestamp: 5, ..real.clone() };
+        fetcher.clone().update_latest(&[real.clone(), older]);
+        assert_eq!(fetcher.latest("AAPL").map(|p| p.timestamp), Some(10));
+        fetcher.update_latest(&[fetch_mock_price("AAPL", "Yahoo")]);
+        assert!(fetcher.latest("AAPL").is_some_and(|p| !p.is_mock));
+        assert_eq!(fetcher.latest_all().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn fetch_cycle_returns_one_price_per_provider() {
+        let symbols = vec!["AAPL".to_string()];
+        let prices = Fetcher::new().fetch_cycle(&symbols).await;
+        assert_eq!(prices.len(), 3);
+        assert!(prices.iter().all(|p| p.symbol == "AAPL"));
+    }
+}
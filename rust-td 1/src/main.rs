@@ -1,8 +1,6 @@
 //**Part 1 – Intro to Async & Tokio Runtime (30 min)**
  
-use rand::Rng;
 use sqlx::postgres::PgPoolOptions;
-use sqlx::PgPool;
 use dotenv::dotenv;
 /* 
 async fn fetch_mock_price(symbol: &str) -> f64 {
@@ -27,52 +25,23 @@ async fn main() {
 
 
 //**Part 2 – Async API Calls & Parallel Fetching (60 min)**
-use reqwest;
-use serde::Deserialize;
 use std::env;
-use chrono::Utc;
-use tracing::{info, error, instrument};
-use tracing_subscriber;
-use sqlx::Row;
+use tracing::{info, error, warn};
+use td_common::{Context, Result};
 use tracing::Level;
 use tokio::time::interval;
 use std::time::Duration;
 use tokio::signal;
 use clap::Parser;
-
-
-#[derive(Deserialize, Debug)]
-struct GlobalQuote {
-    #[serde(rename = "Global Quote")]
-    quote: Quote,
-}
-
-#[derive(Deserialize, Debug)]
-struct Quote {
-    #[serde(rename = "01. symbol")]
-    _symbol: String,
-    #[serde(rename = "05. price")]
-    price: String,
-}
-
-#[derive(Deserialize, Debug)]
-struct FinnhubQuote {
-    c: f64, // current price
-    t: i64, // timestamp
-}
-
-fn should_mock_fetch() -> bool {
-    // Allows offline/testing mode without hitting external HTTP APIs.
-    std::env::var("MOCK_FETCH").is_ok()
-}
-
-#[derive(Debug)]
-struct StockPrice {
-    symbol: String,
-    price: f64,
-    source: String,
-    timestamp: i64,
-}
+use rust_td::{
+    backfill, check_schema, load_bars, run_backtest, compute_indicators, cutoff_timestamp, enrich_symbols, export_prices, find_gaps, http_client, import_file, parse_since, prune,
+    load_positions, portfolio_value, price_series, query_latest, record_changes, record_spreads, repair_schema, sync_corporate_actions, init_tracing, CronSchedule,
+    ChangeTracker, Chaos, ChaosConfig, Exchange, ExportFormat, Fetcher, FillSpec, HookSpec, MarketGate, Route, Session, SpreadMonitor, StrategySpec, TradingHours,
+};
+use td_common::indicators::IndicatorSpec;
+use td_common::portfolio::Portfolio;
+use std::path::PathBuf;
+use sqlx::PgPool;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -84,239 +53,194 @@ struct Cli {
     /// Query latest prices from DB and exit
     #[arg(long)]
     query_latest: bool,
-}
 
-async fn fetch_alpha_vantage(symbol: &str) -> Result<StockPrice, Box<dyn std::error::Error>> {
-    if cfg!(test) || should_mock_fetch() {
-        return Ok(fetch_mock_price(symbol, "AlphaVantage"));
-    }
+    /// Drop a price when its provider fails instead of storing a mock one
+    #[arg(long)]
+    no_mock_fallback: bool,
 
-    // Try to read API key; if missing, return a mock price
-    let api_key = match env::var("ALPHA_VANTAGE_KEY") {
-        Ok(k) => k,
-        Err(_) => return Ok(fetch_mock_price(symbol, "AlphaVantage")),
-    };
+    /// Seconds between two fetch cycles
+    #[arg(long, default_value_t = 60)]
+    interval_secs: u64,
 
-    let url = format!(
-        "https://www.alphavantage.co/query?function=GLOBAL_QUOTE&symbol={}&apikey={}",
-        symbol, api_key
-    );
+    /// Report the stretches longer than 1.5 fetch periods without any price, then exit
+    #[arg(long)]
+    find_gaps: bool,
 
-    // If the HTTP call or parsing fails, fall back to mock
-    match reqwest::get(&url).await {
-        Ok(resp) => match resp.json::<GlobalQuote>().await {
-            Ok(data) => {
-                if let Ok(price) = data.quote.price.parse::<f64>() {
-                    return Ok(StockPrice {
-                        symbol: symbol.to_string(),
-                        price,
-                        source: "AlphaVantage".to_string(),
-                        timestamp: Utc::now().timestamp(),
-                    });
-                }
-                // parsing failed -> fallback
-                Ok(fetch_mock_price(symbol, "AlphaVantage"))
-            }
-            Err(_) => Ok(fetch_mock_price(symbol, "AlphaVantage")),
-        },
-        Err(_) => Ok(fetch_mock_price(symbol, "AlphaVantage")),
-    }
-}
+    /// With --find-gaps, fill the gaps from the Finnhub candle history
+    #[arg(long, requires = "find_gaps")]
+    backfill: bool,
 
-async fn fetch_finnhub(symbol: &str) -> Result<StockPrice, Box<dyn std::error::Error>> {
-    if cfg!(test) || should_mock_fetch() {
-        return Ok(fetch_mock_price(symbol, "Finnhub"));
-    }
+    /// Roll ticks older than the retention into candles, delete them and exit
+    #[arg(long)]
+    prune: bool,
 
-    let api_key = match env::var("FINNHUB_KEY") {
-        Ok(k) => k,
-        Err(_) => return Ok(fetch_mock_price(symbol, "Finnhub")),
-    };
+    /// Days of raw ticks to keep (default 30 for --prune); when set, the
+    /// periodic fetcher also prunes once a day
+    #[arg(long, value_name = "DAYS")]
+    retention_days: Option<u64>,
 
-    let url = format!("https://finnhub.io/api/v1/quote?symbol={}&token={}", symbol, api_key);
-
-    match reqwest::get(&url).await {
-        Ok(resp) => match resp.json::<FinnhubQuote>().await {
-            Ok(data) => Ok(StockPrice {
-                symbol: symbol.to_string(),
-                price: data.c,
-                source: "Finnhub".to_string(),
-                timestamp: data.t,
-            }),
-            Err(_) => Ok(fetch_mock_price(symbol, "Finnhub")),
-        },
-        Err(_) => Ok(fetch_mock_price(symbol, "Finnhub")),
-    }
-}
+    /// Width of the candles produced by pruning and replayed by --backtest, in seconds
+    #[arg(long, default_value_t = 3600)]
+    candle_secs: i64,
 
-fn fetch_mock_price(symbol: &str, source: &str) -> StockPrice {
-    let mut rng = rand::thread_rng();
-    let price = rng.gen_range(100.0..200.0);
-    StockPrice {
-        symbol: symbol.to_string(),
-        price,
-        source: source.to_string(),
-        timestamp: Utc::now().timestamp(),
-    }
-}
-async fn save_price(pool: &PgPool, price: &StockPrice) -> Result<(), sqlx::Error> {
-    sqlx::query(
-        r#"INSERT INTO stock_prices (symbol, price, source, timestamp) VALUES ($1, $2, $3, $4)"#,
-    )
-    .bind(&price.symbol)
-    .bind(price.price)
-    .bind(&price.source)
-    .bind(price.timestamp)
-    .execute(pool)
-    .await?;
+    /// Write the stored prices to --output and exit
+    #[arg(long, requires = "output")]
+    export: bool,
 
-    Ok(())
-}
+    /// With --export, only this symbol; the symbol of --indicator
+    #[arg(long)]
+    symbol: Option<String>,
 
-#[derive(Deserialize, Debug)]
-struct YahooQuote {
-    #[serde(rename = "symbol")]
-    _symbol: Option<String>,
-    #[serde(rename = "regularMarketPrice")]
-    regular_market_price: Option<f64>,
-    #[serde(rename = "regularMarketTime")]
-    regular_market_time: Option<i64>,
-}
+    /// Print this indicator over the stored prices of --symbol and exit:
+    /// sma:N, ema:N or rsi:N (repeatable)
+    #[arg(long, value_name = "KIND:PERIOD", requires = "symbol")]
+    indicator: Vec<IndicatorSpec>,
 
-#[derive(Deserialize, Debug)]
-struct YahooResult {
-    result: Vec<YahooQuote>,
-}
+    /// Replay the candles of --symbol through a strategy, print its trades,
+    /// P&L and drawdown and exit: sma-cross:FAST:SLOW
+    #[arg(long, value_name = "STRATEGY", requires = "symbol")]
+    backtest: Option<StrategySpec>,
 
-#[derive(Deserialize, Debug)]
-struct YahooQuoteResponse {
-    #[serde(rename = "quoteResponse")]
-    quote_response: YahooResult,
-}
+    /// Shares bought or sold by each --backtest trade
+    #[arg(long, value_name = "SHARES", default_value_t = 100.0)]
+    backtest_qty: f64,
 
-async fn fetch_yahoo(symbol: &str) -> Result<StockPrice, Box<dyn std::error::Error>> {
-    if cfg!(test) || should_mock_fetch() {
-        return Ok(fetch_mock_price(symbol, "Yahoo"));
-    }
+    /// Execution price of the --backtest trades at the next candle's open:
+    /// close, slippage:BPS or book (walks a rust-td 4 order book)
+    #[arg(long, value_name = "MODEL", default_value = "close")]
+    fills: FillSpec,
 
-    // Yahoo public quote endpoint
-    let url = format!("https://query1.finance.yahoo.com/v7/finance/quote?symbols={}", symbol);
-
-    match reqwest::get(&url).await {
-        Ok(resp) => match resp.json::<YahooQuoteResponse>().await {
-            Ok(data) => {
-                if let Some(q) = data.quote_response.result.into_iter().next() {
-                    if let Some(price) = q.regular_market_price {
-                        return Ok(StockPrice {
-                            symbol: symbol.to_string(),
-                            price,
-                            source: "Yahoo".to_string(),
-                            timestamp: q
-                                .regular_market_time
-                                .unwrap_or_else(|| Utc::now().timestamp()),
-                        });
-                    }
-                }
-                // fallback
-                Ok(fetch_mock_price(symbol, "Yahoo"))
-            }
-            Err(_) => Ok(fetch_mock_price(symbol, "Yahoo")),
-        },
-        Err(_) => Ok(fetch_mock_price(symbol, "Yahoo")),
-    }
-}
+    /// With --export, --indicator or --backtest, only prices newer than this (e.g. 12h, 7d, 2w)
+    #[arg(long, value_parser = parse_since)]
+    since: Option<Duration>,
 
-async fn query_latest(pool: &PgPool, symbols: &[&str]) -> Result<(), sqlx::Error> {
-    for &sym in symbols {
-        let res = sqlx::query(
-            r#"SELECT symbol, price, source, timestamp, created_at FROM stock_prices WHERE symbol = $1 ORDER BY timestamp DESC LIMIT 1"#,
-        )
-        .bind(sym)
-        .fetch_optional(pool)
-        .await?;
-
-        if let Some(row) = res {
-            let symbol: String = row.try_get("symbol")?;
-            let price: f64 = row.try_get("price")?;
-            let source: String = row.try_get("source")?;
-            let timestamp: i64 = row.try_get("timestamp")?;
-            println!("Latest {}: {} (source={}, ts={})", symbol, price, source, timestamp);
-        } else {
-            println!("No data for {}", sym);
-        }
-    }
+    /// With --export, restate older prices after the later splits and
+    /// dividends (`corporate_actions` table)
+    #[arg(long, requires = "export")]
+    adjusted: bool,
 
-    Ok(())
-}
+    /// Export format: csv or parquet
+    #[arg(long, default_value = "csv")]
+    format: ExportFormat,
+
+    /// Export destination file
+    #[arg(long)]
+    output: Option<PathBuf>,
+
+    /// Validate and load a CSV of prices (or candles) into the database, then exit
+    #[arg(long, value_name = "FILE")]
+    import: Option<PathBuf>,
+
+    /// With --import, the source stored for every row (else the file's `source` column)
+    #[arg(long, requires = "import")]
+    source: Option<String>,
+
+    /// Restate fetched prices in this currency (e.g. USD) before storing them
+    #[arg(long, value_name = "CURRENCY", value_parser = parse_currency_code)]
+    convert_to: Option<String>,
+
+    /// Transform each fetched price before storing it, in order, e.g.
+    /// remap=BRK-B:BRK.B,unit=GBp:GBP,round=2 (hooks: round, unit, remap)
+    #[arg(long, value_name = "HOOKS", value_delimiter = ',')]
+    hooks: Vec<HookSpec>,
+
+    /// Only ask these providers, in order, for the matching symbols, e.g.
+    /// `*.PA=yahoo,alpha` (repeatable; providers: alpha, finnhub, yahoo)
+    #[arg(long = "route", value_name = "PATTERN=PROVIDERS")]
+    routes: Vec<Route>,
+
+    /// Run the fetch cycles on a cron schedule instead of --interval-secs,
+    /// e.g. "*/1 9-17 * * MON-FRI"
+    #[arg(long, value_name = "CRON")]
+    schedule: Option<String>,
+
+    /// Evaluate --schedule in this exchange's time zone and skip its
+    /// weekends and holidays; also the calendar of --closed-interval-secs
+    /// (NYSE, EURONEXT or LSE)
+    #[arg(long)]
+    exchange: Option<Exchange>,
 
-#[instrument(skip(pool))]
-async fn fetch_and_save_all(pool: Option<&PgPool>, symbols: &[String]) -> Result<(), Box<dyn std::error::Error>> {
-    info!(count = symbols.len(), "Starting fetch cycle");
+    /// Outside the trading hours of --exchange (NYSE by default), fetch every
+    /// SECS instead of every cycle; 0 pauses until the market opens
+    #[arg(long, value_name = "SECS")]
+    closed_interval_secs: Option<u64>,
 
-    for symbol in symbols {
-        let (a_res, f_res, y_res) = tokio::join!(
-            fetch_alpha_vantage(symbol),
-            fetch_finnhub(symbol),
-            fetch_yahoo(symbol)
-        );
+    /// Trading session used by --closed-interval-secs, in the exchange's
+    /// local time (default: its regular hours)
+    #[arg(long, value_name = "HH:MM-HH:MM", requires = "closed_interval_secs")]
+    session: Option<Session>,
 
-        if let Ok(a) = a_res {
-            info!(symbol = %a.symbol, source = %a.source, price = a.price, "Alpha result");
-            if let Some(pool) = pool { save_price(pool, &a).await?; }
-        } else { error!(symbol = %symbol, "Alpha failed"); }
+    /// Store the spread between sources after each cycle and alert when it
+    /// stays above this percentage
+    #[arg(long, value_name = "PCT")]
+    spread_threshold_pct: Option<f64>,
 
-        if let Ok(f) = f_res {
-            info!(symbol = %f.symbol, source = %f.source, price = f.price, "Finnhub result");
-            if let Some(pool) = pool { save_price(pool, &f).await?; }
-        } else { error!(symbol = %symbol, "Finnhub failed"); }
+    /// Consecutive cycles above --spread-threshold-pct before alerting
+    #[arg(long, default_value_t = 3, requires = "spread_threshold_pct")]
+    spread_cycles: u32,
 
-        if let Ok(y) = y_res {
-            info!(symbol = %y.symbol, source = %y.source, price = y.price, "Yahoo result");
-            if let Some(pool) = pool { save_price(pool, &y).await?; }
-        } else { error!(symbol = %symbol, "Yahoo failed (unexpected)"); }
-    }
+    /// Record in `price_changes` every quote that moved more than this from
+    /// the last recorded price of its symbol and source
+    #[arg(long, value_name = "EPSILON")]
+    change_epsilon: Option<f64>,
 
-    info!("Completed fetch cycle");
-    Ok(())
-}
+    /// Check the database tables, columns and indexes, then exit
+    #[arg(long)]
+    doctor: bool,
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// With --doctor, apply the fixes for what is missing
+    #[arg(long, requires = "doctor")]
+    fix: bool,
 
-    #[tokio::test]
-    async fn fetchers_return_mock_when_mock_env_set() {
-        let a = fetch_alpha_vantage("TEST").await.unwrap();
-        let f = fetch_finnhub("TEST").await.unwrap();
-        let y = fetch_yahoo("TEST").await.unwrap();
+    /// Refresh the name, exchange and currency of the symbols (`symbols`
+    /// table) when older than this many hours; 0 = never
+    #[arg(long, value_name = "HOURS", default_value_t = 24)]
+    enrich_hours: u64,
 
-        assert_eq!(a.source, "AlphaVantage");
-        assert_eq!(f.source, "Finnhub");
-        assert_eq!(y.source, "Yahoo");
-    }
+    /// Print the value and P&L of the paper-trading portfolio at the latest
+    /// stored prices, then exit
+    #[arg(long)]
+    portfolio_value: bool,
 
-    #[tokio::test]
-    async fn fetch_mock_price_has_expected_shape() {
-        let p = fetch_mock_price("TEST", "MockSource");
-        assert!(p.price >= 100.0 && p.price <= 200.0);
-        assert_eq!(p.symbol, "TEST");
-        assert_eq!(p.source, "MockSource");
-    }
+    /// Positions file (TOML) for --portfolio-value; defaults to the
+    /// `portfolio_positions` table
+    #[arg(long, value_name = "FILE", requires = "portfolio_value")]
+    portfolio: Option<PathBuf>,
 
-    #[tokio::test]
-    async fn fetch_and_save_all_runs_without_db_pool() {
-        let symbols = vec!["AAPL".to_string(), "GOOG".to_string()];
-        let res = fetch_and_save_all(None, &symbols).await;
-        assert!(res.is_ok());
+    /// Inject faults to exercise the fallbacks, e.g.
+    /// timeout=0.1,db=0.05,malformed=0.2,seed=42 (probabilities per call)
+    #[arg(long, value_name = "FAULTS")]
+    chaos: Option<ChaosConfig>,
+
+    /// Look for new splits and dividends every N hours (0 = never)
+    #[arg(long, value_name = "HOURS", default_value_t = 24)]
+    actions_hours: u64,
+}
+
+fn parse_currency_code(s: &str) -> std::result::Result<String, String> {
+    if s.len() == 3 && s.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(s.to_ascii_uppercase())
+    } else {
+        Err(format!("`{}` is not a 3-letter currency code", s))
     }
 }
 
+async fn run_prune(pool: &PgPool, retention_days: u64, candle_secs: i64) -> Result<()> {
+    let cutoff = cutoff_timestamp(chrono::Utc::now().timestamp(), retention_days);
+    let report = prune(pool, cutoff, candle_secs.max(1)).await?;
+    info!(
+        "Pruned {} tick(s) older than {} days into {} candle(s)",
+        report.ticks_deleted, retention_days, report.candles_written
+    );
+    Ok(())
+}
+
 #[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
+async fn main() -> Result<()> {
     dotenv().ok();
 
     // Setup tracing
-    tracing_subscriber::fmt().with_max_level(Level::INFO).init();
+    let _telemetry = init_tracing("rust-td", Level::INFO)?;
 
     let cli = Cli::parse();
 
@@ -327,13 +251,57 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             PgPoolOptions::new()
                 .max_connections(5)
                 .connect(url)
-                .await?,
+                .await
+                .context("connecting to DATABASE_URL")?,
         )
     } else {
         None
     };
 
     let symbols = vec!["AAPL".to_string(), "GOOG".to_string(), "AMZN".to_string()];
+    let chaos = cli.chaos.map(Chaos::new);
+    if let Some(config) = cli.chaos {
+        warn!(
+            timeout = config.timeout,
+            db = config.db_failure,
+            malformed = config.malformed,
+            seed = ?config.seed,
+            "Chaos mode: injecting faults"
+        );
+    }
+    let fetcher = Fetcher::with_client(http_client()?)
+        .mock_fallback(!cli.no_mock_fallback)
+        .chaos(chaos.clone())
+        .convert_to(cli.convert_to.clone())
+        .hooks(cli.hooks.iter().map(HookSpec::build).collect())
+        .routes(cli.routes.clone());
+
+    if cli.doctor {
+        let Some(ref pool) = pool else {
+            println!("DATABASE_URL not set; no database to check");
+            return Ok(());
+        };
+        let problems = check_schema(pool).await?;
+        for problem in &problems {
+            println!("{}", problem);
+            if !cli.fix {
+                println!("  fix: {}", problem.fix_sql().trim().replace('\n', "\n       "));
+            }
+        }
+        if problems.is_empty() {
+            println!("Schema OK");
+        } else if cli.fix {
+            repair_schema(pool, &problems).await?;
+            let left = check_schema(pool).await?;
+            println!("Applied {} fix(es), {} problem(s) left", problems.len(), left.len());
+            for problem in &left {
+                println!("{}", problem);
+            }
+        } else {
+            println!("{} problem(s); run with --fix to repair", problems.len());
+        }
+        return Ok(());
+    }
 
     if cli.query_latest {
         if let Some(ref pool) = pool {
@@ -345,20 +313,253 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    if cli.portfolio_value {
+        let Some(ref pool) = pool else {
+            println!("DATABASE_URL not set; no prices to value the portfolio with");
+            return Ok(());
+        };
+        let portfolio = match &cli.portfolio {
+            Some(path) => Portfolio::load(path)?,
+            None => load_positions(pool).await?,
+        };
+        let value = portfolio_value(pool, &portfolio).await?;
+        for p in &value.positions {
+            match (p.price, p.pnl) {
+                (Some(price), Some(pnl)) => println!(
+                    "{:<8} {:>10} @ {:>10.2}  last {:>10.2}  P&L {:>+12.2}",
+                    p.symbol, p.quantity, p.cost_basis, price, pnl
+                ),
+                _ => println!("{:<8} {:>10} @ {:>10.2}  no price", p.symbol, p.quantity, p.cost_basis),
+            }
+        }
+        println!(
+            "Value {:.2}, cost {:.2}, P&L {:+.2} ({:+.2}%)",
+            value.market_value, value.cost, value.pnl, value.pnl_pct
+        );
+        return Ok(());
+    }
+
+    if !cli.indicator.is_empty() {
+        let Some(ref pool) = pool else {
+            println!("DATABASE_URL not set; no prices to compute indicators on");
+            return Ok(());
+        };
+        let symbol = cli.symbol.as_deref().expect("--symbol is required by --indicator").to_uppercase();
+        let since_ts = cli.since.map(|d| chrono::Utc::now().timestamp() - d.as_secs() as i64);
+        let series = price_series(pool, &symbol, since_ts).await?;
+        let rows = compute_indicators(&series, &cli.indicator);
+        let header: Vec<String> = cli.indicator.iter().map(|spec| format!("{:>10}", spec.to_string())).collect();
+        println!("{:<20} {:>10} {}", "timestamp", "price", header.join(" "));
+        // The warm-up rows, where no indicator has a value yet, are left out.
+        for row in rows.iter().filter(|row| row.values.iter().any(Option::is_some)) {
+            let time = chrono::DateTime::from_timestamp(row.timestamp, 0).map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string());
+            let values: Vec<String> = row
+                .values
+                .iter()
+                .map(|v| v.map_or_else(|| format!("{:>10}", "-"), |v| format!("{:>10.2}", v)))
+                .collect();
+            println!("{:<20} {:>10.2} {}", time.unwrap_or_default(), row.price, values.join(" "));
+        }
+        println!("{} price(s) of {}", series.len(), symbol);
+        return Ok(());
+    }
+
+    if let Some(spec) = cli.backtest {
+        let Some(ref pool) = pool else {
+            println!("DATABASE_URL not set; no candles to replay");
+            return Ok(());
+        };
+        let symbol = cli.symbol.as_deref().expect("--symbol is required by --backtest").to_uppercase();
+        let since_ts = cli.since.map(|d| chrono::Utc::now().timestamp() - d.as_secs() as i64);
+        let bars = load_bars(pool, &symbol, cli.candle_secs.max(1), since_ts).await?;
+        let mut strategy = spec.build();
+        let mut fills = cli.fills.build();
+        let report = run_backtest(&bars, strategy.as_mut(), fills.as_mut(), cli.backtest_qty);
+        for trade in &report.trades {
+            let time = chrono::DateTime::from_timestamp(trade.timestamp, 0).map(|t| t.format("%Y-%m-%d %H:%M").to_string());
+            match trade.pnl {
+                Some(pnl) => println!(
+                    "{:<16} {:<4} {} @ {:.2}  P&L {:+.2}",
+                    time.unwrap_or_default(), trade.side, trade.quantity, trade.price, pnl
+                ),
+                None => println!("{:<16} {:<4} {} @ {:.2}", time.unwrap_or_default(), trade.side, trade.quantity, trade.price),
+            }
+        }
+        println!(
+            "{} on {} ({} candle(s) of {}s): {} trade(s), P&L {:+.2} (realized {:+.2}, open {:+.2}), max drawdown {:.2}",
+            strategy.name(),
+            symbol,
+            report.bars,
+            cli.candle_secs.max(1),
+            report.trades.len(),
+            report.pnl,
+            report.realized,
+            report.unrealized,
+            report.max_drawdown
+        );
+        return Ok(());
+    }
+
+    if cli.find_gaps {
+        let Some(ref pool) = pool else {
+            println!("DATABASE_URL not set; no data to scan");
+            return Ok(());
+        };
+        // Some slack so a slightly late cycle isn't reported.
+        let max_interval = (cli.interval_secs + cli.interval_secs / 2) as i64;
+        let gaps = find_gaps(pool, max_interval).await?;
+        for gap in &gaps {
+            println!("Gap {}: {} -> {} ({}s)", gap.symbol, gap.from, gap.to, gap.secs());
+        }
+        println!("{} gap(s) longer than {}s", gaps.len(), max_interval);
+        if cli.backfill && !gaps.is_empty() {
+            let inserted = backfill(&fetcher, pool, &gaps).await?;
+            println!("Backfilled {} price(s)", inserted);
+        }
+        return Ok(());
+    }
+
+    if cli.export {
+        let Some(ref pool) = pool else {
+            println!("DATABASE_URL not set; nothing to export");
+            return Ok(());
+        };
+        let output = cli.output.as_deref().expect("--output is required by --export");
+        let since_ts = cli.since.map(|d| chrono::Utc::now().timestamp() - d.as_secs() as i64);
+        let symbol = cli.symbol.as_deref().map(str::to_uppercase);
+        let count = export_prices(pool, symbol.as_deref(), since_ts, cli.adjusted, cli.format, output).await?;
+        println!("Exported {} row(s) to {}", count, output.display());
+        return Ok(());
+    }
+
+    if let Some(ref path) = cli.import {
+        let Some(ref pool) = pool else {
+            println!("DATABASE_URL not set; nowhere to import");
+            return Ok(());
+        };
+        let report = import_file(pool, path, cli.source.as_deref()).await?;
+        println!(
+            "Imported {} row(s) from {}, skipped {} duplicate(s)",
+            report.inserted,
+            path.display(),
+            report.duplicates
+        );
+        return Ok(());
+    }
+
+    if cli.prune {
+        let Some(ref pool) = pool else {
+            println!("DATABASE_URL not set; nothing to prune");
+            return Ok(());
+        };
+        run_prune(pool, cli.retention_days.unwrap_or(30), cli.candle_secs).await?;
+        return Ok(());
+    }
+
+    let mut spread_monitor = cli
+        .spread_threshold_pct
+        .map(|pct| SpreadMonitor::new(pct, cli.spread_cycles));
+    let mut change_tracker = cli.change_epsilon.map(ChangeTracker::new);
+    let mut market_gate = cli.closed_interval_secs.map(|secs| {
+        let hours = TradingHours::with_session(cli.exchange.unwrap_or(Exchange::Nyse), cli.session);
+        MarketGate::new(hours, Duration::from_secs(secs))
+    });
+
     if cli.fetch_once {
-        fetch_and_save_all(pool.as_ref(), &symbols).await?;
+        let prices = fetcher.fetch_and_save_all(pool.as_ref(), &symbols).await?;
+        if let Some(monitor) = &mut spread_monitor {
+            record_spreads(pool.as_ref(), monitor, &prices).await?;
+        }
+        if let Some(tracker) = &mut change_tracker {
+            record_changes(pool.as_ref(), tracker, &prices).await?;
+        }
         return Ok(());
     }
 
-    info!("Starting periodic fetcher");
+    let schedule = cli
+        .schedule
+        .as_deref()
+        .map(|expr| CronSchedule::parse(expr, cli.exchange))
+        .transpose()?;
+    match (&cli.schedule, cli.exchange) {
+        (Some(expr), Some(exchange)) => info!("Starting scheduled fetcher: {} ({:?} calendar)", expr, exchange),
+        (Some(expr), None) => info!("Starting scheduled fetcher: {} (UTC)", expr),
+        _ => info!("Starting periodic fetcher"),
+    }
 
-    let mut interval = interval(Duration::from_secs(60));
+    let mut interval = interval(Duration::from_secs(cli.interval_secs.max(1)));
+    let mut prune_timer = tokio::time::interval(Duration::from_secs(24 * 3600));
+    let retention = cli.retention_days.filter(|_| pool.is_some());
+    let enrich_every = Duration::from_secs(cli.enrich_hours * 3600);
+    // Checked hourly: only the symbols past their refresh age are fetched.
+    let mut enrich_timer = tokio::time::interval(Duration::from_secs(3600));
+    let enrich = pool.is_some() && cli.enrich_hours > 0;
+    let mut actions_timer = tokio::time::interval(Duration::from_secs(cli.actions_hours.max(1) * 3600));
+    let sync_actions = pool.is_some() && cli.actions_hours > 0;
+    let hour = Duration::from_secs(3600);
+    let mut usage_timer = tokio::time::interval_at(tokio::time::Instant::now() + hour, hour);
 
     loop {
         tokio::select! {
-            _ = interval.tick() => {
-                if let Err(e) = fetch_and_save_all(pool.as_ref(), &symbols).await {
-                    error!("Fetch cycle failed: {}", e);
+            _ = prune_timer.tick(), if retention.is_some() => {
+                if let (Some(pool), Some(days)) = (&pool, retention)
+                    && let Err(e) = run_prune(pool, days, cli.candle_secs).await
+                {
+                    error!("Prune failed: {}", e);
+                }
+            }
+            _ = enrich_timer.tick(), if enrich => {
+                if let Some(pool) = &pool
+                    && let Err(e) = enrich_symbols(&fetcher, pool, &symbols, enrich_every).await
+                {
+                    error!("Symbol enrichment failed: {}", e);
+                }
+            }
+            _ = actions_timer.tick(), if sync_actions => {
+                if let Some(pool) = &pool
+                    && let Err(e) = sync_corporate_actions(&fetcher, pool, &symbols).await
+                {
+                    error!("Corporate action sync failed: {}", e);
+                }
+            }
+            _ = usage_timer.tick() => {
+                for usage in fetcher.key_usage() {
+                    info!(
+                        provider = %usage.provider,
+                        key = %usage.key,
+                        calls = usage.calls,
+                        throttled = usage.throttled,
+                        "API key usage"
+                    );
+                }
+            }
+            _ = async {
+                match &schedule {
+                    Some(schedule) => schedule.wait_next().await,
+                    None => {
+                        interval.tick().await;
+                    }
+                }
+            } => {
+                if let Some(gate) = &mut market_gate
+                    && !gate.should_fetch(chrono::Utc::now())
+                {
+                    continue;
+                }
+                match fetcher.fetch_and_save_all(pool.as_ref(), &symbols).await {
+                    Ok(prices) => {
+                        if let Some(monitor) = &mut spread_monitor
+                            && let Err(e) = record_spreads(pool.as_ref(), monitor, &prices).await
+                        {
+                            error!("Spread analysis failed: {}", e);
+                        }
+                        if let Some(tracker) = &mut change_tracker
+                            && let Err(e) = record_changes(pool.as_ref(), tracker, &prices).await
+                        {
+                            error!("Price change tracking failed: {}", e);
+                        }
+                    }
+                    Err(e) => error!("Fetch cycle failed: {}", e),
                 }
             }
             _ = signal::ctrl_c() => {
@@ -368,6 +569,16 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
+    if let Some(chaos) = &chaos {
+        let counts = chaos.counts();
+        info!(
+            timeouts = counts.timeouts,
+            db_failures = counts.db_failures,
+            malformed = counts.malformed,
+            "Chaos faults injected"
+        );
+    }
+
     info!("Shutting down: closing DB pool");
     if let Some(pool) = pool {
         pool.close().await;
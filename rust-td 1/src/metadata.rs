@@ -0,0 +1,216 @@
+use crate::Fetcher;
+use crate::fx::listing_currency;
+use crate::providers::{parse_strict, should_mock_fetch};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::time::Duration;
+use td_common::{Context, Error, Result};
+use tracing::{info, warn};
+
+/// Descriptive data of a symbol, so UIs can show `Apple Inc` next to `AAPL`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolInfo {
+    pub symbol: String,
+    pub name: Option<String>,
+    pub exchange: Option<String>,
+    pub currency: Option<String>,
+    pub source: String,
+}
+
+/// `/stock/profile2` answer; `{}` for a symbol Finnhub doesn't cover.
+#[derive(Deserialize, Debug)]
+struct FinnhubProfile {
+    error: Option<String>,
+    name: Option<String>,
+    exchange: Option<String>,
+    currency: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooProfile {
+    #[serde(rename = "longName")]
+    long_name: Option<String>,
+    #[serde(rename = "shortName")]
+    short_name: Option<String>,
+    #[serde(rename = "fullExchangeName")]
+    full_exchange_name: Option<String>,
+    currency: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooProfiles {
+    result: Vec<YahooProfile>,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooProfileResponse {
+    #[serde(rename = "quoteResponse")]
+    quote_response: YahooProfiles,
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty())
+}
+
+/// `None` when Finnhub has no profile for the symbol.
+fn parse_finnhub_profile(symbol: &str, body: &str) -> Result<Option<SymbolInfo>> {
+    let profile: FinnhubProfile = parse_strict("Finnhub", body)?;
+    if let Some(error) = profile.error {
+        return Err(Error::http(error).context(format!("Finnhub profile of {}", symbol)));
+    }
+    let Some(name) = non_empty(profile.name) else {
+        return Ok(None);
+    };
+    Ok(Some(SymbolInfo {
+        symbol: symbol.to_string(),
+        name: Some(name),
+        exchange: non_empty(profile.exchange),
+        currency: non_empty(profile.currency),
+        source: "Finnhub".to_string(),
+    }))
+}
+
+fn parse_yahoo_profile(symbol: &str, body: &str) -> Result<SymbolInfo> {
+    let data: YahooProfileResponse = parse_strict("Yahoo", body)?;
+    let profile = data
+        .quote_response
+        .result
+        .into_iter()
+        .next()
+        .ok_or_else(|| Error::parse(format!("Yahoo has no quote for {}", symbol)))?;
+    Ok(SymbolInfo {
+        symbol: symbol.to_string(),
+        name: non_empty(profile.long_name).or(non_empty(profile.short_name)),
+        exchange: non_empty(profile.full_exchange_name),
+        currency: non_empty(profile.currency),
+        source: "Yahoo".to_string(),
+    })
+}
+
+impl Fetcher {
+    /// Company name, exchange and currency of `symbol`: Finnhub's profile
+    /// when a `FINNHUB_KEY` is available and it knows the symbol, else
+    /// Yahoo's quote.
+    pub async fn fetch_symbol_info(&self, symbol: &str) -> Result<SymbolInfo> {
+        if cfg!(test) || should_mock_fetch() {
+            return Ok(SymbolInfo {
+                symbol: symbol.to_string(),
+                name: None,
+                exchange: None,
+                currency: Some(listing_currency(symbol).to_string()),
+                source: "Mock".to_string(),
+            });
+        }
+
+        if let Ok(api_key) = self.keys.acquire("Finnhub") {
+            let url = format!("https://finnhub.io/api/v1/stock/profile2?symbol={}&token={}", symbol, api_key);
+            let profile = match self.client.get(&url).send().await {
+                Ok(resp) => match resp.text().await {
+                    Ok(body) => parse_finnhub_profile(symbol, &body),
+                    Err(e) => Err(e.into()),
+                },
+                Err(e) => Err(e.into()),
+            };
+            match profile {
+                Ok(Some(info)) => return Ok(info),
+                Ok(None) => {}
+                Err(e) => warn!(symbol = %symbol, "Finnhub profile failed, trying Yahoo: {}", e),
+            }
+        }
+
+        let url = format!("https://query1.finance.yahoo.com/v7/finance/quote?symbols={}", symbol);
+        let body = self.client.get(&url).send().await?.text().await?;
+        parse_yahoo_profile(symbol, &body).with_context(|| format!("Yahoo profile of {}", symbol))
+    }
+}
+
+/// Upserts `info`; a field the provider left out keeps its stored value.
+pub async fn save_symbol_info(pool: &PgPool, info: &SymbolInfo) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO symbols (symbol, name, exchange, currency, source, updated_at)
+        VALUES ($1, $2, $3, $4, $5, CURRENT_TIMESTAMP)
+        ON CONFLICT (symbol) DO UPDATE SET
+            name = COALESCE(EXCLUDED.name, symbols.name),
+            exchange = COALESCE(EXCLUDED.exchange, symbols.exchange),
+            currency = COALESCE(EXCLUDED.currency, symbols.currency),
+            source = EXCLUDED.source,
+            updated_at = EXCLUDED.updated_at
+        "#,
+    )
+    .bind(&info.symbol)
+    .bind(&info.name)
+    .bind(&info.exchange)
+    .bind(&info.currency)
+    .bind(&info.source)
+    .execute(pool)
+    .await
+    .with_context(|| format!("saving metadata of {}", info.symbol))?;
+    Ok(())
+}
+
+/// The symbols among `symbols` never enriched, or last enriched more than
+/// `max_age` ago.
+pub async fn stale_symbols(pool: &PgPool, symbols: &[String], max_age: Duration) -> Result<Vec<String>> {
+    sqlx::query_scalar(
+        r#"
+        SELECT s FROM UNNEST($1::text[]) AS s
+        WHERE NOT EXISTS (
+            SELECT 1 FROM symbols
+            WHERE symbol = s AND updated_at > NOW() - make_interval(secs => $2)
+        )
+        "#,
+    )
+    .bind(symbols)
+    .bind(max_age.as_secs_f64())
+    .fetch_all(pool)
+    .await
+    .context("finding symbols to enrich")
+}
+
+/// Fetches and stores the metadata of the stale symbols; a symbol whose
+/// providers fail is retried on the next run. Returns how many were saved.
+pub async fn enrich_symbols(fetcher: &Fetcher, pool: &PgPool, symbols: &[String], max_age: Duration) -> Result<usize> {
+    let mut saved = 0;
+    for symbol in stale_symbols(pool, symbols, max_age).await? {
+        match fetcher.fetch_symbol_info(&symbol).await {
+            Ok(info) => {
+                save_symbol_info(pool, &info).await?;
+                saved += 1;
+            }
+            Err(e) => warn!(symbol = %symbol, "Symbol metadata unavailable: {}", e),
+        }
+    }
+    if saved > 0 {
+        info!(count = saved, "Enriched symbol metadata");
+    }
+    Ok(saved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finnhub_profile_or_nothing() {
+        let apple = r#"{"country":"US","currency":"USD","exchange":"NASDAQ NMS - GLOBAL MARKET","name":"Apple Inc","ticker":"AAPL"}"#;
+        let info = parse_finnhub_profile("AAPL", apple).unwrap().unwrap();
+        assert_eq!(info.name.as_deref(), Some("Apple Inc"));
+        assert_eq!(info.currency.as_deref(), Some("USD"));
+
+        assert_eq!(parse_finnhub_profile("ZZZZ", "{}").unwrap(), None);
+        let rejected = parse_finnhub_profile("AAPL", r#"{"error":"Invalid API key"}"#).unwrap_err();
+        assert!(matches!(rejected.root(), Error::Http(_)));
+    }
+
+    #[test]
+    fn yahoo_profile_prefers_the_long_name() {
+        let body = r#"{"quoteResponse":{"result":[{"symbol":"MC.PA","shortName":"LVMH","longName":"LVMH Moët Hennessy","fullExchangeName":"Paris","currency":"EUR"}]}}"#;
+        let info = parse_yahoo_profile("MC.PA", body).unwrap();
+        assert_eq!(info.name.as_deref(), Some("LVMH Moët Hennessy"));
+        assert_eq!(info.exchange.as_deref(), Some("Paris"));
+        assert_eq!(info.source, "Yahoo");
+
+        assert!(parse_yahoo_profile("ZZZZ", r#"{"quoteResponse":{"result":[]}}"#).is_err());
+    }
+}
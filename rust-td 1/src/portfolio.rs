@@ -0,0 +1,55 @@
+use sqlx::{PgPool, Row};
+use std::collections::HashMap;
+use td_common::portfolio::{Portfolio, PortfolioValue, Position};
+use td_common::{Context, Result};
+
+/// The positions of the `portfolio_positions` table.
+pub async fn load_positions(pool: &PgPool) -> Result<Portfolio> {
+    let rows = sqlx::query(
+        r#"
+        SELECT symbol, quantity, cost_basis::float8 AS cost_basis
+        FROM portfolio_positions
+        ORDER BY symbol
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+    .context("reading portfolio_positions")?;
+    let positions = rows
+        .iter()
+        .map(|row| {
+            Ok(Position {
+                symbol: row.try_get("symbol")?,
+                quantity: row.try_get("quantity")?,
+                cost_basis: row.try_get("cost_basis")?,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Portfolio { positions })
+}
+
+/// Latest stored price of each symbol; a real price is preferred to a more
+/// recent mock one.
+pub async fn latest_prices(pool: &PgPool, symbols: &[String]) -> Result<HashMap<String, f64>> {
+    let rows = sqlx::query(
+        r#"
+        SELECT DISTINCT ON (symbol) symbol, price::float8 AS price
+        FROM stock_prices
+        WHERE symbol = ANY($1)
+        ORDER BY symbol, is_mock, timestamp DESC
+        "#,
+    )
+    .bind(symbols)
+    .fetch_all(pool)
+    .await
+    .context("reading latest prices")?;
+    rows.iter()
+        .map(|row| Ok((row.try_get("symbol")?, row.try_get("price")?)))
+        .collect()
+}
+
+/// Values `portfolio` at the latest stored prices.
+pub async fn portfolio_value(pool: &PgPool, portfolio: &Portfolio) -> Result<PortfolioValue> {
+    let prices = latest_prices(pool, &portfolio.symbols()).await?;
+    Ok(portfolio.value(&prices))
+}
@@ -0,0 +1,516 @@
+use crate::chaos::{timeout_error, Fault, MALFORMED_BODY};
+use crate::fx::listing_currency;
+use crate::{Fetcher, StockPrice};
+use chrono::Utc;
+use rand::Rng;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::sync::Mutex;
+use std::time::Duration;
+use td_common::{Context, Error, Result};
+use tracing::{error, instrument, warn};
+
+#[derive(Deserialize, Debug)]
+struct GlobalQuote {
+    #[serde(rename = "Global Quote")]
+    quote: Quote,
+}
+
+#[derive(Deserialize, Debug)]
+struct Quote {
+    #[serde(rename = "01. symbol")]
+    _symbol: String,
+    #[serde(rename = "05. price")]
+    price: String,
+}
+
+/// Alpha Vantage answers 200 even when throttled or when the request is
+/// invalid; the body then carries a `Note`/`Information` or `Error Message`.
+/// Notices also announce premium-only endpoints or misused keys: only those
+/// worded as a call limit (`is_throttling`) are rate limiting.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum AlphaVantageResponse {
+    Quote(GlobalQuote),
+    Notice {
+        #[serde(rename = "Note", alias = "Information")]
+        note: String,
+    },
+    Failed {
+        #[serde(rename = "Error Message")]
+        message: String,
+    },
+}
+
+/// Advised wait after a per-minute throttle (free tier: 5 calls/min).
+const ALPHA_MINUTE_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Finnhub's 429 carries no advice; its limits are per minute.
+const FINNHUB_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Finnhub reports bad keys or symbols as `{"error": "..."}`.
+#[derive(Deserialize, Debug)]
+struct FinnhubError {
+    error: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct FinnhubQuote {
+    c: f64, // current price
+    t: i64, // timestamp
+}
+
+/// `/stock/candle` answer: parallel arrays, `s` is `ok` or `no_data`.
+#[derive(Deserialize, Debug)]
+struct FinnhubCandles {
+    s: String,
+    #[serde(default)]
+    c: Vec<f64>,
+    #[serde(default)]
+    t: Vec<i64>,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooQuote {
+    #[serde(rename = "symbol")]
+    _symbol: Option<String>,
+    #[serde(rename = "regularMarketPrice")]
+    regular_market_price: Option<f64>,
+    #[serde(rename = "regularMarketTime")]
+    regular_market_time: Option<i64>,
+    /// Listing currency, e.g. `EUR` for `.PA` tickers.
+    currency: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooResult {
+    result: Vec<YahooQuote>,
+}
+
+#[derive(Deserialize, Debug)]
+struct YahooQuoteResponse {
+    #[serde(rename = "quoteResponse")]
+    quote_response: YahooResult,
+}
+
+/// Yahoo rejects the default reqwest agent, so present a browser-like one.
+const USER_AGENT: &str = concat!(
+    "Mozilla/5.0 (compatible; ",
+    env!("CARGO_PKG_NAME"),
+    "/",
+    env!("CARGO_PKG_VERSION"),
+    ")"
+);
+
+/// The client shared by every provider: keeps connections alive between
+/// cycles, bounds each call and accepts gzip bodies.
+pub fn http_client() -> Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(Duration::from_secs(5))
+        .timeout(Duration::from_secs(10))
+        .pool_idle_timeout(Duration::from_secs(90))
+        .gzip(true)
+        .build()
+        .context("building HTTP client")
+}
+
+/// Longest part of a raw body kept in schema errors.
+const SNIPPET_LEN: usize = 200;
+
+/// Schema mismatches per provider since startup.
+static SCHEMA_MISMATCHES: Mutex<Option<HashMap<String, u64>>> = Mutex::new(None);
+
+/// How many bodies of `provider` failed strict deserialization so far.
+pub fn schema_mismatch_count(provider: &str) -> u64 {
+    SCHEMA_MISMATCHES
+        .lock()
+        .unwrap()
+        .as_ref()
+        .and_then(|counts| counts.get(provider).copied())
+        .unwrap_or(0)
+}
+
+fn snippet(body: &str) -> String {
+    let mut snippet: String = body.chars().take(SNIPPET_LEN).collect();
+    if snippet.len() < body.len() {
+        snippet.push_str("...");
+    }
+    snippet.replace(['\n', '\r'], " ")
+}
+
+/// Counts and logs a body that doesn't match `provider`'s expected shape, so
+/// an API change shows up in the logs and the counter instead of only as
+/// mock prices.
+fn schema_mismatch(provider: &str, reason: impl std::fmt::Display, body: &str) -> Error {
+    let total = {
+        let mut counts = SCHEMA_MISMATCHES.lock().unwrap();
+        let count = counts.get_or_insert_with(HashMap::new).entry(provider.to_string()).or_default();
+        *count += 1;
+        *count
+    };
+    let snippet = snippet(body);
+    error!(
+        provider,
+        monotonic_counter.provider_schema_mismatches = 1_u64,
+        total,
+        snippet = %snippet,
+        "Provider response schema mismatch: {}",
+        reason
+    );
+    Error::Schema {
+        provider: provider.to_string(),
+        message: reason.to_string(),
+        snippet,
+    }
+}
+
+/// Deserializes a provider body, turning any mismatch into [`Error::Schema`].
+pub(crate) fn parse_strict<T: DeserializeOwned>(provider: &str, body: &str) -> Result<T> {
+    serde_json::from_str(body).map_err(|e| schema_mismatch(provider, e, body))
+}
+
+pub(crate) fn should_mock_fetch() -> bool {
+    // Allows offline/testing mode without hitting external HTTP APIs.
+    std::env::var("MOCK_FETCH").is_ok()
+}
+
+/// The daily quota resets at midnight UTC; otherwise wait one minute.
+fn advised_cooldown(note: &str) -> Duration {
+    if note.contains("per day") {
+        let now = Utc::now();
+        let midnight = (now.date_naive() + chrono::Days::new(1))
+            .and_hms_opt(0, 0, 0)
+            .unwrap()
+            .and_utc();
+        (midnight - now).to_std().unwrap_or(ALPHA_MINUTE_COOLDOWN)
+    } else {
+        ALPHA_MINUTE_COOLDOWN
+    }
+}
+
+fn is_throttling(note: &str) -> bool {
+    let note = note.to_lowercase();
+    ["rate limit", "requests per", "calls per", "call frequency"].iter().any(|wording| note.contains(wording))
+}
+
+fn parse_alpha_vantage(symbol: &str, body: &str) -> Result<StockPrice> {
+    match serde_json::from_str::<AlphaVantageResponse>(body) {
+        Ok(AlphaVantageResponse::Quote(data)) => Ok(StockPrice {
+            symbol: symbol.to_string(),
+            price: data.quote.price.parse::<f64>()?,
+            source: "AlphaVantage".to_string(),
+            timestamp: Utc::now().timestamp(),
+            currency: listing_currency(symbol).to_string(),
+            is_mock: false,
+        }),
+        Ok(AlphaVantageResponse::Notice { note }) if is_throttling(&note) => Err(Error::RateLimited {
+            provider: "AlphaVantage".to_string(),
+            retry_after: advised_cooldown(&note),
+        }),
+        Ok(AlphaVantageResponse::Notice { note: message } | AlphaVantageResponse::Failed { message }) => {
+            Err(Error::http(message).context(format!("Alpha Vantage rejected {}", symbol)))
+        }
+        Err(e) => Err(schema_mismatch("AlphaVantage", e, body)),
+    }
+}
+
+fn parse_finnhub_quote(symbol: &str, body: &str) -> Result<StockPrice> {
+    if let Ok(FinnhubError { error }) = serde_json::from_str(body) {
+        return Err(Error::http(error).context(format!("Finnhub rejected {}", symbol)));
+    }
+    let data: FinnhubQuote = parse_strict("Finnhub", body)?;
+    Ok(StockPrice {
+        symbol: symbol.to_string(),
+        price: data.c,
+        source: "Finnhub".to_string(),
+        timestamp: data.t,
+        currency: listing_currency(symbol).to_string(),
+        is_mock: false,
+    })
+}
+
+fn parse_yahoo(symbol: &str, body: &str) -> Result<StockPrice> {
+    let data: YahooQuoteResponse = parse_strict("Yahoo", body)?;
+    let quote = data.quote_response.result.into_iter().next();
+    match quote {
+        Some(YahooQuote {
+            regular_market_price: Some(price),
+            regular_market_time,
+            currency,
+            ..
+        }) => Ok(StockPrice {
+            symbol: symbol.to_string(),
+            price,
+            source: "Yahoo".to_string(),
+            timestamp: regular_market_time.unwrap_or_else(|| Utc::now().timestamp()),
+            currency: currency.unwrap_or_else(|| listing_currency(symbol).to_string()),
+            is_mock: false,
+        }),
+        _ => Err(Error::parse("no regularMarketPrice in Yahoo response")),
+    }
+}
+
+fn parse_finnhub_candles(symbol: &str, body: &str) -> Result<Vec<StockPrice>> {
+    let candles: FinnhubCandles = parse_strict("Finnhub", body)?;
+    match candles.s.as_str() {
+        "ok" => Ok(candles
+            .t
+            .iter()
+            .zip(&candles.c)
+            .map(|(&timestamp, &close)| StockPrice {
+                symbol: symbol.to_string(),
+                price: close,
+                source: "Finnhub".to_string(),
+                timestamp,
+                currency: listing_currency(symbol).to_string(),
+                is_mock: false,
+            })
+            .collect()),
+        "no_data" => Ok(Vec::new()),
+        other => Err(Error::parse(format!("unexpected Finnhub candle status {}", other))),
+    }
+}
+
+impl Fetcher {
+    /// Mock price when fallback is enabled, otherwise the failure itself.
+    fn fallback(&self, symbol: &str, source: &str, reason: Error) -> Result<StockPrice> {
+        if self.mock_fallback {
+            Ok(fetch_mock_price(symbol, source))
+        } else {
+            Err(reason.context(format!("{} fetch for {}", source, symbol)))
+        }
+    }
+
+    /// The outcome of the fault `--chaos` injects in this call, if any: a
+    /// timeout, or `parse` fed a malformed body. Drawn before the mock mode
+    /// check so offline runs see the faults too.
+    fn chaos_outcome(&self, symbol: &str, provider: &str, parse: fn(&str, &str) -> Result<StockPrice>) -> Option<Result<StockPrice>> {
+        let fault = self.chaos.as_ref()?.provider_fault(provider)?;
+        Some(match fault {
+            Fault::Timeout => self.fallback(symbol, provider, timeout_error(provider)),
+            Fault::Malformed => parse(symbol, MALFORMED_BODY).or_else(|e| self.fallback(symbol, provider, e)),
+        })
+    }
+
+    #[instrument(skip(self), fields(provider = "AlphaVantage"))]
+    pub async fn fetch_alpha_vantage(&self, symbol: &str) -> Result<StockPrice> {
+        if let Some(outcome) = self.chaos_outcome(symbol, "AlphaVantage", parse_alpha_vantage) {
+            return outcome;
+        }
+        if cfg!(test) || should_mock_fetch() {
+            return Ok(fetch_mock_price(symbol, "AlphaVantage"));
+        }
+
+        // Every key used up: surfaced. No key at all: mock fallback.
+        let api_key = match self.keys.acquire("AlphaVantage") {
+            Ok(k) => k,
+            Err(e @ Error::RateLimited { .. }) => return Err(e),
+            Err(e) => return self.fallback(symbol, "AlphaVantage", e),
+        };
+
+        let url = format!(
+            "https://www.alphavantage.co/query?function=GLOBAL_QUOTE&symbol={}&apikey={}",
+            symbol, api_key
+        );
+
+        // Throttling and API errors are surfaced; other HTTP or parsing
+        // failures fall back to mock
+        match self.client.get(&url).send().await {
+            Ok(resp) => match resp.text().await {
+                Ok(body) => match parse_alpha_vantage(symbol, &body) {
+                    Ok(price) => Ok(price),
+                    Err(Error::RateLimited { provider, retry_after }) => {
+                        warn!(symbol = %symbol, "Alpha Vantage rate limit hit, key resting for {}s", retry_after.as_secs());
+                        self.keys.throttled("AlphaVantage", &api_key, retry_after);
+                        Err(Error::RateLimited { provider, retry_after })
+                    }
+                    Err(e) if matches!(e.root(), Error::Http(_)) => Err(e),
+                    Err(e) => self.fallback(symbol, "AlphaVantage", e),
+                },
+                Err(e) => self.fallback(symbol, "AlphaVantage", e.into()),
+            },
+            Err(e) => self.fallback(symbol, "AlphaVantage", e.into()),
+        }
+    }
+
+    #[instrument(skip(self), fields(provider = "Finnhub"))]
+    pub async fn fetch_finnhub(&self, symbol: &str) -> Result<StockPrice> {
+        if let Some(outcome) = self.chaos_outcome(symbol, "Finnhub", parse_finnhub_quote) {
+            return outcome;
+        }
+        if cfg!(test) || should_mock_fetch() {
+            return Ok(fetch_mock_price(symbol, "Finnhub"));
+        }
+
+        let api_key = match self.keys.acquire("Finnhub") {
+            Ok(k) => k,
+            Err(e @ Error::RateLimited { .. }) => return Err(e),
+            Err(e) => return self.fallback(symbol, "Finnhub", e),
+        };
+
+        let url = format!("https://finnhub.io/api/v1/quote?symbol={}&token={}", symbol, api_key);
+
+        match self.client.get(&url).send().await {
+            Ok(resp) if resp.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                warn!(symbol = %symbol, "Finnhub rate limit hit, key resting for {}s", FINNHUB_COOLDOWN.as_secs());
+                self.keys.throttled("Finnhub", &api_key, FINNHUB_COOLDOWN);
+                Err(Error::RateLimited {
+                    provider: "Finnhub".to_string(),
+                    retry_after: FINNHUB_COOLDOWN,
+                })
+            }
+            Ok(resp) => match resp.text().await {
+                Ok(body) => match parse_finnhub_quote(symbol, &body) {
+                    Ok(price) => Ok(price),
+                    Err(e) if matches!(e.root(), Error::Http(_)) => Err(e),
+                    Err(e) => self.fallback(symbol, "Finnhub", e),
+                },
+                Err(e) => self.fallback(symbol, "Finnhub", e.into()),
+            },
+            Err(e) => self.fallback(symbol, "Finnhub", e.into()),
+        }
+    }
+
+    /// Closing prices between `from` and `to` (unix seconds) at `resolution`
+    /// (`1`, `5`, `15`, `30`, `60` minutes or `D`). Never mocked: there is
+    /// nothing to backfill with simulated data.
+    pub async fn fetch_finnhub_candles(
+        &self,
+        symbol: &str,
+        from: i64,
+        to: i64,
+        resolution: &str,
+    ) -> Result<Vec<StockPrice>> {
+        if cfg!(test) || should_mock_fetch() {
+            return Ok(Vec::new());
+        }
+
+        let api_key = self.keys.acquire("Finnhub")?;
+        let url = format!(
+            "https://finnhub.io/api/v1/stock/candle?symbol={}&resolution={}&from={}&to={}&token={}",
+            symbol, resolution, from, to, api_key
+        );
+        let body = self.client.get(&url).send().await?.text().await?;
+        parse_finnhub_candles(symbol, &body).with_context(|| format!("Finnhub candles for {}", symbol))
+    }
+
+    #[instrument(skip(self), fields(provider = "Yahoo"))]
+    pub async fn fetch_yahoo(&self, symbol: &str) -> Result<StockPrice> {
+        if let Some(outcome) = self.chaos_outcome(symbol, "Yahoo", parse_yahoo) {
+            return outcome;
+        }
+        if cfg!(test) || should_mock_fetch() {
+            return Ok(fetch_mock_price(symbol, "Yahoo"));
+        }
+
+        // Yahoo public quote endpoint
+        let url = format!("https://query1.finance.yahoo.com/v7/finance/quote?symbols={}", symbol);
+
+        match self.client.get(&url).send().await {
+            Ok(resp) => match resp.text().await {
+                Ok(body) => match parse_yahoo(symbol, &body) {
+                    Ok(price) => Ok(price),
+                    Err(e) => self.fallback(symbol, "Yahoo", e),
+                },
+                Err(e) => self.fallback(symbol, "Yahoo", e.into()),
+            },
+            Err(e) => self.fallback(symbol, "Yahoo", e.into()),
+        }
+    }
+}
+
+pub fn fetch_mock_price(symbol: &str, source: &str) -> StockPrice {
+    let mut rng = rand::thread_rng();
+    let price = rng.gen_range(100.0..200.0);
+    StockPrice {
+        symbol: symbol.to_string(),
+        price,
+        source: source.to_string(),
+        timestamp: Utc::now().timestamp(),
+        currency: listing_currency(symbol).to_string(),
+        is_mock: true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alpha_vantage_payloads_are_classified() {
+        let ok = r#"{"Global Quote": {"01. symbol": "AAPL", "05. price": "187.2500"}}"#;
+        assert_eq!(parse_alpha_vantage("AAPL", ok).unwrap().price, 187.25);
+
+        let note = r#"{"Note": "Thank you for using Alpha Vantage! Our standard API call frequency is 5 calls per minute."}"#;
+        match parse_alpha_vantage("AAPL", note) {
+            Err(Error::RateLimited { retry_after, .. }) => assert_eq!(retry_after, ALPHA_MINUTE_COOLDOWN),
+            other => panic!("expected RateLimited, got {:?}", other),
+        }
+
+        let daily = r#"{"Information": "You have reached the 25 requests per day limit."}"#;
+        assert!(matches!(parse_alpha_vantage("AAPL", daily), Err(Error::RateLimited { .. })));
+
+        let invalid = r#"{"Error Message": "Invalid API call."}"#;
+        let err = parse_alpha_vantage("FOO", invalid).unwrap_err();
+        assert!(matches!(err.root(), Error::Http(_)));
+
+        // not every notice is a throttle
+        let premium = r#"{"Information": "Thank you for using Alpha Vantage! This is a premium endpoint."}"#;
+        let err = parse_alpha_vantage("AAPL", premium).unwrap_err();
+        assert!(matches!(err.root(), Error::Http(_)));
+    }
+
+    #[test]
+    fn finnhub_candles_are_zipped() {
+        let ok = r#"{"s":"ok","c":[187.1,187.4],"t":[1700000000,1700000060]}"#;
+        let prices = parse_finnhub_candles("AAPL", ok).unwrap();
+        assert_eq!(prices.len(), 2);
+        assert_eq!(prices[1].timestamp, 1700000060);
+        assert_eq!(prices[1].price, 187.4);
+
+        assert!(parse_finnhub_candles("AAPL", r#"{"s":"no_data"}"#).unwrap().is_empty());
+        assert!(parse_finnhub_candles("AAPL", r#"{"s":"error"}"#).is_err());
+    }
+
+    #[test]
+    fn changed_payloads_are_schema_errors_with_a_snippet() {
+        let before = schema_mismatch_count("Yahoo");
+        let renamed = r#"{"quoteResponse": {"results": [{"symbol": "AAPL", "regularMarketPrice": 187.2}]}}"#;
+        match parse_yahoo("AAPL", renamed).unwrap_err() {
+            Error::Schema { provider, snippet, .. } => {
+                assert_eq!(provider, "Yahoo");
+                assert!(snippet.starts_with(r#"{"quoteResponse""#));
+            }
+            other => panic!("expected Schema, got {:?}", other),
+        }
+        assert_eq!(schema_mismatch_count("Yahoo"), before + 1);
+
+        let ok = r#"{"quoteResponse": {"result": [{"symbol": "MC.PA", "regularMarketPrice": 702.5, "currency": "EUR"}]}}"#;
+        assert_eq!(parse_yahoo("MC.PA", ok).unwrap().currency, "EUR");
+
+        assert!(matches!(parse_finnhub_quote("AAPL", r#"{"c": "n/a", "t": 1}"#), Err(Error::Schema { .. })));
+        let rejected = parse_finnhub_quote("AAPL", r#"{"error": "Invalid API key"}"#).unwrap_err();
+        assert!(matches!(rejected.root(), Error::Http(_)));
+    }
+
+    #[test]
+    fn snippets_are_truncated_on_one_line() {
+        let long = format!("{{\n{}}}", "x".repeat(500));
+        let snippet = snippet(&long);
+        assert!(snippet.ends_with("...") && !snippet.contains('\n'));
+        assert_eq!(snippet.chars().count(), SNIPPET_LEN + 3);
+    }
+
+    #[test]
+    fn fallback_is_flagged_or_refused() {
+        let mocked = Fetcher::new().fallback("AAPL", "Finnhub", Error::parse("boom")).unwrap();
+        assert!(mocked.is_mock);
+
+        let strict = Fetcher::new().mock_fallback(false);
+        let err = strict.fallback("AAPL", "Finnhub", Error::parse("boom")).unwrap_err();
+        assert!(matches!(err.root(), Error::Parse(_)));
+    }
+}
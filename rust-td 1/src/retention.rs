@@ -0,0 +1,71 @@
+use sqlx::PgPool;
+use td_common::{Context, Result};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PruneReport {
+    pub candles_written: u64,
+    pub ticks_deleted: u64,
+}
+
+/// Unix time before which ticks are pruned.
+pub fn cutoff_timestamp(now: i64, retention_days: u64) -> i64 {
+    now - (retention_days as i64) * 24 * 3600
+}
+
+/// Rolls the real (non-mock) ticks older than `cutoff` into `candle_secs`
+/// OHLC candles, then deletes every tick older than `cutoff`, in one
+/// transaction. A bucket already rolled up by a previous run is merged.
+pub async fn prune(pool: &PgPool, cutoff: i64, candle_secs: i64) -> Result<PruneReport> {
+    let mut tx = pool.begin().await.context("starting prune transaction")?;
+
+    let candles = sqlx::query(
+        r#"
+        INSERT INTO stock_candles (symbol, interval_secs, bucket_start, open, high, low, close, samples)
+        SELECT symbol,
+               $2,
+               (timestamp / $2) * $2 AS bucket_start,
+               (ARRAY_AGG(price ORDER BY timestamp))[1],
+               MAX(price),
+               MIN(price),
+               (ARRAY_AGG(price ORDER BY timestamp DESC))[1],
+               COUNT(*)
+        FROM stock_prices
+        WHERE timestamp < $1 AND NOT is_mock
+        GROUP BY symbol, bucket_start
+        ON CONFLICT (symbol, interval_secs, bucket_start) DO UPDATE SET
+            high = GREATEST(stock_candles.high, EXCLUDED.high),
+            low = LEAST(stock_candles.low, EXCLUDED.low),
+            close = EXCLUDED.close,
+            samples = stock_candles.samples + EXCLUDED.samples
+        "#,
+    )
+    .bind(cutoff)
+    .bind(candle_secs)
+    .execute(&mut *tx)
+    .await
+    .context("rolling ticks up into stock_candles")?;
+
+    let deleted = sqlx::query("DELETE FROM stock_prices WHERE timestamp < $1")
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await
+        .context("deleting pruned ticks")?;
+
+    tx.commit().await.context("committing prune")?;
+
+    Ok(PruneReport {
+        candles_written: candles.rows_affected(),
+        ticks_deleted: deleted.rows_affected(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cutoff_is_whole_days_back() {
+        assert_eq!(cutoff_timestamp(10 * 86_400, 3), 7 * 86_400);
+        assert_eq!(cutoff_timestamp(1_000, 0), 1_000);
+    }
+}
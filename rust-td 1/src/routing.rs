@@ -0,0 +1,155 @@
+use crate::{Fetcher, StockPrice};
+use std::str::FromStr;
+use td_common::Result;
+use tracing::{error, info, instrument};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Provider {
+    AlphaVantage,
+    Finnhub,
+    Yahoo,
+}
+
+impl Provider {
+    pub fn name(self) -> &'static str {
+        match self {
+            Provider::AlphaVantage => "AlphaVantage",
+            Provider::Finnhub => "Finnhub",
+            Provider::Yahoo => "Yahoo",
+        }
+    }
+}
+
+impl FromStr for Provider {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "alpha" | "alphavantage" => Ok(Provider::AlphaVantage),
+            "finnhub" => Ok(Provider::Finnhub),
+            "yahoo" => Ok(Provider::Yahoo),
+            other => Err(format!("unknown provider `{}` (alpha, finnhub, yahoo)", other)),
+        }
+    }
+}
+
+/// Providers to try, in order, for the symbols matching `pattern`: a symbol
+/// (`AAPL`) or a glob with `*` (`*.PA`). Given as `PATTERN=P1,P2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Route {
+    pub pattern: String,
+    pub providers: Vec<Provider>,
+}
+
+impl Route {
+    pub fn matches(&self, symbol: &str) -> bool {
+        glob_match(&self.pattern.to_ascii_uppercase(), &symbol.to_ascii_uppercase())
+    }
+}
+
+impl FromStr for Route {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (pattern, providers) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected PATTERN=PROVIDER[,PROVIDER...], got `{}`", s))?;
+        let pattern = pattern.trim();
+        if pattern.is_empty() {
+            return Err(format!("empty symbol pattern in `{}`", s));
+        }
+        let providers = providers.split(',').map(str::parse).collect::<std::result::Result<Vec<Provider>, _>>()?;
+        Ok(Route {
+            pattern: pattern.to_string(),
+            providers,
+        })
+    }
+}
+
+/// `*` matches any run of characters, everything else itself.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<&str> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*`: exact match.
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+/// The route of `symbol`: the one naming it exactly, else the first pattern
+/// that matches. `None` = query every provider.
+pub fn route_for<'a>(routes: &'a [Route], symbol: &str) -> Option<&'a Route> {
+    routes
+        .iter()
+        .find(|r| r.pattern.eq_ignore_ascii_case(symbol))
+        .or_else(|| routes.iter().find(|r| r.matches(symbol)))
+}
+
+impl Fetcher {
+    pub async fn fetch_from(&self, provider: Provider, symbol: &str) -> Result<StockPrice> {
+        match provider {
+            Provider::AlphaVantage => self.fetch_alpha_vantage(symbol).await,
+            Provider::Finnhub => self.fetch_finnhub(symbol).await,
+            Provider::Yahoo => self.fetch_yahoo(symbol).await,
+        }
+    }
+
+    /// Tries `providers` one after the other and keeps the first real quote.
+    /// A mock price (fallback) only comes back when none of them had one.
+    #[instrument(skip(self))]
+    pub async fn fetch_routed(&self, symbol: &str, providers: &[Provider]) -> Option<StockPrice> {
+        let mut mock = None;
+        for &provider in providers {
+            match self.fetch_from(provider, symbol).await {
+                Ok(price) if !price.is_mock => {
+                    info!(symbol = %price.symbol, source = %price.source, price = price.price, "Routed result");
+                    return Some(price);
+                }
+                Ok(price) => {
+                    mock.get_or_insert(price);
+                }
+                Err(e) => error!(symbol = %symbol, "{} failed: {}", provider.name(), e),
+            }
+        }
+        mock
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_symbols_win_over_patterns() {
+        let routes: Vec<Route> = ["*.PA=yahoo,alpha", "MC.PA=alphavantage", "BRK*=finnhub"]
+            .iter()
+            .map(|r| r.parse().unwrap())
+            .collect();
+        assert_eq!(route_for(&routes, "OR.PA").unwrap().providers, vec![Provider::Yahoo, Provider::AlphaVantage]);
+        assert_eq!(route_for(&routes, "mc.pa").unwrap().providers, vec![Provider::AlphaVantage]);
+        assert_eq!(route_for(&routes, "BRK.B").unwrap().providers, vec![Provider::Finnhub]);
+        assert!(route_for(&routes, "AAPL").is_none());
+        assert!(route_for(&routes, "PA").is_none());
+        assert!("*.PA=bloomberg".parse::<Route>().is_err());
+        assert!("yahoo".parse::<Route>().is_err());
+    }
+
+    #[tokio::test]
+    async fn a_route_queries_one_provider_when_it_answers() {
+        let price = Fetcher::new().fetch_routed("OR.PA", &[Provider::Yahoo, Provider::Finnhub]).await.unwrap();
+        // Tests only get mocks: the first one is kept.
+        assert_eq!(price.source, "Yahoo");
+        assert!(Fetcher::new().fetch_routed("OR.PA", &[]).await.is_none());
+    }
+}
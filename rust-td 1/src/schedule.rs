@@ -0,0 +1,290 @@
+use chrono::{DateTime, Datelike, Days, NaiveDate, Utc, Weekday};
+use chrono_tz::Tz;
+use std::str::FromStr;
+use td_common::{Error, Result};
+
+/// Exchanges with a known trading calendar.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exchange {
+    /// NYSE / Nasdaq.
+    Nyse,
+    /// Euronext Paris, Amsterdam, Brussels, Lisbon.
+    Euronext,
+    /// London Stock Exchange.
+    Lse,
+}
+
+impl FromStr for Exchange {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_uppercase().as_str() {
+            "NYSE" | "NASDAQ" | "US" => Ok(Exchange::Nyse),
+            "EURONEXT" | "XPAR" | "PA" => Ok(Exchange::Euronext),
+            "LSE" | "XLON" | "L" => Ok(Exchange::Lse),
+            other => Err(format!("unknown exchange `{}` (NYSE, EURONEXT or LSE)", other)),
+        }
+    }
+}
+
+/// Easter Sunday (anonymous Gregorian algorithm).
+fn easter_sunday(year: i32) -> NaiveDate {
+    let a = year % 19;
+    let b = year / 100;
+    let c = year % 100;
+    let d = b / 4;
+    let e = b % 4;
+    let f = (b + 8) / 25;
+    let g = (b - f + 1) / 3;
+    let h = (19 * a + b - d - g + 15) % 30;
+    let i = c / 4;
+    let k = c % 4;
+    let l = (32 + 2 * e + 2 * i - h - k) % 7;
+    let m = (a + 11 * h + 22 * l) / 451;
+    let month = (h + l - 7 * m + 114) / 31;
+    let day = (h + l - 7 * m + 114) % 31 + 1;
+    NaiveDate::from_ymd_opt(year, month as u32, day as u32).unwrap()
+}
+
+fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+    NaiveDate::from_ymd_opt(year, month, day).unwrap()
+}
+
+fn nth_weekday(year: i32, month: u32, weekday: Weekday, n: u8) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, n).unwrap()
+}
+
+fn last_weekday(year: i32, month: u32, weekday: Weekday) -> NaiveDate {
+    NaiveDate::from_weekday_of_month_opt(year, month, weekday, 5).unwrap_or_else(|| nth_weekday(year, month, weekday, 4))
+}
+
+/// US rule: Saturday holidays are observed on Friday, Sunday ones on Monday.
+fn observed_us(day: NaiveDate) -> NaiveDate {
+    match day.weekday() {
+        Weekday::Sat => day - Days::new(1),
+        Weekday::Sun => day + Days::new(1),
+        _ => day,
+    }
+}
+
+/// UK rule: a weekend holiday moves to the next free weekday.
+fn substitute_uk(day: NaiveDate, taken: &[NaiveDate]) -> NaiveDate {
+    let mut day = day;
+    while matches!(day.weekday(), Weekday::Sat | Weekday::Sun) || taken.contains(&day) {
+        day = day + Days::new(1);
+    }
+    day
+}
+
+impl Exchange {
+    pub fn timezone(self) -> Tz {
+        match self {
+            Exchange::Nyse => chrono_tz::America::New_York,
+            Exchange::Euronext => chrono_tz::Europe::Paris,
+            Exchange::Lse => chrono_tz::Europe::London,
+        }
+    }
+
+    /// Full-day closures of `year` falling on weekdays.
+    pub fn holidays(self, year: i32) -> Vec<NaiveDate> {
+        let easter = easter_sunday(year);
+        let good_friday = easter - Days::new(2);
+        let easter_monday = easter + Days::new(1);
+        match self {
+            Exchange::Nyse => {
+                let mut days = vec![
+                    nth_weekday(year, 1, Weekday::Mon, 3),
+                    nth_weekday(year, 2, Weekday::Mon, 3),
+                    good_friday,
+                    last_weekday(year, 5, Weekday::Mon),
+                    observed_us(date(year, 7, 4)),
+                    nth_weekday(year, 9, Weekday::Mon, 1),
+                    nth_weekday(year, 11, Weekday::Thu, 4),
+                    observed_us(date(year, 12, 25)),
+                ];
+                // New Year's Day on a Saturday is not observed on Dec 31.
+                if date(year, 1, 1).weekday() != Weekday::Sat {
+                    days.push(observed_us(date(year, 1, 1)));
+                }
+                if year >= 2022 {
+                    days.push(observed_us(date(year, 6, 19)));
+                }
+                days
+            }
+            Exchange::Euronext => vec![
+                date(year, 1, 1),
+                good_friday,
+                easter_monday,
+                date(year, 5, 1),
+                date(year, 12, 25),
+                date(year, 12, 26),
+            ],
+            Exchange::Lse => {
+                let mut days = vec![
+                    good_friday,
+                    easter_monday,
+                    nth_weekday(year, 5, Weekday::Mon, 1),
+                    last_weekday(year, 5, Weekday::Mon),
+                    last_weekday(year, 8, Weekday::Mon),
+                ];
+                for fixed in [date(year, 1, 1), date(year, 12, 25), date(year, 12, 26)] {
+                    let day = substitute_uk(fixed, &days);
+                    days.push(day);
+                }
+                days
+            }
+        }
+    }
+
+    /// Open at all on that (local) date: a weekday that is not a holiday.
+    pub fn is_trading_day(self, day: NaiveDate) -> bool {
+        !matches!(day.weekday(), Weekday::Sat | Weekday::Sun) && !self.holidays(day.year()).contains(&day)
+    }
+}
+
+/// A day-of-week field in the numbering of the `cron` crate, which counts
+/// from 1 = Sunday; names, `*` and steps are left as they are.
+fn crate_weekdays(field: &str) -> std::result::Result<String, String> {
+    let day = |part: &str| match part.parse::<u8>() {
+        Ok(n @ 0..=6) => Ok((n + 1).to_string()),
+        Ok(7) => Ok("1".to_string()),
+        Ok(n) => Err(format!("day of week {} out of 0-7", n)),
+        Err(_) => Ok(part.to_string()),
+    };
+    let items = field.split(',').map(|item| {
+        let (days, step) = match item.split_once('/') {
+            Some((days, step)) => (days, Some(step)),
+            None => (item, None),
+        };
+        let days = match days.split_once('-') {
+            // Sunday as 7 ends the week: `5-7` is 6-7 (FRI-SAT) and 1 (SUN).
+            Some((from, "7")) if step.is_none() => format!("{}-7,1", day(from)?),
+            Some((_, "7")) => return Err(format!("`{}`: end stepped ranges on 6", item)),
+            Some((from, to)) => format!("{}-{}", day(from)?, day(to)?),
+            None => day(days)?,
+        };
+        Ok(match step {
+            Some(step) => format!("{}/{}", days, step),
+            None => days,
+        })
+    });
+    Ok(items.collect::<std::result::Result<Vec<_>, _>>()?.join(","))
+}
+
+/// Cron schedule for the fetch cycles, in the exchange's time zone (UTC
+/// without one), skipping the exchange's non-trading days.
+#[derive(Debug, Clone)]
+pub struct CronSchedule {
+    schedule: cron::Schedule,
+    exchange: Option<Exchange>,
+}
+
+impl CronSchedule {
+    /// Standard 5-field expression (`*/5 9-17 * * MON-FRI`), or 6 fields
+    /// with leading seconds. Days of week are names or numbers from 0 =
+    /// Sunday to 6, 7 being Sunday again.
+    pub fn parse(expr: &str, exchange: Option<Exchange>) -> Result<Self> {
+        let expr = expr.trim();
+        let invalid = |e: String| Error::parse(format!("invalid schedule `{}`: {}", expr, e));
+        let mut fields: Vec<String> = expr.split_whitespace().map(str::to_string).collect();
+        if fields.len() == 5 {
+            fields.insert(0, "0".to_string());
+        }
+        if let Some(weekdays) = fields.get_mut(5) {
+            *weekdays = crate_weekdays(weekdays).map_err(invalid)?;
+        }
+        let schedule = cron::Schedule::from_str(&fields.join(" ")).map_err(|e| invalid(e.to_string()))?;
+        Ok(CronSchedule { schedule, exchange })
+    }
+
+    /// First run strictly after `now`, or `None` if there is none in the
+    /// coming year.
+    pub fn next_after(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let tz = self.exchange.map(Exchange::timezone).unwrap_or(Tz::UTC);
+        let horizon = now + chrono::Duration::days(366);
+        self.schedule
+            .after(&now.with_timezone(&tz))
+            .take_while(|at| at.with_timezone(&Utc) <= horizon)
+            .find(|at| self.exchange.is_none_or(|exchange| exchange.is_trading_day(at.date_naive())))
+            .map(|at| at.with_timezone(&Utc))
+    }
+
+    /// Sleeps until the next run; forever if there is none.
+    pub async fn wait_next(&self) {
+        match self.next_after(Utc::now()) {
+            Some(at) => tokio::time::sleep((at - Utc::now()).to_std().unwrap_or_default()).await,
+            None => std::future::pending().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn easter_dates() {
+        assert_eq!(easter_sunday(2024), date(2024, 3, 31));
+        assert_eq!(easter_sunday(2025), date(2025, 4, 20));
+        assert_eq!(easter_sunday(2026), date(2026, 4, 5));
+    }
+
+    #[test]
+    fn calendars_know_their_holidays() {
+        // Thanksgiving, Good Friday, July 4th on a Saturday observed Friday 3rd.
+        assert!(!Exchange::Nyse.is_trading_day(date(2024, 11, 28)));
+        assert!(!Exchange::Nyse.is_trading_day(date(2024, 3, 29)));
+        assert!(!Exchange::Nyse.is_trading_day(date(2026, 7, 3)));
+        assert!(Exchange::Nyse.is_trading_day(date(2024, 12, 26)));
+        assert!(!Exchange::Euronext.is_trading_day(date(2024, 12, 26)));
+        // Christmas 2021 on a Saturday: Monday 27 and Tuesday 28 are off in London.
+        assert!(!Exchange::Lse.is_trading_day(date(2021, 12, 27)));
+        assert!(!Exchange::Lse.is_trading_day(date(2021, 12, 28)));
+        assert!(!Exchange::Lse.is_trading_day(date(2024, 6, 1)));
+    }
+
+    #[test]
+    fn five_field_expressions_run_in_the_exchange_time_zone() {
+        let schedule = CronSchedule::parse("*/5 9-17 * * MON-FRI", Some(Exchange::Nyse)).unwrap();
+        // Friday 2024-03-08 23:00 UTC is 18:00 in New York: next run is Monday 09:00 EDT.
+        let friday_evening = Utc.with_ymd_and_hms(2024, 3, 8, 23, 0, 0).unwrap();
+        assert_eq!(
+            schedule.next_after(friday_evening),
+            Some(Utc.with_ymd_and_hms(2024, 3, 11, 13, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn numeric_days_of_week_count_from_sunday() {
+        let saturday = Utc.with_ymd_and_hms(2024, 3, 9, 12, 0, 0).unwrap();
+        let monday_9am = Utc.with_ymd_and_hms(2024, 3, 11, 9, 0, 0).unwrap();
+        let weekdays = CronSchedule::parse("* 9-17 * * 1-5", None).unwrap();
+        assert_eq!(weekdays.next_after(saturday), Some(monday_9am));
+
+        let sunday = CronSchedule::parse("0 10 * * 0", None).unwrap();
+        assert_eq!(sunday.next_after(saturday), Some(Utc.with_ymd_and_hms(2024, 3, 10, 10, 0, 0).unwrap()));
+        let weekend = CronSchedule::parse("0 10 * * 6-7", None).unwrap();
+        assert_eq!(weekend.next_after(saturday), Some(Utc.with_ymd_and_hms(2024, 3, 10, 10, 0, 0).unwrap()));
+        assert_eq!(weekend.next_after(monday_9am), Some(Utc.with_ymd_and_hms(2024, 3, 16, 10, 0, 0).unwrap()));
+        assert!(CronSchedule::parse("0 10 * * 8", None).is_err());
+    }
+
+    #[test]
+    fn holidays_are_skipped() {
+        let schedule = CronSchedule::parse("0 10 * * MON-FRI", Some(Exchange::Euronext)).unwrap();
+        // Christmas Eve 2024 after the run: the 25th and 26th are closed.
+        let christmas_eve = Utc.with_ymd_and_hms(2024, 12, 24, 12, 0, 0).unwrap();
+        assert_eq!(
+            schedule.next_after(christmas_eve),
+            Some(Utc.with_ymd_and_hms(2024, 12, 27, 9, 0, 0).unwrap())
+        );
+
+        let plain = CronSchedule::parse("0 10 * * MON-FRI", None).unwrap();
+        assert_eq!(
+            plain.next_after(christmas_eve),
+            Some(Utc.with_ymd_and_hms(2024, 12, 25, 10, 0, 0).unwrap())
+        );
+        assert!(CronSchedule::parse("every minute", None).is_err());
+    }
+}
@@ -0,0 +1,194 @@
+use crate::StockPrice;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use td_common::{Context, Result};
+use tracing::warn;
+
+/// Cheapest and dearest source of one symbol in one fetch cycle.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceSpread {
+    pub symbol: String,
+    pub timestamp: i64,
+    pub min_source: String,
+    pub min_price: f64,
+    pub max_source: String,
+    pub max_price: f64,
+}
+
+impl SourceSpread {
+    /// Difference relative to the lowest price, in percent.
+    pub fn pct(&self) -> f64 {
+        (self.max_price - self.min_price) / self.min_price * 100.0
+    }
+}
+
+/// One spread per symbol quoted by at least two sources in `prices`. Mock
+/// prices are random and left out, and only prices of the same currency
+/// are compared.
+pub fn compute_spreads(prices: &[StockPrice]) -> Vec<SourceSpread> {
+    let mut by_symbol: HashMap<(&str, &str), Vec<&StockPrice>> = HashMap::new();
+    for price in prices.iter().filter(|p| !p.is_mock && p.price > 0.0) {
+        by_symbol.entry((&price.symbol, &price.currency)).or_default().push(price);
+    }
+
+    let mut spreads: Vec<SourceSpread> = by_symbol
+        .into_values()
+        .filter(|quotes| quotes.len() >= 2)
+        .map(|quotes| {
+            let min = quotes.iter().min_by(|a, b| a.price.total_cmp(&b.price)).unwrap();
+            let max = quotes.iter().max_by(|a, b| a.price.total_cmp(&b.price)).unwrap();
+            SourceSpread {
+                symbol: min.symbol.clone(),
+                timestamp: quotes.iter().map(|p| p.timestamp).max().unwrap(),
+                min_source: min.source.clone(),
+                min_price: min.price,
+                max_source: max.source.clone(),
+                max_price: max.price,
+            }
+        })
+        .collect();
+    spreads.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+    spreads
+}
+
+/// A symbol whose spread stayed above the threshold for `cycles` cycles in a row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpreadAlert {
+    pub spread: SourceSpread,
+    pub cycles: u32,
+}
+
+/// Tracks per-symbol streaks of spreads above `threshold_pct`. An alert is
+/// raised once when a streak reaches `cycles`, and re-armed when the spread
+/// falls back under the threshold.
+#[derive(Debug, Clone)]
+pub struct SpreadMonitor {
+    threshold_pct: f64,
+    cycles: u32,
+    streaks: HashMap<String, u32>,
+}
+
+impl SpreadMonitor {
+    pub fn new(threshold_pct: f64, cycles: u32) -> Self {
+        SpreadMonitor {
+            threshold_pct,
+            cycles: cycles.max(1),
+            streaks: HashMap::new(),
+        }
+    }
+
+    /// Feeds one cycle's spreads. A symbol missing from the cycle keeps its streak.
+    pub fn observe(&mut self, spreads: &[SourceSpread]) -> Vec<SpreadAlert> {
+        let mut alerts = Vec::new();
+        for spread in spreads {
+            if spread.pct() <= self.threshold_pct {
+                self.streaks.remove(&spread.symbol);
+                continue;
+            }
+            let streak = self.streaks.entry(spread.symbol.clone()).or_default();
+            *streak += 1;
+            if *streak == self.cycles {
+                alerts.push(SpreadAlert {
+                    spread: spread.clone(),
+                    cycles: *streak,
+                });
+            }
+        }
+        alerts
+    }
+}
+
+pub async fn save_spread(pool: &PgPool, spread: &SourceSpread) -> Result<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO source_spreads (symbol, timestamp, min_source, min_price, max_source, max_price, spread_pct)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+    )
+    .bind(&spread.symbol)
+    .bind(spread.timestamp)
+    .bind(&spread.min_source)
+    .bind(spread.min_price)
+    .bind(&spread.max_source)
+    .bind(spread.max_price)
+    .bind(spread.pct())
+    .execute(pool)
+    .await
+    .with_context(|| format!("saving source spread for {}", spread.symbol))?;
+    Ok(())
+}
+
+/// Computes the cycle's spreads, stores them when a pool is given, and logs
+/// the alerts raised by `monitor`.
+pub async fn record_spreads(
+    pool: Option<&PgPool>,
+    monitor: &mut SpreadMonitor,
+    prices: &[StockPrice],
+) -> Result<Vec<SpreadAlert>> {
+    let spreads = compute_spreads(prices);
+    if let Some(pool) = pool {
+        for spread in &spreads {
+            save_spread(pool, spread).await?;
+        }
+    }
+    let alerts = monitor.observe(&spreads);
+    for alert in &alerts {
+        let s = &alert.spread;
+        warn!(
+            symbol = %s.symbol,
+            "Sources diverge by {:.2}% for {} cycles: {} {} vs {} {}",
+            s.pct(),
+            alert.cycles,
+            s.min_source,
+            s.min_price,
+            s.max_source,
+            s.max_price
+        );
+    }
+    Ok(alerts)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn price(symbol: &str, source: &str, price: f64, is_mock: bool) -> StockPrice {
+        StockPrice {
+            symbol: symbol.into(),
+            price,
+            source: source.into(),
+            timestamp: 1_700_000_000,
+            currency: "USD".into(),
+            is_mock,
+        }
+    }
+
+    #[test]
+    fn spread_is_between_cheapest_and_dearest_real_source() {
+        let prices = [
+            price("AAPL", "AlphaVantage", 100.0, false),
+            price("AAPL", "Finnhub", 102.0, false),
+            price("AAPL", "Yahoo", 150.0, true),
+            price("MSFT", "Yahoo", 400.0, false),
+        ];
+        let spreads = compute_spreads(&prices);
+        assert_eq!(spreads.len(), 1);
+        assert_eq!(spreads[0].min_source, "AlphaVantage");
+        assert_eq!(spreads[0].max_source, "Finnhub");
+        assert!((spreads[0].pct() - 2.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn alert_fires_once_per_streak() {
+        let wide = compute_spreads(&[price("AAPL", "A", 100.0, false), price("AAPL", "B", 105.0, false)]);
+        let narrow = compute_spreads(&[price("AAPL", "A", 100.0, false), price("AAPL", "B", 100.5, false)]);
+        let mut monitor = SpreadMonitor::new(1.0, 2);
+
+        assert!(monitor.observe(&wide).is_empty());
+        assert_eq!(monitor.observe(&wide).len(), 1);
+        assert!(monitor.observe(&wide).is_empty());
+        assert!(monitor.observe(&narrow).is_empty());
+        assert!(monitor.observe(&wide).is_empty());
+        assert_eq!(monitor.observe(&wide)[0].cycles, 2);
+    }
+}
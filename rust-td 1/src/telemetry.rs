@@ -0,0 +1,73 @@
+use td_common::Result;
+use tracing::Level;
+
+/// Keeps the span exporter alive; dropping it flushes the pending spans.
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    #[cfg(feature = "otel")]
+    provider: Option<opentelemetry_sdk::trace::SdkTracerProvider>,
+}
+
+impl Drop for Telemetry {
+    fn drop(&mut self) {
+        #[cfg(feature = "otel")]
+        if let Some(provider) = self.provider.take()
+            && let Err(e) = provider.shutdown()
+        {
+            eprintln!("OpenTelemetry shutdown failed: {}", e);
+        }
+    }
+}
+
+/// Installs the global subscriber: logs on stdout and, with the `otel`
+/// feature and `OTEL_EXPORTER_OTLP_ENDPOINT` set, the spans (fetch cycles,
+/// provider calls, DB writes) exported over OTLP/gRPC as `service`
+/// (`OTEL_SERVICE_NAME` takes precedence). Keep the guard until exit.
+pub fn init_tracing(service: &str, level: Level) -> Result<Telemetry> {
+    #[cfg(feature = "otel")]
+    if std::env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_some() {
+        return otel::init(service, level);
+    }
+
+    let _ = service;
+    tracing_subscriber::fmt().with_max_level(level).init();
+    Ok(Telemetry::default())
+}
+
+#[cfg(feature = "otel")]
+mod otel {
+    use super::Telemetry;
+    use opentelemetry::trace::TracerProvider as _;
+    use opentelemetry_otlp::SpanExporter;
+    use opentelemetry_sdk::trace::SdkTracerProvider;
+    use opentelemetry_sdk::Resource;
+    use td_common::{Error, Result};
+    use tracing::Level;
+    use tracing_subscriber::filter::LevelFilter;
+    use tracing_subscriber::layer::SubscriberExt;
+    use tracing_subscriber::util::SubscriberInitExt;
+
+    pub fn init(service: &str, level: Level) -> Result<Telemetry> {
+        // Reads OTEL_EXPORTER_OTLP_ENDPOINT (and _HEADERS, _TIMEOUT).
+        let exporter = SpanExporter::builder().with_tonic().build().map_err(Error::http)?;
+        let mut resource = Resource::builder();
+        if std::env::var_os("OTEL_SERVICE_NAME").is_none() {
+            resource = resource.with_service_name(service.to_string());
+        }
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(resource.build())
+            .build();
+        let tracer = provider.tracer(service.to_string());
+
+        tracing_subscriber::registry()
+            .with(LevelFilter::from_level(level))
+            .with(tracing_subscriber::fmt::layer())
+            .with(tracing_opentelemetry::layer().with_tracer(tracer))
+            .try_init()
+            .map_err(Error::parse)?;
+        Ok(Telemetry {
+            provider: Some(provider),
+        })
+    }
+}
@@ -4,6 +4,20 @@ CREATE TABLE IF NOT EXISTS stock_prices (
     price DECIMAL(10, 2) NOT NULL,
     source VARCHAR(50) NOT NULL,
     timestamp BIGINT NOT NULL,
+    currency VARCHAR(3) NOT NULL DEFAULT 'USD',
+    is_mock BOOLEAN NOT NULL DEFAULT FALSE,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
 );
 CREATE INDEX IF NOT EXISTS idx_symbol_timestamp ON stock_prices(symbol, timestamp DESC);
+
+CREATE TABLE IF NOT EXISTS stock_candles (
+    symbol VARCHAR(10) NOT NULL,
+    interval_secs INTEGER NOT NULL,
+    bucket_start BIGINT NOT NULL,
+    open NUMERIC(10,2) NOT NULL,
+    high NUMERIC(10,2) NOT NULL,
+    low NUMERIC(10,2) NOT NULL,
+    close NUMERIC(10,2) NOT NULL,
+    samples INTEGER NOT NULL,
+    PRIMARY KEY (symbol, interval_secs, bucket_start)
+);
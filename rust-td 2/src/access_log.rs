@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use td_common::{Context, Result};
+
+/// Rotated files kept next to the current one (`access.log.1` is the newest).
+pub const KEPT_FILES: usize = 5;
+
+/// One client command, as a line of the access log.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AccessEntry {
+    /// Milliseconds since the epoch.
+    pub ts: i64,
+    pub client: String,
+    /// Masked API key of an authenticated client.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub command: String,
+    /// `ok`, `error` or `unknown` (not a command).
+    pub outcome: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Longer commands are cut, so a client can't bloat the log.
+const MAX_COMMAND_CHARS: usize = 512;
+
+/// The command with its secrets hidden: `AUTH <key>` is logged as `AUTH ***`.
+pub fn redact(command: &str) -> String {
+    match command.split_once(char::is_whitespace) {
+        Some((verb, _)) if verb.eq_ignore_ascii_case("AUTH") => format!("{} ***", verb),
+        _ => command.chars().take(MAX_COMMAND_CHARS).collect(),
+    }
+}
+
+#[derive(Debug)]
+struct Inner {
+    file: File,
+    size: u64,
+}
+
+/// NDJSON audit trail of the client commands, rotated past `max_bytes`.
+/// Shared by every client handler.
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    path: PathBuf,
+    max_bytes: u64,
+    inner: Arc<Mutex<Inner>>,
+}
+
+fn open_append(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("opening access log {}", path.display()))
+}
+
+fn rotated(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+impl AccessLog {
+    pub fn open(path: impl Into<PathBuf>, max_bytes: u64) -> Result<Self> {
+        let path = path.into();
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(AccessLog {
+            path,
+            max_bytes: max_bytes.max(1),
+            inner: Arc::new(Mutex::new(Inner { file, size })),
+        })
+    }
+
+    /// Appends `entry`, rotating first when it would overflow the file.
+    pub fn record(&self, entry: &AccessEntry) -> Result<()> {
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut inner = self.inner.lock().unwrap();
+        if inner.size > 0 && inner.size + line.len() as u64 > self.max_bytes {
+            self.rotate(&mut inner)?;
+        }
+        inner
+            .file
+            .write_all(line.as_bytes())
+            .with_context(|| format!("writing access log {}", self.path.display()))?;
+        inner.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// `access.log` → `access.log.1` → ... → `access.log.5`, the oldest dropped.
+    fn rotate(&self, inner: &mut Inner) -> Result<()> {
+        for n in (1..KEPT_FILES).rev() {
+            let from = rotated(&self.path, n);
+            if from.exists() {
+                std::fs::rename(&from, rotated(&self.path, n + 1))?;
+            }
+        }
+        std::fs::rename(&self.path, rotated(&self.path, 1))
+            .with_context(|| format!("rotating access log {}", self.path.display()))?;
+        inner.file = open_append(&self.path)?;
+        inner.size = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(command: &str) -> AccessEntry {
+        AccessEntry {
+            ts: 1,
+            client: "127.0.0.1:9000".into(),
+            key: None,
+            command: command.into(),
+            outcome: "ok".into(),
+            error: None,
+        }
+    }
+
+    #[test]
+    fn secrets_are_not_logged() {
+        assert_eq!(redact("AUTH s3cret-key"), "AUTH ***");
+        assert_eq!(redact("SUB AAPL"), "SUB AAPL");
+        assert_eq!(redact(&"x".repeat(2000)).len(), MAX_COMMAND_CHARS);
+    }
+
+    #[test]
+    fn the_log_rotates_past_its_size() {
+        let dir = std::env::temp_dir().join(format!("access-log-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("access.log");
+        let line_len = serde_json::to_string(&entry("SUB AAPL")).unwrap().len() as u64 + 1;
+        let log = AccessLog::open(&path, line_len * 2).unwrap();
+        for _ in 0..5 {
+            log.record(&entry("SUB AAPL")).unwrap();
+        }
+
+        let lines = |p: PathBuf| std::fs::read_to_string(p).unwrap().lines().count();
+        assert_eq!(lines(path.clone()), 1);
+        assert_eq!(lines(rotated(&path, 1)), 2);
+        assert_eq!(lines(rotated(&path, 2)), 2);
+        let back: AccessEntry = serde_json::from_str(std::fs::read_to_string(&path).unwrap().trim()).unwrap();
+        assert_eq!(back, entry("SUB AAPL"));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+use crate::protocol::FeedEvent;
+use log::warn;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// `CANONICAL=VARIANT[,VARIANT...]`, e.g. `GOOGL=GOOG,GOOGL.O`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AliasSpec {
+    pub canonical: String,
+    pub variants: Vec<String>,
+}
+
+impl FromStr for AliasSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (canonical, variants) = s
+            .split_once('=')
+            .ok_or_else(|| format!("expected CANONICAL=VARIANT[,VARIANT...], got `{}`", s))?;
+        let canonical = normalize(canonical);
+        let variants: Vec<String> = variants.split(',').map(normalize).filter(|v| !v.is_empty()).collect();
+        if canonical.is_empty() || variants.is_empty() {
+            return Err(format!("empty symbol in alias `{}`", s));
+        }
+        Ok(AliasSpec { canonical, variants })
+    }
+}
+
+fn normalize(symbol: &str) -> String {
+    symbol.trim().to_uppercase()
+}
+
+/// The names feeds give to the same instrument (`GOOG`, `GOOGL.O`) mapped
+/// to the one clients see and subscribe to (`GOOGL`).
+#[derive(Debug, Clone, Default)]
+pub struct SymbolAliases(Arc<HashMap<String, String>>);
+
+impl SymbolAliases {
+    /// Fails when a variant is given two canonical names.
+    pub fn new(specs: &[AliasSpec]) -> Result<Self, String> {
+        let mut map = HashMap::new();
+        for spec in specs {
+            for variant in spec.variants.iter().filter(|v| **v != spec.canonical) {
+                match map.insert(variant.clone(), spec.canonical.clone()) {
+                    Some(other) if other != spec.canonical => {
+                        return Err(format!("{} is an alias of both {} and {}", variant, other, spec.canonical))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(SymbolAliases(Arc::new(map)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Uppercase symbol, under its canonical name when it is an alias.
+    pub fn canonical(&self, symbol: &str) -> String {
+        let symbol = normalize(symbol);
+        self.0.get(&symbol).cloned().unwrap_or(symbol)
+    }
+
+    pub fn apply(&self, event: &mut FeedEvent) {
+        let symbol = match event {
+            FeedEvent::Quote(quote) => &mut quote.symbol,
+            FeedEvent::Trade(trade) => &mut trade.symbol,
+        };
+        if let Some(canonical) = self.0.get(&normalize(symbol)) {
+            *symbol = canonical.clone();
+        }
+    }
+
+    /// Forwards the events of `feed` to `tx` under their canonical symbols,
+    /// until the feed closes.
+    pub async fn relay(self, mut feed: broadcast::Receiver<FeedEvent>, tx: broadcast::Sender<FeedEvent>) {
+        loop {
+            let mut event = match feed.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("Symbol aliases: {} feed updates dropped", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            self.apply(&mut event);
+            let _ = tx.send(event);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::PriceUpdate;
+
+    #[test]
+    fn variants_map_to_their_canonical_symbol() {
+        let specs: Vec<AliasSpec> = ["googl=GOOG, googl.o", "BRK.B=BRK-B"].iter().map(|s| s.parse().unwrap()).collect();
+        let aliases = SymbolAliases::new(&specs).unwrap();
+        assert_eq!(aliases.canonical("googl.o"), "GOOGL");
+        assert_eq!(aliases.canonical("GOOGL"), "GOOGL");
+        assert_eq!(aliases.canonical("aapl"), "AAPL");
+
+        let mut event = FeedEvent::Quote(PriceUpdate {
+            symbol: "BRK-B".into(),
+            price: 410.0,
+            source: "Yahoo".into(),
+            timestamp: 1,
+            is_mock: false,
+            produced_at: None,
+            broadcast_at: None,
+        });
+        aliases.apply(&mut event);
+        assert_eq!(event.symbol(), "BRK.B");
+
+        let clash: Vec<AliasSpec> = ["GOOGL=GOOG", "GOOG.O=GOOG"].iter().map(|s| s.parse().unwrap()).collect();
+        assert!(SymbolAliases::new(&clash).is_err());
+        assert!("GOOGL".parse::<AliasSpec>().is_err());
+    }
+}
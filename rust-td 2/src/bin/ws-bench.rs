@@ -0,0 +1,243 @@
+//! Load-test client: opens N concurrent connections, subscribes each one to
+//! a random symbol and reports latency, throughput and disconnects.
+
+use clap::Parser;
+use futures_util::{SinkExt, StreamExt};
+use rand::seq::SliceRandom;
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use ws_price_feed::FAKE_SYMBOLS;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "WebSocket load tester for the price feed", long_about = None)]
+struct Cli {
+    /// Server URL
+    #[arg(long, default_value = "ws://127.0.0.1:8080")]
+    url: String,
+
+    /// Concurrent connections
+    #[arg(short = 'n', long, default_value_t = 100)]
+    connections: usize,
+
+    /// Test duration in seconds
+    #[arg(short, long, default_value_t = 30)]
+    duration: u64,
+
+    /// Symbols picked at random for the subscriptions, comma separated
+    #[arg(long, value_delimiter = ',')]
+    symbols: Vec<String>,
+
+    /// Pause between two connection attempts, in milliseconds
+    #[arg(long, default_value_t = 0)]
+    ramp_ms: u64,
+}
+
+#[derive(Debug, Default)]
+struct ClientReport {
+    connected: bool,
+    /// Closed by the server (or errored) before the end of the test.
+    dropped: bool,
+    messages: u64,
+    latencies_ms: Vec<i64>,
+    /// Quotes only, see [`PipelineLatency`].
+    pipeline: Vec<PipelineLatency>,
+}
+
+/// Where a quote's time went: producer -> server forward -> received here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PipelineLatency {
+    end_to_end_ms: i64,
+    to_server_ms: i64,
+    to_client_ms: i64,
+}
+
+/// Milliseconds between the envelope `ts` (server send time) and now.
+fn latency_ms(json: &serde_json::Value, now_ms: i64) -> Option<i64> {
+    let sent_ms = json.get("ts").and_then(|v| v.as_i64())?;
+    Some(now_ms - sent_ms)
+}
+
+/// Splits the latency of a `quote` stamped with `produced_at` and
+/// `broadcast_at` (full or delta form).
+fn pipeline_latency(json: &serde_json::Value, now_ms: i64) -> Option<PipelineLatency> {
+    let data = json.get("data")?;
+    let (produced, broadcast) = match json.get("type")?.as_str()? {
+        "quote" => (data.get("produced_at")?, data.get("broadcast_at")?),
+        "quote_delta" => (data.get("pa")?, data.get("ba")?),
+        _ => return None,
+    };
+    let (produced, broadcast) = (produced.as_i64()?, broadcast.as_i64()?);
+    Some(PipelineLatency {
+        end_to_end_ms: now_ms - produced,
+        to_server_ms: broadcast - produced,
+        to_client_ms: now_ms - broadcast,
+    })
+}
+
+async fn run_client(url: String, symbol: String, until: Instant) -> ClientReport {
+    let mut report = ClientReport::default();
+    let (ws, _) = match connect_async(url.as_str()).await {
+        Ok(ok) => ok,
+        Err(_) => return report,
+    };
+    report.connected = true;
+    let (mut write, mut read) = ws.split();
+
+    // 1s heartbeats give latency samples even on a quiet feed.
+    for cmd in [format!("SUB {}", symbol), "HEARTBEAT 1".to_string()] {
+        if write.send(Message::Text(cmd)).await.is_err() {
+            report.dropped = true;
+            return report;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            msg = read.next() => match msg {
+                Some(Ok(Message::Text(text))) => {
+                    report.messages += 1;
+                    let now_ms = chrono::Utc::now().timestamp_millis();
+                    if let Ok(json) = serde_json::from_str::<serde_json::Value>(&text) {
+                        if let Some(ms) = latency_ms(&json, now_ms) {
+                            report.latencies_ms.push(ms);
+                        }
+                        if let Some(split) = pipeline_latency(&json, now_ms) {
+                            report.pipeline.push(split);
+                        }
+                    }
+                }
+                Some(Ok(_)) => report.messages += 1,
+                Some(Err(_)) | None => {
+                    report.dropped = true;
+                    return report;
+                }
+            },
+            _ = tokio::time::sleep_until(until) => break,
+        }
+    }
+
+    let _ = write.send(Message::Close(None)).await;
+    report
+}
+
+fn percentile(sorted: &[i64], p: f64) -> i64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * (sorted.len() - 1) as f64).round() as usize;
+    sorted[rank.min(sorted.len() - 1)]
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    let symbols = if cli.symbols.is_empty() {
+        FAKE_SYMBOLS.map(String::from).to_vec()
+    } else {
+        cli.symbols.clone()
+    };
+
+    let started = Instant::now();
+    let until = started + Duration::from_secs(cli.duration);
+    let mut tasks = Vec::with_capacity(cli.connections);
+    for _ in 0..cli.connections {
+        let symbol = symbols.choose(&mut rand::thread_rng()).cloned().unwrap_or_default();
+        tasks.push(tokio::spawn(run_client(cli.url.clone(), symbol, until)));
+        if cli.ramp_ms > 0 {
+            sleep(Duration::from_millis(cli.ramp_ms)).await;
+        }
+    }
+
+    let mut reports = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        reports.push(task.await.unwrap_or_default());
+    }
+    let elapsed = started.elapsed().as_secs_f64();
+
+    let connected = reports.iter().filter(|r| r.connected).count();
+    let dropped = reports.iter().filter(|r| r.dropped).count();
+    let messages: u64 = reports.iter().map(|r| r.messages).sum();
+    let mut latencies: Vec<i64> = reports.iter().flat_map(|r| r.latencies_ms.iter().copied()).collect();
+    latencies.sort_unstable();
+
+    println!("=== ws-bench {} ===", cli.url);
+    println!("Connections:  {} ok / {} failed", connected, cli.connections - connected);
+    println!(
+        "Disconnects:  {} ({:.1}%)",
+        dropped,
+        100.0 * dropped as f64 / connected.max(1) as f64
+    );
+    println!("Messages:     {} in {:.1}s ({:.0} msg/s)", messages, elapsed, messages as f64 / elapsed);
+    println!(
+        "Latency (ms): p50={} p95={} p99={} max={} ({} samples)",
+        percentile(&latencies, 50.0),
+        percentile(&latencies, 95.0),
+        percentile(&latencies, 99.0),
+        latencies.last().copied().unwrap_or(0),
+        latencies.len()
+    );
+
+    let splits: Vec<PipelineLatency> = reports.iter().flat_map(|r| r.pipeline.iter().copied()).collect();
+    if splits.is_empty() {
+        println!("End-to-end:   no stamped quotes received");
+        return;
+    }
+    let print_split = |label: &str, mut values: Vec<i64>| {
+        values.sort_unstable();
+        println!(
+            "{:<13} p50={} p95={} p99={} max={} (ms, {} quotes)",
+            label,
+            percentile(&values, 50.0),
+            percentile(&values, 95.0),
+            percentile(&values, 99.0),
+            values.last().copied().unwrap_or(0),
+            values.len()
+        );
+    };
+    print_split("End-to-end:", splits.iter().map(|s| s.end_to_end_ms).collect());
+    print_split("  to server:", splits.iter().map(|s| s.to_server_ms).collect());
+    print_split("  to client:", splits.iter().map(|s| s.to_client_ms).collect());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn latency_uses_the_envelope_timestamp() {
+        let hb = serde_json::json!({"v": 1, "type": "heartbeat", "seq": 4, "ts": 1_000, "data": {}});
+        assert_eq!(latency_ms(&hb, 1_250), Some(250));
+        assert_eq!(latency_ms(&serde_json::json!({"type": "connected"}), 0), None);
+    }
+
+    #[test]
+    fn quotes_split_their_latency() {
+        let quote = serde_json::json!({
+            "v": 1, "type": "quote", "seq": 2, "ts": 1_090,
+            "data": {"symbol": "AAPL", "price": 1.0, "source": "x", "timestamp": 1, "produced_at": 1_000, "broadcast_at": 1_080}
+        });
+        let split = pipeline_latency(&quote, 1_100).unwrap();
+        assert_eq!(
+            split,
+            PipelineLatency {
+                end_to_end_ms: 100,
+                to_server_ms: 80,
+                to_client_ms: 20
+            }
+        );
+
+        let delta = serde_json::json!({"type": "quote_delta", "data": {"s": "AAPL", "pa": 1_000, "ba": 1_010}});
+        assert_eq!(pipeline_latency(&delta, 1_010).unwrap().end_to_end_ms, 10);
+        let heartbeat = serde_json::json!({"type": "heartbeat", "data": {"server_time": 1, "seq": 1}});
+        assert_eq!(pipeline_latency(&heartbeat, 0), None);
+    }
+
+    #[test]
+    fn percentile_picks_nearest_rank() {
+        let sorted = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        assert_eq!(percentile(&sorted, 50.0), 6);
+        assert_eq!(percentile(&sorted, 99.0), 10);
+        assert_eq!(percentile(&[], 50.0), 0);
+    }
+}
@@ -0,0 +1,107 @@
+//! Redis pub/sub bridge, so several server instances behind a load balancer
+//! deliver the same feed: the instance running the feed publishes its
+//! events on a channel, and every instance (itself included) forwards what
+//! it reads from that channel to its clients.
+
+use crate::protocol::FeedEvent;
+use futures_util::StreamExt;
+use log::{info, warn};
+use redis::AsyncCommands;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::sleep;
+
+pub const DEFAULT_CHANNEL: &str = "td:feed";
+
+/// Wait before reconnecting to Redis.
+const RETRY: Duration = Duration::from_secs(2);
+
+/// Events travel as the JSON of [`FeedEvent`].
+pub fn encode(event: &FeedEvent) -> String {
+    serde_json::to_string(event).expect("FeedEvent serializes")
+}
+
+pub fn decode(payload: &str) -> Option<FeedEvent> {
+    serde_json::from_str(payload).ok()
+}
+
+/// Publishes the events of the local feed on `channel`. Events that come
+/// while Redis is unreachable are lost, as for a lagging client.
+pub async fn publish(client: redis::Client, channel: String, mut feed: broadcast::Receiver<FeedEvent>) {
+    loop {
+        let mut conn = match client.get_multiplexed_async_connection().await {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Redis bridge: cannot publish ({}), retrying", e);
+                sleep(RETRY).await;
+                continue;
+            }
+        };
+        info!("Redis bridge: publishing the feed on {}", channel);
+        loop {
+            let event = match feed.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("Redis bridge: {} feed updates dropped before publishing", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => return,
+            };
+            if let Err(e) = conn.publish::<_, _, ()>(&channel, encode(&event)).await {
+                warn!("Redis bridge: publish failed ({}), reconnecting", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Forwards every event published on `channel` to the local clients.
+pub async fn subscribe(client: redis::Client, channel: String, tx: broadcast::Sender<FeedEvent>) {
+    loop {
+        match relay(&client, &channel, &tx).await {
+            Ok(()) => warn!("Redis bridge: subscription to {} closed, resubscribing", channel),
+            Err(e) => warn!("Redis bridge: subscription to {} failed ({}), retrying", channel, e),
+        }
+        sleep(RETRY).await;
+    }
+}
+
+async fn relay(client: &redis::Client, channel: &str, tx: &broadcast::Sender<FeedEvent>) -> redis::RedisResult<()> {
+    let mut pubsub = client.get_async_pubsub().await?;
+    pubsub.subscribe(channel).await?;
+    info!("Redis bridge: relaying {}", channel);
+    let mut messages = pubsub.on_message();
+    while let Some(message) = messages.next().await {
+        let payload: String = message.get_payload()?;
+        match decode(&payload) {
+            Some(event) => {
+                let _ = tx.send(event);
+            }
+            None => warn!("Redis bridge: ignoring a malformed message on {}", channel),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{Aggressor, TradeUpdate};
+
+    #[test]
+    fn events_round_trip_through_the_channel_format() {
+        let trade = FeedEvent::Trade(TradeUpdate {
+            symbol: "AAPL".into(),
+            price: 187.2,
+            size: 10,
+            aggressor: Aggressor::Buy,
+            timestamp: 1,
+            is_mock: true,
+        });
+        let payload = encode(&trade);
+        assert!(payload.contains(r#""type":"trade""#));
+        assert_eq!(decode(&payload).unwrap().symbol(), "AAPL");
+        assert!(decode(r#"{"type":"news"}"#).is_none());
+    }
+}
@@ -0,0 +1,216 @@
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Row};
+use td_common::{Context, Error, Result};
+
+/// Candles per page when the client doesn't ask for a size, and the most
+/// it can ask for.
+pub const DEFAULT_PAGE: u32 = 500;
+pub const MAX_PAGE: u32 = 1000;
+
+/// `{"action":"candles","symbol":"AAPL","interval":"1m","from":...}`.
+/// `from`/`to` are Unix seconds (`to` excluded); `cursor` is the
+/// `next_cursor` of the previous page and takes over from `from`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CandleQuery {
+    pub symbol: String,
+    pub interval: String,
+    #[serde(default)]
+    pub from: Option<i64>,
+    #[serde(default)]
+    pub to: Option<i64>,
+    #[serde(default)]
+    pub cursor: Option<i64>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Candle {
+    pub bucket_start: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+    pub samples: i64,
+}
+
+/// One page of candles, oldest first; `next_cursor` is `None` on the last.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CandlePage {
+    pub symbol: String,
+    pub interval_secs: i64,
+    pub candles: Vec<Candle>,
+    pub next_cursor: Option<i64>,
+}
+
+/// `60`, `30s`, `1m`, `15m`, `1h`, `1d` → seconds.
+pub fn parse_interval(text: &str) -> Option<i64> {
+    let text = text.trim();
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => text.split_at(i),
+        None => (text, "s"),
+    };
+    let scale = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86_400,
+        _ => return None,
+    };
+    number.parse::<i64>().ok().filter(|&n| n > 0).map(|n| n * scale)
+}
+
+/// First candle `query` wants: after the cursor, else from `from`.
+fn start_of(query: &CandleQuery) -> i64 {
+    query.cursor.map(|c| c + 1).or(query.from).unwrap_or(0)
+}
+
+fn page_size(query: &CandleQuery) -> usize {
+    query.limit.unwrap_or(DEFAULT_PAGE).clamp(1, MAX_PAGE) as usize
+}
+
+/// Cuts a page of `query` out of `candles` (oldest first, in memory).
+pub fn page(symbol: &str, interval_secs: i64, candles: &[Candle], query: &CandleQuery) -> CandlePage {
+    let (start, limit) = (start_of(query), page_size(query));
+    let mut selected: Vec<Candle> = candles
+        .iter()
+        .filter(|c| c.bucket_start >= start && query.to.is_none_or(|to| c.bucket_start < to))
+        .take(limit + 1)
+        .cloned()
+        .collect();
+    let next_cursor = if selected.len() > limit {
+        selected.truncate(limit);
+        selected.last().map(|c| c.bucket_start)
+    } else {
+        None
+    };
+    CandlePage {
+        symbol: symbol.to_string(),
+        interval_secs,
+        candles: selected,
+        next_cursor,
+    }
+}
+
+/// A page of `query`: the candles stored by the retention job plus the
+/// roll-up of the ticks still in `stock_prices` (mock ticks only when the
+/// symbol has no real one).
+pub async fn load_candles(pool: &PgPool, query: &CandleQuery) -> Result<CandlePage> {
+    let symbol = query.symbol.trim().to_uppercase();
+    let interval_secs =
+        parse_interval(&query.interval).ok_or_else(|| Error::parse(format!("invalid interval `{}`", query.interval)))?;
+    let limit = page_size(query);
+
+    // One row more than the page tells whether there is a next one.
+    let rows = sqlx::query(
+        r#"
+        WITH parts AS (
+            SELECT bucket_start, open::float8 AS open, high::float8 AS high, low::float8 AS low,
+                   close::float8 AS close, samples::bigint AS samples, 0 AS part
+            FROM stock_candles
+            WHERE symbol = $1 AND interval_secs = $2
+            UNION ALL
+            SELECT (timestamp / $2) * $2,
+                   (ARRAY_AGG(price::float8 ORDER BY timestamp))[1],
+                   MAX(price::float8),
+                   MIN(price::float8),
+                   (ARRAY_AGG(price::float8 ORDER BY timestamp DESC))[1],
+                   COUNT(*),
+                   1
+            FROM stock_prices
+            WHERE symbol = $1
+              AND is_mock = NOT EXISTS (SELECT 1 FROM stock_prices WHERE symbol = $1 AND NOT is_mock)
+            GROUP BY 1
+        )
+        SELECT bucket_start,
+               (ARRAY_AGG(open ORDER BY part))[1] AS open,
+               MAX(high) AS high,
+               MIN(low) AS low,
+               (ARRAY_AGG(close ORDER BY part DESC))[1] AS close,
+               SUM(samples)::bigint AS samples
+        FROM parts
+        WHERE bucket_start >= $3 AND ($4::bigint IS NULL OR bucket_start < $4)
+        GROUP BY bucket_start
+        ORDER BY bucket_start
+        LIMIT $5
+        "#,
+    )
+    .bind(&symbol)
+    .bind(interval_secs)
+    .bind(start_of(query))
+    .bind(query.to)
+    .bind(limit as i64 + 1)
+    .fetch_all(pool)
+    .await
+    .with_context(|| format!("reading candles of {}", symbol))?;
+
+    let mut candles = Vec::with_capacity(rows.len());
+    for row in &rows {
+        candles.push(Candle {
+            bucket_start: row.try_get("bucket_start")?,
+            open: row.try_get("open")?,
+            high: row.try_get("high")?,
+            low: row.try_get("low")?,
+            close: row.try_get("close")?,
+            samples: row.try_get("samples")?,
+        });
+    }
+    let next_cursor = if candles.len() > limit {
+        candles.truncate(limit);
+        candles.last().map(|c| c.bucket_start)
+    } else {
+        None
+    };
+    Ok(CandlePage {
+        symbol,
+        interval_secs,
+        candles,
+        next_cursor,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals_parse_to_seconds() {
+        assert_eq!(parse_interval("1m"), Some(60));
+        assert_eq!(parse_interval("4h"), Some(14_400));
+        assert_eq!(parse_interval("1d"), Some(86_400));
+        assert_eq!(parse_interval("90"), Some(90));
+        assert_eq!(parse_interval("0m"), None);
+        assert_eq!(parse_interval("1w"), None);
+        assert_eq!(parse_interval("m"), None);
+    }
+
+    #[test]
+    fn pages_follow_the_cursor() {
+        let candles: Vec<Candle> = (0..5)
+            .map(|i| Candle {
+                bucket_start: i * 60,
+                open: 1.0,
+                high: 1.0,
+                low: 1.0,
+                close: 1.0,
+                samples: 1,
+            })
+            .collect();
+        let mut query = CandleQuery {
+            symbol: "AAPL".into(),
+            interval: "1m".into(),
+            from: Some(60),
+            to: None,
+            cursor: None,
+            limit: Some(2),
+        };
+        let first = page("AAPL", 60, &candles, &query);
+        assert_eq!(first.candles.iter().map(|c| c.bucket_start).collect::<Vec<_>>(), vec![60, 120]);
+        assert_eq!(first.next_cursor, Some(120));
+
+        query.cursor = first.next_cursor;
+        let last = page("AAPL", 60, &candles, &query);
+        assert_eq!(last.candles.len(), 2);
+        assert_eq!(last.next_cursor, None);
+    }
+}
@@ -0,0 +1,147 @@
+use log::{info, warn};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::{interval_at, Instant, MissedTickBehavior};
+
+/// Overflow counters of one broadcast channel, shared by all its clients
+/// (every listener of the same feed).
+#[derive(Debug, Clone, Default)]
+pub struct ChannelMetrics(Arc<Counters>);
+
+#[derive(Debug, Default)]
+struct Counters {
+    lagged: AtomicU64,
+    max_depth: AtomicUsize,
+    /// Never reset by `take`.
+    lagged_total: AtomicU64,
+    /// Times a client fell behind, never reset.
+    lag_events_total: AtomicU64,
+}
+
+/// What the clients saw of the channel during one interval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ChannelSample {
+    /// Updates dropped because a client lagged, summed over all clients.
+    pub lagged: u64,
+    /// Largest backlog a client had left after a receive.
+    pub max_depth: usize,
+}
+
+impl ChannelMetrics {
+    pub fn record_lag(&self, dropped: u64) {
+        self.0.lagged.fetch_add(dropped, Ordering::Relaxed);
+        self.0.lagged_total.fetch_add(dropped, Ordering::Relaxed);
+        self.0.lag_events_total.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Updates dropped since startup, over all clients.
+    pub fn dropped_total(&self) -> u64 {
+        self.0.lagged_total.load(Ordering::Relaxed)
+    }
+
+    /// Times a client fell behind since startup.
+    pub fn lag_events_total(&self) -> u64 {
+        self.0.lag_events_total.load(Ordering::Relaxed)
+    }
+
+    pub fn record_depth(&self, depth: usize) {
+        self.0.max_depth.fetch_max(depth, Ordering::Relaxed);
+    }
+
+    /// The counters since the previous call, which resets them.
+    pub fn take(&self) -> ChannelSample {
+        ChannelSample {
+            lagged: self.0.lagged.swap(0, Ordering::Relaxed),
+            max_depth: self.0.max_depth.swap(0, Ordering::Relaxed),
+        }
+    }
+}
+
+/// Decides when the channel capacity is systematically too small: some
+/// client lagged, or a backlog reached 90% of the capacity, for `intervals`
+/// intervals in a row. Warns once per streak.
+#[derive(Debug, Clone)]
+pub struct CapacityAdvisor {
+    capacity: usize,
+    intervals: u32,
+    streak: u32,
+}
+
+impl CapacityAdvisor {
+    pub fn new(capacity: usize, intervals: u32) -> Self {
+        CapacityAdvisor {
+            capacity,
+            intervals: intervals.max(1),
+            streak: 0,
+        }
+    }
+
+    fn saturated(&self, sample: &ChannelSample) -> bool {
+        sample.lagged > 0 || sample.max_depth * 10 >= self.capacity * 9
+    }
+
+    /// Feeds one interval; `true` when the warning is due.
+    pub fn observe(&mut self, sample: &ChannelSample) -> bool {
+        if !self.saturated(sample) {
+            self.streak = 0;
+            return false;
+        }
+        self.streak += 1;
+        self.streak == self.intervals
+    }
+}
+
+/// Logs the channel counters every `every` and warns when the capacity
+/// stays insufficient for `warn_after` intervals.
+pub async fn report_channel(metrics: ChannelMetrics, capacity: usize, every: Duration, warn_after: u32) {
+    let mut advisor = CapacityAdvisor::new(capacity, warn_after);
+    let mut timer = interval_at(Instant::now() + every, every);
+    timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    loop {
+        timer.tick().await;
+        let sample = metrics.take();
+        info!(
+            "Broadcast channel: {} lagged drops, max queue depth {}/{}",
+            sample.lagged, sample.max_depth, capacity
+        );
+        if advisor.observe(&sample) {
+            warn!(
+                "Broadcast channel capacity {} is too small for {} intervals in a row; raise --channel-capacity",
+                capacity, advisor.streak
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn take_resets_the_counters() {
+        let metrics = ChannelMetrics::default();
+        metrics.clone().record_lag(3);
+        metrics.record_lag(2);
+        metrics.record_depth(7);
+        metrics.record_depth(4);
+        assert_eq!(metrics.take(), ChannelSample { lagged: 5, max_depth: 7 });
+        assert_eq!(metrics.take(), ChannelSample::default());
+    }
+
+    #[test]
+    fn warns_once_per_saturated_streak() {
+        let mut advisor = CapacityAdvisor::new(100, 2);
+        let lagging = ChannelSample { lagged: 1, max_depth: 0 };
+        let full = ChannelSample { lagged: 0, max_depth: 95 };
+        let calm = ChannelSample { lagged: 0, max_depth: 10 };
+
+        assert!(!advisor.observe(&lagging));
+        assert!(advisor.observe(&full));
+        assert!(!advisor.observe(&lagging));
+        assert!(!advisor.observe(&calm));
+        assert!(!advisor.observe(&full));
+        assert!(advisor.observe(&full));
+    }
+}
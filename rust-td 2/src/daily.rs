@@ -0,0 +1,172 @@
+use crate::protocol::{FeedEvent, PriceUpdate};
+use chrono::{DateTime, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// `daily_stats` updates kept for a client that is slow to read them.
+const DAILY_CAPACITY: usize = 256;
+
+/// Statistics of a symbol's quotes over the current UTC day, all sources
+/// together.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DailyStats {
+    pub symbol: String,
+    /// `YYYY-MM-DD`.
+    pub date: String,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub last: f64,
+    /// Last price of the previous day, when the server saw it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prev_close: Option<f64>,
+    /// From the previous close, or from the open on the first day.
+    pub change_pct: f64,
+    /// Time of the last quote, in seconds since the epoch.
+    pub timestamp: i64,
+}
+
+impl DailyStats {
+    fn open(quote: &PriceUpdate, date: NaiveDate, prev_close: Option<f64>) -> Self {
+        let mut stats = DailyStats {
+            symbol: quote.symbol.clone(),
+            date: date.to_string(),
+            open: quote.price,
+            high: quote.price,
+            low: quote.price,
+            last: quote.price,
+            prev_close,
+            change_pct: 0.0,
+            timestamp: quote.timestamp,
+        };
+        stats.update_change();
+        stats
+    }
+
+    fn update_change(&mut self) {
+        let base = self.prev_close.unwrap_or(self.open);
+        self.change_pct = if base != 0.0 { (self.last - base) / base * 100.0 } else { 0.0 };
+    }
+
+    fn record(&mut self, quote: &PriceUpdate) {
+        self.high = self.high.max(quote.price);
+        self.low = self.low.min(quote.price);
+        self.last = quote.price;
+        self.timestamp = quote.timestamp;
+        self.update_change();
+    }
+}
+
+/// Daily statistics of every symbol of the feed, updated from its quotes;
+/// each change is also broadcast to the clients that follow them.
+#[derive(Debug, Clone)]
+pub struct DailyTracker {
+    stats: Arc<Mutex<HashMap<String, DailyStats>>>,
+    tx: broadcast::Sender<DailyStats>,
+}
+
+impl Default for DailyTracker {
+    fn default() -> Self {
+        DailyTracker {
+            stats: Arc::default(),
+            tx: broadcast::channel(DAILY_CAPACITY).0,
+        }
+    }
+}
+
+impl DailyTracker {
+    /// Takes a quote into its symbol's day; `None` for unusable prices and
+    /// quotes older than the current day.
+    pub fn record(&self, quote: &PriceUpdate) -> Option<DailyStats> {
+        if !quote.price.is_finite() || quote.price <= 0.0 {
+            return None;
+        }
+        let date = DateTime::from_timestamp(quote.timestamp, 0)?.date_naive();
+        let day = date.to_string();
+        let mut all = self.stats.lock().unwrap();
+        let stats = match all.get_mut(&quote.symbol) {
+            Some(stats) if stats.date == day => {
+                stats.record(quote);
+                stats.clone()
+            }
+            Some(stats) if stats.date > day => return None,
+            previous => {
+                let stats = DailyStats::open(quote, date, previous.map(|p| p.last));
+                all.insert(quote.symbol.clone(), stats.clone());
+                stats
+            }
+        };
+        drop(all);
+        let _ = self.tx.send(stats.clone());
+        Some(stats)
+    }
+
+    pub fn get(&self, symbol: &str) -> Option<DailyStats> {
+        self.stats.lock().unwrap().get(symbol).cloned()
+    }
+
+    /// Every symbol's statistics, by symbol.
+    pub fn all(&self) -> Vec<DailyStats> {
+        let mut all: Vec<DailyStats> = self.stats.lock().unwrap().values().cloned().collect();
+        all.sort_by(|a, b| a.symbol.cmp(&b.symbol));
+        all
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<DailyStats> {
+        self.tx.subscribe()
+    }
+
+    /// Records the quotes of the feed until the channel closes.
+    pub async fn track(self, mut rx: broadcast::Receiver<FeedEvent>) {
+        loop {
+            match rx.recv().await {
+                Ok(FeedEvent::Quote(quote)) => {
+                    self.record(&quote);
+                }
+                Ok(FeedEvent::Trade(_)) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(price: f64, timestamp: i64) -> PriceUpdate {
+        PriceUpdate {
+            symbol: "AAPL".into(),
+            price,
+            source: "Yahoo".into(),
+            timestamp,
+            is_mock: false,
+            produced_at: None,
+            broadcast_at: None,
+        }
+    }
+
+    #[test]
+    fn days_roll_over_at_midnight_utc() {
+        let tracker = DailyTracker::default();
+        let mut rx = tracker.subscribe();
+        // 2024-03-11 14:00 UTC, then later that day.
+        tracker.record(&quote(100.0, 1_710_165_600)).unwrap();
+        tracker.record(&quote(104.0, 1_710_169_200)).unwrap();
+        let stats = tracker.record(&quote(98.0, 1_710_172_800)).unwrap();
+        assert_eq!((stats.open, stats.high, stats.low, stats.last), (100.0, 104.0, 98.0, 98.0));
+        assert!((stats.change_pct + 2.0).abs() < 1e-9);
+        assert_eq!(rx.try_recv().unwrap().last, 100.0);
+
+        // Next day: a new open, the change is from the previous close.
+        let next = tracker.record(&quote(99.0, 1_710_252_000)).unwrap();
+        assert_eq!((next.date.as_str(), next.open, next.prev_close), ("2024-03-12", 99.0, Some(98.0)));
+        assert!((next.change_pct - 1.0 / 98.0 * 100.0).abs() < 1e-9);
+        // A late quote of the day before is ignored.
+        assert!(tracker.record(&quote(150.0, 1_710_172_900)).is_none());
+        assert_eq!(tracker.all().len(), 1);
+    }
+}
@@ -0,0 +1,360 @@
+use flate2::{Compress, Compression, Decompress, FlushCompress, FlushDecompress, Status};
+use std::io::{self, Cursor};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::{Data, OpCode};
+use tokio_tungstenite::tungstenite::protocol::frame::{Frame, FrameHeader};
+use tokio_tungstenite::tungstenite::Message;
+
+// permessage-deflate (RFC 7692), which tungstenite does not implement: the
+// writer sends compressed messages itself as raw frames with RSV1 set, and
+// `InflateStream` turns the compressed frames of the client back into plain
+// ones under tungstenite's reader.
+
+/// Largest message inflated, compressed or not (commands are a few bytes).
+const MAX_MESSAGE_BYTES: usize = 1 << 20;
+
+/// Ends every compressed message (sync flush), left out on the wire.
+const TRAILER: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Parameters of an accepted `permessage-deflate` offer; the window is
+/// always the full 32 KiB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PerMessageDeflate {
+    /// Reset the server's compressor after each message.
+    pub server_no_context_takeover: bool,
+    pub client_no_context_takeover: bool,
+    /// The offer capped the server window; only 15 bits is accepted.
+    server_max_window_bits: bool,
+}
+
+impl PerMessageDeflate {
+    /// The first offer of a `Sec-WebSocket-Extensions` request header the
+    /// server can accept.
+    pub fn negotiate(offers: &str) -> Option<Self> {
+        offers.split(',').find_map(|offer| {
+            let mut params = offer.split(';').map(str::trim);
+            if !params.next()?.eq_ignore_ascii_case("permessage-deflate") {
+                return None;
+            }
+            let mut accepted = PerMessageDeflate::default();
+            for param in params {
+                let (name, value) = match param.split_once('=') {
+                    Some((name, value)) => (name.trim(), Some(value.trim().trim_matches('"'))),
+                    None => (param, None),
+                };
+                let window_bits = |bits: &str| bits.parse::<u8>().is_ok_and(|bits| (8..=15).contains(&bits));
+                match (name.to_ascii_lowercase().as_str(), value) {
+                    ("server_no_context_takeover", None) => accepted.server_no_context_takeover = true,
+                    ("client_no_context_takeover", None) => accepted.client_no_context_takeover = true,
+                    ("server_max_window_bits", Some("15")) => accepted.server_max_window_bits = true,
+                    // the inflater takes any window
+                    ("client_max_window_bits", None) => {}
+                    ("client_max_window_bits", Some(bits)) if window_bits(bits) => {}
+                    _ => return None,
+                }
+            }
+            Some(accepted)
+        })
+    }
+
+    /// The `Sec-WebSocket-Extensions` response header.
+    pub fn response(&self) -> String {
+        let mut header = "permessage-deflate".to_string();
+        if self.server_no_context_takeover {
+            header.push_str("; server_no_context_takeover");
+        }
+        if self.client_no_context_takeover {
+            header.push_str("; client_no_context_takeover");
+        }
+        if self.server_max_window_bits {
+            header.push_str("; server_max_window_bits=15");
+        }
+        header
+    }
+}
+
+/// Compresses the messages of one connection.
+pub struct Deflater {
+    compress: Compress,
+    no_context_takeover: bool,
+}
+
+impl Deflater {
+    pub fn new(params: PerMessageDeflate) -> Self {
+        Deflater {
+            // level 1: the messages are small and frequent
+            compress: Compress::new(Compression::fast(), false),
+            no_context_takeover: params.server_no_context_takeover,
+        }
+    }
+
+    /// Text and binary messages as compressed frames; control messages, and
+    /// messages the compressor fails on, are sent as they are.
+    pub fn compress(&mut self, message: Message) -> Message {
+        let (opcode, data) = match &message {
+            Message::Text(text) => (Data::Text, text.as_bytes()),
+            Message::Binary(bytes) => (Data::Binary, bytes.as_slice()),
+            _ => return message,
+        };
+        let compressed = self.deflate(data);
+        if self.no_context_takeover || compressed.is_none() {
+            self.compress.reset();
+        }
+        match compressed {
+            Some(payload) => {
+                let mut frame = Frame::message(payload, OpCode::Data(opcode), true);
+                frame.header_mut().rsv1 = true;
+                Message::Frame(frame)
+            }
+            None => message,
+        }
+    }
+
+    fn deflate(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        let start = self.compress.total_in();
+        let mut out = Vec::with_capacity(data.len() / 2 + 64);
+        loop {
+            let consumed = (self.compress.total_in() - start) as usize;
+            self.compress.compress_vec(&data[consumed..], &mut out, FlushCompress::Sync).ok()?;
+            let consumed = (self.compress.total_in() - start) as usize;
+            // the flush is complete once there is room left over
+            if consumed == data.len() && out.len() < out.capacity() {
+                break;
+            }
+            out.reserve(256);
+        }
+        out.truncate(out.strip_suffix(&TRAILER)?.len());
+        Some(out)
+    }
+}
+
+/// The transport of a connection, passing the bytes through until `enable`,
+/// then inflating the compressed messages read into plain frames (masked
+/// with a zero key when the client masked them).
+pub struct InflateStream<S> {
+    inner: S,
+    enabled: bool,
+    decompress: Decompress,
+    /// Bytes read, short of a whole frame.
+    raw: Vec<u8>,
+    /// Frames for tungstenite, from `ready_pos` on.
+    ready: Vec<u8>,
+    ready_pos: usize,
+    /// Opcode, masking and payload so far of the compressed message being read.
+    message: Option<(OpCode, bool, Vec<u8>)>,
+}
+
+impl<S> InflateStream<S> {
+    pub fn new(inner: S) -> Self {
+        InflateStream {
+            inner,
+            enabled: false,
+            decompress: Decompress::new(false),
+            raw: Vec::new(),
+            ready: Vec::new(),
+            ready_pos: 0,
+            message: None,
+        }
+    }
+
+    /// After the handshake accepted the extension.
+    pub fn enable(&mut self) {
+        self.enabled = true;
+    }
+
+    /// Moves the whole frames of `raw` to `ready`.
+    fn decode(&mut self) -> io::Result<()> {
+        loop {
+            let mut cursor = Cursor::new(&self.raw);
+            let Some((header, len)) = FrameHeader::parse(&mut cursor).map_err(invalid)? else {
+                return Ok(());
+            };
+            let start = cursor.position() as usize;
+            if len > MAX_MESSAGE_BYTES as u64 {
+                return Err(invalid("frame too large"));
+            }
+            let end = start + len as usize;
+            if self.raw.len() < end {
+                return Ok(());
+            }
+            let frame: Vec<u8> = self.raw.drain(..end).collect();
+            let compressed = match header.opcode {
+                OpCode::Data(Data::Text | Data::Binary) => header.rsv1 && self.message.is_none(),
+                OpCode::Data(Data::Continue) => self.message.is_some(),
+                _ => false,
+            };
+            if !compressed {
+                // control frames and plain messages, and bad RSV1 bits for tungstenite to reject
+                self.ready.extend_from_slice(&frame);
+                continue;
+            }
+            let mut payload = frame[start..].to_vec();
+            if let Some(mask) = header.mask {
+                payload.iter_mut().enumerate().for_each(|(i, b)| *b ^= mask[i & 3]);
+            }
+            let (_, _, data) = self.message.get_or_insert_with(|| (header.opcode, header.mask.is_some(), Vec::new()));
+            data.extend_from_slice(&payload);
+            if data.len() > MAX_MESSAGE_BYTES {
+                return Err(invalid("message too large"));
+            }
+            if header.is_final {
+                let (opcode, masked, data) = self.message.take().unwrap_or((header.opcode, false, Vec::new()));
+                let header = FrameHeader {
+                    opcode,
+                    mask: masked.then_some([0; 4]),
+                    ..FrameHeader::default()
+                };
+                Frame::from_payload(header, self.inflate(data)?).format(&mut self.ready).map_err(invalid)?;
+            }
+        }
+    }
+
+    fn inflate(&mut self, mut data: Vec<u8>) -> io::Result<Vec<u8>> {
+        data.extend_from_slice(&TRAILER);
+        let start = self.decompress.total_in();
+        let mut out = Vec::with_capacity(data.len() * 4);
+        loop {
+            let consumed = (self.decompress.total_in() - start) as usize;
+            let status = self.decompress.decompress_vec(&data[consumed..], &mut out, FlushDecompress::Sync).map_err(invalid)?;
+            if out.len() > MAX_MESSAGE_BYTES {
+                return Err(invalid("message too large"));
+            }
+            if status == Status::StreamEnd {
+                // a final block: the next message starts a new stream
+                self.decompress.reset(false);
+                break;
+            }
+            let consumed = (self.decompress.total_in() - start) as usize;
+            if consumed == data.len() && out.len() < out.capacity() {
+                break;
+            }
+            out.reserve(4096);
+        }
+        Ok(out)
+    }
+}
+
+fn invalid(e: impl std::fmt::Display) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("permessage-deflate: {}", e))
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for InflateStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        if !this.enabled {
+            return Pin::new(&mut this.inner).poll_read(cx, buf);
+        }
+        loop {
+            if this.ready_pos < this.ready.len() {
+                let n = buf.remaining().min(this.ready.len() - this.ready_pos);
+                buf.put_slice(&this.ready[this.ready_pos..this.ready_pos + n]);
+                this.ready_pos += n;
+                if this.ready_pos == this.ready.len() {
+                    this.ready.clear();
+                    this.ready_pos = 0;
+                }
+                return Poll::Ready(Ok(()));
+            }
+            let mut chunk = [0; 4096];
+            let mut read = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut read))?;
+            if read.filled().is_empty() {
+                // end of stream: a truncated frame is tungstenite's to report
+                if this.raw.is_empty() {
+                    return Poll::Ready(Ok(()));
+                }
+                this.ready.append(&mut this.raw);
+                continue;
+            }
+            this.raw.extend_from_slice(read.filled());
+            this.decode()?;
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for InflateStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+
+    #[test]
+    fn offers_are_accepted_or_declined_by_their_parameters() {
+        let accepted = PerMessageDeflate::negotiate("permessage-deflate; client_max_window_bits").unwrap();
+        assert_eq!(accepted.response(), "permessage-deflate");
+
+        let offers = "permessage-deflate; server_max_window_bits=10, \
+                      permessage-deflate; server_no_context_takeover; server_max_window_bits=\"15\"";
+        let accepted = PerMessageDeflate::negotiate(offers).unwrap();
+        assert!(accepted.server_no_context_takeover);
+        assert_eq!(accepted.response(), "permessage-deflate; server_no_context_takeover; server_max_window_bits=15");
+
+        assert_eq!(PerMessageDeflate::negotiate("x-webkit-deflate-frame"), None);
+        assert_eq!(PerMessageDeflate::negotiate("permessage-deflate; unknown"), None);
+    }
+
+    /// The frame as sent, with the client's masking when `mask` is set.
+    fn wire(mut frame: Frame, mask: Option<[u8; 4]>) -> Vec<u8> {
+        frame.header_mut().mask = mask;
+        let mut bytes = Vec::new();
+        frame.format(&mut bytes).unwrap();
+        bytes
+    }
+
+    #[tokio::test]
+    async fn compressed_frames_are_inflated_for_tungstenite() {
+        let quote = r#"{"seq":1,"type":"quote","symbol":"AAPL","price":187.42,"source":"Yahoo","timestamp":1}"#;
+        let mut deflater = Deflater::new(PerMessageDeflate::default());
+        let (mut bytes, mut sizes) = (Vec::new(), Vec::new());
+        for _ in 0..2 {
+            match deflater.compress(Message::Text(quote.into())) {
+                Message::Frame(frame) => {
+                    assert!(frame.header().rsv1);
+                    sizes.push(frame.payload().len());
+                    bytes.extend(wire(frame, Some([1, 2, 3, 4])));
+                }
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+        // the second copy only refers back to the first
+        assert!(sizes[0] < quote.len() && sizes[1] * 4 < sizes[0], "{:?}", sizes);
+        bytes.extend(wire(Frame::ping(b"hi".to_vec()), Some([9, 9, 9, 9])));
+        assert!(matches!(deflater.compress(Message::Ping(Vec::new())), Message::Ping(_)));
+
+        let mut stream = InflateStream::new(bytes.as_slice());
+        stream.enable();
+        let mut plain = Vec::new();
+        stream.read_to_end(&mut plain).await.unwrap();
+
+        let mut cursor = Cursor::new(plain.as_slice());
+        let mut frames = Vec::new();
+        while let Some((header, len)) = FrameHeader::parse(&mut cursor).unwrap() {
+            let start = cursor.position() as usize;
+            let mut payload = plain[start..start + len as usize].to_vec();
+            if let Some(mask) = header.mask {
+                payload.iter_mut().enumerate().for_each(|(i, b)| *b ^= mask[i & 3]);
+            }
+            cursor.set_position((start + len as usize) as u64);
+            frames.push((header.rsv1, header.opcode, String::from_utf8(payload).unwrap()));
+        }
+        let text = OpCode::Data(Data::Text);
+        assert_eq!(frames[0], (false, text, quote.to_string()));
+        assert_eq!(frames[1], (false, text, quote.to_string()));
+        assert_eq!(frames[2].2, "hi");
+    }
+}
@@ -0,0 +1,96 @@
+use crate::envelope::ServerMessage;
+use crate::protocol::PriceUpdate;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+/// Per-client delta wire mode for quotes: the first update of a symbol (and every
+/// `keyframe_every`-th one) is sent in full, the others only carry the
+/// fields that changed, with short keys: `s` symbol (always), `p` price,
+/// `src` source, `t` timestamp, `m` is_mock, `pa`/`ba` produced_at and
+/// broadcast_at (sent whenever set, they change with every update).
+#[derive(Debug)]
+pub struct DeltaEncoder {
+    keyframe_every: u32,
+    last: HashMap<String, (PriceUpdate, u32)>,
+}
+
+impl DeltaEncoder {
+    pub fn new(keyframe_every: u32) -> Self {
+        DeltaEncoder {
+            keyframe_every: keyframe_every.max(1),
+            last: HashMap::new(),
+        }
+    }
+
+    pub fn encode(&mut self, update: &PriceUpdate) -> ServerMessage {
+        let keyframe_every = self.keyframe_every;
+        match self.last.get_mut(&update.symbol) {
+            Some((prev, since_keyframe)) if *since_keyframe + 1 < keyframe_every => {
+                let mut delta = Map::new();
+                delta.insert("s".into(), json!(update.symbol));
+                if prev.price != update.price {
+                    delta.insert("p".into(), json!(update.price));
+                }
+                if prev.source != update.source {
+                    delta.insert("src".into(), json!(update.source));
+                }
+                if prev.timestamp != update.timestamp {
+                    delta.insert("t".into(), json!(update.timestamp));
+                }
+                if prev.is_mock != update.is_mock {
+                    delta.insert("m".into(), json!(update.is_mock));
+                }
+                if let Some(at) = update.produced_at {
+                    delta.insert("pa".into(), json!(at));
+                }
+                if let Some(at) = update.broadcast_at {
+                    delta.insert("ba".into(), json!(at));
+                }
+                *prev = update.clone();
+                *since_keyframe += 1;
+                ServerMessage::QuoteDelta(Value::Object(delta))
+            }
+            _ => {
+                self.last.insert(update.symbol.clone(), (update.clone(), 0));
+                ServerMessage::Quote(update.clone())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(price: f64, timestamp: i64) -> PriceUpdate {
+        PriceUpdate {
+            symbol: "AAPL".into(),
+            price,
+            source: "Finnhub".into(),
+            timestamp,
+            is_mock: false,
+            produced_at: None,
+            broadcast_at: None,
+        }
+    }
+
+    fn delta_of(message: ServerMessage) -> Value {
+        match message {
+            ServerMessage::QuoteDelta(delta) => delta,
+            other => panic!("expected a delta, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn only_changed_fields_between_keyframes() {
+        let mut enc = DeltaEncoder::new(3);
+        assert!(matches!(enc.encode(&update(187.0, 1)), ServerMessage::Quote(_)));
+        assert_eq!(delta_of(enc.encode(&update(187.2, 1))), json!({"s": "AAPL", "p": 187.2}));
+        assert_eq!(delta_of(enc.encode(&update(187.2, 2))), json!({"s": "AAPL", "t": 2}));
+        // third update since the keyframe: full again
+        match enc.encode(&update(187.3, 3)) {
+            ServerMessage::Quote(q) => assert_eq!(q.price, 187.3),
+            other => panic!("expected a keyframe, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,358 @@
+use crate::candles::CandlePage;
+use crate::daily::DailyStats;
+use crate::health::FeedStats;
+use crate::indicators::IndicatorUpdate;
+use crate::protocol::{FeedEvent, PriceUpdate, TradeUpdate, WireFormat};
+use crate::session::SessionSummary;
+use crate::symbols::SymbolMeta;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use td_common::portfolio::PortfolioValue;
+
+/// Bumped on any breaking change of [`ServerMessage`].
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Every server message: `{"v":1,"type":"...","seq":n,"ts":ms,"data":{...}}`.
+/// `seq` numbers the messages of one connection from 1, `ts` is the server
+/// time in milliseconds since the epoch.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub v: u32,
+    #[serde(flatten)]
+    pub message: ServerMessage,
+    pub seq: u64,
+    pub ts: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum ServerMessage {
+    Connected { message: String },
+    Quote(PriceUpdate),
+    /// Delta-mode quote, only the changed fields (see `DeltaEncoder`).
+    QuoteDelta(serde_json::Value),
+    Trade(TradeUpdate),
+    Heartbeat { server_time: i64, seq: u64 },
+    HeartbeatConfig { interval_secs: Option<u64> },
+    /// Feed health, answer to `/stats`.
+    Stats(FeedStats),
+    /// The client fell behind the feed and `missed` updates were dropped.
+    Lagged { missed: u64 },
+    /// Answer to `SUB` / `UNSUB`: `filter` is `ALL`, `NONE` or `AAPL,MSFT`,
+    /// `symbols` the active set (empty for `ALL`).
+    Subscribed { filter: String, symbols: Vec<String> },
+    Symbols {
+        symbols: Vec<String>,
+        /// Name, exchange and currency of the symbols that have them.
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        details: BTreeMap<String, SymbolMeta>,
+    },
+    Delta { enabled: bool },
+    /// Encoding of the messages from this one on.
+    Format { format: WireFormat },
+    /// Source priority of the client, most preferred first; empty = off.
+    Prefer { sources: Vec<String> },
+    /// Whether the client gets `portfolio_update` messages.
+    Portfolio { enabled: bool },
+    /// Value and P&L of the server's portfolio after a quote of a held symbol.
+    PortfolioUpdate(PortfolioValue),
+    /// Indicators the client follows, as `sma:20 AAPL`; empty = none.
+    Indicators { subscriptions: Vec<String> },
+    IndicatorUpdate(IndicatorUpdate),
+    /// Answer to `{"action":"candles"}`.
+    Candles(CandlePage),
+    /// Whether the client gets `daily_stats` updates.
+    Daily { enabled: bool },
+    /// Today's open, high, low and last of a symbol, on request or on each
+    /// of its quotes after `DAILY ON`.
+    DailyStats(DailyStats),
+    Authenticated,
+    SubscriptionsRestored { subscriptions: Vec<String> },
+    SessionSummary(SessionSummary),
+    /// Commands sent too fast are dropped; the next one is accepted after
+    /// `retry_after_ms`.
+    Throttled { retry_after_ms: u64 },
+    /// Groups the client is in, after `JOIN` / `LEAVE`.
+    Groups { groups: Vec<String> },
+    /// Message of an operator to the client's group, or to everyone when
+    /// `group` is `None`.
+    Announcement { group: Option<String>, message: String },
+    /// Answer to `ANNOUNCE`: how many clients it was sent to.
+    Announced { group: Option<String>, recipients: usize },
+    Error { message: String },
+}
+
+impl ServerMessage {
+    pub fn error(message: impl Into<String>) -> Self {
+        ServerMessage::Error {
+            message: message.into(),
+        }
+    }
+
+    pub fn into_envelope(self, seq: u64) -> Envelope {
+        Envelope {
+            v: PROTOCOL_VERSION,
+            message: self,
+            seq,
+            ts: chrono::Utc::now().timestamp_millis(),
+        }
+    }
+}
+
+impl Envelope {
+    /// MessagePack with the field names, the same map as the JSON form.
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        rmp_serde::to_vec_named(self)
+    }
+}
+
+impl From<FeedEvent> for ServerMessage {
+    fn from(event: FeedEvent) -> Self {
+        match event {
+            FeedEvent::Quote(quote) => ServerMessage::Quote(quote),
+            FeedEvent::Trade(trade) => ServerMessage::Trade(trade),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::candles::Candle;
+    use crate::health::FeedMode;
+    use crate::protocol::Aggressor;
+    use td_common::portfolio::PositionValue;
+    use serde_json::json;
+
+    /// Kept as an exhaustive match so a new variant can't skip the tests.
+    fn type_of(message: &ServerMessage) -> &'static str {
+        match message {
+            ServerMessage::Connected { .. } => "connected",
+            ServerMessage::Quote(_) => "quote",
+            ServerMessage::QuoteDelta(_) => "quote_delta",
+            ServerMessage::Trade(_) => "trade",
+            ServerMessage::Heartbeat { .. } => "heartbeat",
+            ServerMessage::HeartbeatConfig { .. } => "heartbeat_config",
+            ServerMessage::Stats(_) => "stats",
+            ServerMessage::Lagged { .. } => "lagged",
+            ServerMessage::Subscribed { .. } => "subscribed",
+            ServerMessage::Symbols { .. } => "symbols",
+            ServerMessage::Delta { .. } => "delta",
+            ServerMessage::Format { .. } => "format",
+            ServerMessage::Prefer { .. } => "prefer",
+            ServerMessage::Portfolio { .. } => "portfolio",
+            ServerMessage::PortfolioUpdate(_) => "portfolio_update",
+            ServerMessage::Indicators { .. } => "indicators",
+            ServerMessage::IndicatorUpdate(_) => "indicator_update",
+            ServerMessage::Candles(_) => "candles",
+            ServerMessage::Daily { .. } => "daily",
+            ServerMessage::DailyStats(_) => "daily_stats",
+            ServerMessage::Authenticated => "authenticated",
+            ServerMessage::SubscriptionsRestored { .. } => "subscriptions_restored",
+            ServerMessage::SessionSummary(_) => "session_summary",
+            ServerMessage::Throttled { .. } => "throttled",
+            ServerMessage::Groups { .. } => "groups",
+            ServerMessage::Announcement { .. } => "announcement",
+            ServerMessage::Announced { .. } => "announced",
+            ServerMessage::Error { .. } => "error",
+        }
+    }
+
+    fn all_messages() -> Vec<ServerMessage> {
+        vec![
+            ServerMessage::Connected {
+                message: "hi".into(),
+            },
+            ServerMessage::Quote(PriceUpdate {
+                symbol: "AAPL".into(),
+                price: 187.2,
+                source: "Finnhub".into(),
+                timestamp: 1,
+                is_mock: false,
+                produced_at: Some(1_000),
+                broadcast_at: Some(1_005),
+            }),
+            ServerMessage::QuoteDelta(json!({"s": "AAPL", "p": 187.3})),
+            ServerMessage::Trade(TradeUpdate {
+                symbol: "AAPL".into(),
+                price: 187.25,
+                size: 100,
+                aggressor: Aggressor::Buy,
+                timestamp: 1,
+                is_mock: true,
+            }),
+            ServerMessage::Heartbeat {
+                server_time: 1_000,
+                seq: 3,
+            },
+            ServerMessage::HeartbeatConfig { interval_secs: None },
+            ServerMessage::Stats(FeedStats {
+                active_clients: 2,
+                uptime_secs: 3600,
+                feed: FeedMode::Db,
+                updates_last_minute: 24,
+                last_update_age_ms: BTreeMap::from([("AAPL".to_string(), 1200)]),
+                dropped: 0,
+                lag_events: 0,
+            }),
+            ServerMessage::Lagged { missed: 12 },
+            ServerMessage::Subscribed {
+                filter: "AAPL,MSFT".into(),
+                symbols: vec!["AAPL".into(), "MSFT".into()],
+            },
+            ServerMessage::Symbols {
+                symbols: vec!["AAPL".into(), "MSFT".into()],
+                details: BTreeMap::from([(
+                    "AAPL".to_string(),
+                    SymbolMeta {
+                        name: Some("Apple Inc".into()),
+                        exchange: Some("NASDAQ".into()),
+                        currency: Some("USD".into()),
+                    },
+                )]),
+            },
+            ServerMessage::Delta { enabled: true },
+            ServerMessage::Format {
+                format: WireFormat::Msgpack,
+            },
+            ServerMessage::Prefer {
+                sources: vec!["finnhub".into(), "yahoo".into()],
+            },
+            ServerMessage::Portfolio { enabled: true },
+            ServerMessage::PortfolioUpdate(PortfolioValue {
+                positions: vec![PositionValue {
+                    symbol: "AAPL".into(),
+                    quantity: 10.0,
+                    cost_basis: 150.0,
+                    price: Some(165.0),
+                    market_value: Some(1650.0),
+                    pnl: Some(150.0),
+                }],
+                cost: 1500.0,
+                market_value: 1650.0,
+                pnl: 150.0,
+                pnl_pct: 10.0,
+            }),
+            ServerMessage::Indicators {
+                subscriptions: vec!["sma:20 AAPL".into()],
+            },
+            ServerMessage::IndicatorUpdate(IndicatorUpdate {
+                symbol: "AAPL".into(),
+                indicator: "sma:20".into(),
+                value: 186.4,
+                price: 187.2,
+                timestamp: 1,
+            }),
+            ServerMessage::Candles(CandlePage {
+                symbol: "AAPL".into(),
+                interval_secs: 60,
+                candles: vec![Candle {
+                    bucket_start: 1_700_000_040,
+                    open: 187.1,
+                    high: 187.5,
+                    low: 186.9,
+                    close: 187.2,
+                    samples: 3,
+                }],
+                next_cursor: Some(1_700_000_040),
+            }),
+            ServerMessage::Daily { enabled: true },
+            ServerMessage::DailyStats(DailyStats {
+                symbol: "AAPL".into(),
+                date: "2024-03-11".into(),
+                open: 186.0,
+                high: 188.0,
+                low: 185.5,
+                last: 187.2,
+                prev_close: Some(185.0),
+                change_pct: 1.19,
+                timestamp: 1,
+            }),
+            ServerMessage::Authenticated,
+            ServerMessage::SubscriptionsRestored {
+                subscriptions: vec!["MSFT".into()],
+            },
+            ServerMessage::SessionSummary(SessionSummary {
+                client: "127.0.0.1:9000".into(),
+                duration_ms: 10,
+                messages_sent: 1,
+                messages_received: 0,
+                subscriptions: vec![],
+                dropped: 0,
+            }),
+            ServerMessage::Throttled { retry_after_ms: 50 },
+            ServerMessage::Groups {
+                groups: vec!["desk-a".into()],
+            },
+            ServerMessage::Announcement {
+                group: None,
+                message: "maintenance in 5 minutes".into(),
+            },
+            ServerMessage::Announced {
+                group: Some("desk-a".into()),
+                recipients: 3,
+            },
+            ServerMessage::error("unknown symbol FOO"),
+        ]
+    }
+
+    #[test]
+    fn every_message_uses_the_envelope_and_round_trips() {
+        for (i, message) in all_messages().into_iter().enumerate() {
+            let expected_type = type_of(&message);
+            let json = serde_json::to_value(message.into_envelope(i as u64 + 1)).unwrap();
+
+            assert_eq!(json["v"], PROTOCOL_VERSION);
+            assert_eq!(json["type"], expected_type);
+            assert_eq!(json["seq"], i as u64 + 1);
+            assert!(json["ts"].as_i64().unwrap() > 0);
+
+            let back: Envelope = serde_json::from_value(json.clone()).unwrap();
+            assert_eq!(type_of(&back.message), expected_type);
+            assert_eq!(serde_json::to_value(back).unwrap(), json);
+        }
+    }
+
+    #[test]
+    fn msgpack_envelopes_round_trip_and_are_smaller() {
+        for (i, message) in all_messages().into_iter().enumerate() {
+            let envelope = message.into_envelope(i as u64 + 1);
+            let bytes = envelope.to_msgpack().unwrap();
+            let back: Envelope = rmp_serde::from_slice(&bytes).unwrap();
+            assert_eq!(serde_json::to_value(back).unwrap(), serde_json::to_value(&envelope).unwrap());
+        }
+
+        let quote = all_messages().swap_remove(1).into_envelope(1);
+        let json = serde_json::to_vec(&quote).unwrap();
+        assert!(quote.to_msgpack().unwrap().len() < json.len());
+    }
+
+    #[test]
+    fn payload_lives_under_data() {
+        let stats = FeedStats {
+            active_clients: 4,
+            uptime_secs: 90,
+            feed: FeedMode::Fake,
+            updates_last_minute: 30,
+            last_update_age_ms: BTreeMap::new(),
+            dropped: 1,
+            lag_events: 1,
+        };
+        let json = serde_json::to_value(ServerMessage::Stats(stats).into_envelope(7)).unwrap();
+        assert_eq!(
+            json["data"],
+            json!({
+                "active_clients": 4,
+                "uptime_secs": 90,
+                "feed": "fake",
+                "updates_last_minute": 30,
+                "last_update_age_ms": {},
+                "dropped": 1,
+                "lag_events": 1
+            })
+        );
+
+        let unit = serde_json::to_value(ServerMessage::Authenticated.into_envelope(1)).unwrap();
+        assert_eq!(unit["type"], "authenticated");
+    }
+}
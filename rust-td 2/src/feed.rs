@@ -0,0 +1,231 @@
+use crate::protocol::{Aggressor, FeedEvent, PriceUpdate, TradeUpdate};
+use crate::symbols::{SymbolMeta, SymbolMetadata};
+use log::{info, warn};
+use sqlx::postgres::PgPoolOptions;
+use sqlx::Row;
+use std::collections::HashMap;
+use tokio::sync::broadcast;
+use tokio::time::{interval, Duration, Instant};
+
+/// Symbols published by the simulated feed.
+pub const FAKE_SYMBOLS: [&str; 3] = ["AAPL", "GOOGL", "MSFT"];
+
+/// Each tick publishes one quote followed by 0 to 2 trades printed close to
+/// it, so demo UIs get both kinds of messages.
+pub async fn fake_price_poller(tx: broadcast::Sender<FeedEvent>) {
+    use rand::Rng;
+
+    let mut timer = interval(Duration::from_secs(2));
+    let symbols = FAKE_SYMBOLS;
+    let sources = ["alpha_vantage", "finnhub"];
+
+    loop {
+        timer.tick().await;
+
+        let mut rng = rand::thread_rng();
+        let symbol = symbols[rng.gen_range(0..symbols.len())];
+        let source = sources[rng.gen_range(0..sources.len())];
+        let price: f64 = rng.gen_range(100.0..200.0);
+
+        let update = PriceUpdate {
+            symbol: symbol.to_string(),
+            price,
+            source: source.to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+            is_mock: true,
+            produced_at: Some(chrono::Utc::now().timestamp_millis()),
+            broadcast_at: None,
+        };
+
+        info!("Broadcasting: {} @ {:.2} ({})", update.symbol, update.price, update.source);
+        let _ = tx.send(FeedEvent::Quote(update));
+
+        for _ in 0..rng.gen_range(0..=2) {
+            let aggressor = if rng.gen_bool(0.5) { Aggressor::Buy } else { Aggressor::Sell };
+            let trade = TradeUpdate {
+                symbol: symbol.to_string(),
+                price: price + rng.gen_range(-0.05..0.05),
+                size: rng.gen_range(1..=50) * 10,
+                aggressor,
+                timestamp: chrono::Utc::now().timestamp(),
+                is_mock: true,
+            };
+            let _ = tx.send(FeedEvent::Trade(trade));
+        }
+    }
+}
+
+/// Keeps only the rows newer than the last one seen for their (symbol, source).
+fn new_rows(
+    last_seen: &mut HashMap<(String, String), i64>,
+    rows: Vec<PriceUpdate>,
+) -> Vec<PriceUpdate> {
+    rows.into_iter()
+        .filter(|row| {
+            let key = (row.symbol.clone(), row.source.clone());
+            match last_seen.get(&key) {
+                Some(&ts) if ts >= row.timestamp => false,
+                _ => {
+                    last_seen.insert(key, row.timestamp);
+                    true
+                }
+            }
+        })
+        .collect()
+}
+
+/// Reloads the `symbols` table written by the TD 1 enrichment.
+async fn load_metadata(pool: &sqlx::Pool<sqlx::Postgres>, metadata: &SymbolMetadata) -> Result<(), sqlx::Error> {
+    let rows = sqlx::query("SELECT symbol, name, exchange, currency FROM symbols")
+        .fetch_all(pool)
+        .await?;
+    for row in rows {
+        let symbol: String = row.try_get("symbol")?;
+        let meta = SymbolMeta {
+            name: row.try_get("name")?,
+            exchange: row.try_get("exchange")?,
+            currency: row.try_get("currency")?,
+        };
+        metadata.set(&symbol, meta);
+    }
+    Ok(())
+}
+
+/// Polls the latest row per (symbol, source) every 5s and broadcasts only
+/// the new ones. With `rebroadcast` set, the full latest snapshot is sent
+/// again at that interval so late joiners and idle UIs still get prices.
+/// Symbol metadata is reloaded every minute.
+pub async fn db_price_poller(
+    pool: sqlx::Pool<sqlx::Postgres>,
+    tx: broadcast::Sender<FeedEvent>,
+    rebroadcast: Option<Duration>,
+    metadata: SymbolMetadata,
+) {
+    let mut timer = interval(Duration::from_secs(5));
+    let mut last_seen = HashMap::new();
+    let mut last_snapshot = Instant::now();
+    let mut metadata_timer = interval(Duration::from_secs(60));
+    let mut metadata_warned = false;
+
+    loop {
+        tokio::select! {
+            _ = timer.tick() => {}
+            _ = metadata_timer.tick() => {
+                // Databases without the `symbols` migration just have no metadata.
+                if let Err(e) = load_metadata(&pool, &metadata).await {
+                    if !metadata_warned {
+                        warn!("Symbol metadata not loaded: {}", e);
+                        metadata_warned = true;
+                    }
+                }
+                continue;
+            }
+        }
+        // `price` is NUMERIC(10,2), which does not decode as f64.
+        let rows = sqlx::query(
+            r#"
+            SELECT DISTINCT ON (symbol, source)
+                symbol, price::float8 AS price, source, timestamp, is_mock,
+                (EXTRACT(EPOCH FROM created_at) * 1000)::bigint AS produced_at
+            FROM stock_prices
+            ORDER BY symbol, source, timestamp DESC
+            "#,
+        )
+        .fetch_all(&pool)
+        .await;
+
+        match rows {
+            Ok(rows) => {
+                let latest: Vec<PriceUpdate> = rows
+                    .iter()
+                    .map(|row| PriceUpdate {
+                        symbol: row.try_get("symbol").unwrap_or_default(),
+                        price: row.try_get("price").unwrap_or(0.0),
+                        source: row.try_get("source").unwrap_or_default(),
+                        timestamp: row.try_get("timestamp").unwrap_or_default(),
+                        is_mock: row.try_get("is_mock").unwrap_or_default(),
+                        // Insert time of the row: includes the polling delay.
+                        produced_at: row.try_get("produced_at").unwrap_or_default(),
+                        broadcast_at: None,
+                    })
+                    .collect();
+
+                let snapshot_due = rebroadcast.is_some_and(|every| last_snapshot.elapsed() >= every);
+                let fresh = new_rows(&mut last_seen, latest.clone());
+                let to_send = if snapshot_due {
+                    last_snapshot = Instant::now();
+                    latest
+                } else {
+                    fresh
+                };
+                for update in to_send {
+                    let _ = tx.send(FeedEvent::Quote(update));
+                }
+            }
+            Err(e) => {
+                warn!("DB poll failed: {}", e);
+            }
+        }
+    }
+}
+
+/// Polls `DATABASE_URL` when it is set and reachable, else simulates
+/// prices; returns the pool of the DB feed.
+pub async fn start_feed(
+    tx: broadcast::Sender<FeedEvent>,
+    rebroadcast: Option<Duration>,
+    metadata: SymbolMetadata,
+) -> Option<sqlx::PgPool> {
+    if let Ok(url) = std::env::var("DATABASE_URL") {
+        match PgPoolOptions::new().max_connections(5).connect(&url).await {
+            Ok(pool) => {
+                info!("Using DB feed (polling every 5s)");
+                let pool_clone = pool.clone();
+                let txc = tx.clone();
+                tokio::spawn(async move {
+                    db_price_poller(pool_clone, txc, rebroadcast, metadata).await;
+                });
+                return Some(pool);
+            }
+            Err(e) => {
+                warn!("Failed to connect DB, falling back to fake feed: {}", e);
+            }
+        }
+    } else {
+        info!("No DATABASE_URL set, using fake feed");
+    }
+
+    let txc = tx.clone();
+    tokio::spawn(async move {
+        fake_price_poller(txc).await;
+    });
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(symbol: &str, source: &str, timestamp: i64) -> PriceUpdate {
+        PriceUpdate {
+            symbol: symbol.into(),
+            price: 100.0,
+            source: source.into(),
+            timestamp,
+            is_mock: false,
+            produced_at: None,
+            broadcast_at: None,
+        }
+    }
+
+    #[test]
+    fn only_rows_newer_than_last_seen_pass() {
+        let mut seen = HashMap::new();
+        let first = new_rows(&mut seen, vec![row("AAPL", "Finnhub", 10), row("AAPL", "Yahoo", 10)]);
+        assert_eq!(first.len(), 2);
+
+        let again = new_rows(&mut seen, vec![row("AAPL", "Finnhub", 10), row("AAPL", "Yahoo", 11)]);
+        assert_eq!(again.len(), 1);
+        assert_eq!(again[0].source, "Yahoo");
+    }
+}
@@ -0,0 +1,496 @@
+//! Embeddable broadcaster: a WebSocket server fed by a channel of the
+//! caller, for the CLI, the `pipeline` fetcher or a test.
+//!
+//! ```no_run
+//! # async fn run() -> td_common::Result<()> {
+//! let (tx, _) = tokio::sync::broadcast::channel(100);
+//! ws_price_feed::FeedServer::builder().bind("127.0.0.1:8080").feed(tx).auth("s3cret").run().await
+//! # }
+//! ```
+
+use crate::groups::Groups;
+use crate::protocol::FeedEvent;
+use crate::server::{serve, ServerConfig};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use td_common::{Context, Result};
+use tokio::net::TcpListener;
+use tokio::sync::{broadcast, Mutex};
+use tokio_rustls::rustls::ServerConfig as TlsConfig;
+
+/// Capacity of the channel made when the builder gets no feed.
+const DEFAULT_CAPACITY: usize = 100;
+
+/// A bound server, ready to `run`.
+#[derive(Debug)]
+pub struct FeedServer {
+    listener: TcpListener,
+    tls: Option<Arc<TlsConfig>>,
+    tx: broadcast::Sender<FeedEvent>,
+    clients: Arc<Mutex<u32>>,
+    config: ServerConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct FeedServerBuilder {
+    addr: String,
+    /// PEM certificate chain and private key, for `wss://`.
+    tls: Option<(PathBuf, PathBuf)>,
+    tx: Option<broadcast::Sender<FeedEvent>>,
+    clients: Option<Arc<Mutex<u32>>>,
+    config: ServerConfig,
+}
+
+impl FeedServer {
+    pub fn builder() -> FeedServerBuilder {
+        FeedServerBuilder {
+            addr: "127.0.0.1:8080".to_string(),
+            tls: None,
+            tx: None,
+            clients: None,
+            config: ServerConfig::default(),
+        }
+    }
+
+    /// The bound address, with the real port when binding to port 0.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Where to send the updates the clients get.
+    pub fn sender(&self) -> broadcast::Sender<FeedEvent> {
+        self.tx.clone()
+    }
+
+    /// Client groups of the server, to announce to them from the process
+    /// (`groups().announce(Some("desk-a"), "...")`).
+    pub fn groups(&self) -> Groups {
+        self.config.groups.clone()
+    }
+
+    /// Accepts clients until the listener fails.
+    pub async fn run(self) {
+        serve(self.listener, self.tls.map(Into::into), self.tx, self.clients, self.config).await
+    }
+}
+
+impl FeedServerBuilder {
+    /// `host:port` to listen on (default `127.0.0.1:8080`).
+    pub fn bind(mut self, addr: impl Into<String>) -> Self {
+        self.addr = addr.into();
+        self
+    }
+
+    /// Serve `wss://` with this PEM certificate chain and private key; a
+    /// plain listener needs a server of its own.
+    pub fn tls(mut self, cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        self.tls = Some((cert.into(), key.into()));
+        self
+    }
+
+    /// The channel the updates come from; without one, the server makes its
+    /// own and `FeedServer::sender` hands it out.
+    pub fn feed(mut self, tx: broadcast::Sender<FeedEvent>) -> Self {
+        self.tx = Some(tx);
+        self
+    }
+
+    /// Only clients that `AUTH` with this key (or another one given here)
+    /// get data.
+    pub fn auth(mut self, key: impl Into<String>) -> Self {
+        self.config.api_keys.get_or_insert_with(Vec::new).push(key.into());
+        self
+    }
+
+    /// A key that may `ANNOUNCE` to the client groups; it is also accepted
+    /// by `AUTH`.
+    pub fn admin(mut self, key: impl Into<String>) -> Self {
+        self.config.admin_keys.push(key.into());
+        self
+    }
+
+    /// Close the clients that do not send a valid key, in the URL
+    /// (`?token=`) or as first message.
+    pub fn require_auth(mut self) -> Self {
+        self.config.require_auth = true;
+        self
+    }
+
+    /// Decline permessage-deflate: every client gets uncompressed frames.
+    pub fn no_compression(mut self) -> Self {
+        self.config.compression = false;
+        self
+    }
+
+    /// Connected client counter, to share it between several servers.
+    pub fn clients(mut self, clients: Arc<Mutex<u32>>) -> Self {
+        self.clients = Some(clients);
+        self
+    }
+
+    /// Every other setting; keys given to `auth` before are kept.
+    pub fn config(mut self, config: ServerConfig) -> Self {
+        let keys = self.config.api_keys.take();
+        self.config = config;
+        if let Some(keys) = keys {
+            self.config.api_keys.get_or_insert_with(Vec::new).extend(keys);
+        }
+        self
+    }
+
+    pub async fn build(self) -> Result<FeedServer> {
+        let tls = self.tls.as_ref().map(|(cert, key)| crate::tls::server_config(cert, key)).transpose()?;
+        let listener = TcpListener::bind(&self.addr)
+            .await
+            .with_context(|| format!("binding WebSocket listener on {}", self.addr))?;
+        Ok(FeedServer {
+            listener,
+            tls,
+            tx: self.tx.unwrap_or_else(|| broadcast::channel(DEFAULT_CAPACITY).0),
+            clients: self.clients.unwrap_or_default(),
+            config: self.config,
+        })
+    }
+
+    /// Binds and serves.
+    pub async fn run(self) -> Result<()> {
+        self.build().await?.run().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::envelope::{Envelope, ServerMessage};
+    use crate::protocol::{PriceUpdate, WireFormat};
+    use futures_util::{SinkExt, StreamExt};
+    use std::time::Duration;
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use tokio_tungstenite::tungstenite::Message;
+    use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+    async fn next_message(ws: &mut WebSocketStream<MaybeTlsStream<TcpStream>>) -> ServerMessage {
+        loop {
+            if let Message::Text(text) = ws.next().await.unwrap().unwrap() {
+                return serde_json::from_str::<Envelope>(&text).unwrap().message;
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn an_embedded_server_streams_to_authenticated_clients() {
+        let server = FeedServer::builder().bind("127.0.0.1:0").auth("k1").build().await.unwrap();
+        let (addr, tx) = (server.local_addr().unwrap(), server.sender());
+        tokio::spawn(server.run());
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr)).await.unwrap();
+        assert!(matches!(next_message(&mut ws).await, ServerMessage::Connected { .. }));
+        ws.send(Message::Text("AUTH k1".into())).await.unwrap();
+        assert!(matches!(next_message(&mut ws).await, ServerMessage::Authenticated));
+
+        tx.send(FeedEvent::Quote(PriceUpdate {
+            symbol: "AAPL".into(),
+            price: 187.0,
+            source: "Yahoo".into(),
+            timestamp: 1,
+            is_mock: false,
+            produced_at: None,
+            broadcast_at: None,
+        }))
+        .unwrap();
+        loop {
+            match next_message(&mut ws).await {
+                ServerMessage::Quote(quote) => break assert_eq!(quote.symbol, "AAPL"),
+                ServerMessage::Heartbeat { .. } => continue,
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn clients_without_a_key_are_closed_when_auth_is_required() {
+        let server = FeedServer::builder().bind("127.0.0.1:0").auth("k1").require_auth().build().await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/?token=k1", addr)).await.unwrap();
+        assert!(matches!(next_message(&mut ws).await, ServerMessage::Connected { .. }));
+        assert!(matches!(next_message(&mut ws).await, ServerMessage::Authenticated));
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr)).await.unwrap();
+        ws.send(Message::Text("AUTH k1".into())).await.unwrap();
+        assert!(matches!(next_message(&mut ws).await, ServerMessage::Connected { .. }));
+        assert!(matches!(next_message(&mut ws).await, ServerMessage::Authenticated));
+
+        for (url, first) in [(format!("ws://{}/?token=nope", addr), None), (format!("ws://{}", addr), Some("SUB ALL"))] {
+            let (mut ws, _) = tokio_tungstenite::connect_async(url).await.unwrap();
+            if let Some(first) = first {
+                ws.send(Message::Text(first.into())).await.unwrap();
+            }
+            match ws.next().await.unwrap().unwrap() {
+                Message::Close(Some(frame)) => assert_eq!(frame.code, CloseCode::Policy),
+                other => panic!("unexpected {:?}", other),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn a_mock_snapshot_does_not_hide_the_others() {
+        let (tx, _) = broadcast::channel(16);
+        let shards = crate::shard::ShardedFeed::start(tx.subscribe(), 2, 16);
+        let config = ServerConfig {
+            heartbeat: None,
+            include_mock: false,
+            known_symbols: vec!["AAPL".into(), "MSFT".into()],
+            shards: Some(shards.clone()),
+            ..ServerConfig::default()
+        };
+        let server = FeedServer::builder().bind("127.0.0.1:0").feed(tx.clone()).config(config).build().await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+
+        for (symbol, is_mock) in [("AAPL", true), ("MSFT", false)] {
+            tx.send(FeedEvent::Quote(PriceUpdate {
+                symbol: symbol.into(),
+                price: 100.0,
+                source: "Yahoo".into(),
+                timestamp: 1,
+                is_mock,
+                produced_at: None,
+                broadcast_at: None,
+            }))
+            .unwrap();
+        }
+        // both quotes cached before the client subscribes
+        while shards.subscribe("MSFT").await.unwrap().1.is_empty() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr)).await.unwrap();
+        ws.send(Message::Text("SUB AAPL,MSFT".into())).await.unwrap();
+        let snapshot = async {
+            loop {
+                match next_message(&mut ws).await {
+                    ServerMessage::Quote(quote) => break quote.symbol,
+                    ServerMessage::Connected { .. } | ServerMessage::Subscribed { .. } => continue,
+                    other => panic!("unexpected {:?}", other),
+                }
+            }
+        };
+        let symbol = tokio::time::timeout(Duration::from_secs(2), snapshot).await.expect("no MSFT snapshot");
+        assert_eq!(symbol, "MSFT");
+    }
+
+    #[tokio::test]
+    async fn clients_read_the_feed_only_once_authenticated() {
+        let server = FeedServer::builder().bind("127.0.0.1:0").auth("k1").require_auth().build().await.unwrap();
+        let (addr, tx) = (server.local_addr().unwrap(), server.sender());
+        tokio::spawn(server.run());
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}", addr)).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        let waiting = tx.receiver_count();
+        ws.send(Message::Text("AUTH k1".into())).await.unwrap();
+        assert!(matches!(next_message(&mut ws).await, ServerMessage::Connected { .. }));
+        assert_eq!(tx.receiver_count(), waiting + 1);
+    }
+
+    #[tokio::test]
+    async fn clients_that_stop_answering_pings_are_closed() {
+        let config = ServerConfig {
+            heartbeat: None,
+            ping: Some(Duration::from_millis(50)),
+            idle_timeout: Some(Duration::from_millis(300)),
+            ..ServerConfig::default()
+        };
+        let clients = Arc::new(Mutex::new(0));
+        let server = FeedServer::builder().bind("127.0.0.1:0").clients(clients.clone()).config(config);
+        let server = server.build().await.unwrap();
+        let url = format!("ws://{}", server.local_addr().unwrap());
+        tokio::spawn(server.run());
+
+        // Reading answers the pings; `idle` reads nothing until it is closed.
+        let (mut alive, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let (mut idle, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let until = tokio::time::Instant::now() + Duration::from_millis(600);
+        let mut pings = 0;
+        while let Ok(Some(Ok(message))) = tokio::time::timeout_at(until, alive.next()).await {
+            pings += matches!(message, Message::Ping(_)) as u32;
+        }
+        assert!(pings >= 5, "{} pings", pings);
+        assert_eq!(*clients.lock().await, 1);
+        let closed = async {
+            while let Some(Ok(message)) = idle.next().await {
+                if let Message::Close(_) = message {
+                    break;
+                }
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), closed).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn lagging_clients_are_told_and_resynced() {
+        let (tx, _) = broadcast::channel(4);
+        let config = ServerConfig {
+            heartbeat: None,
+            resync_on_lag: true,
+            ..ServerConfig::default()
+        };
+        let server = FeedServer::builder().bind("127.0.0.1:0").feed(tx.clone()).config(config).build().await.unwrap();
+        let url = format!("ws://{}", server.local_addr().unwrap());
+        tokio::spawn(server.run());
+        let (mut ws, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        assert!(matches!(next_message(&mut ws).await, ServerMessage::Connected { .. }));
+
+        // Without yielding, the handler can't keep up with the 4-slot channel.
+        for i in 0..20 {
+            tx.send(FeedEvent::Quote(PriceUpdate {
+                symbol: "AAPL".into(),
+                price: 100.0 + i as f64,
+                source: "Yahoo".into(),
+                timestamp: i,
+                is_mock: false,
+                produced_at: None,
+                broadcast_at: None,
+            }))
+            .unwrap();
+        }
+        assert!(matches!(next_message(&mut ws).await, ServerMessage::Lagged { missed: 16 }));
+        match next_message(&mut ws).await {
+            ServerMessage::Quote(quote) => assert_eq!(quote.price, 119.0),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        ws.send(Message::Text("/stats".into())).await.unwrap();
+        loop {
+            if let ServerMessage::Stats(stats) = next_message(&mut ws).await {
+                break assert_eq!((stats.dropped, stats.lag_events), (16, 1));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn msgpack_clients_get_binary_frames() {
+        let server = FeedServer::builder().bind("127.0.0.1:0").build().await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+
+        let (mut ws, _) = tokio_tungstenite::connect_async(format!("ws://{}/?format=msgpack", addr)).await.unwrap();
+        match ws.next().await.unwrap().unwrap() {
+            Message::Binary(bytes) => {
+                let envelope: Envelope = rmp_serde::from_slice(&bytes).unwrap();
+                assert!(matches!(envelope.message, ServerMessage::Connected { .. }));
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+        ws.send(Message::Text("FORMAT JSON".into())).await.unwrap();
+        let format = loop {
+            if let ServerMessage::Format { format } = next_message(&mut ws).await {
+                break format;
+            }
+        };
+        assert_eq!(format, WireFormat::Json);
+    }
+
+    #[tokio::test]
+    async fn clients_offering_deflate_get_compressed_frames() {
+        use crate::deflate::{Deflater, InflateStream, PerMessageDeflate};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        use tokio_tungstenite::tungstenite::protocol::Role;
+
+        // By hand, so that the welcome message is not read with the response
+        // before inflating starts.
+        async fn connect(addr: SocketAddr) -> (WebSocketStream<InflateStream<TcpStream>>, String) {
+            let mut tcp = TcpStream::connect(addr).await.unwrap();
+            let request = format!(
+                "GET / HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                 Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\nSec-WebSocket-Version: 13\r\n\
+                 Sec-WebSocket-Extensions: permessage-deflate; client_max_window_bits\r\n\r\n",
+                addr
+            );
+            tcp.write_all(request.as_bytes()).await.unwrap();
+            let mut response = Vec::new();
+            while !response.ends_with(b"\r\n\r\n") {
+                response.push(tcp.read_u8().await.unwrap());
+            }
+            let mut stream = InflateStream::new(tcp);
+            stream.enable();
+            let ws = WebSocketStream::from_raw_socket(stream, Role::Client, None).await;
+            (ws, String::from_utf8(response).unwrap().to_ascii_lowercase())
+        }
+
+        let server = FeedServer::builder().bind("127.0.0.1:0").build().await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+        let (mut ws, accepted) = connect(addr).await;
+        assert!(accepted.contains("sec-websocket-extensions: permessage-deflate\r\n"), "{}", accepted);
+        match ws.next().await.unwrap().unwrap() {
+            Message::Text(text) => assert!(text.contains("connected"), "{}", text),
+            other => panic!("unexpected {:?}", other),
+        }
+        // a compressed command, answered in (compressed) MessagePack
+        let mut deflater = Deflater::new(PerMessageDeflate::default());
+        ws.send(deflater.compress(Message::Text("FORMAT MSGPACK".into()))).await.unwrap();
+        let format = loop {
+            if let Message::Binary(bytes) = ws.next().await.unwrap().unwrap() {
+                if let ServerMessage::Format { format } = rmp_serde::from_slice::<Envelope>(&bytes).unwrap().message {
+                    break format;
+                }
+            }
+        };
+        assert_eq!(format, WireFormat::Msgpack);
+
+        let server = FeedServer::builder().bind("127.0.0.1:0").no_compression().build().await.unwrap();
+        let addr = server.local_addr().unwrap();
+        tokio::spawn(server.run());
+        let (_, declined) = connect(addr).await;
+        assert!(declined.starts_with("http/1.1 101") && !declined.contains("permessage-deflate"), "{}", declined);
+    }
+
+    #[tokio::test]
+    async fn announcements_reach_the_joined_clients() {
+        let server = FeedServer::builder().bind("127.0.0.1:0").auth("k1").admin("ops").build().await.unwrap();
+        let (addr, groups) = (server.local_addr().unwrap(), server.groups());
+        tokio::spawn(server.run());
+
+        let url = format!("ws://{}", addr);
+        let (mut desk, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        let (mut ops, _) = tokio_tungstenite::connect_async(&url).await.unwrap();
+        assert!(matches!(next_message(&mut desk).await, ServerMessage::Connected { .. }));
+        assert!(matches!(next_message(&mut ops).await, ServerMessage::Connected { .. }));
+
+        desk.send(Message::Text("JOIN desk-a".into())).await.unwrap();
+        assert!(matches!(next_message(&mut desk).await, ServerMessage::Error { .. }));
+        desk.send(Message::Text("AUTH k1".into())).await.unwrap();
+        assert!(matches!(next_message(&mut desk).await, ServerMessage::Authenticated));
+        desk.send(Message::Text("JOIN Desk-A".into())).await.unwrap();
+        match next_message(&mut desk).await {
+            ServerMessage::Groups { groups } => assert_eq!(groups, vec!["desk-a".to_string()]),
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // Only admin keys announce.
+        ops.send(Message::Text("AUTH k1".into())).await.unwrap();
+        assert!(matches!(next_message(&mut ops).await, ServerMessage::Authenticated));
+        ops.send(Message::Text("ANNOUNCE desk-a hello".into())).await.unwrap();
+        assert!(matches!(next_message(&mut ops).await, ServerMessage::Error { .. }));
+        ops.send(Message::Text("AUTH ops".into())).await.unwrap();
+        assert!(matches!(next_message(&mut ops).await, ServerMessage::Authenticated));
+        ops.send(Message::Text("ANNOUNCE desk-a maintenance in 5 minutes".into())).await.unwrap();
+        assert!(matches!(next_message(&mut ops).await, ServerMessage::Announced { recipients: 1, .. }));
+        match next_message(&mut desk).await {
+            ServerMessage::Announcement { group, message } => {
+                assert_eq!((group.as_deref(), message.as_str()), (Some("desk-a"), "maintenance in 5 minutes"))
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+
+        // From the process, to every client.
+        assert_eq!(groups.announce(None, "restart at 18:00"), 2);
+        assert!(matches!(next_message(&mut ops).await, ServerMessage::Announcement { group: None, .. }));
+    }
+}
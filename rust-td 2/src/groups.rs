@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+/// Announcements kept for a client that is slow to read them.
+const ANNOUNCEMENT_CAPACITY: usize = 64;
+
+/// Longest group name accepted by `JOIN`.
+pub const MAX_GROUP_LEN: usize = 32;
+
+/// Groups one client may be in at once.
+pub const MAX_GROUPS_PER_CLIENT: usize = 16;
+
+/// A message for the members of `group`, or every client when `None`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Announcement {
+    pub group: Option<String>,
+    pub message: String,
+}
+
+/// Named client groups (`desk-a`) and the announcements sent to them, shared
+/// by every client handler of the servers given the same handle. Names are
+/// case-insensitive, kept in lowercase.
+#[derive(Debug, Clone)]
+pub struct Groups {
+    tx: broadcast::Sender<Announcement>,
+    members: Arc<Mutex<BTreeMap<String, usize>>>,
+}
+
+impl Default for Groups {
+    fn default() -> Self {
+        Groups {
+            tx: broadcast::channel(ANNOUNCEMENT_CAPACITY).0,
+            members: Arc::default(),
+        }
+    }
+}
+
+/// The lowercase group name, or `None` when it isn't `[A-Za-z0-9_-]{1,32}`.
+pub fn group_name(name: &str) -> Option<String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_GROUP_LEN
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    valid.then(|| name.to_ascii_lowercase())
+}
+
+impl Groups {
+    /// Announcements of every group; each client keeps those it is in.
+    pub fn subscribe(&self) -> broadcast::Receiver<Announcement> {
+        self.tx.subscribe()
+    }
+
+    pub fn join(&self, group: &str) {
+        *self.members.lock().unwrap().entry(group.to_string()).or_default() += 1;
+    }
+
+    pub fn leave(&self, group: &str) {
+        let mut members = self.members.lock().unwrap();
+        if let Some(count) = members.get_mut(group) {
+            *count -= 1;
+            if *count == 0 {
+                members.remove(group);
+            }
+        }
+    }
+
+    pub fn members(&self, group: &str) -> usize {
+        self.members.lock().unwrap().get(group).copied().unwrap_or(0)
+    }
+
+    /// Groups with at least one member, with their member counts.
+    pub fn list(&self) -> Vec<(String, usize)> {
+        self.members.lock().unwrap().iter().map(|(g, n)| (g.clone(), *n)).collect()
+    }
+
+    /// Sends `message` to the members of `group` (every connected client
+    /// when `None`); returns how many clients it reaches.
+    pub fn announce(&self, group: Option<&str>, message: impl Into<String>) -> usize {
+        let group = group.map(str::to_ascii_lowercase);
+        let recipients = match &group {
+            Some(group) => self.members(group),
+            None => self.tx.receiver_count(),
+        };
+        if recipients > 0 {
+            let _ = self.tx.send(Announcement {
+                group,
+                message: message.into(),
+            });
+        }
+        recipients
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn announcements_reach_the_group_members() {
+        let groups = Groups::default();
+        let mut rx = groups.subscribe();
+        assert_eq!(group_name("Desk-A"), Some("desk-a".to_string()));
+        assert_eq!(group_name("desk a"), None);
+
+        groups.join("desk-a");
+        groups.join("desk-a");
+        assert_eq!(groups.announce(Some("DESK-A"), "maintenance in 5 minutes"), 2);
+        assert_eq!(rx.try_recv().unwrap().group.as_deref(), Some("desk-a"));
+        // Nobody left in the group: nothing is sent.
+        groups.leave("desk-a");
+        groups.leave("desk-a");
+        assert_eq!(groups.announce(Some("desk-a"), "anyone?"), 0);
+        assert!(rx.try_recv().is_err());
+        assert!(groups.list().is_empty());
+
+        assert_eq!(groups.announce(None, "restart at 18:00"), 1);
+        assert_eq!(rx.try_recv().unwrap().group, None);
+    }
+}
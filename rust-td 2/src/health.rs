@@ -0,0 +1,154 @@
+use crate::channel::ChannelMetrics;
+use crate::protocol::FeedEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::broadcast;
+
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Where the updates come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedMode {
+    Db,
+    #[default]
+    Fake,
+    /// Another instance's feed, through Redis.
+    Relay,
+    /// The fetcher of the `pipeline` process.
+    Pipeline,
+}
+
+impl fmt::Display for FeedMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FeedMode::Db => "DB feed",
+            FeedMode::Fake => "fake feed",
+            FeedMode::Relay => "Redis relay",
+            FeedMode::Pipeline => "pipeline fetcher",
+        })
+    }
+}
+
+/// Answer to `/stats`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeedStats {
+    pub active_clients: u32,
+    pub uptime_secs: u64,
+    pub feed: FeedMode,
+    pub updates_last_minute: u64,
+    /// Milliseconds since the last update of each symbol.
+    pub last_update_age_ms: BTreeMap<String, u64>,
+    /// Updates lost by lagging clients since startup.
+    pub dropped: u64,
+    /// Times a client fell behind the feed since startup.
+    #[serde(default)]
+    pub lag_events: u64,
+}
+
+#[derive(Debug)]
+struct Inner {
+    started: Instant,
+    /// Updates per second of the last minute, as (second since start, count).
+    per_second: VecDeque<(u64, u64)>,
+    last_update: HashMap<String, Instant>,
+}
+
+/// Liveness of the feed as seen by one server, fed from its broadcast channel.
+#[derive(Debug, Clone)]
+pub struct FeedHealth(Arc<Mutex<Inner>>);
+
+impl Default for FeedHealth {
+    fn default() -> Self {
+        FeedHealth::new(Instant::now())
+    }
+}
+
+impl FeedHealth {
+    pub fn new(started: Instant) -> Self {
+        FeedHealth(Arc::new(Mutex::new(Inner {
+            started,
+            per_second: VecDeque::new(),
+            last_update: HashMap::new(),
+        })))
+    }
+
+    pub fn record(&self, symbol: &str, now: Instant) {
+        let mut inner = self.0.lock().unwrap();
+        let second = now.duration_since(inner.started).as_secs();
+        match inner.per_second.back_mut() {
+            Some((last, count)) if *last == second => *count += 1,
+            _ => inner.per_second.push_back((second, 1)),
+        }
+        while inner.per_second.front().is_some_and(|(s, _)| s + RATE_WINDOW.as_secs() <= second) {
+            inner.per_second.pop_front();
+        }
+        inner.last_update.insert(symbol.to_uppercase(), now);
+    }
+
+    /// Uptime, rate and per-symbol ages at `now`, with the client count and
+    /// the lag counters of the channel.
+    pub fn stats(&self, now: Instant, feed: FeedMode, active_clients: u32, channel: &ChannelMetrics) -> FeedStats {
+        let inner = self.0.lock().unwrap();
+        let uptime = now.duration_since(inner.started);
+        let second = uptime.as_secs();
+        FeedStats {
+            active_clients,
+            uptime_secs: second,
+            feed,
+            updates_last_minute: inner
+                .per_second
+                .iter()
+                .filter(|(s, _)| s + RATE_WINDOW.as_secs() > second)
+                .map(|(_, count)| count)
+                .sum(),
+            last_update_age_ms: inner
+                .last_update
+                .iter()
+                .map(|(symbol, at)| (symbol.clone(), now.duration_since(*at).as_millis() as u64))
+                .collect(),
+            dropped: channel.dropped_total(),
+            lag_events: channel.lag_events_total(),
+        }
+    }
+
+    /// Records every update until the channel closes.
+    pub async fn track(self, mut rx: broadcast::Receiver<FeedEvent>) {
+        loop {
+            match rx.recv().await {
+                Ok(event) => self.record(event.symbol(), Instant::now()),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_cover_the_last_minute() {
+        let start = Instant::now();
+        let health = FeedHealth::new(start);
+        let at = |secs: u64| start + Duration::from_secs(secs);
+        health.record("aapl", at(1));
+        health.record("MSFT", at(1));
+        health.record("AAPL", at(30));
+        health.record("AAPL", at(70));
+
+        let channel = ChannelMetrics::default();
+        channel.record_lag(2);
+        let stats = health.stats(at(75), FeedMode::Db, 3, &channel);
+        assert_eq!(stats.uptime_secs, 75);
+        // The two updates of second 1 are over a minute old.
+        assert_eq!(stats.updates_last_minute, 2);
+        assert_eq!(stats.last_update_age_ms["AAPL"], 5_000);
+        assert_eq!(stats.last_update_age_ms["MSFT"], 74_000);
+        assert_eq!((stats.active_clients, stats.dropped, stats.lag_events), (3, 2, 1));
+    }
+}
@@ -0,0 +1,112 @@
+use crate::protocol::PriceUpdate;
+use serde::{Deserialize, Serialize};
+use td_common::indicators::{Indicator, IndicatorSpec};
+
+/// Indicators one client follows at most.
+pub const MAX_INDICATORS: usize = 16;
+
+/// A new value of a followed indicator, after a quote of its symbol.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndicatorUpdate {
+    pub symbol: String,
+    /// `sma:20`, `ema:12` or `rsi:14`.
+    pub indicator: String,
+    pub value: f64,
+    /// The quote that produced the value.
+    pub price: f64,
+    pub timestamp: i64,
+}
+
+/// The indicators a client follows, each fed the live quotes of its symbol.
+#[derive(Debug, Clone, Default)]
+pub struct IndicatorSubscriptions {
+    entries: Vec<(String, Indicator)>,
+}
+
+impl IndicatorSubscriptions {
+    /// `false` when the client already follows [`MAX_INDICATORS`]; following
+    /// the same indicator twice is a no-op.
+    pub fn add(&mut self, symbol: &str, spec: IndicatorSpec) -> bool {
+        if self.entries.iter().any(|(s, i)| s == symbol && i.spec() == spec) {
+            return true;
+        }
+        if self.entries.len() >= MAX_INDICATORS {
+            return false;
+        }
+        self.entries.push((symbol.to_string(), Indicator::new(spec)));
+        true
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// `sma:20 AAPL`, as echoed back to the client.
+    pub fn labels(&self) -> Vec<String> {
+        self.entries.iter().map(|(symbol, i)| format!("{} {}", i.spec(), symbol)).collect()
+    }
+
+    /// Feeds `quote` to the indicators of its symbol; the values past their
+    /// warm-up are returned.
+    pub fn update(&mut self, quote: &PriceUpdate) -> Vec<IndicatorUpdate> {
+        self.entries
+            .iter_mut()
+            .filter(|(symbol, _)| symbol.eq_ignore_ascii_case(&quote.symbol))
+            .filter_map(|(symbol, indicator)| {
+                indicator.update(quote.price).map(|value| IndicatorUpdate {
+                    symbol: symbol.clone(),
+                    indicator: indicator.spec().to_string(),
+                    value,
+                    price: quote.price,
+                    timestamp: quote.timestamp,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(symbol: &str, price: f64) -> PriceUpdate {
+        PriceUpdate {
+            symbol: symbol.into(),
+            price,
+            source: "Sim".into(),
+            timestamp: 1,
+            is_mock: true,
+            produced_at: None,
+            broadcast_at: None,
+        }
+    }
+
+    #[test]
+    fn only_quotes_of_the_symbol_feed_an_indicator() {
+        let mut subs = IndicatorSubscriptions::default();
+        assert!(subs.add("AAPL", IndicatorSpec::Sma(2)));
+        assert!(subs.add("AAPL", IndicatorSpec::Sma(2)));
+        assert_eq!(subs.labels(), vec!["sma:2 AAPL"]);
+
+        assert!(subs.update(&quote("AAPL", 10.0)).is_empty());
+        assert!(subs.update(&quote("MSFT", 99.0)).is_empty());
+        let updates = subs.update(&quote("AAPL", 12.0));
+        assert_eq!(updates.len(), 1);
+        assert_eq!((updates[0].indicator.as_str(), updates[0].value), ("sma:2", 11.0));
+    }
+
+    #[test]
+    fn a_client_follows_a_bounded_number_of_indicators() {
+        let mut subs = IndicatorSubscriptions::default();
+        for period in 1..=MAX_INDICATORS {
+            assert!(subs.add("AAPL", IndicatorSpec::Ema(period)));
+        }
+        assert!(!subs.add("AAPL", IndicatorSpec::Rsi(14)));
+        subs.clear();
+        assert!(subs.is_empty());
+    }
+}
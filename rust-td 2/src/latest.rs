@@ -0,0 +1,66 @@
+use crate::protocol::{FeedEvent, PriceUpdate, Subscription};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Latest quote of every (symbol, source) of the feed, to resync a client
+/// that fell behind.
+#[derive(Debug, Clone, Default)]
+pub struct LatestQuotes(Arc<Mutex<BTreeMap<(String, String), PriceUpdate>>>);
+
+impl LatestQuotes {
+    pub fn record(&self, quote: &PriceUpdate) {
+        let key = (quote.symbol.to_uppercase(), quote.source.clone());
+        self.0.lock().unwrap().insert(key, quote.clone());
+    }
+
+    /// The latest quotes of the symbols `filter` lets through, by symbol.
+    pub fn snapshot(&self, filter: &Subscription) -> Vec<PriceUpdate> {
+        let latest = self.0.lock().unwrap();
+        latest.iter().filter(|((symbol, _), _)| filter.matches(symbol)).map(|(_, quote)| quote.clone()).collect()
+    }
+
+    /// Records every quote until the channel closes; after a lag, the next
+    /// quotes of a symbol replace the missed ones.
+    pub async fn track(self, mut rx: broadcast::Receiver<FeedEvent>) {
+        loop {
+            match rx.recv().await {
+                Ok(FeedEvent::Quote(quote)) => self.record(&quote),
+                Ok(FeedEvent::Trade(_)) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quote(symbol: &str, source: &str, price: f64) -> PriceUpdate {
+        PriceUpdate {
+            symbol: symbol.into(),
+            price,
+            source: source.into(),
+            timestamp: 1,
+            is_mock: false,
+            produced_at: None,
+            broadcast_at: None,
+        }
+    }
+
+    #[test]
+    fn the_snapshot_keeps_the_last_quote_per_source() {
+        let latest = LatestQuotes::default();
+        latest.record(&quote("AAPL", "Yahoo", 187.0));
+        latest.record(&quote("AAPL", "Finnhub", 187.1));
+        latest.record(&quote("MSFT", "Yahoo", 410.0));
+        latest.record(&quote("aapl", "Yahoo", 187.4));
+
+        let aapl = latest.snapshot(&Subscription::symbol("AAPL"));
+        let prices: Vec<(&str, f64)> = aapl.iter().map(|q| (q.source.as_str(), q.price)).collect();
+        assert_eq!(prices, [("Finnhub", 187.1), ("Yahoo", 187.4)]);
+        assert_eq!(latest.snapshot(&Subscription::All).len(), 3);
+    }
+}
@@ -0,0 +1,63 @@
+//! Real-time stock price WebSocket server (TD 2): feeds (DB polling or
+//! simulator), protocol types and the per-client handler. `FeedServer`
+//! embeds it in-process: the binary is a thin CLI wrapper around it and the
+//! `pipeline` service runs it next to the fetcher.
+
+pub mod access_log;
+pub mod aliases;
+pub mod bridge;
+pub mod candles;
+pub mod channel;
+pub mod daily;
+pub mod deflate;
+pub mod delta;
+pub mod envelope;
+pub mod feed;
+pub mod feed_server;
+pub mod groups;
+pub mod health;
+pub mod indicators;
+pub mod latest;
+pub mod listener;
+pub mod outbox;
+pub mod priority;
+pub mod protocol;
+pub mod server;
+pub mod session;
+pub mod shard;
+pub mod slow_start;
+pub mod subscriptions;
+pub mod symbols;
+pub mod throttle;
+pub mod tls;
+
+pub use access_log::{AccessEntry, AccessLog};
+pub use aliases::{AliasSpec, SymbolAliases};
+pub use candles::{load_candles, page, parse_interval, Candle, CandlePage, CandleQuery};
+pub use channel::{report_channel, CapacityAdvisor, ChannelMetrics, ChannelSample};
+pub use daily::{DailyStats, DailyTracker};
+pub use deflate::{Deflater, InflateStream, PerMessageDeflate};
+pub use delta::DeltaEncoder;
+pub use envelope::{Envelope, ServerMessage, PROTOCOL_VERSION};
+pub use feed::{start_feed, FAKE_SYMBOLS};
+pub use feed_server::{FeedServer, FeedServerBuilder};
+pub use groups::{Announcement, Groups};
+pub use health::{FeedHealth, FeedMode, FeedStats};
+pub use indicators::{IndicatorSubscriptions, IndicatorUpdate, MAX_INDICATORS};
+pub use latest::LatestQuotes;
+pub use listener::ListenerSpec;
+pub use outbox::{Lane, Outbox};
+pub use priority::SourcePriority;
+pub use protocol::{
+    parse_action, parse_auth, parse_daily, parse_delta, parse_format, parse_format_query, parse_group, parse_heartbeat,
+    parse_indicator, parse_portfolio, parse_prefer, parse_subscription, parse_token_query, parse_unsubscription,
+    Aggressor, ClientAction, FeedEvent, GroupCmd, HeartbeatCmd, IndicatorCmd, PreferCmd, PriceUpdate, Subscription,
+    TradeUpdate, WireFormat,
+};
+pub use server::{handle_client, serve, ServerConfig, ServerState};
+pub use session::{SessionStats, SessionSummary};
+pub use shard::ShardedFeed;
+pub use slow_start::SlowStart;
+pub use subscriptions::SubscriptionStore;
+pub use symbols::{KnownSymbols, SymbolMeta, SymbolMetadata};
+pub use throttle::{CommandLimit, CommandThrottle};
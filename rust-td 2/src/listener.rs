@@ -0,0 +1,125 @@
+use crate::protocol::{parse_subscription, Subscription};
+use crate::server::ServerConfig;
+use std::str::FromStr;
+
+/// One `--listen` entry: `ADDR[,option=value...]`, e.g.
+/// `0.0.0.0:8080,mock=no,sub=AAPL,keys=k1|k2` or `127.0.0.1:9090,mock=yes`.
+///
+/// - `mock=yes|no`: forward simulated prices or not
+/// - `sub=ALL|SYMBOL`: filter applied until the client sends `SUB`; `sub=AAPL|MSFT`
+///   for a set of symbols
+/// - `keys=k1|k2`: only clients that `AUTH` with one of these keys get data
+/// - `auth=required|optional`: close clients that do not send a valid key
+///   (`?token=` in the URL or `AUTH` as first message)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenerSpec {
+    pub addr: String,
+    pub include_mock: Option<bool>,
+    pub default_subscription: Option<Subscription>,
+    pub api_keys: Option<Vec<String>>,
+    pub require_auth: Option<bool>,
+}
+
+impl ListenerSpec {
+    /// The server settings of this listener: `base` plus its own options.
+    pub fn config(&self, base: &ServerConfig) -> ServerConfig {
+        let mut config = base.clone();
+        if let Some(include_mock) = self.include_mock {
+            config.include_mock = include_mock;
+        }
+        if let Some(sub) = &self.default_subscription {
+            config.default_subscription = sub.clone();
+        }
+        if let Some(keys) = &self.api_keys {
+            config.api_keys = Some(keys.clone());
+        }
+        if let Some(require_auth) = self.require_auth {
+            config.require_auth = require_auth;
+        }
+        config
+    }
+}
+
+impl FromStr for ListenerSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut parts = spec.split(',');
+        let addr = parts.next().unwrap_or_default().trim();
+        if addr.is_empty() {
+            return Err(format!("missing address in listener `{}`", spec));
+        }
+        let mut listener = ListenerSpec {
+            addr: addr.to_string(),
+            include_mock: None,
+            default_subscription: None,
+            api_keys: None,
+            require_auth: None,
+        };
+
+        for option in parts {
+            let (key, value) = option
+                .split_once('=')
+                .ok_or_else(|| format!("expected option=value, got `{}`", option))?;
+            match key.trim() {
+                "mock" => {
+                    listener.include_mock = Some(match value.trim() {
+                        "yes" | "true" => true,
+                        "no" | "false" => false,
+                        other => return Err(format!("mock must be yes or no, got `{}`", other)),
+                    })
+                }
+                "sub" => {
+                    // `,` separates the options: `sub=AAPL|MSFT` for a set
+                    let sub = parse_subscription(&format!("SUB {}", value.replace('|', ",")))
+                        .ok_or_else(|| format!("invalid default subscription `{}`", value))?;
+                    listener.default_subscription = Some(sub);
+                }
+                "keys" => {
+                    let keys: Vec<String> = value
+                        .split('|')
+                        .map(str::trim)
+                        .filter(|k| !k.is_empty())
+                        .map(String::from)
+                        .collect();
+                    listener.api_keys = Some(keys);
+                }
+                "auth" => {
+                    listener.require_auth = Some(match value.trim() {
+                        "required" => true,
+                        "optional" => false,
+                        other => return Err(format!("auth must be required or optional, got `{}`", other)),
+                    })
+                }
+                other => return Err(format!("unknown listener option `{}`", other)),
+            }
+        }
+        Ok(listener)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listener_spec_parses_options() {
+        let spec: ListenerSpec = "0.0.0.0:8080,mock=no,sub=aapl|msft,keys=k1|k2,auth=required".parse().unwrap();
+        assert_eq!(spec.addr, "0.0.0.0:8080");
+        assert_eq!(spec.include_mock, Some(false));
+        assert_eq!(spec.default_subscription, parse_subscription("SUB AAPL,MSFT"));
+        assert_eq!(spec.api_keys, Some(vec!["k1".to_string(), "k2".to_string()]));
+
+        let config = spec.config(&ServerConfig::default());
+        assert!(!config.include_mock);
+        assert!(config.require_auth);
+
+        let plain: ListenerSpec = "127.0.0.1:9090".parse().unwrap();
+        assert!(plain.config(&ServerConfig::default()).include_mock);
+
+        assert!("127.0.0.1:9090,mock=maybe".parse::<ListenerSpec>().is_err());
+        assert!("127.0.0.1:9090,auth=maybe".parse::<ListenerSpec>().is_err());
+        assert!("127.0.0.1:9090,colour=red".parse::<ListenerSpec>().is_err());
+        assert!(",mock=no".parse::<ListenerSpec>().is_err());
+    }
+}
@@ -1,284 +1,288 @@
+use clap::Parser;
 use env_logger::{Builder, Target};
-use futures_util::{SinkExt, StreamExt};
-use log::{error, info, warn, LevelFilter};
-use serde::{Deserialize, Serialize};
-use sqlx::postgres::PgPoolOptions;
-use sqlx::Row;
+use log::{info, LevelFilter};
+use std::path::PathBuf;
 use std::sync::Arc;
-use tokio::net::{TcpListener, TcpStream};
+use std::time::Duration;
+use td_common::portfolio::Portfolio;
+use td_common::{Error, Result};
 use tokio::sync::{broadcast, Mutex};
-use tokio::time::{interval, Duration};
-use tokio_tungstenite::{accept_async, tungstenite::Message};
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
-struct PriceUpdate {
-    symbol: String,
-    price: f64,
-    source: String,
-    timestamp: i64,
+use ws_price_feed::{
+    bridge, report_channel, start_feed, AccessLog, AliasSpec, ChannelMetrics, CommandLimit, FeedEvent, FeedMode,
+    FeedServer, ListenerSpec, ServerConfig, ShardedFeed, SlowStart, SymbolAliases, SymbolMetadata, FAKE_SYMBOLS,
+};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Listener as `ADDR[,mock=yes|no][,sub=ALL|SYM][,keys=k1|k2]`; repeat the
+    /// flag to serve several ports from the same feed
+    #[arg(long = "listen", value_name = "SPEC", default_value = "127.0.0.1:8080")]
+    listeners: Vec<ListenerSpec>,
+
+    /// TLS (`wss://`) listener, same SPEC as `--listen`; repeatable, served
+    /// next to the plain listeners with `--tls-cert` and `--tls-key`
+    #[arg(long = "tls-listen", value_name = "SPEC", requires_all = ["tls_cert", "tls_key"])]
+    tls_listeners: Vec<ListenerSpec>,
+
+    /// PEM certificate chain of the TLS listeners
+    #[arg(long, env = "WS_TLS_CERT", value_name = "PATH")]
+    tls_cert: Option<PathBuf>,
+
+    /// PEM private key of the TLS listeners
+    #[arg(long, env = "WS_TLS_KEY", value_name = "PATH")]
+    tls_key: Option<PathBuf>,
+
+    /// Resend the latest price of every (symbol, source) every N seconds,
+    /// even when the DB has nothing new (DB feed only)
+    #[arg(long, value_name = "SECS")]
+    rebroadcast_interval: Option<u64>,
+
+    /// Default heartbeat period in seconds (0 = off); clients can override
+    /// it with `HEARTBEAT <secs>` / `HEARTBEAT OFF`
+    #[arg(long, value_name = "SECS", default_value_t = 15)]
+    heartbeat_secs: u64,
+
+    /// Seconds between the WebSocket pings sent to every client (0 = off)
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    ping_secs: u64,
+
+    /// Close the clients that sent nothing, not even a pong, for this many
+    /// seconds (0 = never)
+    #[arg(long, value_name = "SECS", default_value_t = 90)]
+    idle_timeout_secs: u64,
+
+    /// Send the session summary to the client when the server closes it
+    #[arg(long)]
+    session_summary: bool,
+
+    /// JSON file keeping the subscriptions of clients that sent `AUTH <key>`
+    #[arg(long, value_name = "PATH")]
+    subscriptions_file: Option<PathBuf>,
+
+    /// In delta mode (`DELTA ON`), send a full update every N messages per symbol
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    delta_keyframe_every: u32,
+
+    /// Default source priority for quotes, most preferred first (e.g.
+    /// finnhub,yahoo); clients can change it with `PREFER <sources>`
+    #[arg(long, value_name = "SOURCES", value_delimiter = ',')]
+    prefer_sources: Vec<String>,
+
+    /// With a source priority, seconds during which a symbol's less
+    /// preferred sources are dropped after a forwarded quote
+    #[arg(long, value_name = "SECS", default_value_t = 5)]
+    dedup_window_secs: u64,
+
+    /// Capacity of the broadcast channel; a client more than this many
+    /// updates behind loses the oldest ones
+    #[arg(long, value_name = "N", default_value_t = 100)]
+    #[arg(value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    channel_capacity: usize,
+
+    /// Log the channel's lagged drops and max queue depth every N seconds (0 = off)
+    #[arg(long, value_name = "SECS", default_value_t = 60)]
+    channel_report_secs: u64,
+
+    /// Warn when the channel is saturated for this many reports in a row
+    #[arg(long, value_name = "N", default_value_t = 3)]
+    channel_warn_after: u32,
+
+    /// After telling a lagging client how many updates it missed, resend
+    /// the latest quotes of its symbols
+    #[arg(long)]
+    resync_on_lag: bool,
+
+    /// Never negotiate permessage-deflate (compressed frames for the
+    /// clients that offer it, e.g. browsers)
+    #[arg(long)]
+    no_compression: bool,
+
+    /// Paper-trading positions (TOML) valued live for the clients that send
+    /// `PORTFOLIO ON`
+    #[arg(long, value_name = "FILE")]
+    portfolio: Option<PathBuf>,
+
+    /// Relay the feed through Redis pub/sub, so every instance using the
+    /// same Redis and channel delivers the same updates
+    #[arg(long, value_name = "URL")]
+    redis_url: Option<String>,
+
+    /// Redis channel of the feed
+    #[arg(long, value_name = "NAME", default_value = bridge::DEFAULT_CHANNEL)]
+    redis_channel: String,
+
+    /// Run no feed, only deliver what other instances publish on Redis
+    #[arg(long, requires = "redis_url")]
+    relay_only: bool,
+
+    /// Split the per-symbol work (latest quotes, live candles, channels)
+    /// over N worker tasks by symbol hash (0 = off)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    shards: usize,
+
+    /// Log every client command with its outcome to this NDJSON file
+    #[arg(long, value_name = "PATH")]
+    access_log: Option<PathBuf>,
+
+    /// Rotate the access log past this size, keeping 5 old files
+    #[arg(long, value_name = "MB", default_value_t = 10)]
+    access_log_max_mb: u64,
+
+    /// Commands a client may send per second on average (0 = unlimited)
+    #[arg(long, value_name = "N", default_value_t = 20.0)]
+    command_rate: f64,
+
+    /// Commands a client may send at once before the rate applies
+    #[arg(long, value_name = "N", default_value_t = 40)]
+    command_burst: u32,
+
+    /// Keys allowed to `ANNOUNCE` to the client groups (also accepted by
+    /// `AUTH` on every listener)
+    #[arg(long, value_name = "KEYS", value_delimiter = ',')]
+    admin_keys: Vec<String>,
+
+    /// Close the clients that do not send a valid key, as `?token=KEY` in
+    /// the URL or `AUTH KEY` as first message (listener option `auth=`)
+    #[arg(long)]
+    require_auth: bool,
+
+    /// Messages per second a new connection starts at, doubled every second
+    /// until full speed (0 = no slow start)
+    #[arg(long, value_name = "N", default_value_t = 0.0)]
+    slow_start_rate: f64,
+
+    /// Seconds before a new connection gets full speed
+    #[arg(long, value_name = "SECS", default_value_t = 5)]
+    slow_start_secs: u64,
+
+    /// Symbol variants delivered and subscribed to under one name, as
+    /// `CANONICAL=VARIANT[,VARIANT...]` (e.g. GOOGL=GOOG,GOOGL.O); repeatable
+    #[arg(long = "alias", value_name = "SPEC")]
+    aliases: Vec<AliasSpec>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-enum Subscription {
-    All,
-    Symbol(String),
-}
-
-fn parse_subscription(cmd: &str) -> Option<Subscription> {
-    let trimmed = cmd.trim();
-    if trimmed.eq_ignore_ascii_case("SUB ALL") {
-        return Some(Subscription::All);
-    }
-    if let Some(rest) = trimmed.strip_prefix("SUB ") {
-        let sym = rest.trim().to_uppercase();
-        if !sym.is_empty() {
-            return Some(Subscription::Symbol(sym));
-        }
-    }
-    None
-}
-
-async fn handle_client(
-    stream: TcpStream,
-    mut rx: broadcast::Receiver<PriceUpdate>,
-    clients: Arc<Mutex<u32>>,
-) {
-    let addr = match stream.peer_addr() {
-        Ok(a) => a,
-        Err(_) => return,
-    };
+#[tokio::main]
+async fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let portfolio = cli.portfolio.as_deref().map(Portfolio::load).transpose()?;
+    let access_log = cli
+        .access_log
+        .as_ref()
+        .map(|path| AccessLog::open(path, cli.access_log_max_mb * 1024 * 1024))
+        .transpose()?;
 
-    // track active clients
-    {
-        let mut count = clients.lock().await;
-        *count += 1;
-        info!("Client connected: {} ({} active)", addr, *count);
-    }
+    Builder::new()
+        .target(Target::Stdout)
+        .filter_level(LevelFilter::Info)
+        .init();
 
-    let ws_stream = match accept_async(stream).await {
-        Ok(ws) => ws,
-        Err(e) => {
-            error!("WebSocket handshake failed for {}: {}", addr, e);
-            let mut count = clients.lock().await;
-            *count -= 1;
-            return;
-        }
+    // broadcast channel and client counter
+    let (tx, _rx) = broadcast::channel::<FeedEvent>(cli.channel_capacity);
+    let clients = Arc::new(Mutex::new(0u32));
+    let aliases = SymbolAliases::new(&cli.aliases).map_err(Error::parse)?;
+    // With aliases, the feed and Redis write upstream and the relay renames
+    // the symbols on their way to the clients and shards.
+    let upstream = if aliases.is_empty() {
+        tx.clone()
+    } else {
+        let (upstream, _) = broadcast::channel::<FeedEvent>(cli.channel_capacity);
+        tokio::spawn(aliases.clone().relay(upstream.subscribe(), tx.clone()));
+        upstream
     };
-
-    let (mut write, mut read) = ws_stream.split();
-
-    // welcome message
-    let welcome = serde_json::json!({
-        "type": "connected",
-        "message": "Connected to stock price feed"
-    });
-    if write
-        .send(Message::Text(welcome.to_string()))
-        .await
-        .is_err()
-    {
-        let mut count = clients.lock().await;
-        *count -= 1;
-        return;
+    let shards = (cli.shards > 0).then(|| ShardedFeed::start(tx.subscribe(), cli.shards, cli.channel_capacity));
+    let channel = ChannelMetrics::default();
+    if cli.channel_report_secs > 0 {
+        let every = Duration::from_secs(cli.channel_report_secs);
+        tokio::spawn(report_channel(channel.clone(), cli.channel_capacity, every, cli.channel_warn_after));
     }
 
-    // per-client filter: None = all, Some(sym) = only that symbol
-    let mut filter: Subscription = Subscription::All;
-
-    loop {
-        tokio::select! {
-            // broadcast path
-            Ok(update) = rx.recv() => {
-                match &filter {
-                    Subscription::All => {}
-                    Subscription::Symbol(sym) if &update.symbol != sym => continue,
-                    _ => {}
-                }
-
-                match serde_json::to_string(&update) {
-                    Ok(json) => {
-                        if write.send(Message::Text(json)).await.is_err() {
-                            info!("Client disconnected: {}", addr);
-                            break;
-                        }
-                    }
-                    Err(e) => warn!("Serialize error: {e}"),
-                }
-            }
-
-            // incoming messages
-            msg = read.next() => {
-                match msg {
-                    Some(Ok(Message::Text(t))) => {
-                        let trimmed = t.trim();
-                        if trimmed.eq_ignore_ascii_case("/stats") {
-                            let count = *clients.lock().await;
-                            let _ = write.send(Message::Text(format!(r#"{{"type":"stats","active_clients":{}}}"#, count))).await;
-                        } else if let Some(sub) = parse_subscription(trimmed) {
-                            filter = sub.clone();
-                            let label = match &filter {
-                                Subscription::All => "ALL".to_string(),
-                                Subscription::Symbol(s) => s.clone(),
-                            };
-                            let _ = write.send(Message::Text(format!(r#"{{"type":"subscribed","filter":"{}"}}"#, label))).await;
-                        } else {
-                            info!("Client {} says: {}", addr, trimmed);
-                        }
-                    }
-                    Some(Ok(Message::Close(_))) | None => {
-                        info!("Client closed connection: {}", addr);
-                        break;
-                    }
-                    Some(Err(e)) => {
-                        warn!("WebSocket error: {}", e);
-                        break;
-                    }
-                    _ => {}
-                }
+    // With Redis, the feed goes to Redis and the clients get what comes back.
+    let feed_tx = match &cli.redis_url {
+        Some(url) => {
+            let redis = redis::Client::open(url.as_str()).map_err(|e| Error::parse(format!("Redis URL: {}", e)))?;
+            let (feed_tx, _) = broadcast::channel::<FeedEvent>(cli.channel_capacity);
+            tokio::spawn(bridge::subscribe(redis.clone(), cli.redis_channel.clone(), upstream.clone()));
+            if !cli.relay_only {
+                tokio::spawn(bridge::publish(redis, cli.redis_channel.clone(), feed_tx.subscribe()));
             }
+            feed_tx
         }
-    }
-
-    // decrement active clients
-    {
-        let mut count = clients.lock().await;
-        *count -= 1;
-        info!("Client {} disconnected ({} active)", addr, *count);
-    }
-}
-
-async fn fake_price_poller(tx: broadcast::Sender<PriceUpdate>) {
-    use rand::Rng;
-
-    let mut timer = interval(Duration::from_secs(2));
-    let symbols = ["AAPL", "GOOGL", "MSFT"];
-    let sources = ["alpha_vantage", "finnhub"];
-
-    loop {
-        timer.tick().await;
-
-        let mut rng = rand::thread_rng();
-        let symbol = symbols[rng.gen_range(0..symbols.len())];
-        let source = sources[rng.gen_range(0..sources.len())];
-        let price: f64 = rng.gen_range(100.0..200.0);
+        None => upstream,
+    };
 
-        let update = PriceUpdate {
-            symbol: symbol.to_string(),
-            price,
-            source: source.to_string(),
-            timestamp: chrono::Utc::now().timestamp(),
-        };
+    // spawn producer (DB if available, else fake)
+    let rebroadcast = cli.rebroadcast_interval.map(Duration::from_secs);
+    let metadata = SymbolMetadata::default();
+    let db = if cli.relay_only { None } else { start_feed(feed_tx, rebroadcast, metadata.clone()).await };
+    let using_db = db.is_some();
+    let feed = match (cli.relay_only, using_db) {
+        (true, _) => FeedMode::Relay,
+        (false, true) => FeedMode::Db,
+        (false, false) => FeedMode::Fake,
+    };
 
-        info!("Broadcasting: {} @ {:.2} ({})", update.symbol, update.price, update.source);
-        let _ = tx.send(update);
-    }
-}
+    let base = ServerConfig {
+        heartbeat: (cli.heartbeat_secs > 0).then(|| Duration::from_secs(cli.heartbeat_secs)),
+        ping: (cli.ping_secs > 0).then(|| Duration::from_secs(cli.ping_secs)),
+        idle_timeout: (cli.idle_timeout_secs > 0).then(|| Duration::from_secs(cli.idle_timeout_secs)),
+        session_summary_to_client: cli.session_summary,
+        // The DB feed's symbols are learned from its first poll, a relay's
+        // from the first updates it gets.
+        known_symbols: if feed == FeedMode::Fake { FAKE_SYMBOLS.map(String::from).to_vec() } else { Vec::new() },
+        subscriptions_file: cli.subscriptions_file,
+        delta_keyframe_every: cli.delta_keyframe_every,
+        source_priority: (!cli.prefer_sources.is_empty()).then_some(cli.prefer_sources),
+        dedup_window: Duration::from_secs(cli.dedup_window_secs),
+        channel,
+        metadata,
+        portfolio,
+        db,
+        shards,
+        access_log,
+        feed,
+        command_limit: (cli.command_rate > 0.0).then(|| CommandLimit {
+            per_sec: cli.command_rate,
+            burst: cli.command_burst.max(1),
+            ..CommandLimit::default()
+        }),
+        admin_keys: cli.admin_keys,
+        require_auth: cli.require_auth,
+        resync_on_lag: cli.resync_on_lag,
+        compression: !cli.no_compression,
+        aliases,
+        slow_start: (cli.slow_start_rate > 0.0).then(|| SlowStart {
+            initial_rate: cli.slow_start_rate,
+            ramp: Duration::from_secs(cli.slow_start_secs),
+        }),
+        ..ServerConfig::default()
+    };
 
-async fn db_price_poller(pool: sqlx::Pool<sqlx::Postgres>, tx: broadcast::Sender<PriceUpdate>) {
-    let mut timer = interval(Duration::from_secs(5));
-
-    loop {
-        timer.tick().await;
-        let rows = sqlx::query(
-            r#"
-            SELECT DISTINCT ON (symbol, source)
-                symbol, price, source, timestamp
-            FROM stock_prices
-            ORDER BY symbol, source, timestamp DESC
-            "#,
-        )
-        .fetch_all(&pool)
-        .await;
-
-        match rows {
-            Ok(rows) => {
-                for row in rows {
-                    let update = PriceUpdate {
-                        symbol: row.try_get("symbol").unwrap_or_default(),
-                        price: row.try_get("price").unwrap_or(0.0),
-                        source: row.try_get("source").unwrap_or_default(),
-                        timestamp: row.try_get("timestamp").unwrap_or_default(),
-                    };
-                    let _ = tx.send(update);
-                }
-            }
-            Err(e) => {
-                warn!("DB poll failed: {}", e);
-            }
+    let tls = cli.tls_cert.zip(cli.tls_key);
+    let listeners = cli.listeners.iter().map(|spec| (spec, None));
+    let tls_listeners = cli.tls_listeners.iter().map(|spec| (spec, tls.clone()));
+    let mut servers = Vec::with_capacity(cli.listeners.len() + cli.tls_listeners.len());
+    for (spec, tls) in listeners.chain(tls_listeners) {
+        let config = spec.config(&base);
+        if config.require_auth && config.api_keys.is_none() && config.admin_keys.is_empty() {
+            return Err(Error::parse(format!("{}: auth required but no keys= nor --admin-keys", spec.addr)));
         }
-    }
-}
-
-async fn start_feed(tx: broadcast::Sender<PriceUpdate>) -> bool {
-    if let Ok(url) = std::env::var("DATABASE_URL") {
-        match PgPoolOptions::new().max_connections(5).connect(&url).await {
-            Ok(pool) => {
-                info!("Using DB feed (polling every 5s)");
-                let pool_clone = pool.clone();
-                let txc = tx.clone();
-                tokio::spawn(async move {
-                    db_price_poller(pool_clone, txc).await;
-                });
-                return true;
-            }
-            Err(e) => {
-                warn!("Failed to connect DB, falling back to fake feed: {}", e);
-            }
+        let mut builder = FeedServer::builder()
+            .bind(&spec.addr)
+            .feed(tx.clone())
+            .clients(clients.clone())
+            .config(config);
+        let scheme = if tls.is_some() { "wss" } else { "ws" };
+        if let Some((cert, key)) = tls {
+            builder = builder.tls(cert, key);
         }
-    } else {
-        info!("No DATABASE_URL set, using fake feed");
+        let server = builder.build().await?;
+        info!("WebSocket listening on {}://{} ({})", scheme, spec.addr, feed);
+        servers.push(tokio::spawn(server.run()));
     }
-
-    let txc = tx.clone();
-    tokio::spawn(async move {
-        fake_price_poller(txc).await;
-    });
-    false
-}
-
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    Builder::new()
-        .target(Target::Stdout)
-        .filter_level(LevelFilter::Info)
-        .init();
-
-    // broadcast channel and client counter
-    let (tx, _rx) = broadcast::channel::<PriceUpdate>(100);
-    let clients = Arc::new(Mutex::new(0u32));
-
-    // spawn producer (DB if available, else fake)
-    let using_db = start_feed(tx.clone()).await;
-
-    let listener = TcpListener::bind("127.0.0.1:8080").await?;
-    if using_db {
-        info!("WebSocket listening on ws://127.0.0.1:8080 (DB feed)");
-    } else {
-        info!("WebSocket listening on ws://127.0.0.1:8080 (fake feed)");
-    }
-
-    while let Ok((stream, _)) = listener.accept().await {
-        let rx = tx.subscribe();
-        let clients = clients.clone();
-        tokio::spawn(handle_client(stream, rx, clients));
+    for server in servers {
+        let _ = server.await;
     }
 
     Ok(())
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn parse_subscription_handles_all_and_symbol() {
-        assert_eq!(parse_subscription("SUB ALL"), Some(Subscription::All));
-        assert_eq!(
-            parse_subscription("SUB aapl"),
-            Some(Subscription::Symbol("AAPL".into()))
-        );
-        assert_eq!(parse_subscription("SUB  aapl   "), Some(Subscription::Symbol("AAPL".into())));
-        assert_eq!(parse_subscription("SUB"), None);
-        assert_eq!(parse_subscription("/stats"), None);
-    }
-}
@@ -0,0 +1,300 @@
+use crate::envelope::ServerMessage;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Notify;
+
+/// Live updates queued for a client before the oldest are dropped; quote
+/// deltas are never dropped (see `Outbox::push_to`).
+pub const MAX_LIVE_QUEUED: usize = 512;
+
+/// Lanes of a client's outbound queue, drained in this order: replies to
+/// commands and heartbeats first, then history and snapshots, then the feed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    Control,
+    Snapshot,
+    Live,
+}
+
+impl Lane {
+    pub fn of(message: &ServerMessage) -> Lane {
+        match message {
+            ServerMessage::Quote(_)
+            | ServerMessage::QuoteDelta(_)
+            | ServerMessage::Trade(_)
+            | ServerMessage::PortfolioUpdate(_)
+            | ServerMessage::IndicatorUpdate(_)
+            | ServerMessage::DailyStats(_) => Lane::Live,
+            ServerMessage::Candles(_) | ServerMessage::Symbols { .. } => Lane::Snapshot,
+            _ => Lane::Control,
+        }
+    }
+}
+
+/// A queued live message replaced by a newer one with the same key: the
+/// quote of a (symbol, source), an indicator, the portfolio value, the daily
+/// statistics of a symbol. Trades and deltas (relative to the previous one)
+/// are all kept.
+fn conflation_key(message: &ServerMessage) -> Option<String> {
+    match message {
+        ServerMessage::Quote(quote) => Some(format!("quote {} {}", quote.symbol, quote.source)),
+        ServerMessage::IndicatorUpdate(update) => Some(format!("indicator {} {}", update.indicator, update.symbol)),
+        ServerMessage::PortfolioUpdate(_) => Some("portfolio".to_string()),
+        ServerMessage::DailyStats(stats) => Some(format!("daily {}", stats.symbol)),
+        _ => None,
+    }
+}
+
+fn delta_symbol(message: &ServerMessage) -> Option<&str> {
+    match message {
+        ServerMessage::QuoteDelta(delta) => delta.get("s")?.as_str(),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Default)]
+struct Lanes {
+    control: VecDeque<ServerMessage>,
+    snapshot: VecDeque<ServerMessage>,
+    live: VecDeque<(Option<String>, ServerMessage)>,
+    sent: u64,
+    /// Live messages conflated or pushed out of a full queue.
+    dropped: u64,
+    closed: bool,
+}
+
+impl Lanes {
+    fn pop(&mut self) -> Option<ServerMessage> {
+        self.control
+            .pop_front()
+            .or_else(|| self.snapshot.pop_front())
+            .or_else(|| self.live.pop_front().map(|(_, message)| message))
+    }
+}
+
+/// Outbound queue of one client, filled by its handler and drained by its
+/// writer task, so a flood of live ticks can't hold back the answer to a
+/// command. A live message replaces the queued one it conflates, at the back
+/// of the queue.
+#[derive(Debug, Clone, Default)]
+pub struct Outbox {
+    lanes: Arc<Mutex<Lanes>>,
+    ready: Arc<Notify>,
+}
+
+impl Outbox {
+    /// Queues `message` on its lane; `false` once the client is gone.
+    pub fn push(&self, message: ServerMessage) -> bool {
+        self.push_to(Lane::of(&message), message)
+    }
+
+    /// A quote is a delta keyframe: the queued deltas of its symbol go with
+    /// the quote it conflates, they were relative to it or to an older one.
+    /// As each delta is relative to the previous one, a full queue drops its
+    /// oldest other message; deltas stay bounded by the keyframes.
+    pub fn push_to(&self, lane: Lane, message: ServerMessage) -> bool {
+        let mut lanes = self.lanes.lock().unwrap();
+        if lanes.closed {
+            return false;
+        }
+        match lane {
+            Lane::Control => lanes.control.push_back(message),
+            Lane::Snapshot => lanes.snapshot.push_back(message),
+            Lane::Live => {
+                let key = conflation_key(&message);
+                if key.is_some() {
+                    let keyframe = match &message {
+                        ServerMessage::Quote(quote) => Some(quote.symbol.as_str()),
+                        _ => None,
+                    };
+                    let before = lanes.live.len();
+                    lanes.live.retain(|(k, queued)| *k != key && (keyframe.is_none() || delta_symbol(queued) != keyframe));
+                    lanes.dropped += (before - lanes.live.len()) as u64;
+                }
+                if lanes.live.len() >= MAX_LIVE_QUEUED {
+                    let oldest = lanes.live.iter().position(|(_, queued)| !matches!(queued, ServerMessage::QuoteDelta(_)));
+                    if let Some(oldest) = oldest {
+                        lanes.live.remove(oldest);
+                        lanes.dropped += 1;
+                    }
+                }
+                lanes.live.push_back((key, message));
+            }
+        }
+        drop(lanes);
+        self.ready.notify_one();
+        true
+    }
+
+    /// Forgets the queued live messages, e.g. those of a previous filter.
+    pub fn clear_live(&self) {
+        self.lanes.lock().unwrap().live.clear();
+    }
+
+    /// Next message to send, waiting for one; `None` once closed and empty.
+    pub async fn next(&self) -> Option<ServerMessage> {
+        loop {
+            {
+                let mut lanes = self.lanes.lock().unwrap();
+                if let Some(message) = lanes.pop() {
+                    return Some(message);
+                }
+                if lanes.closed {
+                    return None;
+                }
+            }
+            self.ready.notified().await;
+        }
+    }
+
+    /// Counts a message the writer sent.
+    pub fn record_sent(&self) {
+        self.lanes.lock().unwrap().sent += 1;
+    }
+
+    pub fn sent(&self) -> u64 {
+        self.lanes.lock().unwrap().sent
+    }
+
+    pub fn dropped(&self) -> u64 {
+        self.lanes.lock().unwrap().dropped
+    }
+
+    /// No more pushes; the writer sends what is queued, then stops.
+    pub fn close(&self) {
+        self.lanes.lock().unwrap().closed = true;
+        self.ready.notify_one();
+    }
+
+    /// The client is gone: no more pushes and nothing left to send.
+    pub fn abandon(&self) {
+        let mut lanes = self.lanes.lock().unwrap();
+        lanes.closed = true;
+        lanes.control.clear();
+        lanes.snapshot.clear();
+        lanes.live.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::delta::DeltaEncoder;
+    use crate::protocol::PriceUpdate;
+    use std::collections::HashMap;
+
+    fn quote(symbol: &str, price: f64) -> ServerMessage {
+        ServerMessage::Quote(PriceUpdate {
+            symbol: symbol.into(),
+            price,
+            source: "Yahoo".into(),
+            timestamp: 1,
+            is_mock: false,
+            produced_at: None,
+            broadcast_at: None,
+        })
+    }
+
+    fn price(message: Option<ServerMessage>) -> f64 {
+        match message {
+            Some(ServerMessage::Quote(quote)) => quote.price,
+            other => panic!("expected a quote, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn replies_jump_the_live_queue_and_quotes_conflate() {
+        let outbox = Outbox::default();
+        outbox.push(quote("AAPL", 1.0));
+        outbox.push(quote("MSFT", 2.0));
+        outbox.push(quote("AAPL", 3.0));
+        outbox.push(ServerMessage::Symbols {
+            symbols: vec!["AAPL".into()],
+            details: Default::default(),
+        });
+        outbox.push(ServerMessage::Authenticated);
+
+        assert!(matches!(outbox.next().await, Some(ServerMessage::Authenticated)));
+        assert!(matches!(outbox.next().await, Some(ServerMessage::Symbols { .. })));
+        // AAPL's latest price goes behind MSFT.
+        assert_eq!(price(outbox.next().await), 2.0);
+        assert_eq!(price(outbox.next().await), 3.0);
+        assert_eq!(outbox.dropped(), 1);
+
+        outbox.push(quote("AAPL", 4.0));
+        outbox.close();
+        assert!(!outbox.push(quote("AAPL", 5.0)));
+        assert_eq!(price(outbox.next().await), 4.0);
+        assert!(outbox.next().await.is_none());
+    }
+
+    #[test]
+    fn a_full_live_queue_drops_the_oldest_but_not_deltas() {
+        let outbox = Outbox::default();
+        outbox.push(ServerMessage::QuoteDelta(serde_json::json!({ "s": "AAPL", "p": 1.0 })));
+        for i in 0..MAX_LIVE_QUEUED {
+            outbox.push(quote(&format!("S{}", i), 1.0));
+        }
+        assert_eq!(outbox.dropped(), 1);
+        let mut lanes = outbox.lanes.lock().unwrap();
+        assert!(matches!(lanes.pop(), Some(ServerMessage::QuoteDelta(_))));
+        match lanes.pop() {
+            Some(ServerMessage::Quote(quote)) => assert_eq!(quote.symbol, "S1"),
+            other => panic!("expected a quote, got {:?}", other),
+        }
+    }
+
+    /// The client side of delta mode: keyframes replace, deltas patch.
+    fn apply(book: &mut HashMap<String, PriceUpdate>, message: ServerMessage) {
+        match message {
+            ServerMessage::Quote(quote) => {
+                book.insert(quote.symbol.clone(), quote);
+            }
+            ServerMessage::QuoteDelta(delta) => {
+                let quote = book.get_mut(delta["s"].as_str().unwrap()).expect("a delta before its keyframe");
+                if let Some(price) = delta.get("p") {
+                    quote.price = price.as_f64().unwrap();
+                }
+                if let Some(timestamp) = delta.get("t") {
+                    quote.timestamp = timestamp.as_i64().unwrap();
+                }
+            }
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn a_slow_writer_keeps_the_delta_chain() {
+        let outbox = Outbox::default();
+        let mut encoder = DeltaEncoder::new(4);
+        let mut book = HashMap::new();
+        let mut last = HashMap::new();
+        // 65 updates per symbol: each ends on a keyframe, behind queued deltas
+        for i in 0..195i64 {
+            let symbol = ["AAPL", "MSFT", "TSLA"][(i % 3) as usize];
+            let quote = PriceUpdate {
+                symbol: symbol.into(),
+                price: 100.0 + i as f64,
+                source: "Yahoo".into(),
+                timestamp: i,
+                is_mock: false,
+                produced_at: None,
+                broadcast_at: None,
+            };
+            outbox.push(encoder.encode(&quote));
+            last.insert(symbol, quote);
+            // the writer only gets to send every seventh update
+            if i % 7 == 0 {
+                apply(&mut book, outbox.next().await.unwrap());
+            }
+        }
+        outbox.close();
+        while let Some(message) = outbox.next().await {
+            apply(&mut book, message);
+        }
+        assert!(outbox.dropped() > 0);
+        for (symbol, quote) in last {
+            assert_eq!((book[symbol].price, book[symbol].timestamp), (quote.price, quote.timestamp));
+        }
+    }
+}
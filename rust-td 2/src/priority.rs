@@ -0,0 +1,88 @@
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// `finnhub` matches `Finnhub`, `alpha_vantage` matches `AlphaVantage`.
+fn normalize(source: &str) -> String {
+    source
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Source preference for quotes (`prefer finnhub, fallback yahoo`). Within
+/// `window` after a quote of a symbol is forwarded, quotes of that symbol
+/// from a less preferred source are dropped; unlisted sources come last.
+#[derive(Debug, Clone)]
+pub struct SourcePriority {
+    order: Vec<String>,
+    window: Duration,
+    /// Per symbol: when the window started and the rank forwarded in it.
+    last: HashMap<String, (Instant, usize)>,
+}
+
+impl SourcePriority {
+    pub fn new<S: AsRef<str>>(order: &[S], window: Duration) -> Self {
+        SourcePriority {
+            order: order.iter().map(|s| normalize(s.as_ref())).collect(),
+            window,
+            last: HashMap::new(),
+        }
+    }
+
+    /// The sources, normalized, most preferred first.
+    pub fn sources(&self) -> &[String] {
+        &self.order
+    }
+
+    fn rank(&self, source: &str) -> usize {
+        let source = normalize(source);
+        self.order.iter().position(|s| *s == source).unwrap_or(self.order.len())
+    }
+
+    /// Whether a quote of `symbol` from `source` arriving at `now` is forwarded.
+    pub fn accept(&mut self, symbol: &str, source: &str, now: Instant) -> bool {
+        let rank = self.rank(source);
+        if let Some((since, forwarded)) = self.last.get(symbol) {
+            if now.duration_since(*since) < self.window && rank > *forwarded {
+                return false;
+            }
+        }
+        self.last.insert(symbol.to_string(), (now, rank));
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lesser_sources_are_dropped_inside_the_window() {
+        let mut priority = SourcePriority::new(&["finnhub", "yahoo"], Duration::from_secs(5));
+        let t0 = Instant::now();
+
+        assert!(priority.accept("AAPL", "Finnhub", t0));
+        assert!(!priority.accept("AAPL", "Yahoo", t0 + Duration::from_secs(1)));
+        assert!(!priority.accept("AAPL", "AlphaVantage", t0 + Duration::from_secs(2)));
+        assert!(priority.accept("AAPL", "finnhub", t0 + Duration::from_secs(3)));
+        assert!(priority.accept("MSFT", "Yahoo", t0 + Duration::from_secs(3)));
+        // No Finnhub quote for a whole window: the fallback goes through.
+        assert!(priority.accept("AAPL", "Yahoo", t0 + Duration::from_secs(9)));
+    }
+
+    #[test]
+    fn preferred_source_replaces_a_fallback() {
+        let mut priority = SourcePriority::new(&["finnhub", "yahoo"], Duration::from_secs(5));
+        let t0 = Instant::now();
+        assert!(priority.accept("AAPL", "Yahoo", t0));
+        assert!(priority.accept("AAPL", "Finnhub", t0 + Duration::from_secs(1)));
+        assert!(!priority.accept("AAPL", "Yahoo", t0 + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn source_names_are_normalized() {
+        assert_eq!(normalize("alpha_vantage"), normalize("AlphaVantage"));
+    }
+}
@@ -0,0 +1,565 @@
+use crate::candles::CandleQuery;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::time::Duration;
+use td_common::indicators::IndicatorSpec;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceUpdate {
+    pub symbol: String,
+    pub price: f64,
+    pub source: String,
+    pub timestamp: i64,
+    /// Simulated price, never a real quote.
+    #[serde(default)]
+    pub is_mock: bool,
+    /// When the producer (fetcher, DB row, simulator) made it, in ms since
+    /// the epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub produced_at: Option<i64>,
+    /// When the WebSocket server forwarded it to the client, in ms since the
+    /// epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub broadcast_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggressor {
+    Buy,
+    Sell,
+}
+
+/// An executed trade: `size` shares at `price`, initiated by `aggressor`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TradeUpdate {
+    pub symbol: String,
+    pub price: f64,
+    pub size: u64,
+    pub aggressor: Aggressor,
+    pub timestamp: i64,
+    #[serde(default)]
+    pub is_mock: bool,
+}
+
+/// What goes through the broadcast channel; on the wire the `type` field
+/// (`quote` or `trade`) tells them apart.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum FeedEvent {
+    Quote(PriceUpdate),
+    Trade(TradeUpdate),
+}
+
+impl FeedEvent {
+    pub fn symbol(&self) -> &str {
+        match self {
+            FeedEvent::Quote(q) => &q.symbol,
+            FeedEvent::Trade(t) => &t.symbol,
+        }
+    }
+
+    pub fn is_mock(&self) -> bool {
+        match self {
+            FeedEvent::Quote(q) => q.is_mock,
+            FeedEvent::Trade(t) => t.is_mock,
+        }
+    }
+}
+
+impl From<PriceUpdate> for FeedEvent {
+    fn from(update: PriceUpdate) -> Self {
+        FeedEvent::Quote(update)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Subscription {
+    All,
+    /// Only these symbols; empty after `UNSUB ALL`.
+    Symbols(BTreeSet<String>),
+}
+
+impl Subscription {
+    pub fn symbol(symbol: &str) -> Self {
+        Subscription::Symbols(BTreeSet::from([symbol.to_uppercase()]))
+    }
+
+    /// `ALL`, `NONE` or the symbols as `AAPL,MSFT`, as echoed back to clients.
+    pub fn label(&self) -> String {
+        match self {
+            Subscription::All => "ALL".to_string(),
+            Subscription::Symbols(symbols) if symbols.is_empty() => "NONE".to_string(),
+            Subscription::Symbols(symbols) => symbols.iter().cloned().collect::<Vec<_>>().join(","),
+        }
+    }
+
+    /// `["ALL"]` or the symbols, as persisted for `AUTH`enticated clients.
+    pub fn entries(&self) -> Vec<String> {
+        match self {
+            Subscription::All => vec!["ALL".to_string()],
+            Subscription::Symbols(symbols) => symbols.iter().cloned().collect(),
+        }
+    }
+
+    pub fn matches(&self, symbol: &str) -> bool {
+        match self {
+            Subscription::All => true,
+            Subscription::Symbols(symbols) => symbols.contains(symbol),
+        }
+    }
+
+    /// The symbol of a one-symbol set.
+    pub fn single(&self) -> Option<&str> {
+        match self {
+            Subscription::Symbols(symbols) if symbols.len() == 1 => symbols.first().map(String::as_str),
+            _ => None,
+        }
+    }
+
+    /// Whether everything `other` lets through also passes this one.
+    pub fn covers(&self, other: &Subscription) -> bool {
+        match (self, other) {
+            (Subscription::All, _) => true,
+            (Subscription::Symbols(_), Subscription::All) => false,
+            (Subscription::Symbols(ours), Subscription::Symbols(theirs)) => theirs.is_subset(ours),
+        }
+    }
+
+    /// `SUB`: the symbols join the set. From `ALL`, the first `SUB <SYM>`
+    /// narrows the feed to that symbol.
+    pub fn add(&mut self, sub: Subscription) {
+        match (&mut *self, sub) {
+            (Subscription::Symbols(ours), Subscription::Symbols(added)) => ours.extend(added),
+            (_, sub) => *self = sub,
+        }
+    }
+
+    /// `UNSUB`: the symbols leave the set, `UNSUB ALL` empties it. Single
+    /// symbols can't be taken out of `ALL`.
+    pub fn remove(&mut self, sub: &Subscription) -> Result<(), String> {
+        match (&mut *self, sub) {
+            (_, Subscription::All) => *self = Subscription::Symbols(BTreeSet::new()),
+            (Subscription::Symbols(ours), Subscription::Symbols(removed)) => ours.retain(|s| !removed.contains(s)),
+            (Subscription::All, Subscription::Symbols(_)) => {
+                return Err("subscribed to ALL, UNSUB ALL then SUB the symbols to keep".to_string())
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `<verb> ALL` or `<verb> AAPL,MSFT`.
+fn subscription_arg(cmd: &str, verb: &str) -> Option<Subscription> {
+    let (word, arg) = cmd.trim().split_once(' ')?;
+    if !word.eq_ignore_ascii_case(verb) {
+        return None;
+    }
+    let mut symbols = BTreeSet::new();
+    for symbol in arg.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        if symbol.eq_ignore_ascii_case("ALL") {
+            return Some(Subscription::All);
+        }
+        symbols.insert(symbol.to_uppercase());
+    }
+    (!symbols.is_empty()).then_some(Subscription::Symbols(symbols))
+}
+
+/// `SUB ALL` or `SUB AAPL,MSFT`.
+pub fn parse_subscription(cmd: &str) -> Option<Subscription> {
+    subscription_arg(cmd, "SUB")
+}
+
+/// `UNSUB ALL` or `UNSUB AAPL,MSFT`.
+pub fn parse_unsubscription(cmd: &str) -> Option<Subscription> {
+    subscription_arg(cmd, "UNSUB")
+}
+
+/// `AUTH <api-key>`: identifies the client so its subscriptions are kept.
+pub fn parse_auth(cmd: &str) -> Option<String> {
+    let mut parts = cmd.split_whitespace();
+    if !parts.next()?.eq_ignore_ascii_case("AUTH") {
+        return None;
+    }
+    let key = parts.next()?;
+    parts.next().is_none().then(|| key.to_string())
+}
+
+/// Value of `name` in the handshake URL's query string.
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query
+        .split('&')
+        .find_map(|pair| pair.split_once('=').filter(|(key, _)| *key == name))
+        .map(|(_, value)| value)
+        .filter(|value| !value.is_empty())
+}
+
+/// API key of the handshake URL's query string, `ws://host/?token=<key>`.
+pub fn parse_token_query(query: &str) -> Option<String> {
+    query_param(query, "token").map(String::from)
+}
+
+/// Encoding of the messages sent to a client: JSON in text frames, or the
+/// same envelopes in MessagePack in binary frames.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WireFormat {
+    #[default]
+    Json,
+    Msgpack,
+}
+
+impl WireFormat {
+    fn parse(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("json") {
+            Some(WireFormat::Json)
+        } else if name.eq_ignore_ascii_case("msgpack") {
+            Some(WireFormat::Msgpack)
+        } else {
+            None
+        }
+    }
+}
+
+/// `FORMAT JSON` / `FORMAT MSGPACK`.
+pub fn parse_format(cmd: &str) -> Option<WireFormat> {
+    let mut parts = cmd.split_whitespace();
+    if !parts.next()?.eq_ignore_ascii_case("FORMAT") {
+        return None;
+    }
+    let format = WireFormat::parse(parts.next()?)?;
+    parts.next().is_none().then_some(format)
+}
+
+/// `ws://host/?format=msgpack`.
+pub fn parse_format_query(query: &str) -> Option<WireFormat> {
+    query_param(query, "format").and_then(WireFormat::parse)
+}
+
+/// `<KEYWORD> ON` / `<KEYWORD> OFF`.
+fn parse_toggle(cmd: &str, keyword: &str) -> Option<bool> {
+    let mut parts = cmd.split_whitespace();
+    if !parts.next()?.eq_ignore_ascii_case(keyword) {
+        return None;
+    }
+    let enabled = match parts.next()? {
+        arg if arg.eq_ignore_ascii_case("ON") => true,
+        arg if arg.eq_ignore_ascii_case("OFF") => false,
+        _ => return None,
+    };
+    parts.next().is_none().then_some(enabled)
+}
+
+/// `DELTA ON` / `DELTA OFF`: toggles the delta wire mode.
+pub fn parse_delta(cmd: &str) -> Option<bool> {
+    parse_toggle(cmd, "DELTA")
+}
+
+/// `PORTFOLIO ON` / `PORTFOLIO OFF`: toggles the `portfolio_update` stream.
+pub fn parse_portfolio(cmd: &str) -> Option<bool> {
+    parse_toggle(cmd, "PORTFOLIO")
+}
+
+/// `DAILY ON` / `DAILY OFF`: toggles the `daily_stats` stream.
+pub fn parse_daily(cmd: &str) -> Option<bool> {
+    parse_toggle(cmd, "DAILY")
+}
+
+/// `INDICATOR sma:20 AAPL` / `INDICATOR OFF`: follows an indicator of a
+/// symbol, or stops them all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndicatorCmd {
+    Off,
+    Follow { spec: IndicatorSpec, symbol: String },
+}
+
+pub fn parse_indicator(cmd: &str) -> Option<IndicatorCmd> {
+    let mut parts = cmd.split_whitespace();
+    if !parts.next()?.eq_ignore_ascii_case("INDICATOR") {
+        return None;
+    }
+    let cmd = match (parts.next()?, parts.next()) {
+        (arg, None) if arg.eq_ignore_ascii_case("OFF") => IndicatorCmd::Off,
+        (spec, Some(symbol)) => IndicatorCmd::Follow {
+            spec: spec.parse().ok()?,
+            symbol: symbol.to_uppercase(),
+        },
+        _ => return None,
+    };
+    parts.next().is_none().then_some(cmd)
+}
+
+/// JSON commands: `{"action":"list_symbols"}`, `{"action":"candles",...}`,
+/// `{"action":"daily_stats"}`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum ClientAction {
+    ListSymbols,
+    /// A page of stored candles (see [`CandleQuery`]).
+    Candles(CandleQuery),
+    /// Today's statistics of `symbol`, or of every symbol without one.
+    DailyStats {
+        #[serde(default)]
+        symbol: Option<String>,
+    },
+}
+
+pub fn parse_action(cmd: &str) -> Option<ClientAction> {
+    serde_json::from_str(cmd.trim()).ok()
+}
+
+/// `HEARTBEAT <secs>` / `HEARTBEAT OFF`: per-client heartbeat override.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeartbeatCmd {
+    Off,
+    Every(Duration),
+}
+
+pub fn parse_heartbeat(cmd: &str) -> Option<HeartbeatCmd> {
+    let mut parts = cmd.split_whitespace();
+    if !parts.next()?.eq_ignore_ascii_case("HEARTBEAT") {
+        return None;
+    }
+    let arg = parts.next()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    if arg.eq_ignore_ascii_case("OFF") {
+        return Some(HeartbeatCmd::Off);
+    }
+    match arg.parse::<u64>().ok()? {
+        0 => Some(HeartbeatCmd::Off),
+        secs => Some(HeartbeatCmd::Every(Duration::from_secs(secs))),
+    }
+}
+
+/// `PREFER finnhub, fallback yahoo` / `PREFER OFF`: per-client source
+/// priority for quotes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PreferCmd {
+    Off,
+    Sources(Vec<String>),
+}
+
+pub fn parse_prefer(cmd: &str) -> Option<PreferCmd> {
+    let (verb, rest) = cmd.trim().split_once(char::is_whitespace)?;
+    if !verb.eq_ignore_ascii_case("PREFER") {
+        return None;
+    }
+    if rest.trim().eq_ignore_ascii_case("OFF") {
+        return Some(PreferCmd::Off);
+    }
+    let sources: Vec<String> = rest
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|s| !s.is_empty() && !s.eq_ignore_ascii_case("fallback"))
+        .map(str::to_string)
+        .collect();
+    (!sources.is_empty()).then_some(PreferCmd::Sources(sources))
+}
+
+/// `JOIN desk-a`, `LEAVE desk-a` / `LEAVE ALL` and, for admin keys,
+/// `ANNOUNCE desk-a <text>` / `ANNOUNCE ALL <text>`. Group names are checked
+/// by the server (see `groups::group_name`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GroupCmd {
+    Join(String),
+    /// `None` = every group of the client.
+    Leave(Option<String>),
+    /// `group: None` = every connected client.
+    Announce { group: Option<String>, message: String },
+}
+
+/// `ALL` or a group name.
+fn group_arg(arg: &str) -> Option<String> {
+    (!arg.eq_ignore_ascii_case("ALL")).then(|| arg.to_string())
+}
+
+pub fn parse_group(cmd: &str) -> Option<GroupCmd> {
+    let (verb, rest) = cmd.trim().split_once(char::is_whitespace)?;
+    let rest = rest.trim_start();
+    if verb.eq_ignore_ascii_case("ANNOUNCE") {
+        let (group, message) = rest.split_once(char::is_whitespace)?;
+        let message = message.trim();
+        return (!message.is_empty()).then(|| GroupCmd::Announce {
+            group: group_arg(group),
+            message: message.to_string(),
+        });
+    }
+    let mut parts = rest.split_whitespace();
+    let group = parts.next()?;
+    if parts.next().is_some() {
+        return None;
+    }
+    if verb.eq_ignore_ascii_case("JOIN") {
+        Some(GroupCmd::Join(group.to_string()))
+    } else if verb.eq_ignore_ascii_case("LEAVE") {
+        Some(GroupCmd::Leave(group_arg(group)))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_subscription_handles_all_and_symbol() {
+        assert_eq!(parse_subscription("SUB ALL"), Some(Subscription::All));
+        assert_eq!(
+            parse_subscription("SUB aapl"),
+            Some(Subscription::symbol("AAPL"))
+        );
+        assert_eq!(parse_subscription("SUB  aapl   "), Some(Subscription::symbol("AAPL")));
+        assert_eq!(parse_subscription("SUB"), None);
+        assert_eq!(parse_subscription("/stats"), None);
+    }
+
+    #[test]
+    fn subscription_sets_grow_and_shrink() {
+        let mut sub = Subscription::All;
+        sub.add(parse_subscription("SUB msft, aapl,").unwrap());
+        assert_eq!(sub.label(), "AAPL,MSFT");
+        sub.add(parse_subscription("SUB TSLA").unwrap());
+        assert!(sub.matches("TSLA") && !sub.matches("GOOGL"));
+        assert!(sub.covers(&Subscription::symbol("AAPL")) && !sub.covers(&Subscription::All));
+
+        sub.remove(&parse_unsubscription("UNSUB AAPL,MSFT").unwrap()).unwrap();
+        assert_eq!(sub.single(), Some("TSLA"));
+        assert_eq!(sub.entries(), vec!["TSLA".to_string()]);
+        sub.remove(&parse_unsubscription("UNSUB all").unwrap()).unwrap();
+        assert_eq!(sub.label(), "NONE");
+        assert!(!sub.matches("TSLA"));
+
+        let mut all = Subscription::All;
+        assert!(all.remove(&Subscription::symbol("AAPL")).is_err());
+        assert_eq!(parse_unsubscription("UNSUB ,"), None);
+        assert_eq!(parse_subscription("SUB AAPL,all"), Some(Subscription::All));
+    }
+
+    #[test]
+    fn feed_events_are_tagged_with_their_type() {
+        let trade = FeedEvent::Trade(TradeUpdate {
+            symbol: "AAPL".into(),
+            price: 187.5,
+            size: 100,
+            aggressor: Aggressor::Sell,
+            timestamp: 1,
+            is_mock: true,
+        });
+        let json = serde_json::to_value(&trade).unwrap();
+        assert_eq!(json["type"], "trade");
+        assert_eq!(json["aggressor"], "sell");
+
+        let quote: FeedEvent =
+            serde_json::from_str(r#"{"type":"quote","symbol":"AAPL","price":1.0,"source":"x","timestamp":1}"#).unwrap();
+        assert_eq!(quote.symbol(), "AAPL");
+        assert!(!quote.is_mock());
+    }
+
+    #[test]
+    fn parse_auth_takes_a_single_key() {
+        assert_eq!(parse_auth("AUTH abc123"), Some("abc123".into()));
+        assert_eq!(parse_auth("auth  abc123 "), Some("abc123".into()));
+        assert_eq!(parse_auth("AUTH"), None);
+        assert_eq!(parse_auth("AUTH a b"), None);
+
+        assert_eq!(parse_token_query("v=1&token=abc123"), Some("abc123".into()));
+        assert_eq!(parse_token_query("token="), None);
+        assert_eq!(parse_token_query("tokens=abc"), None);
+    }
+
+    #[test]
+    fn parse_format_from_command_or_url() {
+        assert_eq!(parse_format("FORMAT msgpack"), Some(WireFormat::Msgpack));
+        assert_eq!(parse_format("format JSON"), Some(WireFormat::Json));
+        assert_eq!(parse_format("FORMAT cbor"), None);
+        assert_eq!(parse_format("FORMAT"), None);
+        assert_eq!(parse_format_query("token=k1&format=MsgPack"), Some(WireFormat::Msgpack));
+        assert_eq!(parse_format_query("token=k1"), None);
+    }
+
+    #[test]
+    fn parse_delta_toggles() {
+        assert_eq!(parse_delta("DELTA ON"), Some(true));
+        assert_eq!(parse_delta("delta off"), Some(false));
+        assert_eq!(parse_delta("DELTA"), None);
+        assert_eq!(parse_delta("DELTA maybe"), None);
+        assert_eq!(parse_portfolio("portfolio on"), Some(true));
+        assert_eq!(parse_portfolio("DELTA ON"), None);
+        assert_eq!(parse_daily("DAILY off"), Some(false));
+    }
+
+    #[test]
+    fn parse_indicator_reads_spec_and_symbol() {
+        assert_eq!(
+            parse_indicator("INDICATOR sma:20 aapl"),
+            Some(IndicatorCmd::Follow { spec: IndicatorSpec::Sma(20), symbol: "AAPL".into() })
+        );
+        assert_eq!(parse_indicator("indicator off"), Some(IndicatorCmd::Off));
+        assert_eq!(parse_indicator("INDICATOR sma:20"), None);
+        assert_eq!(parse_indicator("INDICATOR vwap:5 AAPL"), None);
+        assert_eq!(parse_indicator("INDICATOR rsi:14 AAPL MSFT"), None);
+    }
+
+    #[test]
+    fn parse_action_reads_json_commands() {
+        assert_eq!(
+            parse_action(r#"{"action":"list_symbols"}"#),
+            Some(ClientAction::ListSymbols)
+        );
+        assert_eq!(
+            parse_action(r#"{"action":"candles","symbol":"AAPL","interval":"1m","cursor":120}"#),
+            Some(ClientAction::Candles(CandleQuery {
+                symbol: "AAPL".into(),
+                interval: "1m".into(),
+                from: None,
+                to: None,
+                cursor: Some(120),
+                limit: None,
+            }))
+        );
+        assert_eq!(parse_action(r#"{"action":"daily_stats"}"#), Some(ClientAction::DailyStats { symbol: None }));
+        assert_eq!(parse_action(r#"{"action":"candles","symbol":"AAPL"}"#), None);
+        assert_eq!(parse_action(r#"{"action":"dance"}"#), None);
+        assert_eq!(parse_action("SUB ALL"), None);
+    }
+
+    #[test]
+    fn parse_prefer_reads_the_natural_form() {
+        let expected = Some(PreferCmd::Sources(vec!["finnhub".into(), "yahoo".into()]));
+        assert_eq!(parse_prefer("PREFER finnhub, fallback yahoo"), expected);
+        assert_eq!(parse_prefer("prefer finnhub,yahoo"), expected);
+        assert_eq!(parse_prefer("PREFER off"), Some(PreferCmd::Off));
+        assert_eq!(parse_prefer("PREFER"), None);
+        assert_eq!(parse_prefer("PREFER fallback"), None);
+    }
+
+    #[test]
+    fn parse_heartbeat_handles_interval_and_off() {
+        assert_eq!(
+            parse_heartbeat("HEARTBEAT 5"),
+            Some(HeartbeatCmd::Every(Duration::from_secs(5)))
+        );
+        assert_eq!(parse_heartbeat("heartbeat off"), Some(HeartbeatCmd::Off));
+        assert_eq!(parse_heartbeat("HEARTBEAT 0"), Some(HeartbeatCmd::Off));
+        assert_eq!(parse_heartbeat("HEARTBEAT"), None);
+        assert_eq!(parse_heartbeat("HEARTBEAT soon"), None);
+        assert_eq!(parse_heartbeat("SUB ALL"), None);
+    }
+
+    #[test]
+    fn parse_group_reads_join_leave_and_announce() {
+        assert_eq!(parse_group("JOIN desk-A"), Some(GroupCmd::Join("desk-A".into())));
+        assert_eq!(parse_group("leave all"), Some(GroupCmd::Leave(None)));
+        assert_eq!(parse_group("LEAVE desk-a"), Some(GroupCmd::Leave(Some("desk-a".into()))));
+        assert_eq!(
+            parse_group("ANNOUNCE ALL  maintenance in 5 minutes "),
+            Some(GroupCmd::Announce { group: None, message: "maintenance in 5 minutes".into() })
+        );
+        assert_eq!(parse_group("JOIN"), None);
+        assert_eq!(parse_group("JOIN a b"), None);
+        assert_eq!(parse_group("ANNOUNCE desk-a"), None);
+        assert_eq!(parse_group("SUB ALL"), None);
+    }
+}
@@ -0,0 +1,1160 @@
+use crate::access_log::{redact, AccessEntry, AccessLog};
+use crate::aliases::SymbolAliases;
+use crate::candles::{load_candles, page, parse_interval};
+use crate::channel::ChannelMetrics;
+use crate::daily::{DailyStats, DailyTracker};
+use crate::deflate::{Deflater, InflateStream, PerMessageDeflate};
+use crate::delta::DeltaEncoder;
+use crate::health::{FeedHealth, FeedMode};
+use crate::envelope::ServerMessage;
+use crate::groups::{group_name, Groups, MAX_GROUPS_PER_CLIENT};
+use crate::indicators::{IndicatorSubscriptions, MAX_INDICATORS};
+use crate::latest::LatestQuotes;
+use crate::outbox::{Lane, Outbox};
+use crate::priority::SourcePriority;
+use crate::protocol::{
+    parse_action, parse_auth, parse_daily, parse_delta, parse_format, parse_format_query, parse_group, parse_heartbeat,
+    parse_indicator, parse_portfolio, parse_prefer, parse_subscription, parse_token_query, parse_unsubscription,
+    ClientAction, FeedEvent, GroupCmd, HeartbeatCmd, IndicatorCmd, PreferCmd, PriceUpdate, Subscription, WireFormat,
+};
+use crate::session::{SessionStats, SessionSummary};
+use crate::shard::{ShardedFeed, CANDLE_SECS};
+use crate::slow_start::{Pacer, SlowStart};
+use crate::subscriptions::SubscriptionStore;
+use crate::symbols::{KnownSymbols, SymbolMetadata};
+use crate::throttle::{CommandLimit, CommandThrottle, Verdict};
+use futures_util::{Sink, SinkExt, Stream, StreamExt};
+use log::{error, info, warn};
+use sqlx::PgPool;
+use std::collections::{BTreeSet, HashMap};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpListener;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, Mutex};
+use td_common::mask_key;
+use td_common::portfolio::Portfolio;
+use tokio::time::{interval_at, sleep_until, Instant, Interval, MissedTickBehavior};
+use tokio_rustls::TlsAcceptor;
+use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+use tokio_tungstenite::tungstenite::http::header::{HeaderValue, SEC_WEBSOCKET_EXTENSIONS};
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
+use tokio_tungstenite::tungstenite::{Error as WsError, Message};
+use tokio_tungstenite::{accept_hdr_async, WebSocketStream};
+
+/// How long a closing connection may take to send its queued messages.
+const WRITER_GRACE: Duration = Duration::from_secs(5);
+
+/// How long a client has to finish the TLS and WebSocket handshakes and,
+/// with `require_auth`, to send `AUTH <key>`.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Server-wide settings shared by every client handler.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    /// Default heartbeat period; clients may change or disable it with
+    /// `HEARTBEAT <secs>` / `HEARTBEAT OFF`. `None` = off by default.
+    pub heartbeat: Option<Duration>,
+    /// Period of the WebSocket pings sent to every client; `None` = no ping.
+    pub ping: Option<Duration>,
+    /// Close the clients that sent nothing (not even a pong) for this long;
+    /// `None` = never.
+    pub idle_timeout: Option<Duration>,
+    /// Also send the `session_summary` to the client when the server ends
+    /// the session (it is always logged).
+    pub session_summary_to_client: bool,
+    /// Symbols accepted by `SUB` before the feed has published them.
+    pub known_symbols: Vec<String>,
+    /// Where the subscriptions of `AUTH`enticated clients are kept.
+    pub subscriptions_file: Option<PathBuf>,
+    /// In delta mode, a full update is resent after this many deltas.
+    pub delta_keyframe_every: u32,
+    /// Forward simulated (`is_mock`) prices to the clients.
+    pub include_mock: bool,
+    /// Filter of a client that hasn't sent `SUB` yet.
+    pub default_subscription: Subscription,
+    /// When set, only clients that `AUTH` with one of these keys get data.
+    pub api_keys: Option<Vec<String>>,
+    /// Close the connection of clients without a valid key (`api_keys` or
+    /// `admin_keys`) in the handshake URL (`?token=`) or first message.
+    pub require_auth: bool,
+    /// Default source priority (most preferred first); clients may change
+    /// it with `PREFER <sources>` / `PREFER OFF`.
+    pub source_priority: Option<Vec<String>>,
+    /// With a source priority, how long a forwarded quote of a symbol keeps
+    /// the less preferred sources of that symbol out.
+    pub dedup_window: Duration,
+    /// Lag and queue depth counters of the broadcast channel.
+    pub channel: ChannelMetrics,
+    /// Names, exchanges and currencies listed by `list_symbols`.
+    pub metadata: SymbolMetadata,
+    /// Paper-trading positions streamed as `portfolio_update` to the
+    /// clients that send `PORTFOLIO ON`.
+    pub portfolio: Option<Portfolio>,
+    /// Where `{"action":"candles"}` reads the history; `None` without a DB.
+    pub db: Option<PgPool>,
+    /// Per-symbol shards: a client following one symbol reads that symbol's
+    /// channel instead of the whole feed.
+    pub shards: Option<ShardedFeed>,
+    /// Audit trail of every client command and its outcome.
+    pub access_log: Option<AccessLog>,
+    /// Where the updates come from, reported by `/stats`.
+    pub feed: FeedMode,
+    /// Budget of inbound commands per connection; `None` = unlimited.
+    pub command_limit: Option<CommandLimit>,
+    /// Named client groups and the announcements sent to them; servers
+    /// given the same handle share them.
+    pub groups: Groups,
+    /// Keys allowed to `ANNOUNCE`; they are also accepted by `AUTH`.
+    pub admin_keys: Vec<String>,
+    /// Paces the first seconds of each connection; `None` = full speed.
+    pub slow_start: Option<SlowStart>,
+    /// Symbol variants accepted by `SUB` and `INDICATOR` under their
+    /// canonical name; the feed is renamed upstream (`SymbolAliases::relay`).
+    pub aliases: SymbolAliases,
+    /// After a `lagged` notice, resend the latest quotes of the client's
+    /// symbols in place of the missed ones.
+    pub resync_on_lag: bool,
+    /// Compress the messages of the clients that offer permessage-deflate.
+    pub compression: bool,
+}
+
+/// State shared by all the client handlers of one server.
+#[derive(Debug, Clone, Default)]
+pub struct ServerState {
+    pub known: KnownSymbols,
+    pub subscriptions: Option<SubscriptionStore>,
+    pub health: FeedHealth,
+    pub daily: DailyTracker,
+    pub latest: LatestQuotes,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            heartbeat: Some(Duration::from_secs(15)),
+            ping: Some(Duration::from_secs(30)),
+            idle_timeout: Some(Duration::from_secs(90)),
+            session_summary_to_client: false,
+            known_symbols: Vec::new(),
+            subscriptions_file: None,
+            delta_keyframe_every: 20,
+            include_mock: true,
+            default_subscription: Subscription::All,
+            api_keys: None,
+            require_auth: false,
+            source_priority: None,
+            dedup_window: Duration::from_secs(5),
+            channel: ChannelMetrics::default(),
+            metadata: SymbolMetadata::default(),
+            portfolio: None,
+            db: None,
+            shards: None,
+            access_log: None,
+            feed: FeedMode::default(),
+            command_limit: Some(CommandLimit::default()),
+            groups: Groups::default(),
+            admin_keys: Vec::new(),
+            slow_start: None,
+            aliases: SymbolAliases::default(),
+            resync_on_lag: false,
+            compression: true,
+        }
+    }
+}
+
+fn heartbeat_timer(every: Option<Duration>) -> Option<Interval> {
+    every.map(|period| {
+        let mut timer = interval_at(Instant::now() + period, period);
+        timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        timer
+    })
+}
+
+/// `server_time` is in milliseconds since the epoch so clients can estimate
+/// their clock skew; `seq` lets them spot a missed beat.
+fn heartbeat_message(seq: u64) -> ServerMessage {
+    ServerMessage::Heartbeat {
+        server_time: chrono::Utc::now().timestamp_millis(),
+        seq,
+    }
+}
+
+/// Sends the queued messages of a client, each wrapped in the envelope
+/// numbered after the last one sent, in MessagePack while `msgpack` is set,
+/// compressed when the client negotiated permessage-deflate, and a ping every
+/// `ping`, then closes the connection. During a slow start, what piles up
+/// meanwhile is conflated in the outbox.
+async fn write_queued<S>(
+    mut write: S,
+    outbox: Outbox,
+    slow_start: Option<SlowStart>,
+    ping: Option<Duration>,
+    msgpack: Arc<AtomicBool>,
+    mut deflater: Option<Deflater>,
+) where
+    S: Sink<Message> + Unpin,
+{
+    let mut pacer = slow_start.map(|slow_start| Pacer::new(slow_start, Instant::now()));
+    let mut ping_timer = heartbeat_timer(ping);
+    loop {
+        let message = tokio::select! {
+            message = outbox.next() => message,
+            _ = async { ping_timer.as_mut().unwrap().tick().await }, if ping_timer.is_some() => {
+                if write.send(Message::Ping(Vec::new())).await.is_err() {
+                    outbox.abandon();
+                    return;
+                }
+                continue;
+            }
+        };
+        let Some(message) = message else {
+            break;
+        };
+        if let Some(at) = pacer.as_mut().and_then(|pacer| pacer.reserve(Instant::now())) {
+            tokio::time::sleep_until(at).await;
+        }
+        let envelope = message.into_envelope(outbox.sent() + 1);
+        let frame = if msgpack.load(Ordering::Relaxed) {
+            envelope.to_msgpack().map(Message::Binary).map_err(|e| e.to_string())
+        } else {
+            serde_json::to_string(&envelope).map(Message::Text).map_err(|e| e.to_string())
+        };
+        let frame = match (frame, deflater.as_mut()) {
+            (Ok(frame), Some(deflater)) => deflater.compress(frame),
+            (Ok(frame), None) => frame,
+            (Err(e), _) => {
+                warn!("Serialize error: {e}");
+                continue;
+            }
+        };
+        if write.send(frame).await.is_err() {
+            outbox.abandon();
+            return;
+        }
+        outbox.record_sent();
+    }
+    let _ = write.send(Message::Close(None)).await;
+}
+
+/// Next update of the whole feed (when followed) or of the client's symbol
+/// channel; `true` when it came from the whole feed.
+async fn next_event(
+    feed: &mut broadcast::Receiver<FeedEvent>,
+    follow_feed: bool,
+    symbol: &mut Option<broadcast::Receiver<FeedEvent>>,
+) -> (Result<FeedEvent, RecvError>, bool) {
+    match symbol {
+        None => (feed.recv().await, true),
+        Some(symbol) => tokio::select! {
+            res = feed.recv(), if follow_feed => (res, true),
+            res = symbol.recv() => (res, false),
+        },
+    }
+}
+
+/// A subscription under the symbols' canonical names.
+fn canonical(aliases: &SymbolAliases, sub: Subscription) -> Subscription {
+    match sub {
+        Subscription::Symbols(symbols) => {
+            Subscription::Symbols(symbols.iter().map(|symbol| aliases.canonical(symbol)).collect())
+        }
+        Subscription::All => Subscription::All,
+    }
+}
+
+/// The shard channel of a one-symbol subscription; larger sets read the
+/// whole feed.
+async fn follow_symbol(shards: Option<&ShardedFeed>, filter: &Subscription) -> Option<broadcast::Receiver<FeedEvent>> {
+    let (updates, _) = shards?.subscribe(filter.single()?).await?;
+    Some(updates)
+}
+
+/// The last quotes the shards hold for the symbols of a `SUB`.
+async fn cached_quotes(shards: Option<&ShardedFeed>, sub: &Subscription) -> Vec<PriceUpdate> {
+    let (Some(shards), Subscription::Symbols(symbols)) = (shards, sub) else {
+        return Vec::new();
+    };
+    let mut quotes = Vec::new();
+    for symbol in symbols {
+        if let Some((_, cached)) = shards.subscribe(symbol).await {
+            quotes.extend(cached);
+        }
+    }
+    quotes
+}
+
+/// `subscribed`, with the client's active set.
+fn subscribed(filter: &Subscription) -> ServerMessage {
+    let symbols = match filter {
+        Subscription::All => Vec::new(),
+        Subscription::Symbols(symbols) => symbols.iter().cloned().collect(),
+    };
+    ServerMessage::Subscribed {
+        filter: filter.label(),
+        symbols,
+    }
+}
+
+/// The `AUTH <key>` to run before the client's commands: the URL token's,
+/// or with `require_auth` the first message, which must carry a valid key.
+async fn first_auth<S>(
+    ws: &mut WebSocketStream<S>,
+    url_token: Option<String>,
+    config: &ServerConfig,
+) -> Result<Option<String>, &'static str>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let command = url_token.map(|token| format!("AUTH {}", token));
+    if !config.require_auth {
+        return Ok(command);
+    }
+    let command = match command {
+        Some(command) => command,
+        None => {
+            let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+            loop {
+                match tokio::time::timeout_at(deadline, ws.next()).await {
+                    Err(_) => return Err("no AUTH in time"),
+                    Ok(Some(Ok(Message::Text(text)))) => break text,
+                    Ok(Some(Ok(Message::Ping(_) | Message::Pong(_)))) => continue,
+                    Ok(_) => return Err("authentication required"),
+                }
+            }
+        }
+    };
+    let valid = |key: &String| {
+        config.api_keys.as_ref().is_some_and(|keys| keys.contains(key)) || config.admin_keys.contains(key)
+    };
+    match parse_auth(&command) {
+        Some(key) if valid(&key) => Ok(Some(command)),
+        _ => Err("invalid API key"),
+    }
+}
+
+/// The pending `AUTH` first, then the client's messages.
+async fn next_command<S>(read: &mut S, pending: &mut Option<String>) -> Option<Result<Message, WsError>>
+where
+    S: Stream<Item = Result<Message, WsError>> + Unpin,
+{
+    match pending.take() {
+        Some(command) => Some(Ok(Message::Text(command))),
+        None => read.next().await,
+    }
+}
+
+/// A client's state, read by the feed loop and changed by its commands.
+struct Session {
+    addr: SocketAddr,
+    config: ServerConfig,
+    state: ServerState,
+    /// Replies go out before snapshots, snapshots before the live feed.
+    outbox: Outbox,
+    stats: SessionStats,
+    /// `FORMAT MSGPACK`: binary frames, switched by the handler, read by the
+    /// writer.
+    msgpack: Arc<AtomicBool>,
+    /// All symbols or a set grown by `SUB`, shrunk by `UNSUB`.
+    filter: Subscription,
+    /// Set by `AUTH <key>`; only then are subscriptions persisted.
+    api_key: Option<String>,
+    /// `DELTA ON`: only changed fields after a full keyframe.
+    delta: Option<DeltaEncoder>,
+    /// `PREFER <sources>`: one source's quote per symbol per window.
+    priority: Option<SourcePriority>,
+    /// `PORTFOLIO ON`: latest price of each held symbol, revalued per quote.
+    portfolio_updates: bool,
+    portfolio_prices: HashMap<String, f64>,
+    /// `INDICATOR <spec> <symbol>`: fed every quote of their symbol.
+    indicators: IndicatorSubscriptions,
+    heartbeat: Option<Interval>,
+    /// With shards, the channel of the client's symbol when it follows only
+    /// one; the whole feed is then only read for the portfolio and indicators.
+    symbol_rx: Option<broadcast::Receiver<FeedEvent>>,
+    /// `JOIN <group>`: the groups whose announcements the client gets.
+    joined: BTreeSet<String>,
+    /// `DAILY ON`: the daily statistics of the subscribed symbols.
+    daily_rx: Option<broadcast::Receiver<DailyStats>>,
+}
+
+impl Session {
+    fn new(addr: SocketAddr, config: ServerConfig, state: ServerState, outbox: Outbox, msgpack: Arc<AtomicBool>) -> Self {
+        let priority = config
+            .source_priority
+            .as_ref()
+            .map(|sources| SourcePriority::new(sources, config.dedup_window));
+        Session {
+            addr,
+            filter: config.default_subscription.clone(),
+            heartbeat: heartbeat_timer(config.heartbeat),
+            priority,
+            config,
+            state,
+            outbox,
+            stats: SessionStats::new(),
+            msgpack,
+            api_key: None,
+            delta: None,
+            portfolio_updates: false,
+            portfolio_prices: HashMap::new(),
+            indicators: IndicatorSubscriptions::default(),
+            symbol_rx: None,
+            joined: BTreeSet::new(),
+            daily_rx: None,
+        }
+    }
+
+    /// Queues the message for the client's writer; `false` once the client
+    /// is gone.
+    fn send(&mut self, message: ServerMessage) -> bool {
+        if let ServerMessage::Error { message } = &message {
+            self.stats.last_error = Some(message.clone());
+        }
+        self.outbox.push(message)
+    }
+
+    /// Queues the outcome of a command, its snapshots behind the reply.
+    fn reply(&mut self, reply: Reply) {
+        if let Some(message) = reply.message {
+            self.send(message);
+        }
+        for message in reply.snapshots {
+            self.outbox.push_to(Lane::Snapshot, message);
+        }
+    }
+
+    /// With API keys, no data, snapshots included, before `AUTH`.
+    fn authorized(&self) -> bool {
+        self.config.api_keys.is_none() || self.api_key.is_some()
+    }
+
+    /// The quote, as a delta in delta mode.
+    fn encode(&mut self, quote: PriceUpdate) -> ServerMessage {
+        match self.delta.as_mut() {
+            Some(encoder) => encoder.encode(&quote),
+            None => ServerMessage::Quote(quote),
+        }
+    }
+
+    /// Keeps the filter of an `AUTH`enticated client for its next session.
+    async fn persist_filter(&self) {
+        if let (Some(key), Some(store)) = (&self.api_key, &self.state.subscriptions) {
+            if let Err(e) = store.set(key, self.filter.entries()).await {
+                warn!("Could not persist subscriptions of {}: {}", self.addr, e);
+            }
+        }
+    }
+
+    /// The session's summary, with what the writer sent and conflated so far.
+    fn summary(&mut self) -> SessionSummary {
+        self.stats.sent = self.outbox.sent();
+        let mut summary = self.stats.summary(self.addr);
+        summary.dropped += self.outbox.dropped();
+        summary
+    }
+}
+
+/// What a command sends back: a reply, most of the time, then the snapshots
+/// queued behind it.
+#[derive(Default)]
+struct Reply {
+    message: Option<ServerMessage>,
+    snapshots: Vec<ServerMessage>,
+}
+
+impl From<ServerMessage> for Reply {
+    fn from(message: ServerMessage) -> Self {
+        Reply {
+            message: Some(message),
+            snapshots: Vec::new(),
+        }
+    }
+}
+
+/// Runs a text command; `None` when it isn't one.
+async fn handle_command(session: &mut Session, command: &str, clients: &Mutex<u32>) -> Option<Reply> {
+    let reply = if command.eq_ignore_ascii_case("/stats") {
+        handle_stats(session, clients).await
+    } else if let Some(action) = parse_action(command) {
+        handle_action(session, action).await
+    } else if let Some(sub) = parse_subscription(command) {
+        handle_sub(session, sub).await
+    } else if let Some(sub) = parse_unsubscription(command) {
+        handle_unsub(session, sub).await
+    } else if let Some(format) = parse_format(command) {
+        handle_format(session, format)
+    } else if let Some(enabled) = parse_delta(command) {
+        handle_delta(session, enabled)
+    } else if let Some(enabled) = parse_daily(command) {
+        handle_daily(session, enabled)
+    } else if let Some(enabled) = parse_portfolio(command) {
+        handle_portfolio(session, enabled)
+    } else if let Some(cmd) = parse_indicator(command) {
+        handle_indicator(session, cmd)
+    } else if let Some(cmd) = parse_prefer(command) {
+        handle_prefer(session, cmd)
+    } else if let Some(key) = parse_auth(command) {
+        handle_auth(session, key).await
+    } else if let Some(cmd) = parse_group(command) {
+        handle_group(session, cmd)
+    } else if let Some(cmd) = parse_heartbeat(command) {
+        handle_heartbeat(session, cmd)
+    } else {
+        return None;
+    };
+    Some(reply)
+}
+
+async fn handle_stats(session: &mut Session, clients: &Mutex<u32>) -> Reply {
+    let count = *clients.lock().await;
+    let config = &session.config;
+    let stats = session.state.health.stats(std::time::Instant::now(), config.feed, count, &config.channel);
+    ServerMessage::Stats(stats).into()
+}
+
+async fn handle_action(session: &mut Session, action: ClientAction) -> Reply {
+    let config = &session.config;
+    match action {
+        ClientAction::ListSymbols => {
+            let symbols = session.state.known.list();
+            let details = config.metadata.details(&symbols);
+            ServerMessage::Symbols { symbols, details }.into()
+        }
+        ClientAction::DailyStats { symbol } => {
+            let stats = match symbol {
+                Some(symbol) => session.state.daily.get(&config.aliases.canonical(&symbol)).into_iter().collect(),
+                None => session.state.daily.all(),
+            };
+            Reply {
+                message: stats.is_empty().then(|| ServerMessage::error("no daily stats yet")),
+                snapshots: stats.into_iter().map(ServerMessage::DailyStats).collect(),
+            }
+        }
+        ClientAction::Candles(query) => {
+            let reply = match (&config.db, &config.shards) {
+                // Without a DB, the candles the shards built from the live feed.
+                (None, Some(shards)) if parse_interval(&query.interval) == Some(CANDLE_SECS) => {
+                    let symbol = query.symbol.trim().to_uppercase();
+                    let candles = shards.candles(&symbol).await;
+                    ServerMessage::Candles(page(&symbol, CANDLE_SECS, &candles, &query))
+                }
+                (None, Some(_)) => ServerMessage::error("only 1m candles without a database"),
+                (None, None) => ServerMessage::error("no candle history without a database"),
+                (Some(pool), _) => match load_candles(pool, &query).await {
+                    Ok(page) => ServerMessage::Candles(page),
+                    Err(e) => {
+                        warn!("Candles for {} failed: {}", session.addr, e);
+                        ServerMessage::error(e.to_string())
+                    }
+                },
+            };
+            reply.into()
+        }
+    }
+}
+
+async fn handle_sub(session: &mut Session, sub: Subscription) -> Reply {
+    let sub = canonical(&session.config.aliases, sub);
+    if let Subscription::Symbols(symbols) = &sub {
+        if let Some(sym) = symbols.iter().find(|sym| !session.state.known.contains(sym)) {
+            return ServerMessage::error(format!("unknown symbol {}", sym)).into();
+        }
+    }
+    let previous = session.filter.clone();
+    session.filter.add(sub.clone());
+    if !session.filter.covers(&previous) {
+        // Queued updates of the symbols left behind.
+        session.outbox.clear_live();
+    }
+    session.symbol_rx = follow_symbol(session.config.shards.as_ref(), &session.filter).await;
+    for label in sub.entries() {
+        session.stats.record_subscription(&label);
+    }
+    session.persist_filter().await;
+    let mut reply = Reply::from(subscribed(&session.filter));
+    if !session.authorized() {
+        return reply;
+    }
+    for quote in cached_quotes(session.config.shards.as_ref(), &sub).await {
+        if quote.is_mock && !session.config.include_mock {
+            continue;
+        }
+        reply.snapshots.push(session.encode(quote));
+    }
+    reply
+}
+
+async fn handle_unsub(session: &mut Session, sub: Subscription) -> Reply {
+    let sub = canonical(&session.config.aliases, sub);
+    if let Err(e) = session.filter.remove(&sub) {
+        return ServerMessage::error(e).into();
+    }
+    session.outbox.clear_live();
+    session.symbol_rx = follow_symbol(session.config.shards.as_ref(), &session.filter).await;
+    session.persist_filter().await;
+    subscribed(&session.filter).into()
+}
+
+fn handle_format(session: &mut Session, format: WireFormat) -> Reply {
+    // set first: the reply already comes in the new format
+    session.msgpack.store(format == WireFormat::Msgpack, Ordering::Relaxed);
+    ServerMessage::Format { format }.into()
+}
+
+fn handle_delta(session: &mut Session, enabled: bool) -> Reply {
+    session.delta = enabled.then(|| DeltaEncoder::new(session.config.delta_keyframe_every));
+    ServerMessage::Delta { enabled }.into()
+}
+
+fn handle_daily(session: &mut Session, enabled: bool) -> Reply {
+    session.daily_rx = enabled.then(|| session.state.daily.subscribe());
+    let mut reply = Reply::from(ServerMessage::Daily { enabled });
+    if enabled && session.authorized() {
+        for stats in session.state.daily.all() {
+            if session.filter.matches(&stats.symbol) {
+                reply.snapshots.push(ServerMessage::DailyStats(stats));
+            }
+        }
+    }
+    reply
+}
+
+fn handle_portfolio(session: &mut Session, enabled: bool) -> Reply {
+    let Some(portfolio) = &session.config.portfolio else {
+        return ServerMessage::error("no portfolio on this server").into();
+    };
+    session.portfolio_updates = enabled;
+    let mut reply = Reply::from(ServerMessage::Portfolio { enabled });
+    if enabled {
+        let update = ServerMessage::PortfolioUpdate(portfolio.value(&session.portfolio_prices));
+        reply.snapshots.push(update);
+    }
+    reply
+}
+
+fn handle_indicator(session: &mut Session, cmd: IndicatorCmd) -> Reply {
+    match cmd {
+        IndicatorCmd::Off => session.indicators.clear(),
+        IndicatorCmd::Follow { spec, symbol } => {
+            let symbol = session.config.aliases.canonical(&symbol);
+            if !session.state.known.contains(&symbol) {
+                return ServerMessage::error(format!("unknown symbol {}", symbol)).into();
+            }
+            if !session.indicators.add(&symbol, spec) {
+                return ServerMessage::error(format!("at most {} indicators", MAX_INDICATORS)).into();
+            }
+        }
+    }
+    ServerMessage::Indicators { subscriptions: session.indicators.labels() }.into()
+}
+
+fn handle_prefer(session: &mut Session, cmd: PreferCmd) -> Reply {
+    session.priority = match cmd {
+        PreferCmd::Off => None,
+        PreferCmd::Sources(sources) => Some(SourcePriority::new(&sources, session.config.dedup_window)),
+    };
+    let sources = session.priority.as_ref().map(|p| p.sources().to_vec()).unwrap_or_default();
+    ServerMessage::Prefer { sources }.into()
+}
+
+async fn handle_auth(session: &mut Session, key: String) -> Reply {
+    let config = &session.config;
+    let admin = config.admin_keys.contains(&key);
+    if !admin && config.api_keys.as_ref().is_some_and(|keys| !keys.contains(&key)) {
+        warn!("Client {} sent an invalid API key", session.addr);
+        return ServerMessage::error("invalid API key").into();
+    }
+    let saved = session.state.subscriptions.as_ref().and_then(|store| store.get(&key));
+    session.api_key = Some(key);
+    let restored: Vec<Subscription> = saved
+        .unwrap_or_default()
+        .iter()
+        .filter_map(|label| parse_subscription(&format!("SUB {}", label)))
+        .map(|sub| canonical(&session.config.aliases, sub))
+        .collect();
+    if let Some(first) = restored.first() {
+        session.filter = first.clone();
+        for sub in &restored[1..] {
+            session.filter.add(sub.clone());
+        }
+        session.symbol_rx = follow_symbol(session.config.shards.as_ref(), &session.filter).await;
+    }
+    let restored: Vec<String> = restored.iter().map(Subscription::label).collect();
+    if restored.is_empty() {
+        return ServerMessage::Authenticated.into();
+    }
+    for label in &restored {
+        session.stats.record_subscription(label);
+    }
+    ServerMessage::SubscriptionsRestored { subscriptions: restored }.into()
+}
+
+fn handle_group(session: &mut Session, cmd: GroupCmd) -> Reply {
+    let group = match &cmd {
+        GroupCmd::Join(group) | GroupCmd::Leave(Some(group)) => Some(group),
+        GroupCmd::Announce { group, .. } => group.as_ref(),
+        GroupCmd::Leave(None) => None,
+    };
+    let group = match group.map(|g| group_name(g).ok_or(g)).transpose() {
+        Ok(group) => group,
+        Err(invalid) => return ServerMessage::error(format!("invalid group name {}", invalid)).into(),
+    };
+    let config = &session.config;
+    match cmd {
+        GroupCmd::Announce { message, .. } => {
+            if !session.api_key.as_ref().is_some_and(|key| config.admin_keys.contains(key)) {
+                warn!("Client {} tried to announce without an admin key", session.addr);
+                return ServerMessage::error("ANNOUNCE needs an admin key").into();
+            }
+            let recipients = config.groups.announce(group.as_deref(), message);
+            ServerMessage::Announced { group, recipients }.into()
+        }
+        GroupCmd::Join(_) => {
+            let group = group.unwrap_or_default();
+            if session.api_key.is_none() {
+                return ServerMessage::error("AUTH first to join a group").into();
+            }
+            if !session.joined.contains(&group) && session.joined.len() >= MAX_GROUPS_PER_CLIENT {
+                return ServerMessage::error(format!("at most {} groups", MAX_GROUPS_PER_CLIENT)).into();
+            }
+            if session.joined.insert(group.clone()) {
+                config.groups.join(&group);
+            }
+            ServerMessage::Groups { groups: session.joined.iter().cloned().collect() }.into()
+        }
+        GroupCmd::Leave(_) => {
+            let left = match group {
+                Some(group) => session.joined.take(&group).into_iter().collect(),
+                None => std::mem::take(&mut session.joined),
+            };
+            for group in &left {
+                config.groups.leave(group);
+            }
+            ServerMessage::Groups { groups: session.joined.iter().cloned().collect() }.into()
+        }
+    }
+}
+
+fn handle_heartbeat(session: &mut Session, cmd: HeartbeatCmd) -> Reply {
+    let every = match cmd {
+        HeartbeatCmd::Off => None,
+        HeartbeatCmd::Every(period) => Some(period),
+    };
+    session.heartbeat = heartbeat_timer(every);
+    ServerMessage::HeartbeatConfig { interval_secs: every.map(|d| d.as_secs()) }.into()
+}
+
+/// One client, over plain TCP or TLS; it reads `tx` once its handshake and
+/// authentication went through.
+pub async fn handle_client<S>(
+    stream: S,
+    addr: SocketAddr,
+    tx: broadcast::Sender<FeedEvent>,
+    clients: Arc<Mutex<u32>>,
+    config: ServerConfig,
+    state: ServerState,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    // track active clients
+    {
+        let mut count = clients.lock().await;
+        *count += 1;
+        info!("Client connected: {} ({} active)", addr, *count);
+    }
+
+    // `?token=<key>` of the handshake URL, run as a first `AUTH <key>`;
+    // `?format=msgpack` from the welcome message on
+    let (mut url_token, mut url_format, mut deflate) = (None, None, None);
+    #[allow(clippy::result_large_err)] // the callback's signature is tungstenite's
+    let handshake = accept_hdr_async(InflateStream::new(stream), |request: &Request, mut response: Response| {
+        url_token = request.uri().query().and_then(parse_token_query);
+        url_format = request.uri().query().and_then(parse_format_query);
+        if config.compression {
+            let offers: Vec<&str> =
+                request.headers().get_all(SEC_WEBSOCKET_EXTENSIONS).iter().filter_map(|v| v.to_str().ok()).collect();
+            deflate = PerMessageDeflate::negotiate(&offers.join(","));
+        }
+        if let Some(params) = &deflate {
+            let header = HeaderValue::from_str(&params.response()).expect("ASCII extension parameters");
+            response.headers_mut().insert(SEC_WEBSOCKET_EXTENSIONS, header);
+        }
+        Ok(response)
+    });
+    let handshake = match tokio::time::timeout(HANDSHAKE_TIMEOUT, handshake).await {
+        Ok(result) => result.map_err(|e| e.to_string()),
+        Err(_) => Err("timed out".to_string()),
+    };
+    let mut ws_stream = match handshake {
+        Ok(ws) => ws,
+        Err(e) => {
+            error!("WebSocket handshake failed for {}: {}", addr, e);
+            let mut count = clients.lock().await;
+            *count -= 1;
+            return;
+        }
+    };
+    if deflate.is_some() {
+        ws_stream.get_mut().enable();
+    }
+
+    let mut pending_auth = match first_auth(&mut ws_stream, url_token, &config).await {
+        Ok(command) => command,
+        Err(reason) => {
+            warn!("Rejecting {}: {}", addr, reason);
+            let frame = CloseFrame {
+                code: CloseCode::Policy,
+                reason: reason.into(),
+            };
+            let _ = ws_stream.close(Some(frame)).await;
+            let mut count = clients.lock().await;
+            *count -= 1;
+            return;
+        }
+    };
+    let mut rx = tx.subscribe();
+
+    let (write, mut read) = ws_stream.split();
+
+    let outbox = Outbox::default();
+    let msgpack = Arc::new(AtomicBool::new(url_format == Some(WireFormat::Msgpack)));
+    let deflater = deflate.map(Deflater::new);
+    let mut writer =
+        tokio::spawn(write_queued(write, outbox.clone(), config.slow_start, config.ping, msgpack.clone(), deflater));
+    let mut session = Session::new(addr, config, state, outbox, msgpack);
+
+    // welcome message
+    let welcome = ServerMessage::Connected {
+        message: "Connected to stock price feed".to_string(),
+    };
+    session.send(welcome);
+
+    let mut heartbeat_seq: u64 = 0;
+
+    // any inbound frame, pongs included, proves the client is still there
+    let mut last_seen = Instant::now();
+
+    // Inbound commands over budget are dropped, persistent abusers cut off.
+    let mut throttle = session.config.command_limit.map(|limit| CommandThrottle::new(limit, Instant::now()));
+
+    let mut following_feed = true;
+    let mut announcements = session.config.groups.subscribe();
+
+    loop {
+        let follow_feed =
+            session.symbol_rx.is_none() || session.config.portfolio.is_some() || !session.indicators.is_empty();
+        if follow_feed && !following_feed {
+            // Skip what was published while it wasn't read.
+            rx = rx.resubscribe();
+        }
+        following_feed = follow_feed;
+
+        tokio::select! {
+            // broadcast path
+            (res, from_feed) = next_event(&mut rx, follow_feed, &mut session.symbol_rx) => {
+                let event = match res {
+                    Ok(event) => {
+                        let depth = match (&session.symbol_rx, from_feed) {
+                            (Some(symbol), false) => symbol.len(),
+                            _ => rx.len(),
+                        };
+                        session.config.channel.record_depth(depth);
+                        event
+                    }
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        warn!("Client {} lagged, {} updates dropped", addr, n);
+                        session.stats.dropped += n;
+                        session.config.channel.record_lag(n);
+                        if !session.send(ServerMessage::Lagged { missed: n }) {
+                            info!("Client disconnected: {}", addr);
+                            break;
+                        }
+                        // On the live lane, they replace the older queued quotes.
+                        if session.config.resync_on_lag && session.authorized() {
+                            for quote in session.state.latest.snapshot(&session.filter) {
+                                if quote.is_mock && !session.config.include_mock {
+                                    continue;
+                                }
+                                let message = session.encode(quote);
+                                session.send(message);
+                            }
+                        }
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        info!("Feed closed, ending session with {}", addr);
+                        if session.config.session_summary_to_client {
+                            let summary = ServerMessage::SessionSummary(session.summary());
+                            session.send(summary);
+                        }
+                        break;
+                    }
+                };
+
+                if event.is_mock() && !session.config.include_mock {
+                    continue;
+                }
+                if !session.authorized() {
+                    continue;
+                }
+
+                // Held symbols are tracked whatever the client subscribed to.
+                let update = match (&event, &session.config.portfolio) {
+                    (FeedEvent::Quote(quote), Some(portfolio)) if portfolio.holds(&quote.symbol) => {
+                        session.portfolio_prices.insert(quote.symbol.to_uppercase(), quote.price);
+                        let value = || ServerMessage::PortfolioUpdate(portfolio.value(&session.portfolio_prices));
+                        session.portfolio_updates.then(value)
+                    }
+                    _ => None,
+                };
+                if let Some(update) = update {
+                    if !session.send(update) {
+                        info!("Client disconnected: {}", addr);
+                        break;
+                    }
+                }
+
+                // Like the portfolio, indicators don't depend on `SUB`.
+                if let FeedEvent::Quote(quote) = &event {
+                    let mut gone = false;
+                    for update in session.indicators.update(quote) {
+                        if !session.send(ServerMessage::IndicatorUpdate(update)) {
+                            gone = true;
+                            break;
+                        }
+                    }
+                    if gone {
+                        info!("Client disconnected: {}", addr);
+                        break;
+                    }
+                }
+
+                // Its own updates come through the symbol channel.
+                if from_feed && session.symbol_rx.is_some() {
+                    continue;
+                }
+
+                if !session.filter.matches(event.symbol()) {
+                    continue;
+                }
+
+                if let (FeedEvent::Quote(quote), Some(priority)) = (&event, session.priority.as_mut()) {
+                    if !priority.accept(&quote.symbol, &quote.source, Instant::now()) {
+                        continue;
+                    }
+                }
+
+                let message = match event {
+                    FeedEvent::Quote(mut quote) => {
+                        quote.broadcast_at = Some(chrono::Utc::now().timestamp_millis());
+                        session.encode(quote)
+                    }
+                    event => ServerMessage::from(event),
+                };
+                if !session.send(message) {
+                    info!("Client disconnected: {}", addr);
+                    break;
+                }
+            }
+
+            // liveness + clock sync
+            _ = async { session.heartbeat.as_mut().unwrap().tick().await }, if session.heartbeat.is_some() => {
+                heartbeat_seq += 1;
+                if !session.send(heartbeat_message(heartbeat_seq)) {
+                    info!("Client disconnected: {}", addr);
+                    break;
+                }
+            }
+
+            // dead client: its receivers would otherwise live forever
+            _ = sleep_until(last_seen + session.config.idle_timeout.unwrap_or_default()), if session.config.idle_timeout.is_some() => {
+                warn!("Client {} idle for {:?}, closing", addr, session.config.idle_timeout.unwrap_or_default());
+                break;
+            }
+
+            // daily statistics, after each quote
+            res = async { session.daily_rx.as_mut().unwrap().recv().await }, if session.daily_rx.is_some() => {
+                // Lagged: later updates carry the same figures.
+                let Ok(stats) = res else {
+                    continue;
+                };
+                if !session.authorized() {
+                    continue;
+                }
+                if !session.filter.matches(&stats.symbol) {
+                    continue;
+                }
+                if !session.send(ServerMessage::DailyStats(stats)) {
+                    info!("Client disconnected: {}", addr);
+                    break;
+                }
+            }
+
+            // operator announcements, to everyone or to the client's groups
+            res = announcements.recv() => {
+                // Lagged: the missed ones are gone. Never closed while
+                // `config` holds the sender.
+                let Ok(announcement) = res else {
+                    continue;
+                };
+                if announcement.group.as_ref().is_some_and(|group| !session.joined.contains(group)) {
+                    continue;
+                }
+                let message = ServerMessage::Announcement {
+                    group: announcement.group,
+                    message: announcement.message,
+                };
+                if !session.send(message) {
+                    info!("Client disconnected: {}", addr);
+                    break;
+                }
+            }
+
+            // incoming messages
+            msg = next_command(&mut read, &mut pending_auth) => {
+                if let Some(Ok(_)) = msg {
+                    last_seen = Instant::now();
+                }
+                match msg {
+                    Some(Ok(Message::Text(t))) => {
+                        session.stats.received += 1;
+                        match throttle.as_mut().map_or(Verdict::Allow, |t| t.check(Instant::now())) {
+                            Verdict::Allow => {}
+                            Verdict::Throttle { retry_after, notify } => {
+                                if notify {
+                                    let retry_after_ms = retry_after.as_millis().max(1) as u64;
+                                    session.send(ServerMessage::Throttled { retry_after_ms });
+                                }
+                                continue;
+                            }
+                            Verdict::Disconnect => {
+                                warn!("Disconnecting {}: too many commands", addr);
+                                session.send(ServerMessage::error("too many commands, disconnecting"));
+                                break;
+                            }
+                        }
+                        let trimmed = t.trim();
+                        session.stats.last_error = None;
+                        let known_command = match handle_command(&mut session, trimmed, &clients).await {
+                            Some(reply) => {
+                                session.reply(reply);
+                                true
+                            }
+                            None => {
+                                info!("Client {} says: {}", addr, trimmed);
+                                false
+                            }
+                        };
+                        if let Some(log) = &session.config.access_log {
+                            let (outcome, error) = match (known_command, session.stats.last_error.take()) {
+                                (false, _) => ("unknown", None),
+                                (true, Some(error)) => ("error", Some(error)),
+                                (true, None) => ("ok", None),
+                            };
+                            let entry = AccessEntry {
+                                ts: chrono::Utc::now().timestamp_millis(),
+                                client: addr.to_string(),
+                                key: session.api_key.as_deref().map(mask_key),
+                                command: redact(trimmed),
+                                outcome: outcome.to_string(),
+                                error,
+                            };
+                            if let Err(e) = log.record(&entry) {
+                                warn!("Access log not written: {}", e);
+                            }
+                        }
+                    }
+                    Some(Ok(Message::Close(_))) | None => {
+                        info!("Client closed connection: {}", addr);
+                        break;
+                    }
+                    Some(Err(e)) => {
+                        warn!("WebSocket error: {}", e);
+                        break;
+                    }
+                    Some(Ok(_)) => session.stats.received += 1,
+                }
+            }
+        }
+    }
+
+    for group in &session.joined {
+        session.config.groups.leave(group);
+    }
+
+    // Lets the writer flush what is queued, unless the client stopped reading.
+    session.outbox.close();
+    if tokio::time::timeout(WRITER_GRACE, &mut writer).await.is_err() {
+        writer.abort();
+    }
+
+    match serde_json::to_string(&session.summary()) {
+        Ok(json) => info!("session_summary {}", json),
+        Err(e) => warn!("Serialize error: {e}"),
+    }
+
+    // decrement active clients
+    {
+        let mut count = clients.lock().await;
+        *count -= 1;
+        info!("Client {} disconnected ({} active)", addr, *count);
+    }
+}
+
+/// Accept loop: one task per client, each with its own broadcast receiver.
+/// With `tls`, clients connect over `wss://`.
+pub async fn serve(
+    listener: TcpListener,
+    tls: Option<TlsAcceptor>,
+    tx: broadcast::Sender<FeedEvent>,
+    clients: Arc<Mutex<u32>>,
+    config: ServerConfig,
+) {
+    let known = KnownSymbols::new(config.known_symbols.iter().map(|symbol| config.aliases.canonical(symbol)));
+    tokio::spawn(known.clone().track(tx.subscribe()));
+
+    let subscriptions = config.subscriptions_file.as_ref().and_then(|path| {
+        SubscriptionStore::open(path)
+            .map_err(|e| error!("Subscriptions won't be persisted: {}", e))
+            .ok()
+    });
+    let health = FeedHealth::default();
+    tokio::spawn(health.clone().track(tx.subscribe()));
+    let daily = DailyTracker::default();
+    tokio::spawn(daily.clone().track(tx.subscribe()));
+    let latest = LatestQuotes::default();
+    tokio::spawn(latest.clone().track(tx.subscribe()));
+    let state = ServerState {
+        known,
+        subscriptions,
+        health,
+        daily,
+        latest,
+    };
+
+    while let Ok((stream, addr)) = listener.accept().await {
+        let (tx, clients) = (tx.clone(), clients.clone());
+        let (config, state) = (config.clone(), state.clone());
+        match &tls {
+            None => {
+                tokio::spawn(handle_client(stream, addr, tx, clients, config, state));
+            }
+            Some(tls) => {
+                let tls = tls.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, tls.accept(stream)).await {
+                        Ok(Ok(stream)) => handle_client(stream, addr, tx, clients, config, state).await,
+                        Ok(Err(e)) => warn!("TLS handshake failed for {}: {}", addr, e),
+                        Err(_) => warn!("TLS handshake timed out for {}", addr),
+                    }
+                });
+            }
+        }
+    }
+}
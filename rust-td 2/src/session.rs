@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::Instant;
+
+/// Per-connection counters, published as a `session_summary` on disconnect.
+#[derive(Debug)]
+pub struct SessionStats {
+    started: Instant,
+    pub sent: u64,
+    pub received: u64,
+    /// Updates lost because the client lagged behind the broadcast channel.
+    pub dropped: u64,
+    /// Last `error` sent, for the access log.
+    pub last_error: Option<String>,
+    subscriptions: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSummary {
+    pub client: String,
+    pub duration_ms: u64,
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    pub subscriptions: Vec<String>,
+    pub dropped: u64,
+}
+
+impl SessionStats {
+    pub fn new() -> Self {
+        SessionStats {
+            started: Instant::now(),
+            sent: 0,
+            received: 0,
+            dropped: 0,
+            last_error: None,
+            subscriptions: Vec::new(),
+        }
+    }
+
+    /// Remembers each distinct filter the client used, in order.
+    pub fn record_subscription(&mut self, label: &str) {
+        if !self.subscriptions.iter().any(|s| s == label) {
+            self.subscriptions.push(label.to_string());
+        }
+    }
+
+    pub fn summary(&self, client: SocketAddr) -> SessionSummary {
+        SessionSummary {
+            client: client.to_string(),
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            messages_sent: self.sent,
+            messages_received: self.received,
+            subscriptions: self.subscriptions.clone(),
+            dropped: self.dropped,
+        }
+    }
+}
+
+impl Default for SessionStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_lists_distinct_subscriptions() {
+        let mut stats = SessionStats::new();
+        stats.sent = 3;
+        stats.dropped = 2;
+        stats.record_subscription("AAPL");
+        stats.record_subscription("ALL");
+        stats.record_subscription("AAPL");
+
+        let json = serde_json::to_value(stats.summary("127.0.0.1:9000".parse().unwrap())).unwrap();
+        assert_eq!(json["client"], "127.0.0.1:9000");
+        assert_eq!(json["messages_sent"], 3);
+        assert_eq!(json["dropped"], 2);
+        assert_eq!(json["subscriptions"], serde_json::json!(["AAPL", "ALL"]));
+    }
+}
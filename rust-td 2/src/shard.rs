@@ -0,0 +1,243 @@
+//! Per-symbol sharding of the feed: events are routed by symbol hash to N
+//! worker tasks, each owning the last-value cache, live candles and
+//! broadcast channel of its symbols, so the per-symbol work spreads over the
+//! cores and a client following one symbol only wakes up for it.
+
+use crate::candles::Candle;
+use crate::protocol::{FeedEvent, PriceUpdate};
+use log::warn;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::hash::{Hash, Hasher};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+/// Period of the candles built from the live feed.
+pub const CANDLE_SECS: i64 = 60;
+/// Completed candles kept per symbol.
+const CANDLES_KEPT: usize = 240;
+
+/// OHLC of the quotes of one symbol, `CANDLE_SECS` per candle.
+#[derive(Debug, Clone, Default)]
+pub struct CandleBuilder {
+    closed: VecDeque<Candle>,
+    current: Option<Candle>,
+}
+
+impl CandleBuilder {
+    pub fn update(&mut self, price: f64, timestamp: i64) {
+        let bucket = timestamp - timestamp.rem_euclid(CANDLE_SECS);
+        match &mut self.current {
+            Some(candle) if candle.bucket_start == bucket => {
+                candle.high = candle.high.max(price);
+                candle.low = candle.low.min(price);
+                candle.close = price;
+                candle.samples += 1;
+                return;
+            }
+            // Late quote of a candle already closed.
+            Some(candle) if candle.bucket_start > bucket => return,
+            _ => {}
+        }
+        let new = Candle {
+            bucket_start: bucket,
+            open: price,
+            high: price,
+            low: price,
+            close: price,
+            samples: 1,
+        };
+        if let Some(done) = self.current.replace(new) {
+            if self.closed.len() == CANDLES_KEPT {
+                self.closed.pop_front();
+            }
+            self.closed.push_back(done);
+        }
+    }
+
+    /// Oldest first, the one in progress last.
+    pub fn candles(&self) -> Vec<Candle> {
+        self.closed.iter().chain(&self.current).cloned().collect()
+    }
+}
+
+#[derive(Debug)]
+struct SymbolState {
+    channel: broadcast::Sender<FeedEvent>,
+    /// Latest quote of each source.
+    latest: BTreeMap<String, PriceUpdate>,
+    candles: CandleBuilder,
+}
+
+#[derive(Debug)]
+enum Request {
+    Event(FeedEvent),
+    /// The symbol's channel and its cached quotes, taken at the same point
+    /// of the stream so nothing is missed or doubled.
+    Subscribe {
+        symbol: String,
+        reply: oneshot::Sender<(broadcast::Receiver<FeedEvent>, Vec<PriceUpdate>)>,
+    },
+    Candles {
+        symbol: String,
+        reply: oneshot::Sender<Vec<Candle>>,
+    },
+}
+
+/// Handle on the shard workers; cloning it is cheap.
+#[derive(Debug, Clone)]
+pub struct ShardedFeed {
+    shards: Vec<mpsc::Sender<Request>>,
+}
+
+pub fn shard_of(symbol: &str, shards: usize) -> usize {
+    let mut hasher = DefaultHasher::new();
+    symbol.to_uppercase().hash(&mut hasher);
+    (hasher.finish() % shards as u64) as usize
+}
+
+impl ShardedFeed {
+    /// Spawns `shards` workers fed from `source`; `capacity` bounds each
+    /// worker's queue and each symbol's channel.
+    pub fn start(source: broadcast::Receiver<FeedEvent>, shards: usize, capacity: usize) -> Self {
+        let shards: Vec<mpsc::Sender<Request>> = (0..shards.max(1))
+            .map(|_| {
+                let (tx, rx) = mpsc::channel(capacity);
+                tokio::spawn(run_shard(rx, capacity));
+                tx
+            })
+            .collect();
+        tokio::spawn(dispatch(source, shards.clone()));
+        ShardedFeed { shards }
+    }
+
+    fn shard(&self, symbol: &str) -> &mpsc::Sender<Request> {
+        &self.shards[shard_of(symbol, self.shards.len())]
+    }
+
+    /// Updates of `symbol` only, plus its latest quote per source.
+    pub async fn subscribe(&self, symbol: &str) -> Option<(broadcast::Receiver<FeedEvent>, Vec<PriceUpdate>)> {
+        let (reply, answer) = oneshot::channel();
+        let symbol = symbol.to_uppercase();
+        self.shard(&symbol).send(Request::Subscribe { symbol, reply }).await.ok()?;
+        answer.await.ok()
+    }
+
+    /// The `CANDLE_SECS` candles built from the live quotes of `symbol`.
+    pub async fn candles(&self, symbol: &str) -> Vec<Candle> {
+        let (reply, answer) = oneshot::channel();
+        let symbol = symbol.to_uppercase();
+        if self.shard(&symbol).send(Request::Candles { symbol, reply }).await.is_err() {
+            return Vec::new();
+        }
+        answer.await.unwrap_or_default()
+    }
+}
+
+async fn dispatch(mut source: broadcast::Receiver<FeedEvent>, shards: Vec<mpsc::Sender<Request>>) {
+    loop {
+        let event = match source.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(n)) => {
+                warn!("Shard dispatcher lagged, {} updates dropped", n);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let shard = &shards[shard_of(event.symbol(), shards.len())];
+        if shard.send(Request::Event(event)).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn run_shard(mut requests: mpsc::Receiver<Request>, capacity: usize) {
+    let mut symbols: HashMap<String, SymbolState> = HashMap::new();
+    while let Some(request) = requests.recv().await {
+        let symbol = match &request {
+            Request::Event(event) => event.symbol().to_uppercase(),
+            Request::Subscribe { symbol, .. } | Request::Candles { symbol, .. } => symbol.clone(),
+        };
+        let state = symbols.entry(symbol).or_insert_with(|| SymbolState {
+            channel: broadcast::channel(capacity).0,
+            latest: BTreeMap::new(),
+            candles: CandleBuilder::default(),
+        });
+        match request {
+            Request::Event(event) => {
+                if let FeedEvent::Quote(quote) = &event {
+                    state.candles.update(quote.price, quote.timestamp);
+                    state.latest.insert(quote.source.clone(), quote.clone());
+                }
+                // Nobody follows the symbol: fine.
+                let _ = state.channel.send(event);
+            }
+            Request::Subscribe { reply, .. } => {
+                let _ = reply.send((state.channel.subscribe(), state.latest.values().cloned().collect()));
+            }
+            Request::Candles { reply, .. } => {
+                let _ = reply.send(state.candles.candles());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn candles_roll_over_each_minute() {
+        let mut builder = CandleBuilder::default();
+        builder.update(10.0, 60);
+        builder.update(12.0, 90);
+        builder.update(9.0, 100);
+        builder.update(11.0, 125);
+        builder.update(50.0, 30); // late, ignored
+
+        let candles = builder.candles();
+        assert_eq!(candles.len(), 2);
+        assert_eq!((candles[0].open, candles[0].high, candles[0].low, candles[0].close), (10.0, 12.0, 9.0, 9.0));
+        assert_eq!((candles[0].bucket_start, candles[0].samples), (60, 3));
+        assert_eq!((candles[1].bucket_start, candles[1].close), (120, 11.0));
+    }
+
+    fn quote(symbol: &str, source: &str, price: f64) -> FeedEvent {
+        FeedEvent::Quote(PriceUpdate {
+            symbol: symbol.into(),
+            price,
+            source: source.into(),
+            timestamp: 60,
+            is_mock: false,
+            produced_at: None,
+            broadcast_at: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn subscribers_get_their_symbol_and_its_cached_quotes() {
+        let (tx, rx) = broadcast::channel(16);
+        let feed = ShardedFeed::start(rx, 4, 16);
+        tx.send(quote("AAPL", "Yahoo", 187.0)).unwrap();
+        tx.send(quote("AAPL", "Finnhub", 187.5)).unwrap();
+        tx.send(quote("MSFT", "Yahoo", 410.0)).unwrap();
+
+        // The dispatcher forwards them asynchronously.
+        while feed.candles("aapl").await.first().is_none_or(|c| c.samples < 2) {
+            tokio::task::yield_now().await;
+        }
+        let (mut updates, cached) = feed.subscribe("AAPL").await.unwrap();
+        assert_eq!(cached.iter().map(|q| q.source.as_str()).collect::<Vec<_>>(), vec!["Finnhub", "Yahoo"]);
+
+        tx.send(quote("MSFT", "Yahoo", 411.0)).unwrap();
+        tx.send(quote("AAPL", "Yahoo", 188.0)).unwrap();
+        assert_eq!(updates.recv().await.unwrap().symbol(), "AAPL");
+        assert!(updates.try_recv().is_err());
+    }
+
+    #[test]
+    fn a_symbol_always_lands_on_the_same_shard() {
+        assert_eq!(shard_of("aapl", 8), shard_of("AAPL", 8));
+        assert!((0..100).map(|i| shard_of(&format!("S{}", i), 4)).all(|s| s < 4));
+    }
+}
@@ -0,0 +1,79 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Pace of a new connection: `initial_rate` messages per second, doubled
+/// every second, until full speed after `ramp`. Spares clients that just
+/// reconnected (mobile, browser tabs) the whole snapshot and backlog at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SlowStart {
+    pub initial_rate: f64,
+    pub ramp: Duration,
+}
+
+impl Default for SlowStart {
+    fn default() -> Self {
+        SlowStart {
+            initial_rate: 20.0,
+            ramp: Duration::from_secs(5),
+        }
+    }
+}
+
+impl SlowStart {
+    /// Messages per second allowed `elapsed` after the connection; `None`
+    /// = full speed.
+    pub fn rate_at(&self, elapsed: Duration) -> Option<f64> {
+        (elapsed < self.ramp && self.initial_rate > 0.0).then(|| self.initial_rate * elapsed.as_secs_f64().exp2())
+    }
+}
+
+/// Spaces the messages of one connection while its slow start lasts.
+#[derive(Debug, Clone)]
+pub struct Pacer {
+    slow_start: SlowStart,
+    started: Instant,
+    next: Instant,
+}
+
+impl Pacer {
+    pub fn new(slow_start: SlowStart, now: Instant) -> Self {
+        Pacer {
+            slow_start,
+            started: now,
+            next: now,
+        }
+    }
+
+    /// Takes the next send slot: `Some(at)` when the message has to wait
+    /// until then, `None` when it may go now.
+    pub fn reserve(&mut self, now: Instant) -> Option<Instant> {
+        let rate = self.slow_start.rate_at(now - self.started)?;
+        let at = self.next.max(now);
+        self.next = at + Duration::from_secs_f64(1.0 / rate);
+        (at > now).then_some(at)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_pace_doubles_until_full_speed() {
+        let slow_start = SlowStart {
+            initial_rate: 10.0,
+            ramp: Duration::from_secs(3),
+        };
+        assert_eq!(slow_start.rate_at(Duration::ZERO), Some(10.0));
+        assert_eq!(slow_start.rate_at(Duration::from_secs(2)), Some(40.0));
+        assert_eq!(slow_start.rate_at(Duration::from_secs(3)), None);
+
+        let start = Instant::now();
+        let mut pacer = Pacer::new(slow_start, start);
+        assert_eq!(pacer.reserve(start), None);
+        // A burst waits 100 ms per message at first.
+        assert_eq!(pacer.reserve(start), Some(start + Duration::from_millis(100)));
+        assert_eq!(pacer.reserve(start), Some(start + Duration::from_millis(200)));
+        assert_eq!(pacer.reserve(start + Duration::from_secs(3)), None);
+    }
+}
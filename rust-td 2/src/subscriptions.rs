@@ -0,0 +1,70 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use td_common::{Context, Result};
+
+/// Subscriptions of authenticated clients, keyed by API key and kept in a
+/// JSON file so they survive reconnects and server restarts.
+#[derive(Debug, Clone)]
+pub struct SubscriptionStore {
+    path: PathBuf,
+    entries: Arc<Mutex<HashMap<String, Vec<String>>>>,
+}
+
+impl SubscriptionStore {
+    /// Loads `path`, or starts empty when it doesn't exist yet.
+    pub fn open(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(text) => serde_json::from_str(&text)
+                .with_context(|| format!("reading subscriptions from {}", path.display()))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e).with_context(|| format!("opening {}", path.display())),
+        };
+        Ok(SubscriptionStore {
+            path,
+            entries: Arc::new(Mutex::new(entries)),
+        })
+    }
+
+    pub fn get(&self, api_key: &str) -> Option<Vec<String>> {
+        self.entries.lock().unwrap().get(api_key).cloned()
+    }
+
+    /// Replaces the client's subscriptions and rewrites the file.
+    pub async fn set(&self, api_key: &str, subscriptions: Vec<String>) -> Result<()> {
+        let json = {
+            let mut entries = self.entries.lock().unwrap();
+            entries.insert(api_key.to_string(), subscriptions);
+            serde_json::to_string_pretty(&*entries)?
+        };
+        // Write then rename so a crash never leaves a truncated file.
+        let tmp = self.path.with_extension("tmp");
+        tokio::fs::write(&tmp, json)
+            .await
+            .with_context(|| format!("writing {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, &self.path)
+            .await
+            .with_context(|| format!("replacing {}", self.path.display()))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn subscriptions_survive_reopening() {
+        let path = std::env::temp_dir().join(format!("ws-subs-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = SubscriptionStore::open(&path).unwrap();
+        assert_eq!(store.get("key-1"), None);
+        store.set("key-1", vec!["AAPL".into()]).await.unwrap();
+
+        let reopened = SubscriptionStore::open(&path).unwrap();
+        assert_eq!(reopened.get("key-1"), Some(vec!["AAPL".to_string()]));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
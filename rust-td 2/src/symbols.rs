@@ -0,0 +1,109 @@
+use crate::protocol::FeedEvent;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::{Arc, RwLock};
+use tokio::sync::broadcast;
+
+/// The symbols the feed is known to publish: seeded at startup, then grown
+/// from every update seen on the broadcast channel.
+#[derive(Debug, Clone, Default)]
+pub struct KnownSymbols(Arc<RwLock<BTreeSet<String>>>);
+
+impl KnownSymbols {
+    pub fn new<I, S>(seed: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let set = seed.into_iter().map(|s| s.into().to_uppercase()).collect();
+        KnownSymbols(Arc::new(RwLock::new(set)))
+    }
+
+    pub fn insert(&self, symbol: &str) {
+        if !self.contains(symbol) {
+            self.0.write().unwrap().insert(symbol.to_uppercase());
+        }
+    }
+
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.0.read().unwrap().contains(&symbol.to_uppercase())
+    }
+
+    /// Sorted list, for `list_symbols`.
+    pub fn list(&self) -> Vec<String> {
+        self.0.read().unwrap().iter().cloned().collect()
+    }
+
+    /// Records the symbol of every update until the channel closes.
+    pub async fn track(self, mut rx: broadcast::Receiver<FeedEvent>) {
+        loop {
+            match rx.recv().await {
+                Ok(event) => self.insert(event.symbol()),
+                Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }
+}
+
+/// Name, exchange and currency of a symbol, shown by UIs next to the ticker.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SymbolMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exchange: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+}
+
+/// Metadata of the symbols, filled by the feed (the `symbols` table of the
+/// DB, or the `pipeline` enrichment) and shared by every listener.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolMetadata(Arc<RwLock<HashMap<String, SymbolMeta>>>);
+
+impl SymbolMetadata {
+    pub fn set(&self, symbol: &str, meta: SymbolMeta) {
+        self.0.write().unwrap().insert(symbol.to_uppercase(), meta);
+    }
+
+    /// The metadata known for `symbols`, for `list_symbols`.
+    pub fn details(&self, symbols: &[String]) -> BTreeMap<String, SymbolMeta> {
+        let all = self.0.read().unwrap();
+        symbols
+            .iter()
+            .filter_map(|s| all.get(&s.to_uppercase()).map(|meta| (s.clone(), meta.clone())))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded_and_learned_symbols_are_known() {
+        let known = KnownSymbols::new(["aapl"]);
+        known.insert("MSFT");
+        assert!(known.contains("AAPL"));
+        assert!(known.contains("msft"));
+        assert!(!known.contains("FOO123"));
+        assert_eq!(known.list(), vec!["AAPL", "MSFT"]);
+    }
+
+    #[test]
+    fn details_cover_listed_symbols_only() {
+        let metadata = SymbolMetadata::default();
+        let apple = SymbolMeta {
+            name: Some("Apple Inc".into()),
+            exchange: Some("NASDAQ".into()),
+            currency: Some("USD".into()),
+        };
+        metadata.set("aapl", apple.clone());
+        metadata.set("TSLA", SymbolMeta::default());
+
+        let details = metadata.details(&["AAPL".into(), "MSFT".into()]);
+        assert_eq!(details.len(), 1);
+        assert_eq!(details["AAPL"], apple);
+    }
+}
@@ -0,0 +1,110 @@
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Inbound command budget of one connection: `per_sec` commands per second
+/// on average, bursts of up to `burst`. After `max_strikes` commands in a
+/// row over budget the client is disconnected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CommandLimit {
+    pub per_sec: f64,
+    pub burst: u32,
+    pub max_strikes: u32,
+}
+
+impl Default for CommandLimit {
+    fn default() -> Self {
+        CommandLimit {
+            per_sec: 20.0,
+            burst: 40,
+            max_strikes: 50,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// Dropped; `notify` on the first command of a streak, so the warning
+    /// itself doesn't flood the client.
+    Throttle { retry_after: Duration, notify: bool },
+    Disconnect,
+}
+
+/// Token bucket over the commands of one connection.
+#[derive(Debug, Clone)]
+pub struct CommandThrottle {
+    limit: CommandLimit,
+    tokens: f64,
+    refilled: Instant,
+    strikes: u32,
+}
+
+impl CommandThrottle {
+    pub fn new(limit: CommandLimit, now: Instant) -> Self {
+        CommandThrottle {
+            limit,
+            tokens: limit.burst as f64,
+            refilled: now,
+            strikes: 0,
+        }
+    }
+
+    pub fn check(&mut self, now: Instant) -> Verdict {
+        let elapsed = now.saturating_duration_since(self.refilled).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.per_sec).min(self.limit.burst as f64);
+        self.refilled = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            self.strikes = 0;
+            return Verdict::Allow;
+        }
+        self.strikes += 1;
+        if self.strikes > self.limit.max_strikes {
+            return Verdict::Disconnect;
+        }
+        Verdict::Throttle {
+            retry_after: Duration::from_secs_f64((1.0 - self.tokens) / self.limit.per_sec),
+            notify: self.strikes == 1,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limit(per_sec: f64, burst: u32, max_strikes: u32) -> CommandLimit {
+        CommandLimit {
+            per_sec,
+            burst,
+            max_strikes,
+        }
+    }
+
+    #[test]
+    fn a_burst_passes_then_the_rate_applies() {
+        let now = Instant::now();
+        let mut throttle = CommandThrottle::new(limit(10.0, 3, 5), now);
+        assert!((0..3).all(|_| throttle.check(now) == Verdict::Allow));
+        assert_eq!(
+            throttle.check(now),
+            Verdict::Throttle {
+                retry_after: Duration::from_millis(100),
+                notify: true
+            }
+        );
+        assert!(matches!(throttle.check(now), Verdict::Throttle { notify: false, .. }));
+        assert_eq!(throttle.check(now + Duration::from_millis(100)), Verdict::Allow);
+    }
+
+    #[test]
+    fn abusers_are_disconnected() {
+        let now = Instant::now();
+        let mut throttle = CommandThrottle::new(limit(1.0, 1, 2), now);
+        let verdicts: Vec<Verdict> = (0..4).map(|_| throttle.check(now)).collect();
+        assert_eq!(verdicts[0], Verdict::Allow);
+        assert!(matches!(verdicts[1], Verdict::Throttle { .. }));
+        assert!(matches!(verdicts[2], Verdict::Throttle { .. }));
+        assert_eq!(verdicts[3], Verdict::Disconnect);
+    }
+}
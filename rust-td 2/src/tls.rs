@@ -0,0 +1,46 @@
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use td_common::{Context, Error, Result};
+use tokio_rustls::rustls::ServerConfig;
+
+/// TLS for `wss://` listeners, from a PEM certificate chain (leaf first)
+/// and its PEM private key (PKCS#8, PKCS#1 or SEC1).
+pub fn server_config(cert_path: &Path, key_path: &Path) -> Result<Arc<ServerConfig>> {
+    let certs = rustls_pemfile::certs(&mut BufReader::new(
+        std::fs::File::open(cert_path).with_context(|| format!("opening certificate {}", cert_path.display()))?,
+    ))
+    .collect::<std::result::Result<Vec<_>, _>>()
+    .with_context(|| format!("reading certificate {}", cert_path.display()))?;
+    if certs.is_empty() {
+        return Err(Error::parse(format!("no certificate in {}", cert_path.display())));
+    }
+    let key = rustls_pemfile::private_key(&mut BufReader::new(
+        std::fs::File::open(key_path).with_context(|| format!("opening private key {}", key_path.display()))?,
+    ))
+    .with_context(|| format!("reading private key {}", key_path.display()))?
+    .ok_or_else(|| Error::parse(format!("no private key in {}", key_path.display())))?;
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| Error::parse(e).context(format!("loading {} and {}", cert_path.display(), key_path.display())))?;
+    Ok(Arc::new(config))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pem_files_without_certificate_or_key_are_rejected() {
+        let dir = std::env::temp_dir();
+        let empty = dir.join(format!("ws-tls-empty-{}.pem", std::process::id()));
+        std::fs::write(&empty, "not a pem file\n").unwrap();
+
+        let err = server_config(&empty, &empty).unwrap_err().to_string();
+        assert!(err.contains("no certificate"), "{}", err);
+        let err = server_config(&dir.join("missing-cert.pem"), &empty).unwrap_err().to_string();
+        assert!(err.contains("opening certificate"), "{}", err);
+        std::fs::remove_file(empty).unwrap();
+    }
+}
@@ -242,14 +242,33 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 */
 
+mod registry;
+
+use clap::Parser;
 use env_logger::{Builder, Target};
 use futures_util::{SinkExt, StreamExt};
 use log::{error, info, warn, LevelFilter};
+use registry::Registry;
 use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
 use tokio::net::{TcpListener, TcpStream};
 use tokio::sync::broadcast;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::CloseFrame;
 use tokio_tungstenite::{accept_async, tungstenite::Message};
 
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Cli {
+    /// Clients served at once; the next ones are closed with "server full" (0 = unlimited)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    max_clients: usize,
+
+    /// Print the connection statistics every N seconds (0 = off)
+    #[arg(long, value_name = "SECS", default_value_t = 30)]
+    stats_interval: u64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct PriceUpdate {
     symbol: String,
@@ -258,24 +277,49 @@ struct PriceUpdate {
     timestamp: i64,
 }
 
+/// Texte de `/info` : la ligne de résumé puis une ligne par client.
+fn info_text(registry: &Registry) -> String {
+    let snapshot = registry.snapshot(Instant::now());
+    let mut text = snapshot.to_string();
+    for client in &snapshot.clients {
+        text.push_str(&format!(
+            "\n{} connected {}s, {} messages, {} bytes",
+            client.addr, client.connected_secs, client.messages, client.bytes
+        ));
+    }
+    text
+}
 
-async fn handle_client(stream: TcpStream, mut rx: broadcast::Receiver<PriceUpdate>) {
+async fn handle_client(stream: TcpStream, mut rx: broadcast::Receiver<PriceUpdate>, registry: Registry) {
     let addr = match stream.peer_addr() {
         Ok(a) => a,
         Err(_) => return,
     };
+    let accepted = registry.register(addr, Instant::now());
     info!("New client connected: {}", addr);
 
     let ws_stream = match accept_async(stream).await {
         Ok(ws) => ws,
         Err(e) => {
             error!("WebSocket handshake failed: {}", e);
+            registry.unregister(addr);
             return;
         }
     };
 
     let (mut write, mut read) = ws_stream.split();
 
+    // Serveur plein : on ferme proprement pour que le client réessaie plus tard.
+    if !accepted {
+        warn!("Rejecting {}: server full", addr);
+        let frame = CloseFrame {
+            code: CloseCode::Again,
+            reason: "server full".into(),
+        };
+        let _ = write.send(Message::Close(Some(frame))).await;
+        return;
+    }
+
     let welcome = serde_json::json!({
         "type": "connected",
         "message": "Connected to stock price feed"
@@ -286,6 +330,7 @@ async fn handle_client(stream: TcpStream, mut rx: broadcast::Receiver<PriceUpdat
         .await
         .is_err()
     {
+        registry.unregister(addr);
         return;
     }
 
@@ -306,11 +351,18 @@ async fn handle_client(stream: TcpStream, mut rx: broadcast::Receiver<PriceUpdat
                 }
             }
 
-         
+            // `/info` donne les statistiques, le reste est renvoyé tel quel
             msg = read.next() => {
-                match msg {
+                let reply = match msg {
+                    Some(Ok(Message::Text(text))) if text.trim() == "/info" => Message::Text(info_text(&registry)),
                     Some(Ok(Message::Text(text))) => {
                         info!("Received from {}: {}", addr, text);
+                        registry.record_echo(addr, text.len(), Instant::now());
+                        Message::Text(text)
+                    }
+                    Some(Ok(Message::Binary(data))) => {
+                        registry.record_echo(addr, data.len(), Instant::now());
+                        Message::Binary(data)
                     }
                     Some(Ok(Message::Close(_))) | None => {
                         info!("Client closed connection: {}", addr);
@@ -320,22 +372,27 @@ async fn handle_client(stream: TcpStream, mut rx: broadcast::Receiver<PriceUpdat
                         warn!("WebSocket error: {}", e);
                         break;
                     }
-                    _ => {}
+                    _ => continue,
+                };
+                if write.send(reply).await.is_err() {
+                    info!("Client disconnected: {}", addr);
+                    break;
                 }
             }
         }
     }
 
+    registry.unregister(addr);
     info!("Connection handler finished: {}", addr);
 }
 
 async fn fake_price_poller(tx: broadcast::Sender<PriceUpdate>) {
     use rand::Rng;
-    use tokio::time::{interval, Duration};
+    use tokio::time::interval;
 
     let mut timer = interval(Duration::from_secs(2));
-    let symbols = vec!["AAPL", "GOOGL", "MSFT"];
-    let sources = vec!["alpha_vantage", "finnhub"];
+    let symbols = ["AAPL", "GOOGL", "MSFT"];
+    let sources = ["alpha_vantage", "finnhub"];
 
     loop {
         timer.tick().await;
@@ -357,8 +414,19 @@ async fn fake_price_poller(tx: broadcast::Sender<PriceUpdate>) {
     }
 }
 
+async fn report_stats(registry: Registry, every: Duration) {
+    let mut timer = tokio::time::interval(every);
+    timer.tick().await;
+    loop {
+        timer.tick().await;
+        info!("{}", registry.snapshot(Instant::now()));
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+
     Builder::new()
         .target(Target::Stdout)
         .filter_level(LevelFilter::Info)
@@ -375,12 +443,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         });
     }
 
+    let registry = Registry::new((cli.max_clients > 0).then_some(cli.max_clients), Instant::now());
+    if cli.stats_interval > 0 {
+        tokio::spawn(report_stats(registry.clone(), Duration::from_secs(cli.stats_interval)));
+    }
+
     let listener = TcpListener::bind("127.0.0.1:8080").await?;
     info!("WebSocket (fake DB) listening on ws://127.0.0.1:8080");
 
     while let Ok((stream, _)) = listener.accept().await {
         let rx = tx.subscribe();
-        tokio::spawn(handle_client(stream, rx));
+        tokio::spawn(handle_client(stream, rx, registry.clone()));
     }
 
     Ok(())
@@ -0,0 +1,180 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Fenêtre du débit en messages par seconde.
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+struct Client {
+    connected_at: Instant,
+    messages: u64,
+    bytes: u64,
+}
+
+#[derive(Debug)]
+struct Inner {
+    started: Instant,
+    max_clients: Option<usize>,
+    clients: HashMap<SocketAddr, Client>,
+    messages: u64,
+    bytes: u64,
+    /// Messages renvoyés par seconde depuis le démarrage : (seconde, nombre).
+    per_second: VecDeque<(u64, u64)>,
+}
+
+/// Registre partagé par toutes les connexions : clients connectés, octets
+/// renvoyés et débit.
+#[derive(Debug, Clone)]
+pub struct Registry(Arc<Mutex<Inner>>);
+
+/// Une connexion, pour `/info`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClientInfo {
+    pub addr: SocketAddr,
+    pub connected_secs: u64,
+    pub messages: u64,
+    pub bytes: u64,
+}
+
+/// État du serveur à un instant donné.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub clients: Vec<ClientInfo>,
+    pub max_clients: Option<usize>,
+    pub messages: u64,
+    pub bytes: u64,
+    pub messages_per_sec: f64,
+}
+
+impl Registry {
+    /// `max_clients` : `None` = pas de limite.
+    pub fn new(max_clients: Option<usize>, now: Instant) -> Self {
+        Registry(Arc::new(Mutex::new(Inner {
+            started: now,
+            max_clients,
+            clients: HashMap::new(),
+            messages: 0,
+            bytes: 0,
+            per_second: VecDeque::new(),
+        })))
+    }
+
+    /// Ajoute un client ; `false` quand le serveur est plein.
+    pub fn register(&self, addr: SocketAddr, now: Instant) -> bool {
+        let mut inner = self.0.lock().unwrap();
+        if inner.max_clients.is_some_and(|max| inner.clients.len() >= max) {
+            return false;
+        }
+        inner.clients.insert(
+            addr,
+            Client {
+                connected_at: now,
+                messages: 0,
+                bytes: 0,
+            },
+        );
+        true
+    }
+
+    pub fn unregister(&self, addr: SocketAddr) {
+        self.0.lock().unwrap().clients.remove(&addr);
+    }
+
+    pub fn record_echo(&self, addr: SocketAddr, bytes: usize, now: Instant) {
+        let mut inner = self.0.lock().unwrap();
+        let second = now.duration_since(inner.started).as_secs();
+        match inner.per_second.back_mut() {
+            Some((last, count)) if *last == second => *count += 1,
+            _ => inner.per_second.push_back((second, 1)),
+        }
+        while inner.per_second.front().is_some_and(|(s, _)| second - s >= RATE_WINDOW.as_secs()) {
+            inner.per_second.pop_front();
+        }
+        inner.messages += 1;
+        inner.bytes += bytes as u64;
+        if let Some(client) = inner.clients.get_mut(&addr) {
+            client.messages += 1;
+            client.bytes += bytes as u64;
+        }
+    }
+
+    pub fn snapshot(&self, now: Instant) -> Snapshot {
+        let inner = self.0.lock().unwrap();
+        let elapsed = now.duration_since(inner.started);
+        let second = elapsed.as_secs();
+        let recent: u64 = inner
+            .per_second
+            .iter()
+            .filter(|(s, _)| second - s < RATE_WINDOW.as_secs())
+            .map(|(_, count)| count)
+            .sum();
+        // Au démarrage, le débit porte sur le temps écoulé.
+        let window = elapsed.min(RATE_WINDOW).as_secs_f64().max(1.0);
+        let mut clients: Vec<ClientInfo> = inner
+            .clients
+            .iter()
+            .map(|(addr, client)| ClientInfo {
+                addr: *addr,
+                connected_secs: now.duration_since(client.connected_at).as_secs(),
+                messages: client.messages,
+                bytes: client.bytes,
+            })
+            .collect();
+        clients.sort_by_key(|c| c.addr);
+        Snapshot {
+            clients,
+            max_clients: inner.max_clients,
+            messages: inner.messages,
+            bytes: inner.bytes,
+            messages_per_sec: recent as f64 / window,
+        }
+    }
+}
+
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let max = self.max_clients.map(|max| format!("/{}", max)).unwrap_or_default();
+        write!(
+            f,
+            "clients: {}{}, echoed: {} messages ({} bytes), rate: {:.1} msg/s",
+            self.clients.len(),
+            max,
+            self.messages,
+            self.bytes,
+            self.messages_per_sec
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_and_counters() {
+        let start = Instant::now();
+        let registry = Registry::new(Some(1), start);
+        let (a, b): (SocketAddr, SocketAddr) = ("127.0.0.1:5000".parse().unwrap(), "127.0.0.1:5001".parse().unwrap());
+        assert!(registry.register(a, start));
+        assert!(!registry.register(b, start));
+
+        registry.record_echo(a, 5, start);
+        registry.record_echo(a, 7, start + Duration::from_secs(1));
+        let snapshot = registry.snapshot(start + Duration::from_secs(2));
+        assert_eq!((snapshot.messages, snapshot.bytes), (2, 12));
+        assert_eq!(snapshot.clients[0].messages, 2);
+        assert!((snapshot.messages_per_sec - 1.0).abs() < 1e-9);
+        assert_eq!(
+            snapshot.to_string(),
+            "clients: 1/1, echoed: 2 messages (12 bytes), rate: 1.0 msg/s"
+        );
+        // Hors de la fenêtre, le débit retombe à zéro.
+        assert_eq!(registry.snapshot(start + Duration::from_secs(30)).messages_per_sec, 0.0);
+
+        registry.unregister(a);
+        assert!(registry.register(b, start));
+    }
+}
@@ -0,0 +1,276 @@
+// Petit langage d'expressions de `--count-where`, ex.
+// `level==ERROR && msg~"timeout"` ou `!(thread==main) || level!=DEBUG`.
+//
+// Champs : `level`, `msg` (ou `message`), `thread`, `ts` (ou `timestamp`).
+// Opérateurs : `==`, `!=`, `~` (regex), `!~`, combinés par `&&`, `||`, `!`
+// et des parenthèses. Les valeurs sont des mots (`ERROR`) ou des chaînes
+// entre guillemets (`"disk full"`, `\"` pour un guillemet).
+
+use regex::Regex;
+
+/// Les champs d'une entrée, tels que vus par une expression ; `level` est
+/// en majuscules (`ERROR`, `WARNING`, `INFO`, `DEBUG`).
+pub struct Fields<'a> {
+    pub level: &'a str,
+    pub message: &'a str,
+    pub thread: Option<&'a str>,
+    pub timestamp: &'a str,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Level,
+    Message,
+    Thread,
+    Timestamp,
+}
+
+#[derive(Debug)]
+enum Test {
+    Eq(String),
+    Ne(String),
+    Matches(Regex),
+    NotMatches(Regex),
+}
+
+#[derive(Debug)]
+enum Node {
+    Cmp(Field, Test),
+    Not(Box<Node>),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+}
+
+/// Une expression compilée.
+#[derive(Debug)]
+pub struct Expr(Node);
+
+impl Expr {
+    pub fn parse(text: &str) -> Result<Expr, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let node = parser.or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(Expr(node)),
+            Some(token) => Err(format!("unexpected `{}`", token)),
+        }
+    }
+
+    pub fn matches(&self, fields: &Fields) -> bool {
+        self.0.matches(fields)
+    }
+}
+
+impl Node {
+    fn matches(&self, fields: &Fields) -> bool {
+        match self {
+            Node::Cmp(field, test) => {
+                let value = match field {
+                    Field::Level => fields.level,
+                    Field::Message => fields.message,
+                    Field::Thread => fields.thread.unwrap_or(""),
+                    Field::Timestamp => fields.timestamp,
+                };
+                match test {
+                    Test::Eq(expected) => value == expected,
+                    Test::Ne(expected) => value != expected,
+                    Test::Matches(re) => re.is_match(value),
+                    Test::NotMatches(re) => !re.is_match(value),
+                }
+            }
+            Node::Not(inner) => !inner.matches(fields),
+            Node::And(a, b) => a.matches(fields) && b.matches(fields),
+            Node::Or(a, b) => a.matches(fields) || b.matches(fields),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Op(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(w) => f.write_str(w),
+            Token::Str(s) => write!(f, "\"{}\"", s),
+            Token::Op(op) => f.write_str(op),
+        }
+    }
+}
+
+// les opérateurs de deux caractères d'abord
+const OPERATORS: [&str; 9] = ["&&", "||", "==", "!=", "!~", "~", "!", "(", ")"];
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text.trim_start();
+    while !rest.is_empty() {
+        if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else if let Some(quoted) = rest.strip_prefix('"') {
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let end = loop {
+                match chars.next() {
+                    Some((i, '"')) => break i,
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, c)) => value.push(c),
+                        None => return Err("unterminated string".into()),
+                    },
+                    Some((_, c)) => value.push(c),
+                    None => return Err("unterminated string".into()),
+                }
+            };
+            tokens.push(Token::Str(value));
+            rest = &quoted[end + 1..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "&|=!~()\"".contains(c))
+                .unwrap_or(rest.len());
+            if end == 0 {
+                return Err(format!("unexpected `{}`", &rest[..1]));
+            }
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if matches!(self.tokens.get(self.pos), Some(Token::Op(o)) if *o == op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Node, String> {
+        let mut expr = self.and()?;
+        while self.eat("||") {
+            expr = Node::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Node, String> {
+        let mut expr = self.unary()?;
+        while self.eat("&&") {
+            expr = Node::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.eat("!") {
+            return Ok(Node::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("(") {
+            let expr = self.or()?;
+            if !self.eat(")") {
+                return Err("missing `)`".into());
+            }
+            return Ok(expr);
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Node, String> {
+        let field = match self.next() {
+            Some(Token::Word(name)) => match name.as_str() {
+                "level" => Field::Level,
+                "msg" | "message" => Field::Message,
+                "thread" => Field::Thread,
+                "ts" | "timestamp" => Field::Timestamp,
+                _ => return Err(format!("unknown field `{}` (level, msg, thread, ts)", name)),
+            },
+            Some(token) => return Err(format!("expected a field, got `{}`", token)),
+            None => return Err("expected a field".into()),
+        };
+        let op = match self.next() {
+            Some(Token::Op(op)) if ["==", "!=", "~", "!~"].contains(&op) => op,
+            Some(token) => return Err(format!("expected ==, !=, ~ or !~, got `{}`", token)),
+            None => return Err("expected ==, !=, ~ or !~".into()),
+        };
+        let mut value = match self.next() {
+            Some(Token::Word(v)) | Some(Token::Str(v)) => v,
+            Some(token) => return Err(format!("expected a value, got `{}`", token)),
+            None => return Err("expected a value".into()),
+        };
+        if field == Field::Level && (op == "==" || op == "!=") {
+            value = value.to_uppercase();
+            if value == "WARN" {
+                value = "WARNING".to_string();
+            }
+        }
+        let regex = || Regex::new(&value).map_err(|e| format!("invalid regex `{}`: {}", value, e));
+        let test = match op {
+            "==" => Test::Eq(value.clone()),
+            "!=" => Test::Ne(value.clone()),
+            "~" => Test::Matches(regex()?),
+            _ => Test::NotMatches(regex()?),
+        };
+        Ok(Node::Cmp(field, test))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields<'a>(level: &'a str, message: &'a str, thread: Option<&'a str>) -> Fields<'a> {
+        Fields {
+            level,
+            message,
+            thread,
+            timestamp: "2024-01-15 10:00:00",
+        }
+    }
+
+    #[test]
+    fn expressions_combine_comparisons() {
+        let expr = Expr::parse(r#"level==error && msg~"time(d )?out""#).unwrap();
+        assert!(expr.matches(&fields("ERROR", "API timeout", None)));
+        assert!(!expr.matches(&fields("WARNING", "API timeout", None)));
+        assert!(!expr.matches(&fields("ERROR", "disk full", None)));
+
+        let expr = Expr::parse(r#"!(thread==main) || level==WARN"#).unwrap();
+        assert!(expr.matches(&fields("INFO", "x", Some("worker-1"))));
+        assert!(expr.matches(&fields("WARNING", "x", Some("main"))));
+        assert!(!expr.matches(&fields("INFO", "x", Some("main"))));
+
+        // && lie plus fort que ||
+        let expr = Expr::parse(r#"level==INFO || level==ERROR && msg!~"\"ok\"""#).unwrap();
+        assert!(expr.matches(&fields("INFO", "\"ok\"", None)));
+        assert!(!expr.matches(&fields("ERROR", "said \"ok\"", None)));
+    }
+
+    #[test]
+    fn bad_expressions_are_explained() {
+        assert_eq!(Expr::parse("lvl==ERROR").unwrap_err(), "unknown field `lvl` (level, msg, thread, ts)");
+        assert!(Expr::parse("level==").is_err());
+        assert!(Expr::parse("(level==ERROR").is_err());
+        assert!(Expr::parse(r#"msg~"unterminated"#).is_err());
+        assert!(Expr::parse(r#"msg~"(""#).unwrap_err().starts_with("invalid regex"));
+        assert!(Expr::parse("level==ERROR level==INFO").is_err());
+    }
+}
@@ -0,0 +1,77 @@
+// Paires `clé=valeur` des messages façon logfmt, ex.
+// `request done status_code=200 path=/api user="Jane Doe"`.
+//
+// Une clé commence un mot (après un `(` ou `[` éventuel) par une lettre ou
+// `_`, puis lettres, chiffres, `_`, `.` et `-`. Une valeur va jusqu'au
+// prochain blanc, sans `,` `;` `)` `]` final, ou est entre guillemets (`\"`
+// pour un guillemet). Une clé répétée garde sa première valeur.
+
+use std::collections::BTreeMap;
+
+pub fn extract(message: &str) -> BTreeMap<String, String> {
+    let mut fields = BTreeMap::new();
+    let mut rest = message.trim_start();
+    while !rest.is_empty() {
+        let word = rest.trim_start_matches(['(', '[']);
+        let key_len = word.find(|c: char| !is_key_char(c)).unwrap_or(word.len());
+        let key = &word[..key_len];
+        if key.starts_with(|c: char| c.is_alphabetic() || c == '_') && word[key_len..].starts_with('=') {
+            let (value, after) = value(&word[key_len + 1..]);
+            fields.entry(key.to_string()).or_insert(value);
+            rest = after;
+        } else {
+            rest = word.find(char::is_whitespace).map_or("", |i| &word[i..]);
+        }
+        rest = rest.trim_start();
+    }
+    fields
+}
+
+fn is_key_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | '-')
+}
+
+/// Valeur qui suit un `=`, et la suite du message.
+fn value(text: &str) -> (String, &str) {
+    let Some(quoted) = text.strip_prefix('"') else {
+        let end = text.find(char::is_whitespace).unwrap_or(text.len());
+        return (text[..end].trim_end_matches([',', ';', ')', ']']).to_string(), &text[end..]);
+    };
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return (value, &quoted[i + 1..]),
+            '\\' => value.extend(chars.next().map(|(_, escaped)| escaped)),
+            c => value.push(c),
+        }
+    }
+    // guillemet jamais fermé : jusqu'à la fin du message
+    (value, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logfmt_pairs() {
+        let fields = extract(r#"request done status_code=200 path=/api/v1?x=1, user="Jane \"JD\" Doe" (pid=42) dur=1.5s"#);
+        let pairs: Vec<(&str, &str)> = fields.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+        assert_eq!(
+            pairs,
+            vec![
+                ("dur", "1.5s"),
+                ("path", "/api/v1?x=1"),
+                ("pid", "42"),
+                ("status_code", "200"),
+                ("user", "Jane \"JD\" Doe"),
+            ]
+        );
+
+        // ni URL, ni comparaison, ni clé qui commence par un chiffre
+        assert!(extract("GET https://host/a?b=c failed, 2=3 and a == b").is_empty());
+        assert_eq!(extract("a=1 a=2 empty= b=\"open").len(), 3);
+        assert_eq!(extract("b=\"open end")["b"], "open end");
+    }
+}
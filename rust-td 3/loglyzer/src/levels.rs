@@ -0,0 +1,110 @@
+// Correspondance entre les niveaux écrits dans les logs (`NOTICE`, `FATAL`,
+// `TRACE`...) et les quatre niveaux de l'analyse, complétée par
+// `--level-map` et `--level-config`. Les lignes d'un niveau inconnu sont
+// comptées pour être signalées au lieu de disparaître sans bruit.
+
+use crate::LogLevel;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// Niveaux reconnus sans configuration.
+const BUILTIN: &[(&str, LogLevel)] = &[
+    ("INFO", LogLevel::Info),
+    ("NOTICE", LogLevel::Info),
+    ("WARN", LogLevel::Warning),
+    ("WARNING", LogLevel::Warning),
+    ("ERROR", LogLevel::Error),
+    ("FATAL", LogLevel::Error),
+    ("CRITICAL", LogLevel::Error),
+    ("DEBUG", LogLevel::Debug),
+    ("TRACE", LogLevel::Debug),
+];
+
+#[derive(Debug)]
+pub struct LevelMap {
+    levels: HashMap<String, LogLevel>,
+    /// Niveaux rencontrés sans correspondance, avec leur nombre de lignes.
+    unmapped: Mutex<BTreeMap<String, usize>>,
+}
+
+impl Default for LevelMap {
+    fn default() -> Self {
+        LevelMap {
+            levels: BUILTIN.iter().map(|(name, level)| (name.to_string(), level.clone())).collect(),
+            unmapped: Mutex::default(),
+        }
+    }
+}
+
+impl LevelMap {
+    /// Ajoute `NOM=>NIVEAU[,NOM=>NIVEAU...]` (`=` suffit) ; un nom déjà
+    /// connu change de niveau.
+    pub fn add(&mut self, spec: &str) -> Result<(), String> {
+        for mapping in spec.split(',').map(str::trim).filter(|m| !m.is_empty()) {
+            let (name, level) = mapping
+                .split_once("=>")
+                .or_else(|| mapping.split_once('='))
+                .ok_or_else(|| format!("expected NAME=>LEVEL, got `{}`", mapping))?;
+            let (name, level) = (name.trim(), level.trim());
+            if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                return Err(format!("invalid level name `{}`", name));
+            }
+            let level = LogLevel::from_str(level)
+                .ok_or_else(|| format!("unknown level `{}` (expected Info, Warning, Error or Debug)", level))?;
+            self.levels.insert(name.to_uppercase(), level);
+        }
+        Ok(())
+    }
+
+    /// Contenu d'un `--level-config` : une ou plusieurs correspondances par
+    /// ligne, `#` pour les commentaires.
+    pub fn add_config(&mut self, text: &str) -> Result<(), String> {
+        for (n, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default();
+            self.add(line).map_err(|e| format!("line {}: {}", n + 1, e))?;
+        }
+        Ok(())
+    }
+
+    /// Niveau d'un nom lu dans une ligne ; `None` (et la ligne est comptée)
+    /// quand il n'a pas de correspondance.
+    pub fn level(&self, name: &str) -> Option<LogLevel> {
+        let name = name.to_uppercase();
+        let level = self.levels.get(&name).cloned();
+        if level.is_none() {
+            *self.unmapped.lock().unwrap().entry(name).or_insert(0) += 1;
+        }
+        level
+    }
+
+    pub fn unmapped(&self) -> BTreeMap<String, usize> {
+        self.unmapped.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn configured_levels_override_the_builtin_ones() {
+        let mut levels = LevelMap::default();
+        assert_eq!(levels.level("fatal"), Some(LogLevel::Error));
+        levels.add("NOTICE=>Warning, audit = info").unwrap();
+        levels
+            .add_config("# niveaux de l'appli\nSEVERE=>ERROR\n\nFINE=>debug  # java.util.logging\n")
+            .unwrap();
+        assert_eq!(levels.level("Notice"), Some(LogLevel::Warning));
+        assert_eq!(levels.level("AUDIT"), Some(LogLevel::Info));
+        assert_eq!(levels.level("SEVERE"), Some(LogLevel::Error));
+        assert_eq!(levels.level("FINE"), Some(LogLevel::Debug));
+
+        assert_eq!(levels.level("VERBOSE"), None);
+        assert_eq!(levels.level("verbose"), None);
+        assert_eq!(levels.unmapped(), BTreeMap::from([("VERBOSE".to_string(), 2)]));
+
+        assert!(levels.add("NOTICE=>Loud").is_err());
+        assert!(levels.add("NOTICE").is_err());
+        assert!(levels.add_config("OK=>Info\nbad line").unwrap_err().starts_with("line 2"));
+    }
+}
@@ -1,17 +1,29 @@
 
 // PARTIE 1 
+mod expr;
+mod kv;
+mod levels;
+mod sources;
+
 use clap::Parser;
 use colored::*;
+use expr::{Expr, Fields};
+use indicatif::{ProgressBar, ProgressStyle};
+use levels::LevelMap;
 use once_cell::sync::Lazy;
 use prettytable::{Cell, Row, Table};
 use rayon::prelude::*;
 use regex::Regex;
 use serde::Serialize;
-use std::collections::HashMap;
+use sources::InputKind;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
 use std::time::Instant;
+use td_common::{Context, Error, Result};
 
 /// CLI du projet (options utilisateur)
 #[derive(Parser, Debug)]
@@ -19,8 +31,18 @@ use std::time::Instant;
 #[command(version = "1.0")]
 #[command(about = "Analyze log files and extract patterns", long_about = None)]
 struct Cli {
+    /// Fichiers de logs, lus en parallèle et fusionnés dans l'ordre
+    /// chronologique ; avec `--input journald`, fichier journal optionnel
+    /// (sinon le journal du système) ; avec `--input evtx`, fichier .evtx ou
+    /// nom de journal (`System`)
     #[arg(value_name = "FILE")]
-    input: PathBuf,
+    input: Vec<PathBuf>,
+
+    /// Source des entrées : fichier texte, journal systemd (`journalctl`) ou
+    /// journal d'événements Windows (PowerShell `Get-WinEvent`, sous Windows
+    /// uniquement ; ailleurs `--input evtx` échoue)
+    #[arg(long = "input", value_name = "KIND", value_enum, default_value = "file")]
+    input_kind: InputKind,
 
     #[arg(short, long, value_enum, default_value = "text")]
     format: OutputFormat,
@@ -40,8 +62,93 @@ struct Cli {
     #[arg(long, value_name = "FILE")]
     output: Option<PathBuf>,
 
+    /// Ignore les entrées avant cette date (`YYYY-MM-DD[ HH:MM:SS]`)
+    #[arg(long, value_name = "TIME", value_parser = parse_time_bound)]
+    since: Option<i64>,
+
+    /// Ignore les entrées à partir de cette date (`YYYY-MM-DD[ HH:MM:SS]`)
+    #[arg(long, value_name = "TIME", value_parser = parse_time_bound)]
+    until: Option<i64>,
+
+    /// Écrit aussi les lignes brutes qui passent tous les filtres dans ce fichier
+    #[arg(long, value_name = "FILE")]
+    emit_matching: Option<PathBuf>,
+
+    /// Compteur personnalisé : entrées qui vérifient l'expression, ex.
+    /// 'level==ERROR && msg~"timeout"' (répétable)
+    #[arg(long = "count-where", value_name = "EXPR")]
+    count_where: Vec<String>,
+
+    /// Nom du compteur de chaque --count-where, dans le même ordre
+    #[arg(long = "as", value_name = "NAME")]
+    count_as: Vec<String>,
+
+    /// Range des niveaux propres à l'application sous Info, Warning, Error ou
+    /// Debug, ex. 'NOTICE=>Warning,SEVERE=>Error' (répétable)
+    #[arg(long = "level-map", value_name = "NAME=>LEVEL")]
+    level_map: Vec<String>,
+
+    /// Fichier de correspondances de niveaux : `NOM=>NIVEAU` par ligne, `#`
+    /// pour les commentaires ; --level-map l'emporte
+    #[arg(long, value_name = "FILE")]
+    level_config: Option<PathBuf>,
+
     #[arg(long)]
     parallel: bool,
+
+    /// Threads de lecture et d'analyse, partagés entre les fichiers et les
+    /// blocs des gros fichiers (0 = un par cœur)
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    threads: usize,
+
+    /// N'analyse que les N premières entrées reconnues
+    #[arg(long, value_name = "N", conflicts_with = "tail")]
+    head: Option<usize>,
+
+    /// N'analyse que les N dernières entrées reconnues (lecture depuis la fin)
+    #[arg(long, value_name = "N")]
+    tail: Option<usize>,
+
+    /// Taille des tranches de `errors_by_time`
+    #[arg(long, value_enum, default_value = "hour")]
+    bucket: Bucket,
+
+    /// Ajoute des recommandations d'hygiène des logs au rapport
+    #[arg(long)]
+    advise: bool,
+
+    /// --advise : part du volume (%) au-delà de laquelle DEBUG ou INFO est signalé
+    #[arg(long, value_name = "PCT", default_value_t = 30.0)]
+    advise_level_pct: f64,
+
+    /// --advise : part des erreurs (%) au-delà de laquelle un message est signalé
+    #[arg(long, value_name = "PCT", default_value_t = 20.0)]
+    advise_message_pct: f64,
+
+    /// --advise : entropie (bits) des messages d'un niveau en dessous de
+    /// laquelle quelques gabarits dominent
+    #[arg(long, value_name = "BITS", default_value_t = 1.0)]
+    advise_min_entropy: f64,
+
+    /// Cherche les warnings suivis d'une erreur dans les N secondes plus
+    /// souvent que le hasard
+    #[arg(long, value_name = "SECS")]
+    correlate_secs: Option<u64>,
+
+    /// Entrées, erreurs et taux d'erreur par valeur d'une clé des paires
+    /// `clé=valeur` des messages, ex. 'key:status_code'
+    #[arg(long, value_name = "key:NAME", value_parser = parse_group_by)]
+    group_by: Option<String>,
+
+    /// Regroupe les entrées par identifiant de requête, capturé par le groupe
+    /// `id` (ou le premier groupe) de cette regex, ex. 'req=(?P<id>[a-f0-9-]+)'
+    #[arg(long, value_name = "REGEX")]
+    trace_id_regex: Option<String>,
+
+    /// Version du JSON de `--format json` (1 = forme d'origine, sans les
+    /// champs ajoutés depuis)
+    #[arg(long, value_name = "N", default_value_t = SCHEMA_VERSION, value_parser = clap::value_parser!(u32).range(1..=2))]
+    schema_version: u32,
 }
 
 #[derive(Debug, Clone, clap::ValueEnum)]
@@ -49,6 +156,41 @@ enum OutputFormat {
     Text,
     Json,
     Csv,
+    /// Format texte Prometheus, pour le collecteur textfile de node_exporter
+    Prometheus,
+}
+
+/// Tranche de temps des erreurs, datée : `2024-01-15`, `2024-01-15 10:00`
+/// ou `2024-01-15 10:23`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+enum Bucket {
+    Day,
+    Hour,
+    Minute,
+}
+
+impl Bucket {
+    fn name(self) -> &'static str {
+        match self {
+            Bucket::Day => "day",
+            Bucket::Hour => "hour",
+            Bucket::Minute => "minute",
+        }
+    }
+
+    /// Clé de la tranche d'un timestamp `YYYY-MM-DD HH:MM:SS` ; l'ordre
+    /// alphabétique des clés est l'ordre chronologique.
+    fn key(self, timestamp: &str) -> Option<String> {
+        let mut parts = timestamp.split_whitespace();
+        let date = parts.next()?;
+        let time = parts.next()?;
+        match self {
+            Bucket::Day => Some(date.to_string()),
+            Bucket::Hour => Some(format!("{} {}:00", date, time.get(0..2)?)),
+            Bucket::Minute => Some(format!("{} {}", date, time.get(0..5)?)),
+        }
+    }
 }
 
 
@@ -57,9 +199,15 @@ enum OutputFormat {
 //Modèle pour une entrée de log
 #[derive(Debug, Clone)]
 struct LogEntry {
+    /// Position du début de la ligne dans le fichier, pour `--emit-matching`.
+    offset: u64,
     timestamp: String,
     level: LogLevel,
+    /// `main` pour `[main]` après le niveau, `pid=1234` pour un jeton `pid=`.
+    thread: Option<String>,
     message: String,
+    /// Paires `clé=valeur` du message.
+    fields: BTreeMap<String, String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -71,6 +219,15 @@ enum LogLevel {
 }
 
 impl LogLevel {
+    fn name(&self) -> &'static str {
+        match self {
+            LogLevel::Info => "INFO",
+            LogLevel::Warning => "WARNING",
+            LogLevel::Error => "ERROR",
+            LogLevel::Debug => "DEBUG",
+        }
+    }
+
     fn from_str(s: &str) -> Option<Self> {
         match s.to_uppercase().as_str() {
             "INFO" => Some(LogLevel::Info),
@@ -83,48 +240,300 @@ impl LogLevel {
 }
 
 // Regex compilée une seule fois
+// Le thread est un `[nom]` optionnel juste après le niveau.
 static LOG_LINE_RE: Lazy<Regex> = Lazy::new(|| {
-    Regex::new(r"^(\d{4}-\d{2}-\d{2}\s+\d{2}:\d{2}:\d{2})\s+\[(\w+)\]\s+(.+)$").unwrap()
+    Regex::new(r"^(\d{4}-\d{2}-\d{2}\s+\d{2}:\d{2}:\d{2})\s+\[(\w+)\]\s+(?:\[([^\]\s]+)\]\s+)?(.+)$").unwrap()
 });
 
-fn parse_log_line(line: &str) -> Option<LogEntry> {
+static PID_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\bpid=(\d+)\b").unwrap());
+
+fn parse_log_line(line: &str, levels: &LevelMap) -> Option<LogEntry> {
     LOG_LINE_RE.captures(line).and_then(|caps| {
+        let message = caps.get(4)?.as_str();
+        let thread = match caps.get(3) {
+            Some(t) => Some(t.as_str().to_string()),
+            None => PID_RE.captures(message).map(|p| format!("pid={}", &p[1])),
+        };
         Some(LogEntry {
+            offset: 0,
             timestamp: caps.get(1)?.as_str().to_string(),
-            level: LogLevel::from_str(caps.get(2)?.as_str())?,
-            message: caps.get(3)?.as_str().to_string(),
+            level: levels.level(caps.get(2)?.as_str())?,
+            thread,
+            message: message.to_string(),
+            fields: kv::extract(message),
         })
     })
 }
 
+/// Au-delà de cette taille : lecture parallèle et barre de progression.
+const LARGE_FILE_BYTES: u64 = 10_000_000;
+
+/// Suivi d'une lecture : barre de progression (octets lus), ctrl-c, qui
+/// arrête la lecture pour analyser ce qui a déjà été lu, et niveaux reconnus.
+struct Reading {
+    bar: Option<ProgressBar>,
+    bytes_read: AtomicU64,
+    interrupted: Arc<AtomicBool>,
+    levels: Arc<LevelMap>,
+    /// Lignes non vides qui ne sont pas une entrée (format, niveau inconnu).
+    unparsed: AtomicU64,
+}
+
+impl Reading {
+    fn new(bar: Option<ProgressBar>, interrupted: Arc<AtomicBool>, levels: Arc<LevelMap>) -> Self {
+        Reading {
+            bar,
+            bytes_read: AtomicU64::new(0),
+            interrupted,
+            levels,
+            unparsed: AtomicU64::new(0),
+        }
+    }
+
+    /// Entrée de la ligne commençant à `offset` ; compte celles qui n'en sont pas.
+    fn parse(&self, line: &str, offset: u64) -> Option<LogEntry> {
+        let entry = parse_log_line_at(line, offset, &self.levels);
+        if entry.is_none() && !line.trim().is_empty() {
+            self.unparsed.fetch_add(1, Ordering::Relaxed);
+        }
+        entry
+    }
+
+    fn advance(&self, bytes: u64) {
+        self.bytes_read.fetch_add(bytes, Ordering::Relaxed);
+        if let Some(bar) = &self.bar {
+            bar.inc(bytes);
+        }
+    }
+
+    fn interrupted(&self) -> bool {
+        self.interrupted.load(Ordering::Relaxed)
+    }
+
+    fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+fn progress_bar(total: u64) -> ProgressBar {
+    let bar = ProgressBar::new(total);
+    if let Ok(style) = ProgressStyle::with_template("{bar:40.cyan/blue} {bytes}/{total_bytes} ({eta}) {msg}") {
+        bar.set_style(style);
+    }
+    bar.set_message("ctrl-c: partial results");
+    bar
+}
+
+/// Ligne lue avec sa position dans le fichier.
+fn parse_log_line_at(line: &str, offset: u64, levels: &LevelMap) -> Option<LogEntry> {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    let line = line.strip_suffix('\r').unwrap_or(line);
+    parse_log_line(line, levels).map(|entry| LogEntry { offset, ..entry })
+}
+
 //Lecture séquentielle
-fn read_logs(path: &Path) -> Result<Vec<LogEntry>, std::io::Error> {
-    let reader = BufReader::new(File::open(path)?);
+fn read_logs(path: &Path, limit: Option<usize>, reading: &Reading) -> Result<Vec<LogEntry>> {
+    let file = File::open(path).with_context(|| format!("opening {}", path.display()))?;
+    let mut reader = BufReader::new(file);
     let mut entries = Vec::new();
-
-    for line in reader.lines() {
-        if let Some(entry) = parse_log_line(&line?) {
+    let mut line = String::new();
+    let mut offset = 0;
+
+    while limit.is_none_or(|n| entries.len() < n) && !reading.interrupted() {
+        line.clear();
+        let read = reader
+            .read_line(&mut line)
+            .with_context(|| format!("reading {}", path.display()))?;
+        if read == 0 {
+            break;
+        }
+        if let Some(entry) = reading.parse(&line, offset) {
             entries.push(entry);
         }
+        offset += read as u64;
+        reading.advance(read as u64);
+    }
+    Ok(entries)
+}
+
+//Lecture depuis la fin (--tail) : par blocs, sans lire le début du fichier
+const TAIL_CHUNK: usize = 64 * 1024;
+
+fn read_logs_tail(path: &Path, n: usize, reading: &Reading) -> Result<Vec<LogEntry>> {
+    read_tail(path, n, TAIL_CHUNK, reading).with_context(|| format!("reading {}", path.display()))
+}
+
+fn read_tail(path: &Path, n: usize, chunk: usize, reading: &Reading) -> Result<Vec<LogEntry>> {
+    let mut file = File::open(path)?;
+    let mut pos = file.metadata()?.len();
+    // début de ligne coupé par le bloc précédent, complété au bloc suivant
+    let mut partial: Vec<u8> = Vec::new();
+    let mut entries = Vec::new();
+
+    while pos > 0 && entries.len() < n && !reading.interrupted() {
+        let size = (chunk as u64).min(pos);
+        pos -= size;
+        let mut buf = vec![0; size as usize];
+        file.seek(SeekFrom::Start(pos))?;
+        file.read_exact(&mut buf)?;
+        reading.advance(size);
+        buf.extend_from_slice(&partial);
+
+        let mut lines = Vec::new();
+        let mut start = 0;
+        for line in buf.split(|&b| b == b'\n') {
+            lines.push((pos + start as u64, line));
+            start += line.len() + 1;
+        }
+        // la première ligne du bloc n'est complète qu'au début du fichier
+        let first = if pos > 0 { Some(lines.remove(0).1) } else { None };
+        for (offset, line) in lines.into_iter().rev() {
+            if let Some(entry) = reading.parse(&String::from_utf8_lossy(line), offset) {
+                entries.push(entry);
+                if entries.len() == n {
+                    break;
+                }
+            }
+        }
+        partial = first.unwrap_or_default().to_vec();
     }
+    entries.reverse();
     Ok(entries)
 }
 
 //Lecture parallèle
-fn read_logs_parallel(path: &Path) -> Result<Vec<LogEntry>, std::io::Error> {
-    let reader = BufReader::new(File::open(path)?);
+/// Lu par blocs pour suivre l'avancement ; interrompu, le contenu s'arrête
+/// à la dernière ligne complète.
+fn read_text(path: &Path, reading: &Reading) -> std::io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut bytes = Vec::with_capacity(file.metadata()?.len() as usize);
+    let mut block = vec![0; 1 << 20];
+    loop {
+        if reading.interrupted() {
+            let end = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+            bytes.truncate(end);
+            break;
+        }
+        let read = file.read(&mut block)?;
+        if read == 0 {
+            break;
+        }
+        bytes.extend_from_slice(&block[..read]);
+        reading.advance(read as u64);
+    }
+    String::from_utf8(bytes).map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
 
-    let lines: Vec<String> = reader.lines().collect::<Result<_, _>>()?;
+fn read_logs_parallel(path: &Path, reading: &Reading) -> Result<Vec<LogEntry>> {
+    let text = read_text(path, reading).with_context(|| format!("reading {}", path.display()))?;
+
+    let mut offset = 0;
+    let lines: Vec<(u64, &str)> = text
+        .split_inclusive('\n')
+        .map(|line| {
+            offset += line.len() as u64;
+            (offset - line.len() as u64, line)
+        })
+        .collect();
 
     let entries: Vec<LogEntry> = lines
         .par_iter()
-        .filter_map(|line| parse_log_line(line))
+        .filter_map(|&(offset, line)| reading.parse(line, offset))
         .collect();
 
     Ok(entries)
 }
 
 
+/// Niveaux intégrés, puis `--level-config`, puis `--level-map`.
+fn level_map(cli: &Cli) -> Result<LevelMap> {
+    let mut levels = LevelMap::default();
+    if let Some(path) = &cli.level_config {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        levels.add_config(&text).map_err(|e| Error::parse(format!("{}: {}", path.display(), e)))?;
+    }
+    for spec in &cli.level_map {
+        levels.add(spec).map_err(|e| Error::parse(format!("--level-map: {}", e)))?;
+    }
+    Ok(levels)
+}
+
+/// Les `--count-where` avec leur nom (`--as`, sinon `count_where_N`).
+fn custom_counters(exprs: &[String], names: &[String]) -> Result<Vec<(String, Expr)>> {
+    if names.len() > exprs.len() {
+        return Err(Error::parse("more --as names than --count-where expressions"));
+    }
+    exprs
+        .iter()
+        .enumerate()
+        .map(|(i, text)| {
+            let expr = Expr::parse(text).map_err(|e| Error::parse(format!("--count-where `{}`: {}", text, e)))?;
+            let name = names.get(i).cloned().unwrap_or_else(|| format!("count_where_{}", i + 1));
+            Ok((name, expr))
+        })
+        .collect()
+}
+
+fn count_where(entries: &[LogEntry], counters: &[(String, Expr)]) -> BTreeMap<String, usize> {
+    let mut counts: BTreeMap<String, usize> = counters.iter().map(|(name, _)| (name.clone(), 0)).collect();
+    for entry in entries {
+        let fields = Fields {
+            level: entry.level.name(),
+            message: &entry.message,
+            thread: entry.thread.as_deref(),
+            timestamp: &entry.timestamp,
+        };
+        for (name, expr) in counters {
+            if expr.matches(&fields) {
+                *counts.get_mut(name).unwrap() += 1;
+            }
+        }
+    }
+    counts
+}
+
+/// `--since` / `--until` : `YYYY-MM-DD HH:MM:SS` ou `YYYY-MM-DD` (minuit).
+fn parse_time_bound(text: &str) -> std::result::Result<i64, String> {
+    let text = text.trim();
+    let full = if text.contains(' ') { text.to_string() } else { format!("{} 00:00:00", text) };
+    epoch_secs(&full).ok_or_else(|| format!("expected YYYY-MM-DD[ HH:MM:SS], got `{}`", text))
+}
+
+/// Recopie dans `out` les lignes brutes de `entries` (dans l'ordre du
+/// fichier), en partant de la première pour ne pas relire tout le fichier.
+fn emit_matching(input: &Path, entries: &[LogEntry], out: &Path) -> Result<usize> {
+    let mut writer = BufWriter::new(File::create(out).with_context(|| format!("creating {}", out.display()))?);
+    let mut offsets = entries.iter().map(|e| e.offset).peekable();
+    let mut written = 0;
+    if let Some(&first) = offsets.peek() {
+        let mut reader = BufReader::new(File::open(input).with_context(|| format!("opening {}", input.display()))?);
+        reader.seek(SeekFrom::Start(first))?;
+        let mut pos = first;
+        let mut line = Vec::new();
+        while let Some(&next) = offsets.peek() {
+            line.clear();
+            let read = reader.read_until(b'\n', &mut line)?;
+            if read == 0 {
+                break;
+            }
+            if pos == next {
+                writer.write_all(&line)?;
+                if !line.ends_with(b"\n") {
+                    writer.write_all(b"\n")?;
+                }
+                offsets.next();
+                written += 1;
+            }
+            pos += read as u64;
+        }
+    }
+    writer.flush().with_context(|| format!("writing {}", out.display()))?;
+    Ok(written)
+}
+
+
 /// PARTIE 3 — ANALYSE DES LOGS 
 
 #[derive(Debug, Serialize)]
@@ -132,7 +541,54 @@ struct LogStats {
     total_entries: usize,
     by_level: HashMap<String, usize>,
     top_errors: Vec<ErrorFrequency>,
-    errors_by_hour: HashMap<String, usize>,
+    bucket: Bucket,
+    /// Erreurs par tranche, dans l'ordre chronologique.
+    errors_by_time: BTreeMap<String, usize>,
+    /// Lignes répétées à la suite (boucle de retry, spam), les plus longues
+    /// séries d'abord.
+    repeated_lines: Vec<RepeatedLine>,
+    /// Entrées et erreurs par thread / PID, quand les lignes en portent.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    by_thread: BTreeMap<String, ThreadStats>,
+    /// Recommandations de `--advise`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    advice: Vec<String>,
+    /// Paires warning → erreur de `--correlate-secs`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    warning_error_pairs: Vec<WarningErrorPair>,
+    /// Requêtes de `--trace-id-regex`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    traces: Option<TraceReport>,
+    /// Présent quand ctrl-c a interrompu la lecture : les statistiques ne
+    /// couvrent que le début du fichier.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    partial: Option<PartialRead>,
+    /// Compteurs de `--count-where`.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    counters: BTreeMap<String, usize>,
+    /// Lignes non vides qui ne sont pas une entrée, niveaux inconnus compris.
+    #[serde(skip_serializing_if = "is_zero")]
+    unparsed_lines: u64,
+    /// Clés `clé=valeur` les plus fréquentes dans les messages.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    keys: Vec<KeyStats>,
+    /// Groupes de `--group-by`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    group_by: Option<GroupReport>,
+    /// Niveaux sans correspondance (`--level-map`) et nombre de lignes
+    /// ignorées pour chacun.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    unmapped_levels: BTreeMap<String, usize>,
+}
+
+fn is_zero(n: &u64) -> bool {
+    *n == 0
+}
+
+#[derive(Debug, Serialize)]
+struct PartialRead {
+    bytes_read: u64,
+    file_size: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -141,21 +597,174 @@ struct ErrorFrequency {
     count: usize,
 }
 
-fn analyze_logs(entries: &[LogEntry], top_n: Option<usize>) -> LogStats {
+#[derive(Debug, PartialEq, Serialize)]
+struct ThreadStats {
+    entries: usize,
+    errors: usize,
+    /// Part d'erreurs parmi les entrées du thread, en %.
+    error_rate: f64,
+}
+
+/// (entrées, erreurs) par thread → `ThreadStats`.
+fn thread_stats(counts: HashMap<String, (usize, usize)>) -> BTreeMap<String, ThreadStats> {
+    counts
+        .into_iter()
+        .map(|(thread, (entries, errors))| {
+            let error_rate = errors as f64 * 100.0 / entries as f64;
+            (thread, ThreadStats { entries, errors, error_rate })
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct KeyStats {
+    key: String,
+    /// Entrées dont le message porte la clé.
+    entries: usize,
+    distinct_values: usize,
+}
+
+/// Les `limit` clés des paires `clé=valeur` portées par le plus d'entrées.
+fn key_stats(entries: &[LogEntry], limit: usize) -> Vec<KeyStats> {
+    let mut keys: HashMap<&str, (usize, HashSet<&str>)> = HashMap::new();
+    for entry in entries {
+        for (key, value) in &entry.fields {
+            let (count, values) = keys.entry(key).or_default();
+            *count += 1;
+            values.insert(value);
+        }
+    }
+    let mut stats: Vec<KeyStats> = keys
+        .into_iter()
+        .map(|(key, (entries, values))| KeyStats {
+            key: key.to_string(),
+            entries,
+            distinct_values: values.len(),
+        })
+        .collect();
+    stats.sort_by(|a, b| b.entries.cmp(&a.entries).then_with(|| a.key.cmp(&b.key)));
+    stats.truncate(limit);
+    stats
+}
+
+/// `key:NOM` → `NOM`.
+fn parse_group_by(text: &str) -> std::result::Result<String, String> {
+    match text.split_once(':') {
+        Some(("key", name)) if !name.is_empty() => Ok(name.to_string()),
+        _ => Err(format!("expected key:NAME, got `{}`", text)),
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct GroupReport {
+    key: String,
+    /// Entrées dont le message n'a pas la clé.
+    missing: usize,
+    /// Les valeurs les plus fréquentes d'abord.
+    groups: Vec<Group>,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct Group {
+    value: String,
+    entries: usize,
+    errors: usize,
+    /// Part d'erreurs parmi les entrées du groupe, en %.
+    error_rate: f64,
+}
+
+/// Entrées et erreurs par valeur de `key` ; les `limit` valeurs les plus
+/// fréquentes.
+fn group_by(entries: &[LogEntry], key: &str, limit: usize) -> GroupReport {
+    let mut counts: HashMap<&str, (usize, usize)> = HashMap::new();
+    let mut missing = 0;
+    for entry in entries {
+        match entry.fields.get(key) {
+            Some(value) => {
+                let (total, errors) = counts.entry(value).or_default();
+                *total += 1;
+                *errors += (entry.level == LogLevel::Error) as usize;
+            }
+            None => missing += 1,
+        }
+    }
+    let mut groups: Vec<Group> = counts
+        .into_iter()
+        .map(|(value, (entries, errors))| Group {
+            value: value.to_string(),
+            entries,
+            errors,
+            error_rate: errors as f64 * 100.0 / entries as f64,
+        })
+        .collect();
+    groups.sort_by(|a, b| b.entries.cmp(&a.entries).then_with(|| a.value.cmp(&b.value)));
+    groups.truncate(limit);
+    GroupReport {
+        key: key.to_string(),
+        missing,
+        groups,
+    }
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct RepeatedLine {
+    level: String,
+    message: String,
+    count: usize,
+    first: String,
+    last: String,
+}
+
+/// Séries d'au moins deux entrées consécutives de même niveau et même
+/// message ; les `limit` plus longues, à égalité la plus ancienne d'abord.
+fn repeated_lines(entries: &[LogEntry], limit: usize) -> Vec<RepeatedLine> {
+    let mut runs: Vec<RepeatedLine> = Vec::new();
+    let mut start = 0;
+    for end in 1..=entries.len() {
+        let same = end < entries.len()
+            && entries[end].level == entries[start].level
+            && entries[end].message == entries[start].message;
+        if same {
+            continue;
+        }
+        if end - start >= 2 {
+            runs.push(RepeatedLine {
+                level: format!("{:?}", entries[start].level),
+                message: entries[start].message.clone(),
+                count: end - start,
+                first: entries[start].timestamp.clone(),
+                last: entries[end - 1].timestamp.clone(),
+            });
+        }
+        start = end;
+    }
+    // tri stable : l'ordre d'apparition départage
+    runs.sort_by_key(|r| std::cmp::Reverse(r.count));
+    runs.truncate(limit);
+    runs
+}
+
+fn analyze_logs(entries: &[LogEntry], top_n: Option<usize>, bucket: Bucket) -> LogStats {
     let mut by_level = HashMap::new();
     let mut error_messages = HashMap::new();
-    let mut errors_by_hour = HashMap::new();
+    let mut errors_by_time = BTreeMap::new();
+    let mut by_thread: HashMap<String, (usize, usize)> = HashMap::new();
 
     for entry in entries {
         let level_name = format!("{:?}", entry.level);
         *by_level.entry(level_name.clone()).or_insert(0) += 1;
 
+        if let Some(thread) = &entry.thread {
+            let counts = by_thread.entry(thread.clone()).or_default();
+            counts.0 += 1;
+            counts.1 += (entry.level == LogLevel::Error) as usize;
+        }
+
         if entry.level == LogLevel::Error {
             *error_messages.entry(entry.message.clone()).or_insert(0) += 1;
 
-            if let Some(timepart) = entry.timestamp.split_whitespace().nth(1) {
-                let hour = &timepart[0..2];
-                *errors_by_hour.entry(hour.to_string()).or_insert(0) += 1;
+            if let Some(key) = bucket.key(&entry.timestamp) {
+                *errors_by_time.entry(key).or_insert(0) += 1;
             }
         }
     }
@@ -165,7 +774,7 @@ fn analyze_logs(entries: &[LogEntry], top_n: Option<usize>) -> LogStats {
         .map(|(msg, count)| ErrorFrequency { message: msg, count })
         .collect();
 
-    top_errors.sort_by(|a, b| b.count.cmp(&a.count));
+    top_errors.sort_by_key(|e| std::cmp::Reverse(e.count));
 
     let limit = top_n.unwrap_or(5);
     if top_errors.len() > limit {
@@ -176,30 +785,49 @@ fn analyze_logs(entries: &[LogEntry], top_n: Option<usize>) -> LogStats {
         total_entries: entries.len(),
         by_level,
         top_errors,
-        errors_by_hour,
+        bucket,
+        errors_by_time,
+        by_thread: thread_stats(by_thread),
+        repeated_lines: repeated_lines(entries, limit),
+        advice: Vec::new(),
+        warning_error_pairs: Vec::new(),
+        traces: None,
+        partial: None,
+        counters: BTreeMap::new(),
+        unparsed_lines: 0,
+        keys: Vec::new(),
+        group_by: None,
+        unmapped_levels: BTreeMap::new(),
     }
 }
 
 /// Analyse parallèle 
-fn analyze_logs_parallel(entries: &[LogEntry], top_n: Option<usize>) -> LogStats {
+fn analyze_logs_parallel(entries: &[LogEntry], top_n: Option<usize>, bucket: Bucket) -> LogStats {
     use std::sync::Mutex;
 
     let by_level = Mutex::new(HashMap::new());
     let error_messages = Mutex::new(HashMap::new());
-    let errors_by_hour = Mutex::new(HashMap::new());
+    let errors_by_time = Mutex::new(BTreeMap::new());
+    let by_thread: Mutex<HashMap<String, (usize, usize)>> = Mutex::new(HashMap::new());
 
     entries.par_iter().for_each(|entry| {
         let mut bl = by_level.lock().unwrap();
         *bl.entry(format!("{:?}", entry.level)).or_insert(0) += 1;
 
+        if let Some(thread) = &entry.thread {
+            let mut bt = by_thread.lock().unwrap();
+            let counts = bt.entry(thread.clone()).or_default();
+            counts.0 += 1;
+            counts.1 += (entry.level == LogLevel::Error) as usize;
+        }
+
         if entry.level == LogLevel::Error {
             let mut em = error_messages.lock().unwrap();
             *em.entry(entry.message.clone()).or_insert(0) += 1;
 
-            let mut eb = errors_by_hour.lock().unwrap();
-            if let Some(tp) = entry.timestamp.split_whitespace().nth(1) {
-                let hour = &tp[0..2];
-                *eb.entry(hour.to_string()).or_insert(0) += 1;
+            let mut eb = errors_by_time.lock().unwrap();
+            if let Some(key) = bucket.key(&entry.timestamp) {
+                *eb.entry(key).or_insert(0) += 1;
             }
         }
     });
@@ -211,7 +839,7 @@ fn analyze_logs_parallel(entries: &[LogEntry], top_n: Option<usize>) -> LogStats
         .map(|(msg, count)| ErrorFrequency { message: msg, count })
         .collect();
 
-    top_errors.sort_by(|a, b| b.count.cmp(&a.count));
+    top_errors.sort_by_key(|e| std::cmp::Reverse(e.count));
 
     let limit = top_n.unwrap_or(5);
     if top_errors.len() > limit {
@@ -222,7 +850,315 @@ fn analyze_logs_parallel(entries: &[LogEntry], top_n: Option<usize>) -> LogStats
         total_entries: entries.len(),
         by_level: by_level.into_inner().unwrap(),
         top_errors,
-        errors_by_hour: errors_by_hour.into_inner().unwrap(),
+        bucket,
+        errors_by_time: errors_by_time.into_inner().unwrap(),
+        by_thread: thread_stats(by_thread.into_inner().unwrap()),
+        // les séries dépendent de l'ordre des lignes : pas de par_iter ici
+        repeated_lines: repeated_lines(entries, limit),
+        advice: Vec::new(),
+        warning_error_pairs: Vec::new(),
+        traces: None,
+        partial: None,
+        counters: BTreeMap::new(),
+        unparsed_lines: 0,
+        keys: Vec::new(),
+        group_by: None,
+        unmapped_levels: BTreeMap::new(),
+    }
+}
+
+
+/// Seuils de `--advise`.
+#[derive(Debug, Clone, Copy)]
+struct AdviseThresholds {
+    level_pct: f64,
+    message_pct: f64,
+    min_entropy: f64,
+}
+
+/// En dessous, l'entropie d'un niveau ne veut pas dire grand-chose.
+const MIN_ENTRIES_FOR_ENTROPY: usize = 20;
+
+/// Entropie de Shannon (bits) de la répartition des messages.
+fn entropy(counts: &HashMap<&str, usize>) -> f64 {
+    let total: usize = counts.values().sum();
+    counts
+        .values()
+        .map(|&c| {
+            let p = c as f64 / total as f64;
+            p * (1.0 / p).log2()
+        })
+        .sum()
+}
+
+/// Audit rapide : niveaux bavards, erreurs dominées par un message et
+/// niveaux dont les messages se répètent (faible entropie).
+fn advise(entries: &[LogEntry], t: AdviseThresholds) -> Vec<String> {
+    let total = entries.len();
+    if total == 0 {
+        return Vec::new();
+    }
+    let mut messages: HashMap<LogLevel, HashMap<&str, usize>> = HashMap::new();
+    for e in entries {
+        *messages.entry(e.level.clone()).or_default().entry(e.message.as_str()).or_insert(0) += 1;
+    }
+    let level_count = |level: &LogLevel| messages.get(level).map_or(0, |m| m.values().sum::<usize>());
+    let mut advice = Vec::new();
+
+    for level in [LogLevel::Debug, LogLevel::Info] {
+        let pct = level_count(&level) as f64 * 100.0 / total as f64;
+        if pct > t.level_pct {
+            advice.push(format!(
+                "{} is {:.0}% of volume; consider raising the log level",
+                format!("{:?}", level).to_uppercase(),
+                pct
+            ));
+        }
+    }
+
+    let errors = level_count(&LogLevel::Error);
+    if let Some(by_message) = messages.get(&LogLevel::Error) {
+        let mut frequent: Vec<_> = by_message
+            .iter()
+            .map(|(msg, &count)| (*msg, count as f64 * 100.0 / errors as f64))
+            .filter(|(_, pct)| *pct > t.message_pct)
+            .collect();
+        frequent.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(b.0)));
+        for (msg, pct) in frequent {
+            advice.push(format!("message \"{}\" is {:.0}% of errors; add dedup", msg, pct));
+        }
+    }
+
+    for level in [LogLevel::Error, LogLevel::Warning, LogLevel::Info, LogLevel::Debug] {
+        let Some(by_message) = messages.get(&level) else { continue };
+        let count = level_count(&level);
+        let bits = entropy(by_message);
+        if count >= MIN_ENTRIES_FOR_ENTROPY && bits < t.min_entropy {
+            advice.push(format!(
+                "{} messages carry {:.2} bits of entropy over {} lines; a few templates dominate, consider sampling",
+                format!("{:?}", level).to_uppercase(),
+                bits,
+                count
+            ));
+        }
+    }
+    advice
+}
+
+
+/// `YYYY-MM-DD HH:MM:SS` → secondes depuis l'epoch (UTC supposé).
+fn epoch_secs(timestamp: &str) -> Option<i64> {
+    let mut parts = timestamp.split_whitespace();
+    let mut date = parts.next()?.split('-').map(|p| p.parse::<i64>().ok());
+    let mut time = parts.next()?.split(':').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = (date.next()??, date.next()??, date.next()??);
+    let (hh, mm, ss) = (time.next()??, time.next()??, time.next()??);
+    // jours depuis 1970-01-01 (algorithme « days from civil »)
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146_097 + doe - 719_468;
+    Some(days * 86_400 + hh * 3600 + mm * 60 + ss)
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+struct WarningErrorPair {
+    warning: String,
+    error: String,
+    /// Warnings suivis de cette erreur dans la fenêtre.
+    count: usize,
+    /// Occurrences du warning.
+    warnings: usize,
+    /// `count / warnings` rapporté à la même probabilité après n'importe
+    /// quelle ligne qui n'est pas une erreur ; > 1 = plus souvent que le hasard.
+    lift: f64,
+}
+
+/// En dessous, une paire n'est qu'une coïncidence.
+const MIN_PAIR_COUNT: usize = 2;
+const MIN_LIFT: f64 = 1.5;
+
+/// Paires warning → erreur où l'erreur suit le warning dans les `window`
+/// secondes nettement plus souvent qu'elle ne suit une ligne quelconque ; les `limit`
+/// plus fréquentes. Les entrées sont supposées dans l'ordre du fichier.
+fn warning_error_pairs(entries: &[LogEntry], window: u64, limit: usize) -> Vec<WarningErrorPair> {
+    let times: Vec<Option<i64>> = entries.iter().map(|e| epoch_secs(&e.timestamp)).collect();
+    let errors: Vec<usize> = (0..entries.len()).filter(|&i| entries[i].level == LogLevel::Error).collect();
+
+    let mut baseline: HashMap<&str, usize> = HashMap::new();
+    let mut warnings: HashMap<&str, usize> = HashMap::new();
+    let mut pairs: HashMap<(&str, &str), usize> = HashMap::new();
+    let mut others = 0;
+
+    for (i, entry) in entries.iter().enumerate() {
+        let Some(t) = times[i] else { continue };
+        if entry.level == LogLevel::Error {
+            continue;
+        }
+        others += 1;
+        let is_warning = entry.level == LogLevel::Warning;
+        if is_warning {
+            *warnings.entry(entry.message.as_str()).or_insert(0) += 1;
+        }
+        // erreurs distinctes des `window` secondes suivantes
+        let mut followed: Vec<&str> = errors[errors.partition_point(|&j| j <= i)..]
+            .iter()
+            .take_while(|&&j| times[j].is_some_and(|tj| tj - t <= window as i64))
+            .map(|&j| entries[j].message.as_str())
+            .collect();
+        followed.sort_unstable();
+        followed.dedup();
+        for error in followed {
+            *baseline.entry(error).or_insert(0) += 1;
+            if is_warning {
+                *pairs.entry((entry.message.as_str(), error)).or_insert(0) += 1;
+            }
+        }
+    }
+
+    let mut result: Vec<WarningErrorPair> = pairs
+        .into_iter()
+        .filter(|&(_, count)| count >= MIN_PAIR_COUNT)
+        .map(|((warning, error), count)| {
+            let rate = count as f64 / warnings[warning] as f64;
+            let chance = baseline[error] as f64 / others as f64;
+            WarningErrorPair {
+                warning: warning.to_string(),
+                error: error.to_string(),
+                count,
+                warnings: warnings[warning],
+                lift: rate / chance,
+            }
+        })
+        .filter(|p| p.lift >= MIN_LIFT)
+        .collect();
+    result.sort_by(|a, b| {
+        b.count
+            .cmp(&a.count)
+            .then(b.lift.total_cmp(&a.lift))
+            .then_with(|| (&a.warning, &a.error).cmp(&(&b.warning, &b.error)))
+    });
+    result.truncate(limit);
+    result
+}
+
+
+/// Entrées gardées par chronologie de requête lente.
+const MAX_TIMELINE_ENTRIES: usize = 50;
+
+#[derive(Debug, Serialize)]
+struct TimelineEntry {
+    timestamp: String,
+    level: String,
+    message: String,
+}
+
+#[derive(Debug, Serialize)]
+struct RequestTrace {
+    id: String,
+    start: String,
+    end: String,
+    /// De la première à la dernière entrée de la requête.
+    duration_secs: i64,
+    entries: usize,
+    errors: usize,
+    /// Ses premières entrées, pour les requêtes les plus lentes seulement.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    timeline: Vec<TimelineEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct TraceReport {
+    requests: usize,
+    with_errors: usize,
+    /// Requêtes ayant le plus d'erreurs.
+    failing: Vec<RequestTrace>,
+    /// Requêtes les plus longues, avec leur chronologie.
+    slowest: Vec<RequestTrace>,
+}
+
+/// Compile `--trace-id-regex` ; il lui faut un groupe `id` ou un groupe 1.
+fn trace_id_regex(pattern: &str) -> Result<Regex> {
+    let re = Regex::new(pattern).map_err(|e| Error::parse(format!("invalid --trace-id-regex: {}", e)))?;
+    if re.captures_len() < 2 {
+        return Err(Error::parse("--trace-id-regex needs a capture group, e.g. 'req=(?P<id>[a-f0-9-]+)'"));
+    }
+    Ok(re)
+}
+
+/// Regroupe les entrées dont le message porte un identifiant de requête.
+fn trace_requests(entries: &[LogEntry], re: &Regex, limit: usize) -> TraceReport {
+    let mut by_id: HashMap<&str, Vec<&LogEntry>> = HashMap::new();
+    for entry in entries {
+        let Some(caps) = re.captures(&entry.message) else { continue };
+        if let Some(id) = caps.name("id").or_else(|| caps.get(1)) {
+            by_id.entry(id.as_str()).or_default().push(entry);
+        }
+    }
+
+    let mut traces: Vec<(RequestTrace, Vec<&LogEntry>)> = by_id
+        .into_iter()
+        .map(|(id, group)| {
+            let times: Vec<(i64, &str)> = group
+                .iter()
+                .filter_map(|e| Some((epoch_secs(&e.timestamp)?, e.timestamp.as_str())))
+                .collect();
+            let first = times.iter().min().copied().unwrap_or((0, ""));
+            let last = times.iter().max().copied().unwrap_or((0, ""));
+            let trace = RequestTrace {
+                id: id.to_string(),
+                start: first.1.to_string(),
+                end: last.1.to_string(),
+                duration_secs: last.0 - first.0,
+                entries: group.len(),
+                errors: group.iter().filter(|e| e.level == LogLevel::Error).count(),
+                timeline: Vec::new(),
+            };
+            (trace, group)
+        })
+        .collect();
+    let requests = traces.len();
+    let with_errors = traces.iter().filter(|(t, _)| t.errors > 0).count();
+
+    traces.sort_by(|a, b| b.0.errors.cmp(&a.0.errors).then_with(|| a.0.id.cmp(&b.0.id)));
+    let failing = traces
+        .iter()
+        .take_while(|(t, _)| t.errors > 0)
+        .take(limit)
+        .map(|(t, _)| RequestTrace {
+            id: t.id.clone(),
+            start: t.start.clone(),
+            end: t.end.clone(),
+            timeline: Vec::new(),
+            ..*t
+        })
+        .collect();
+
+    traces.sort_by(|a, b| b.0.duration_secs.cmp(&a.0.duration_secs).then_with(|| a.0.id.cmp(&b.0.id)));
+    traces.truncate(limit);
+    let slowest = traces
+        .into_iter()
+        .map(|(mut trace, group)| {
+            trace.timeline = group
+                .iter()
+                .take(MAX_TIMELINE_ENTRIES)
+                .map(|e| TimelineEntry {
+                    timestamp: e.timestamp.clone(),
+                    level: format!("{:?}", e.level),
+                    message: e.message.clone(),
+                })
+                .collect();
+            trace
+        })
+        .collect();
+
+    TraceReport {
+        requests,
+        with_errors,
+        failing,
+        slowest,
     }
 }
 
@@ -235,7 +1171,19 @@ fn output_text(stats: &LogStats) -> String {
     out.push_str("\nLog Analysis Results\n");
     out.push_str("========================\n\n");
 
-    out.push_str(&format!("Total entries: {}\n\n", stats.total_entries));
+    if let Some(p) = &stats.partial {
+        let banner = format!(
+            "PARTIAL RESULTS: interrupted by ctrl-c after {} of {} bytes",
+            p.bytes_read, p.file_size
+        );
+        out.push_str(&format!("{}\n\n", banner.red().bold()));
+    }
+
+    out.push_str(&format!("Total entries: {}\n", stats.total_entries));
+    if stats.unparsed_lines > 0 {
+        out.push_str(&format!("Unparsed lines: {}\n", stats.unparsed_lines));
+    }
+    out.push('\n');
 
     // petit tableau
     let mut table = Table::new();
@@ -264,6 +1212,25 @@ fn output_text(stats: &LogStats) -> String {
     out.push_str(&String::from_utf8(tmp).unwrap());
     out.push('\n');
 
+    if !stats.unmapped_levels.is_empty() {
+        let levels: Vec<String> = stats.unmapped_levels.iter().map(|(name, n)| format!("{} ({})", name, n)).collect();
+        let warning = format!("Lines skipped, unknown levels: {} (see --level-map)", levels.join(", "));
+        out.push_str(&format!("{}\n", warning.yellow()));
+    }
+
+    if !stats.counters.is_empty() {
+        out.push_str("\nCounters:\n");
+        let mut t = Table::new();
+        t.add_row(Row::new(vec![Cell::new("Counter"), Cell::new("Count")]));
+        for (name, count) in &stats.counters {
+            t.add_row(Row::new(vec![Cell::new(name), Cell::new(&count.to_string())]));
+        }
+
+        let mut tmp = Vec::new();
+        t.print(&mut tmp).unwrap();
+        out.push_str(&String::from_utf8(tmp).unwrap());
+    }
+
     // top erreurs
     if !stats.top_errors.is_empty() {
         out.push_str("\nTop errors:\n");
@@ -285,64 +1252,516 @@ fn output_text(stats: &LogStats) -> String {
         out.push_str(&String::from_utf8(tmp).unwrap());
     }
 
+    // threads les plus en erreur d'abord
+    if !stats.by_thread.is_empty() {
+        out.push_str("\nBy thread:\n");
+        let mut threads: Vec<_> = stats.by_thread.iter().collect();
+        threads.sort_by(|a, b| b.1.error_rate.total_cmp(&a.1.error_rate).then(a.0.cmp(b.0)));
+        let mut t = Table::new();
+        t.add_row(Row::new(vec![
+            Cell::new("Thread"),
+            Cell::new("Entries"),
+            Cell::new("Errors"),
+            Cell::new("Error rate"),
+        ]));
+        for (thread, ts) in threads {
+            t.add_row(Row::new(vec![
+                Cell::new(thread),
+                Cell::new(&ts.entries.to_string()),
+                Cell::new(&ts.errors.to_string()),
+                Cell::new(&format!("{:.1}%", ts.error_rate)),
+            ]));
+        }
+
+        let mut tmp = Vec::new();
+        t.print(&mut tmp).unwrap();
+        out.push_str(&String::from_utf8(tmp).unwrap());
+    }
+
+    if !stats.keys.is_empty() {
+        out.push_str("\nKeys:\n");
+        let mut t = Table::new();
+        t.add_row(Row::new(vec![Cell::new("Key"), Cell::new("Entries"), Cell::new("Distinct values")]));
+        for k in &stats.keys {
+            t.add_row(Row::new(vec![
+                Cell::new(&k.key),
+                Cell::new(&k.entries.to_string()),
+                Cell::new(&k.distinct_values.to_string()),
+            ]));
+        }
+
+        let mut tmp = Vec::new();
+        t.print(&mut tmp).unwrap();
+        out.push_str(&String::from_utf8(tmp).unwrap());
+    }
+
+    if let Some(report) = &stats.group_by {
+        out.push_str(&format!("\nBy {} ({} entries without it):\n", report.key, report.missing));
+        let mut t = Table::new();
+        t.add_row(Row::new(vec![
+            Cell::new(&report.key),
+            Cell::new("Entries"),
+            Cell::new("Errors"),
+            Cell::new("Error rate"),
+        ]));
+        for g in &report.groups {
+            t.add_row(Row::new(vec![
+                Cell::new(&g.value),
+                Cell::new(&g.entries.to_string()),
+                Cell::new(&g.errors.to_string()),
+                Cell::new(&format!("{:.1}%", g.error_rate)),
+            ]));
+        }
+
+        let mut tmp = Vec::new();
+        t.print(&mut tmp).unwrap();
+        out.push_str(&String::from_utf8(tmp).unwrap());
+    }
+
+    if !stats.repeated_lines.is_empty() {
+        out.push_str("\nMost repeated consecutive lines:\n");
+        let mut t = Table::new();
+        t.add_row(Row::new(vec![
+            Cell::new("Level"),
+            Cell::new("Message"),
+            Cell::new("Repeats"),
+            Cell::new("First"),
+            Cell::new("Last"),
+        ]));
+        for r in &stats.repeated_lines {
+            t.add_row(Row::new(vec![
+                Cell::new(&r.level),
+                Cell::new(&r.message),
+                Cell::new(&r.count.to_string()),
+                Cell::new(&r.first),
+                Cell::new(&r.last),
+            ]));
+        }
+
+        let mut tmp = Vec::new();
+        t.print(&mut tmp).unwrap();
+        out.push_str(&String::from_utf8(tmp).unwrap());
+    }
+
+    if let Some(traces) = &stats.traces {
+        out.push_str(&format!(
+            "\nRequests: {} traced, {} with errors\n",
+            traces.requests, traces.with_errors
+        ));
+        if !traces.slowest.is_empty() {
+            out.push_str("\nSlowest requests:\n");
+            let mut t = Table::new();
+            t.add_row(Row::new(vec![
+                Cell::new("Request"),
+                Cell::new("Duration"),
+                Cell::new("Entries"),
+                Cell::new("Errors"),
+                Cell::new("Start"),
+            ]));
+            for r in &traces.slowest {
+                t.add_row(Row::new(vec![
+                    Cell::new(&r.id),
+                    Cell::new(&format!("{}s", r.duration_secs)),
+                    Cell::new(&r.entries.to_string()),
+                    Cell::new(&r.errors.to_string()),
+                    Cell::new(&r.start),
+                ]));
+            }
+
+            let mut tmp = Vec::new();
+            t.print(&mut tmp).unwrap();
+            out.push_str(&String::from_utf8(tmp).unwrap());
+
+            for r in &traces.slowest {
+                out.push_str(&format!("\n{} ({}s):\n", r.id, r.duration_secs));
+                for e in &r.timeline {
+                    out.push_str(&format!("  {} [{}] {}\n", e.timestamp, e.level, e.message));
+                }
+                if r.entries > r.timeline.len() {
+                    out.push_str(&format!("  ... {} more\n", r.entries - r.timeline.len()));
+                }
+            }
+        }
+        if !traces.failing.is_empty() {
+            out.push_str("\nRequests with errors:\n");
+            for r in &traces.failing {
+                out.push_str(&format!("  {}: {} error(s) in {} entries\n", r.id, r.errors, r.entries));
+            }
+        }
+    }
+
+    if !stats.warning_error_pairs.is_empty() {
+        out.push_str("\nWarnings followed by errors:\n");
+        let mut t = Table::new();
+        t.add_row(Row::new(vec![
+            Cell::new("Warning"),
+            Cell::new("Error"),
+            Cell::new("Followed"),
+            Cell::new("Lift"),
+        ]));
+        if let Some(traces) = &stats.traces {
+        out.push_str(&format!("trace,requests,{}\n", traces.requests));
+        out.push_str(&format!("trace,with_errors,{}\n", traces.with_errors));
+        for r in &traces.slowest {
+            out.push_str(&format!("slowest_request,{},{}\n", r.id, r.duration_secs));
+        }
+        for r in &traces.failing {
+            out.push_str(&format!("failing_request,{},{}\n", r.id, r.errors));
+        }
+    }
+
+    for p in &stats.warning_error_pairs {
+            t.add_row(Row::new(vec![
+                Cell::new(&p.warning),
+                Cell::new(&p.error),
+                Cell::new(&format!("{}/{}", p.count, p.warnings)),
+                Cell::new(&format!("{:.1}x", p.lift)),
+            ]));
+        }
+
+        let mut tmp = Vec::new();
+        t.print(&mut tmp).unwrap();
+        out.push_str(&String::from_utf8(tmp).unwrap());
+    }
+
+    if !stats.advice.is_empty() {
+        out.push_str("\nRecommendations:\n");
+        for a in &stats.advice {
+            out.push_str(&format!("  - {}\n", a));
+        }
+    }
+
+    // erreurs par tranche, dans l'ordre chronologique
+    if !stats.errors_by_time.is_empty() {
+        out.push_str(&format!("\nErrors by {}:\n", stats.bucket.name()));
+        let mut t = Table::new();
+        t.add_row(Row::new(vec![Cell::new("Period"), Cell::new("Errors")]));
+        for (period, count) in &stats.errors_by_time {
+            t.add_row(Row::new(vec![Cell::new(period), Cell::new(&count.to_string())]));
+        }
+
+        let mut tmp = Vec::new();
+        t.print(&mut tmp).unwrap();
+        out.push_str(&String::from_utf8(tmp).unwrap());
+    }
+
     out
 }
 
-fn output_json(stats: &LogStats) -> Result<String, serde_json::Error> {
-    serde_json::to_string_pretty(stats)
+/// Version du JSON de `--format json`. Ajouter un champ ne la change pas ;
+/// en retirer ou en changer le sens la fait passer à la suivante, l'ancienne
+/// forme restant disponible avec `--schema-version`.
+const SCHEMA_VERSION: u32 = 2;
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    schema_version: u32,
+    #[serde(flatten)]
+    stats: &'a LogStats,
+}
+
+/// Forme v1 (avant `schema_version`) : erreurs par heure de la journée `HH`,
+/// sans les champs ajoutés depuis.
+#[derive(Serialize)]
+struct StatsV1<'a> {
+    total_entries: usize,
+    by_level: &'a HashMap<String, usize>,
+    top_errors: &'a [ErrorFrequency],
+    errors_by_hour: BTreeMap<String, usize>,
+}
+
+impl<'a> StatsV1<'a> {
+    fn from_stats(stats: &'a LogStats) -> Result<Self> {
+        if stats.bucket == Bucket::Day {
+            return Err(Error::parse("--schema-version 1 needs --bucket hour or minute"));
+        }
+        let mut errors_by_hour = BTreeMap::new();
+        for (key, count) in &stats.errors_by_time {
+            // `2024-01-15 10:00` → `10`
+            if let Some(hour) = key.split_whitespace().nth(1).and_then(|t| t.get(0..2)) {
+                *errors_by_hour.entry(hour.to_string()).or_insert(0) += count;
+            }
+        }
+        Ok(StatsV1 {
+            total_entries: stats.total_entries,
+            by_level: &stats.by_level,
+            top_errors: &stats.top_errors,
+            errors_by_hour,
+        })
+    }
+}
+
+fn output_json(stats: &LogStats, schema_version: u32) -> Result<String> {
+    let json = if schema_version == 1 {
+        serde_json::to_string_pretty(&StatsV1::from_stats(stats)?)
+    } else {
+        serde_json::to_string_pretty(&JsonReport {
+            schema_version: SCHEMA_VERSION,
+            stats,
+        })
+    };
+    json.context("serializing stats to JSON")
 }
 
 fn output_csv(stats: &LogStats) -> String {
     let mut out = String::new();
     out.push_str("metric,category,value\n");
 
+    if let Some(p) = &stats.partial {
+        out.push_str(&format!("partial,bytes_read,{}\n", p.bytes_read));
+        out.push_str(&format!("partial,file_size,{}\n", p.file_size));
+    }
+
     out.push_str(&format!("total,all,{}\n", stats.total_entries));
+    if stats.unparsed_lines > 0 {
+        out.push_str(&format!("unparsed,all,{}\n", stats.unparsed_lines));
+    }
 
     for (lvl, cnt) in &stats.by_level {
         out.push_str(&format!("level,{},{}\n", lvl, cnt));
     }
 
-    for (hour, cnt) in &stats.errors_by_hour {
-        out.push_str(&format!("error_by_hour,{},{}\n", hour, cnt));
+    for (name, cnt) in &stats.counters {
+        out.push_str(&format!("counter,{},{}\n", name, cnt));
+    }
+
+    for (name, cnt) in &stats.unmapped_levels {
+        out.push_str(&format!("unmapped_level,{},{}\n", name, cnt));
+    }
+
+    for (period, cnt) in &stats.errors_by_time {
+        out.push_str(&format!("error_by_{},{},{}\n", stats.bucket.name(), period, cnt));
     }
 
     for err in &stats.top_errors {
         out.push_str(&format!("top_error,\"{}\",{}\n", err.message, err.count));
     }
 
+    for (thread, ts) in &stats.by_thread {
+        out.push_str(&format!("thread_entries,{},{}\n", thread, ts.entries));
+        out.push_str(&format!("thread_errors,{},{}\n", thread, ts.errors));
+    }
+
+    for k in &stats.keys {
+        out.push_str(&format!("key_entries,{},{}\n", k.key, k.entries));
+    }
+
+    if let Some(report) = &stats.group_by {
+        for g in &report.groups {
+            out.push_str(&format!("group_entries,\"{}={}\",{}\n", report.key, g.value, g.entries));
+            out.push_str(&format!("group_errors,\"{}={}\",{}\n", report.key, g.value, g.errors));
+        }
+    }
+
+    for r in &stats.repeated_lines {
+        out.push_str(&format!("repeated_line,\"{}\",{}\n", r.message, r.count));
+    }
+
+    for p in &stats.warning_error_pairs {
+        out.push_str(&format!("warning_error,\"{} -> {}\",{}\n", p.warning, p.error, p.count));
+    }
+
+    for a in &stats.advice {
+        out.push_str(&format!("advice,\"{}\",\n", a.replace('"', "\"\"")));
+    }
+
     out
 }
 
-/// PARTIE 4
-
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+/// Métriques au format texte de Prometheus : entrées par niveau (les quatre,
+/// même à zéro, pour que les alertes aient toujours une série), erreurs les
+/// plus fréquentes, lignes non reconnues et compteurs de `--count-where`.
+fn output_prometheus(stats: &LogStats) -> String {
+    let mut out = String::new();
+    let mut family = |name: &str, kind: &str, help: &str, samples: Vec<(String, String)>| {
+        out.push_str(&format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind));
+        for (labels, value) in samples {
+            out.push_str(&format!("{}{} {}\n", name, labels, value));
+        }
+    };
 
-    if cli.verbose {
-        println!("File: {:?}", cli.input);
-        println!("Parallel forced: {}", cli.parallel);
+    let levels = [LogLevel::Error, LogLevel::Warning, LogLevel::Info, LogLevel::Debug];
+    family(
+        "log_entries_total",
+        "counter",
+        "Log entries by level.",
+        levels
+            .iter()
+            .map(|level| {
+                let count = stats.by_level.get(&format!("{:?}", level)).copied().unwrap_or(0);
+                (format!("{{level=\"{}\"}}", level.name().to_lowercase()), count.to_string())
+            })
+            .collect(),
+    );
+    family(
+        "log_top_error_count",
+        "gauge",
+        "Occurrences of the most frequent error messages.",
+        stats
+            .top_errors
+            .iter()
+            .map(|e| (format!("{{message=\"{}\"}}", label_value(&e.message)), e.count.to_string()))
+            .collect(),
+    );
+    family(
+        "log_unparsed_lines",
+        "gauge",
+        "Non-empty lines that are not log entries, unknown levels included.",
+        vec![(String::new(), stats.unparsed_lines.to_string())],
+    );
+    if !stats.counters.is_empty() {
+        family(
+            "log_counter",
+            "gauge",
+            "Entries matching each --count-where expression.",
+            stats
+                .counters
+                .iter()
+                .map(|(name, count)| (format!("{{name=\"{}\"}}", label_value(name)), count.to_string()))
+                .collect(),
+        );
     }
+    out
+}
 
-    let start = Instant::now();
+/// Valeur de label échappée (`\\`, `\"`, saut de ligne).
+fn label_value(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
 
-    let file_size = std::fs::metadata(&cli.input)?.len();
-    let use_parallel = cli.parallel || file_size > 10_000_000;
+// PARTIE 4
+
+/// Résultat de la lecture des entrées.
+struct Loaded {
+    entries: Vec<LogEntry>,
+    /// Part lue quand ctrl-c a interrompu la lecture d'un fichier.
+    partial: Option<PartialRead>,
+    /// Analyse parallèle (`--parallel` ou gros fichier).
+    parallel: bool,
+    unparsed: u64,
+}
+
+/// Lit les fichiers texte, un par tâche du pool courant : chacun en
+/// séquentiel, par blocs en parallèle au-delà de `LARGE_FILE_BYTES` ou
+/// depuis la fin avec `--tail`. Plusieurs fichiers sont fusionnés dans
+/// l'ordre chronologique, `--head`/`--tail` s'appliquant à l'ensemble.
+fn read_files(cli: &Cli, paths: &[PathBuf], interrupted: Arc<AtomicBool>, levels: Arc<LevelMap>) -> Result<Loaded> {
+    let sizes = paths
+        .iter()
+        .map(|path| {
+            std::fs::metadata(path)
+                .map(|m| m.len())
+                .with_context(|| format!("reading metadata of {}", path.display()))
+        })
+        .collect::<Result<Vec<u64>>>()?;
+    let total_size: u64 = sizes.iter().sum();
+    let use_parallel = cli.parallel || total_size > LARGE_FILE_BYTES;
 
     if cli.verbose {
-        println!("File size: {} bytes", file_size);
+        println!("File size: {} bytes", total_size);
         println!("Mode: {}", if use_parallel { "Parallel" } else { "Sequential" });
     }
 
-    let entries = if use_parallel {
-        read_logs_parallel(&cli.input)?
+    let show_progress = total_size > LARGE_FILE_BYTES && cli.tail.is_none() && std::io::stderr().is_terminal();
+    let reading = Reading::new(show_progress.then(|| progress_bar(total_size)), interrupted, levels);
+
+    let mut per_file = paths
+        .par_iter()
+        .zip(sizes.par_iter())
+        .map(|(path, &size)| {
+            if let Some(n) = cli.tail {
+                read_logs_tail(path, n, &reading)
+            } else if cli.head.is_some() || !(cli.parallel || size > LARGE_FILE_BYTES) {
+                read_logs(path, cli.head, &reading)
+            } else {
+                read_logs_parallel(path, &reading)
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    let entries = if per_file.len() == 1 {
+        per_file.pop().unwrap_or_default()
     } else {
-        read_logs(&cli.input)?
+        // tri stable : à timestamp égal, l'ordre des fichiers puis des lignes
+        let mut entries = per_file.concat();
+        entries.par_sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        if let Some(n) = cli.head {
+            entries.truncate(n);
+        }
+        if let Some(n) = cli.tail {
+            entries.drain(..entries.len().saturating_sub(n));
+        }
+        entries
+    };
+
+    reading.finish();
+    let partial = reading.interrupted().then(|| PartialRead {
+        bytes_read: reading.bytes_read.load(Ordering::Relaxed),
+        file_size: total_size,
+    });
+    if partial.is_some() {
+        eprintln!("{}", "Interrupted: statistics below cover the part of the file read so far".yellow());
+    }
+    Ok(Loaded {
+        entries,
+        partial,
+        parallel: use_parallel,
+        unparsed: reading.unparsed.load(Ordering::Relaxed),
+    })
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    if cli.verbose {
+        println!("File: {:?}", cli.input);
+        println!("Parallel forced: {}", cli.parallel);
+    }
+
+    let counters = custom_counters(&cli.count_where, &cli.count_as)?;
+    let levels = Arc::new(level_map(&cli)?);
+    if cli.input_kind != InputKind::File && cli.emit_matching.is_some() {
+        return Err(Error::parse("--emit-matching needs --input file"));
+    }
+    if cli.input.len() > 1 && cli.emit_matching.is_some() {
+        return Err(Error::parse("--emit-matching needs a single FILE"));
+    }
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(cli.threads)
+        .build()
+        .map_err(std::io::Error::other)?;
+
+    let start = Instant::now();
+
+    // ctrl-c arrête la lecture ; un second quitte sans attendre
+    let interrupted = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register_conditional_shutdown(signal_hook::consts::SIGINT, 130, interrupted.clone())?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, interrupted.clone())?;
+
+    let loaded = match (cli.input_kind, cli.input.as_slice()) {
+        (InputKind::File, []) => return Err(Error::parse("FILE is required with --input file")),
+        (InputKind::File, paths) => pool.install(|| read_files(&cli, paths, interrupted, levels.clone()))?,
+        (_, [_, _, ..]) => return Err(Error::parse("--input journald and evtx take a single FILE")),
+        (kind, path) => {
+            let reading = Reading::new(None, interrupted, levels.clone());
+            let entries = sources::read(kind, path.first().map(PathBuf::as_path), cli.head, cli.tail, &reading)?;
+            if reading.interrupted() {
+                eprintln!("{}", "Interrupted: statistics below cover the records read so far".yellow());
+            }
+            Loaded {
+                entries,
+                partial: None,
+                parallel: cli.parallel,
+                unparsed: reading.unparsed.load(Ordering::Relaxed),
+            }
+        }
     };
+    let use_parallel = loaded.parallel;
 
     let parse_time = start.elapsed();
 
     //filtres
-    let filtered: Vec<_> = entries
+    let filtered: Vec<_> = loaded
+        .entries
         .into_iter()
         .filter(|e| {
             if cli.errors_only && e.level != LogLevel::Error {
@@ -353,27 +1772,74 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                     return false;
                 }
             }
+            if cli.since.is_some() || cli.until.is_some() {
+                let Some(t) = epoch_secs(&e.timestamp) else { return false };
+                if cli.since.is_some_and(|since| t < since) || cli.until.is_some_and(|until| t >= until) {
+                    return false;
+                }
+            }
             true
         })
         .collect();
 
-    let stats = if use_parallel {
-        analyze_logs_parallel(&filtered, cli.top)
+    if let (Some(path), [input]) = (&cli.emit_matching, cli.input.as_slice()) {
+        let written = emit_matching(input, &filtered, path)?;
+        if cli.verbose {
+            eprintln!("Wrote {} matching lines to {}", written, path.display());
+        }
+    }
+
+    let mut stats = if use_parallel {
+        pool.install(|| analyze_logs_parallel(&filtered, cli.top, cli.bucket))
     } else {
-        analyze_logs(&filtered, cli.top)
+        analyze_logs(&filtered, cli.top, cli.bucket)
     };
 
+    stats.partial = loaded.partial;
+    stats.unparsed_lines = loaded.unparsed;
+    stats.unmapped_levels = levels.unmapped();
+    stats.counters = count_where(&filtered, &counters);
+
+    stats.keys = key_stats(&filtered, cli.top.unwrap_or(5));
+    if let Some(key) = &cli.group_by {
+        stats.group_by = Some(group_by(&filtered, key, cli.top.unwrap_or(5)));
+    }
+
+    if let Some(pattern) = &cli.trace_id_regex {
+        stats.traces = Some(trace_requests(&filtered, &trace_id_regex(pattern)?, cli.top.unwrap_or(5)));
+    }
+
+    if let Some(window) = cli.correlate_secs {
+        stats.warning_error_pairs = warning_error_pairs(&filtered, window, cli.top.unwrap_or(5));
+    }
+
+    if cli.advise {
+        stats.advice = advise(
+            &filtered,
+            AdviseThresholds {
+                level_pct: cli.advise_level_pct,
+                message_pct: cli.advise_message_pct,
+                min_entropy: cli.advise_min_entropy,
+            },
+        );
+    }
+
     let total_time = start.elapsed();
 
     // formats d’output
     let output = match cli.format {
         OutputFormat::Text => output_text(&stats),
-        OutputFormat::Json => output_json(&stats)?,
+        OutputFormat::Json => output_json(&stats, cli.schema_version)?,
         OutputFormat::Csv => output_csv(&stats),
+        OutputFormat::Prometheus => output_prometheus(&stats),
     };
 
     if let Some(path) = cli.output {
-        std::fs::write(path, output)?;
+        // écrit à côté puis renommé : un collecteur (textfile de node_exporter)
+        // ne lit jamais un fichier à moitié écrit
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, output).with_context(|| format!("writing {}", tmp.display()))?;
+        std::fs::rename(&tmp, &path).with_context(|| format!("writing {}", path.display()))?;
     } else {
         print!("{}", output);
     }
@@ -386,3 +1852,360 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn quiet() -> Reading {
+        Reading::new(None, Arc::new(AtomicBool::new(false)), Arc::default())
+    }
+
+    fn error_at(timestamp: &str) -> LogEntry {
+        LogEntry {
+            offset: 0,
+            timestamp: timestamp.to_string(),
+            level: LogLevel::Error,
+            thread: None,
+            message: "boom".to_string(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    fn entry(second: u32, level: LogLevel, message: &str) -> LogEntry {
+        LogEntry {
+            offset: 0,
+            timestamp: format!("2024-01-15 10:00:{:02}", second),
+            level,
+            thread: None,
+            message: message.to_string(),
+            fields: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn buckets_keep_the_date() {
+        assert_eq!(Bucket::Day.key("2024-01-15 10:23:45").as_deref(), Some("2024-01-15"));
+        assert_eq!(Bucket::Hour.key("2024-01-15 10:23:45").as_deref(), Some("2024-01-15 10:00"));
+        assert_eq!(Bucket::Minute.key("2024-01-15  10:23:45").as_deref(), Some("2024-01-15 10:23"));
+        assert_eq!(Bucket::Hour.key("2024-01-15"), None);
+    }
+
+    #[test]
+    fn same_hour_of_different_days_is_not_merged() {
+        let entries = vec![
+            error_at("2024-01-16 10:05:00"),
+            error_at("2024-01-15 10:00:00"),
+            error_at("2024-01-15 09:59:59"),
+            error_at("2024-01-15 10:59:59"),
+        ];
+        let expected = vec![("2024-01-15 09:00", 1), ("2024-01-15 10:00", 2), ("2024-01-16 10:00", 1)];
+        for stats in [
+            analyze_logs(&entries, None, Bucket::Hour),
+            analyze_logs_parallel(&entries, None, Bucket::Hour),
+        ] {
+            let got: Vec<_> = stats.errors_by_time.iter().map(|(k, v)| (k.as_str(), *v)).collect();
+            assert_eq!(got, expected);
+        }
+    }
+
+    #[test]
+    fn consecutive_repeats_are_collapsed() {
+        let entries = vec![
+            entry(0, LogLevel::Warning, "retrying"),
+            entry(1, LogLevel::Warning, "retrying"),
+            entry(2, LogLevel::Warning, "retrying"),
+            entry(3, LogLevel::Info, "connected"),
+            entry(4, LogLevel::Error, "retrying"),
+            entry(5, LogLevel::Info, "tick"),
+            entry(6, LogLevel::Info, "tick"),
+            entry(7, LogLevel::Warning, "retrying"),
+        ];
+        let runs = repeated_lines(&entries, 5);
+        assert_eq!(runs.len(), 2);
+        assert_eq!((runs[0].message.as_str(), runs[0].count), ("retrying", 3));
+        assert_eq!((runs[0].first.as_str(), runs[0].last.as_str()), ("2024-01-15 10:00:00", "2024-01-15 10:00:02"));
+        assert_eq!((runs[1].message.as_str(), runs[1].count), ("tick", 2));
+        assert_eq!(repeated_lines(&entries, 1).len(), 1);
+    }
+
+    #[test]
+    fn advice_flags_noisy_levels_and_dominant_errors() {
+        let mut entries: Vec<LogEntry> = (0..30).map(|i| entry(i % 60, LogLevel::Debug, "poll")).collect();
+        entries.extend((0..10).map(|i| entry(i, LogLevel::Info, &format!("request {}", i))));
+        entries.extend((0..6).map(|i| entry(i, LogLevel::Error, "db timeout")));
+        entries.extend((0..4).map(|i| entry(i, LogLevel::Error, &format!("bad input {}", i))));
+        let thresholds = AdviseThresholds {
+            level_pct: 30.0,
+            message_pct: 20.0,
+            min_entropy: 1.0,
+        };
+        let advice = advise(&entries, thresholds);
+        assert_eq!(
+            advice,
+            vec![
+                "DEBUG is 60% of volume; consider raising the log level",
+                "message \"db timeout\" is 60% of errors; add dedup",
+                "DEBUG messages carry 0.00 bits of entropy over 30 lines; a few templates dominate, consider sampling",
+            ]
+        );
+        assert!(advise(&entries, AdviseThresholds { level_pct: 90.0, message_pct: 90.0, min_entropy: 0.0 }).is_empty());
+    }
+
+    #[test]
+    fn threads_and_pids_are_extracted() {
+        let lines = [
+            "2024-01-15 10:00:00 [INFO] [main] Application started",
+            "2024-01-15 10:00:01 [ERROR] [worker-2] Query failed",
+            "2024-01-15 10:00:02 [INFO] [worker-2] Query ok",
+            "2024-01-15 10:00:03 [WARN] pid=4242 Disk almost full",
+            "2024-01-15 10:00:04 [ERROR] Lost connection (pid=4242)",
+            "2024-01-15 10:00:05 [INFO] No thread here",
+        ];
+        let entries: Vec<LogEntry> = lines.iter().filter_map(|l| parse_log_line(l, &LevelMap::default())).collect();
+        assert_eq!(entries[0].thread.as_deref(), Some("main"));
+        assert_eq!(entries[1].message, "Query failed");
+        assert_eq!(entries[3].thread.as_deref(), Some("pid=4242"));
+        assert_eq!(entries[5].thread, None);
+
+        for stats in [
+            analyze_logs(&entries, None, Bucket::Hour),
+            analyze_logs_parallel(&entries, None, Bucket::Hour),
+        ] {
+            assert_eq!(stats.by_thread.len(), 3);
+            let worker = &stats.by_thread["worker-2"];
+            assert_eq!((worker.entries, worker.errors, worker.error_rate), (2, 1, 50.0));
+            assert_eq!(stats.by_thread["pid=4242"].errors, 1);
+        }
+    }
+
+    #[test]
+    fn timestamps_convert_to_epoch_seconds() {
+        assert_eq!(epoch_secs("1970-01-01 00:00:00"), Some(0));
+        assert_eq!(epoch_secs("2024-01-15 10:30:45"), Some(1_705_314_645));
+        assert_eq!(epoch_secs("2024-03-01 00:00:00"), Some(1_709_251_200));
+        assert_eq!(epoch_secs("2024-01-15"), None);
+    }
+
+    #[test]
+    fn warnings_that_precede_errors_are_paired() {
+        let mut entries = Vec::new();
+        for minute in 0..4u32 {
+            let at = |sec: u32, level, msg: &str| LogEntry {
+                offset: 0,
+                timestamp: format!("2024-01-15 10:{:02}:{:02}", minute, sec),
+                level,
+                thread: None,
+                message: msg.to_string(),
+                fields: BTreeMap::new(),
+            };
+            entries.push(at(0, LogLevel::Info, "tick"));
+            entries.push(at(1, LogLevel::Warning, "pool almost empty"));
+            entries.push(at(3, LogLevel::Error, "no connection available"));
+            entries.push(at(20, LogLevel::Warning, "slow request"));
+            entries.push(at(40, LogLevel::Info, "tick"));
+        }
+        let pairs = warning_error_pairs(&entries, 5, 5);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].warning.as_str(), pairs[0].error.as_str()), ("pool almost empty", "no connection available"));
+        assert_eq!((pairs[0].count, pairs[0].warnings), (4, 4));
+        // toujours après le warning, après 8 des 16 lignes hors erreurs
+        assert_eq!(pairs[0].lift, 2.0);
+        assert!(warning_error_pairs(&entries, 1, 5).is_empty());
+    }
+
+    fn keys(json: &str) -> Vec<String> {
+        let value: serde_json::Value = serde_json::from_str(json).unwrap();
+        value.as_object().unwrap().keys().cloned().collect()
+    }
+
+    #[test]
+    fn json_schemas_are_stable() {
+        let entries = vec![error_at("2024-01-15 10:05:00"), error_at("2024-01-16 10:30:00")];
+        let stats = analyze_logs(&entries, None, Bucket::Minute);
+
+        // v2 : champs toujours présents ; les optionnels sont absents quand vides
+        let v2 = output_json(&stats, SCHEMA_VERSION).unwrap();
+        assert_eq!(
+            keys(&v2),
+            vec!["bucket", "by_level", "errors_by_time", "repeated_lines", "schema_version", "top_errors", "total_entries"]
+        );
+        assert!(v2.contains("\"schema_version\": 2"));
+
+        let v1: serde_json::Value = serde_json::from_str(&output_json(&stats, 1).unwrap()).unwrap();
+        assert_eq!(keys(&v1.to_string()), vec!["by_level", "errors_by_hour", "top_errors", "total_entries"]);
+        assert_eq!(v1["errors_by_hour"]["10"], 2);
+        assert!(output_json(&analyze_logs(&entries, None, Bucket::Day), 1).is_err());
+    }
+
+    #[test]
+    fn prometheus_exposition() {
+        let mut entries = vec![error_at("2024-01-15 10:05:00"), error_at("2024-01-15 10:06:00")];
+        entries[1].message = "disk \"sda\" full".to_string();
+        let mut stats = analyze_logs(&entries, None, Bucket::Hour);
+        stats.unparsed_lines = 3;
+        let text = output_prometheus(&stats);
+        assert!(text.contains("# TYPE log_entries_total counter\n"));
+        assert!(text.contains("log_entries_total{level=\"error\"} 2\n"));
+        assert!(text.contains("log_entries_total{level=\"debug\"} 0\n"));
+        assert!(text.contains("log_top_error_count{message=\"disk \\\"sda\\\" full\"} 1\n"));
+        assert!(text.contains("log_unparsed_lines 3\n"));
+        assert!(!text.contains("log_counter"));
+    }
+
+    #[test]
+    fn head_and_tail_keep_the_ends_of_the_file() {
+        let path = std::env::temp_dir().join(format!("loglyzer-tail-{}.log", std::process::id()));
+        let mut text = String::new();
+        for i in 0..50 {
+            text.push_str(&format!("2024-01-15 10:00:{:02} [INFO] line {}\r\n", i, i));
+            text.push_str("not a log line\n");
+        }
+        std::fs::write(&path, text).unwrap();
+
+        let messages = |entries: Vec<LogEntry>| entries.into_iter().map(|e| e.message).collect::<Vec<_>>();
+        assert_eq!(messages(read_logs(&path, Some(2), &quiet()).unwrap()), vec!["line 0", "line 1"]);
+        // blocs plus petits qu'une ligne : les lignes à cheval sont recollées
+        for chunk in [7, 40, TAIL_CHUNK] {
+            assert_eq!(messages(read_tail(&path, 3, chunk, &quiet()).unwrap()), vec!["line 47", "line 48", "line 49"]);
+        }
+        assert_eq!(read_tail(&path, 500, 7, &quiet()).unwrap().len(), 50);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn files_are_merged_in_time_order() {
+        let dir = std::env::temp_dir();
+        let (a, b) = (
+            dir.join(format!("loglyzer-merge-a-{}.log", std::process::id())),
+            dir.join(format!("loglyzer-merge-b-{}.log", std::process::id())),
+        );
+        std::fs::write(&a, "2024-01-15 10:00:00 [INFO] a0\n2024-01-15 10:00:02 [INFO] a2\n").unwrap();
+        std::fs::write(&b, "2024-01-15 10:00:01 [INFO] b1\n2024-01-15 10:00:02 [INFO] b2\nnoise\n").unwrap();
+        let read = |args: &[&str]| {
+            let cli = Cli::parse_from(["loglyzer"].iter().copied().chain(args.iter().copied()));
+            let loaded = read_files(&cli, &[a.clone(), b.clone()], Arc::default(), Arc::default()).unwrap();
+            let messages: Vec<String> = loaded.entries.into_iter().map(|e| e.message).collect();
+            (messages, loaded.unparsed)
+        };
+
+        // à timestamp égal, l'ordre des fichiers
+        assert_eq!(read(&[]), (vec!["a0".into(), "b1".into(), "a2".into(), "b2".into()], 1));
+        assert_eq!(read(&["--head", "2"]).0, vec!["a0", "b1"]);
+        assert_eq!(read(&["--tail", "1"]).0, vec!["b2"]);
+        assert_eq!(read(&["--parallel"]).0.len(), 4);
+        std::fs::remove_file(a).unwrap();
+        std::fs::remove_file(b).unwrap();
+    }
+
+    #[test]
+    fn entries_are_grouped_by_key() {
+        let lines = [
+            "2024-01-15 10:00:00 [INFO] GET /a status_code=200 dur=12ms",
+            "2024-01-15 10:00:01 [ERROR] GET /b status_code=500 dur=3ms",
+            "2024-01-15 10:00:02 [INFO] GET /a status_code=200",
+            "2024-01-15 10:00:03 [ERROR] GET /c status_code=500 dur=40ms",
+            "2024-01-15 10:00:04 [WARN] slow request",
+        ];
+        let entries: Vec<LogEntry> = lines.iter().filter_map(|l| parse_log_line(l, &LevelMap::default())).collect();
+        assert_eq!(entries[0].fields["dur"], "12ms");
+
+        let keys = key_stats(&entries, 5);
+        assert_eq!((keys[0].key.as_str(), keys[0].entries, keys[0].distinct_values), ("status_code", 4, 2));
+        assert_eq!((keys[1].key.as_str(), keys[1].entries, keys[1].distinct_values), ("dur", 3, 3));
+
+        let report = group_by(&entries, "status_code", 5);
+        assert_eq!(report.missing, 1);
+        let groups: Vec<_> = report.groups.iter().map(|g| (g.value.as_str(), g.entries, g.errors)).collect();
+        assert_eq!(groups, vec![("200", 2, 0), ("500", 2, 2)]);
+        assert_eq!(parse_group_by("key:status_code").unwrap(), "status_code");
+        assert!(parse_group_by("status_code").is_err());
+    }
+
+    #[test]
+    fn requests_are_traced_by_correlation_id() {
+        let lines = [
+            "2024-01-15 10:00:00 [INFO] req=a1 received",
+            "2024-01-15 10:00:01 [INFO] req=b2 received",
+            "2024-01-15 10:00:02 [ERROR] req=b2 upstream timeout",
+            "2024-01-15 10:00:03 [INFO] housekeeping",
+            "2024-01-15 10:00:09 [INFO] req=a1 done",
+            "2024-01-15 10:00:04 [INFO] req=b2 done",
+        ];
+        let entries: Vec<LogEntry> = lines.iter().filter_map(|l| parse_log_line(l, &LevelMap::default())).collect();
+        let report = trace_requests(&entries, &trace_id_regex(r"req=(?P<id>[a-f0-9-]+)").unwrap(), 1);
+        assert_eq!((report.requests, report.with_errors), (2, 1));
+        assert_eq!(report.failing.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), vec!["b2"]);
+        let slowest = &report.slowest[0];
+        assert_eq!((slowest.id.as_str(), slowest.duration_secs, slowest.entries), ("a1", 9, 2));
+        assert_eq!(slowest.timeline[1].message, "req=a1 done");
+        assert!(report.failing[0].timeline.is_empty());
+
+        assert!(trace_id_regex("req=[a-f]+").is_err());
+        assert!(trace_id_regex("req=(").is_err());
+    }
+
+    #[test]
+    fn matching_lines_are_copied_verbatim() {
+        let dir = std::env::temp_dir();
+        let input = dir.join(format!("loglyzer-emit-{}.log", std::process::id()));
+        let out = dir.join(format!("loglyzer-emit-{}.out", std::process::id()));
+        let text = "2024-01-15 10:00:00 [INFO] up\r\n\
+                    garbage\n\
+                    2024-01-15 10:00:01  [ERROR]   disk full\n\
+                    2024-01-15 10:00:02 [INFO] ok\n\
+                    2024-01-15 10:00:03 [ERROR] disk full again";
+        std::fs::write(&input, text).unwrap();
+
+        for entries in [
+            read_logs(&input, None, &quiet()).unwrap(),
+            read_logs_parallel(&input, &quiet()).unwrap(),
+            read_tail(&input, 10, 7, &quiet()).unwrap(),
+        ] {
+            let errors: Vec<LogEntry> = entries.into_iter().filter(|e| e.level == LogLevel::Error).collect();
+            assert_eq!(emit_matching(&input, &errors, &out).unwrap(), 2);
+            assert_eq!(
+                std::fs::read_to_string(&out).unwrap(),
+                "2024-01-15 10:00:01  [ERROR]   disk full\n2024-01-15 10:00:03 [ERROR] disk full again\n"
+            );
+        }
+        assert_eq!(emit_matching(&input, &[], &out).unwrap(), 0);
+        assert_eq!(parse_time_bound("2024-01-15"), Ok(1_705_276_800));
+        assert!(parse_time_bound("yesterday").is_err());
+        std::fs::remove_file(input).unwrap();
+        std::fs::remove_file(out).unwrap();
+    }
+
+    #[test]
+    fn reading_stops_once_interrupted() {
+        let path = std::env::temp_dir().join(format!("loglyzer-interrupt-{}.log", std::process::id()));
+        std::fs::write(&path, "2024-01-15 10:00:00 [INFO] a\n2024-01-15 10:00:01 [INFO] b\n").unwrap();
+
+        let reading = quiet();
+        assert_eq!(read_logs_parallel(&path, &reading).unwrap().len(), 2);
+        assert_eq!(reading.bytes_read.load(Ordering::Relaxed), 58);
+
+        let interrupted = Reading::new(None, Arc::new(AtomicBool::new(true)), Arc::default());
+        assert!(read_logs(&path, None, &interrupted).unwrap().is_empty());
+        assert!(read_logs_parallel(&path, &interrupted).unwrap().is_empty());
+        assert!(read_tail(&path, 5, 16, &interrupted).unwrap().is_empty());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn custom_counters_are_named_and_counted() {
+        let entries = vec![
+            entry(0, LogLevel::Error, "API timeout"),
+            entry(1, LogLevel::Error, "disk full"),
+            entry(2, LogLevel::Warning, "slow, near timeout"),
+        ];
+        let exprs = vec![r#"level==ERROR && msg~"timeout""#.to_string(), "level!=DEBUG".to_string()];
+        let counters = custom_counters(&exprs, &["timeout_errors".to_string()]).unwrap();
+        let counts = count_where(&entries, &counters);
+        assert_eq!(counts["timeout_errors"], 1);
+        assert_eq!(counts["count_where_2"], 3);
+
+        assert!(custom_counters(&exprs[..1], &["a".to_string(), "b".to_string()]).is_err());
+        assert!(custom_counters(&["levle==ERROR".to_string()], &[]).is_err());
+    }
+}
@@ -0,0 +1,299 @@
+// Entrées qui ne sont pas des fichiers texte : journal systemd et journaux
+// d'événements Windows. Les enregistrements sont lus en JSON, un par ligne,
+// sur la sortie d'un outil du système (`journalctl -o json`, PowerShell
+// `Get-WinEvent`, d'où les journaux Windows lus sous Windows uniquement) et
+// deviennent des `LogEntry` datées en UTC, avec la première ligne du
+// message ; `offset` est le numéro de l'enregistrement.
+
+use crate::{LogEntry, Reading};
+use std::path::Path;
+use td_common::{Error, Result};
+#[cfg(any(feature = "journald", all(feature = "evtx", windows)))]
+use {
+    serde_json::Value,
+    std::io::{BufRead, BufReader},
+    std::process::{Command, Stdio},
+    std::sync::atomic::Ordering,
+    td_common::Context,
+};
+
+/// Source des entrées (`--input`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum InputKind {
+    /// Fichier texte, une entrée par ligne
+    File,
+    /// Journal systemd, via `journalctl` (feature `journald`)
+    Journald,
+    /// Journal d'événements Windows, via PowerShell (feature `evtx`, Windows
+    /// uniquement)
+    Evtx,
+}
+
+/// Lit une source autre qu'un fichier texte. `path` : fichier journal
+/// (`journalctl --file`) ou fichier .evtx / nom de journal (`System`) ;
+/// `head` et `tail` sont passés à l'outil quand il sait les appliquer.
+#[cfg_attr(not(all(feature = "journald", feature = "evtx", windows)), allow(unused_variables))]
+pub fn read(
+    kind: InputKind,
+    path: Option<&Path>,
+    head: Option<usize>,
+    tail: Option<usize>,
+    reading: &Reading,
+) -> Result<Vec<LogEntry>> {
+    match kind {
+        InputKind::File => unreachable!("text files are read by read_logs"),
+        #[cfg(feature = "journald")]
+        InputKind::Journald => journald::read(path, head, tail, reading),
+        #[cfg(all(feature = "evtx", windows))]
+        InputKind::Evtx => evtx::read(path, head, tail, reading),
+        #[cfg(not(feature = "journald"))]
+        InputKind::Journald => Err(missing_feature("journald")),
+        #[cfg(all(not(feature = "evtx"), windows))]
+        InputKind::Evtx => Err(missing_feature("evtx")),
+        #[cfg(not(windows))]
+        InputKind::Evtx => Err(Error::parse("--input evtx is Windows-only (PowerShell Get-WinEvent)")),
+    }
+}
+
+#[cfg(any(not(feature = "journald"), all(not(feature = "evtx"), windows)))]
+fn missing_feature(name: &str) -> Error {
+    Error::parse(format!("--input {} needs loglyzer built with the `{}` feature", name, name))
+}
+
+/// Lance `command` et convertit chaque ligne JSON de sa sortie avec
+/// `record` ; s'arrête après `head` entrées ou au ctrl-c.
+#[cfg(any(feature = "journald", all(feature = "evtx", windows)))]
+fn run(
+    mut command: Command,
+    head: Option<usize>,
+    reading: &Reading,
+    record: fn(&Value, u64) -> Option<LogEntry>,
+) -> Result<Vec<LogEntry>> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("running {}", program))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let full = |entries: &Vec<LogEntry>| head.is_some_and(|n| entries.len() >= n);
+
+    let mut entries = Vec::new();
+    for (n, line) in BufReader::new(stdout).lines().enumerate() {
+        if full(&entries) || reading.interrupted() {
+            break;
+        }
+        let line = line.with_context(|| format!("reading the output of {}", program))?;
+        match serde_json::from_str::<Value>(&line).ok().and_then(|value| record(&value, n as u64)) {
+            Some(entry) => entries.push(entry),
+            None => {
+                reading.unparsed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    // arrêté avant la fin : l'outil n'a plus personne pour le lire
+    let stopped = full(&entries) || reading.interrupted();
+    if stopped {
+        let _ = child.kill();
+    }
+    let status = child.wait()?;
+    if !stopped && !status.success() {
+        return Err(Error::Io(std::io::Error::other(format!("{} exited with {}", program, status))));
+    }
+    Ok(entries)
+}
+
+/// `YYYY-MM-DD HH:MM:SS` (UTC) d'un instant en secondes depuis 1970,
+/// inverse de `epoch_secs` (algorithme « civil from days »).
+#[cfg(any(feature = "journald", all(feature = "evtx", windows)))]
+fn format_epoch(secs: i64) -> String {
+    let (days, rem) = (secs.div_euclid(86_400), secs.rem_euclid(86_400));
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = doy - (153 * mp + 2) / 5 + 1;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 };
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02} {:02}:{:02}:{:02}", y, m, d, rem / 3600, rem / 60 % 60, rem % 60)
+}
+
+/// Première ligne non vide d'un message.
+#[cfg(any(feature = "journald", all(feature = "evtx", windows)))]
+fn first_line(message: &str) -> Option<&str> {
+    message.lines().map(str::trim).find(|line| !line.is_empty())
+}
+
+#[cfg(feature = "journald")]
+mod journald {
+    use super::*;
+    use crate::{kv, LogLevel};
+
+    pub fn read(path: Option<&Path>, head: Option<usize>, tail: Option<usize>, reading: &Reading) -> Result<Vec<LogEntry>> {
+        let mut command = Command::new("journalctl");
+        command.args(["-o", "json", "--no-pager"]);
+        if let Some(path) = path {
+            command.arg("--file").arg(path);
+        }
+        if let Some(n) = tail {
+            command.arg("-n").arg(n.to_string());
+        }
+        run(command, head, reading, record)
+    }
+
+    /// PRIORITY syslog : 0-3 (emerg..err) erreur, 4 warning, 5-6 (notice,
+    /// info) info, 7 debug ; `SYSLOG_IDENTIFIER` préfixe le message.
+    pub fn record(value: &Value, n: u64) -> Option<LogEntry> {
+        let micros: i64 = field(value, "__REALTIME_TIMESTAMP")?.parse().ok()?;
+        let message = field(value, "MESSAGE")?;
+        let message = first_line(&message)?;
+        let level = match field(value, "PRIORITY").and_then(|p| p.parse::<u8>().ok()) {
+            Some(0..=3) => LogLevel::Error,
+            Some(4) => LogLevel::Warning,
+            Some(7) => LogLevel::Debug,
+            _ => LogLevel::Info,
+        };
+        let message = match field(value, "SYSLOG_IDENTIFIER") {
+            Some(ident) => format!("{}: {}", ident, message),
+            None => message.to_string(),
+        };
+        Some(LogEntry {
+            offset: n,
+            timestamp: format_epoch(micros.div_euclid(1_000_000)),
+            level,
+            thread: field(value, "_PID").map(|pid| format!("pid={}", pid)),
+            fields: kv::extract(&message),
+            message,
+        })
+    }
+
+    /// Valeur texte d'un champ : journald écrit les valeurs non UTF-8 en
+    /// tableau d'octets et les champs répétés en tableau de chaînes.
+    fn field(value: &Value, name: &str) -> Option<String> {
+        match value.get(name)? {
+            Value::String(s) => Some(s.clone()),
+            Value::Array(items) if items.iter().all(Value::is_u64) => {
+                let bytes: Vec<u8> = items.iter().filter_map(Value::as_u64).map(|b| b as u8).collect();
+                Some(String::from_utf8_lossy(&bytes).into_owned())
+            }
+            Value::Array(items) => items.first()?.as_str().map(str::to_string),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(all(feature = "evtx", windows))]
+mod evtx {
+    use super::*;
+    use crate::{kv, LogLevel};
+
+    /// Un objet JSON par événement, sur une ligne.
+    const SCRIPT: &str = "[Console]::OutputEncoding = [Text.Encoding]::UTF8; \
+        Get-WinEvent {source} {order} -ErrorAction Stop | ForEach-Object { \
+        [pscustomobject]@{ t = ([DateTimeOffset]$_.TimeCreated).ToUnixTimeSeconds(); level = [int]$_.Level; \
+        provider = $_.ProviderName; id = $_.Id; pid = $_.ProcessId; message = $_.Message } \
+        | ConvertTo-Json -Compress }";
+
+    /// `path` : fichier .evtx exporté, ou nom d'un journal de la machine
+    /// (`System`, `Application`).
+    pub fn read(path: Option<&Path>, head: Option<usize>, tail: Option<usize>, reading: &Reading) -> Result<Vec<LogEntry>> {
+        let path = path.ok_or_else(|| Error::parse("--input evtx needs an .evtx file or a log name"))?;
+        let quoted = format!("'{}'", path.to_string_lossy().replace('\'', "''"));
+        let source = if path.is_file() { format!("-Path {}", quoted) } else { format!("-LogName {}", quoted) };
+        // sans -Oldest, Get-WinEvent part des plus récents
+        let order = match (head, tail) {
+            (_, Some(n)) => format!("-MaxEvents {}", n),
+            (Some(n), None) => format!("-Oldest -MaxEvents {}", n),
+            (None, None) => "-Oldest".to_string(),
+        };
+        let mut command = Command::new("powershell");
+        command.args(["-NoProfile", "-NonInteractive", "-Command"]);
+        command.arg(SCRIPT.replace("{source}", &source).replace("{order}", &order));
+        let mut entries = run(command, head, reading, record)?;
+        if tail.is_some() {
+            entries.reverse();
+        }
+        Ok(entries)
+    }
+
+    /// Niveaux Windows : 1-2 (critique, erreur) erreur, 3 avertissement,
+    /// 5 (verbose) debug, 0 et 4 (information) info.
+    pub fn record(value: &Value, n: u64) -> Option<LogEntry> {
+        let secs = value.get("t")?.as_i64()?;
+        let level = match value.get("level").and_then(Value::as_u64) {
+            Some(1 | 2) => LogLevel::Error,
+            Some(3) => LogLevel::Warning,
+            Some(5) => LogLevel::Debug,
+            _ => LogLevel::Info,
+        };
+        let provider = value.get("provider").and_then(Value::as_str).unwrap_or("?");
+        // sans les métadonnées du fournisseur, l'événement n'a pas de texte
+        let message = match value.get("message").and_then(Value::as_str).and_then(first_line) {
+            Some(message) => format!("{}: {}", provider, message),
+            None => format!("{}: event {}", provider, value.get("id").and_then(Value::as_u64).unwrap_or(0)),
+        };
+        Some(LogEntry {
+            offset: n,
+            timestamp: format_epoch(secs),
+            level,
+            thread: value.get("pid").and_then(Value::as_u64).filter(|&pid| pid > 0).map(|pid| format!("pid={}", pid)),
+            fields: kv::extract(&message),
+            message,
+        })
+    }
+}
+
+#[cfg(all(test, any(feature = "journald", all(feature = "evtx", windows))))]
+mod tests {
+    use super::*;
+    use crate::{epoch_secs, LogLevel};
+
+    #[test]
+    fn format_epoch_is_the_inverse_of_epoch_secs() {
+        for timestamp in ["1970-01-01 00:00:00", "2000-02-29 23:59:59", "2024-01-15 10:23:45", "1969-12-31 12:00:00"] {
+            assert_eq!(format_epoch(epoch_secs(timestamp).unwrap()), timestamp);
+        }
+    }
+
+    #[cfg(feature = "journald")]
+    #[test]
+    fn journald_records() {
+        let value: Value = serde_json::from_str(
+            r#"{"__REALTIME_TIMESTAMP":"1705314225123456","PRIORITY":"3","_PID":"812",
+                "SYSLOG_IDENTIFIER":"sshd","MESSAGE":"error: connection reset\nsecond line"}"#,
+        )
+        .unwrap();
+        let entry = journald::record(&value, 4).unwrap();
+        assert_eq!(entry.timestamp, "2024-01-15 10:23:45");
+        assert_eq!(entry.level, LogLevel::Error);
+        assert_eq!(entry.thread.as_deref(), Some("pid=812"));
+        assert_eq!(entry.message, "sshd: error: connection reset");
+        assert_eq!(entry.offset, 4);
+
+        // message non UTF-8 en octets, sans priorité
+        let value: Value =
+            serde_json::from_str(r#"{"__REALTIME_TIMESTAMP":"0","MESSAGE":[104,105,255]}"#).unwrap();
+        let entry = journald::record(&value, 0).unwrap();
+        assert_eq!((entry.level, entry.message.as_str()), (LogLevel::Info, "hi\u{fffd}"));
+        assert!(journald::record(&serde_json::json!({ "MESSAGE": "no time" }), 0).is_none());
+    }
+
+    #[cfg(all(feature = "evtx", windows))]
+    #[test]
+    fn evtx_records() {
+        let value = serde_json::json!({
+            "t": 1705314225, "level": 3, "provider": "Disk", "id": 51, "pid": 4,
+            "message": "An error was detected on device \\Device\\Harddisk1\\DR1 during a paging operation.\r\n"
+        });
+        let entry = evtx::record(&value, 0).unwrap();
+        assert_eq!(entry.level, LogLevel::Warning);
+        assert_eq!(entry.timestamp, "2024-01-15 10:23:45");
+        assert!(entry.message.starts_with("Disk: An error was detected"));
+
+        let value = serde_json::json!({ "t": 0, "level": 2, "provider": "App", "id": 1000, "pid": 0, "message": null });
+        let entry = evtx::record(&value, 1).unwrap();
+        assert_eq!((entry.level, entry.thread, entry.message.as_str()), (LogLevel::Error, None, "App: event 1000"));
+    }
+}
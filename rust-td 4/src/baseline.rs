@@ -0,0 +1,193 @@
+use crate::benchmarks::{BenchmarkResult, JournalResult, L3BenchmarkResult};
+use crate::journal::FsyncPolicy;
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+
+// Garde-fou contre les régressions : les temps d'un run sont enregistrés en
+// JSON (`{"default/OrderBook/update": 14.2, ...}`, ns par opération) et un
+// run suivant se compare à ce fichier ; une métrique plus lente que la
+// référence au-delà du seuil fait échouer le benchmark. Le débit de la
+// contention et le journal en `fsync` à chaque update dépendent trop de la
+// machine et du disque pour en faire partie.
+
+/// Écart absolu sous lequel une métrique ne régresse pas : les lectures
+/// tombent à quelques centièmes de ns, où 10 % n'est que du bruit.
+const NOISE_FLOOR_NS: f64 = 0.5;
+
+/// Temps d'un run par métrique, en ns par opération (plus bas = mieux).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics(BTreeMap<String, f64>);
+
+impl Metrics {
+    pub fn insert(&mut self, name: impl Into<String>, ns: f64) {
+        self.0.insert(name.into(), ns);
+    }
+
+    /// `<group>/<implémentation>/<opération>` ; `group` distingue les formes de carnet.
+    pub fn add_orderbook(&mut self, group: &str, result: &BenchmarkResult) {
+        let prefix = format!("{}/{}", group, result.name);
+        for (op, ns) in [
+            ("update", result.avg_update_ns),
+            ("update_p99", result.p99_update_ns),
+            ("spread", result.avg_spread_ns),
+            ("best_bid", result.avg_best_bid_ns),
+            ("best_ask", result.avg_best_ask_ns),
+            ("random_read", result.avg_random_read_ns),
+            ("top_levels", result.avg_top_levels_ns),
+            ("insert", result.breakdown.insert_ns),
+            ("modify", result.breakdown.modify_ns),
+            ("remove_best", result.breakdown.remove_best_ns),
+            ("remove_deep", result.breakdown.remove_deep_ns),
+        ] {
+            self.insert(format!("{}/{}", prefix, op), ns);
+        }
+    }
+
+    pub fn add_l3(&mut self, result: &L3BenchmarkResult) {
+        self.insert(format!("l3/{}/add", result.name), result.avg_add_ns);
+        self.insert(format!("l3/{}/cancel", result.name), result.avg_cancel_ns);
+    }
+
+    pub fn add_journal(&mut self, results: &[JournalResult]) {
+        for r in results {
+            let policy = match r.policy {
+                None => "book".to_string(),
+                Some(FsyncPolicy::Never) => "never".to_string(),
+                Some(FsyncPolicy::Every(n)) => format!("every{}", n),
+                Some(FsyncPolicy::Always) => continue,
+            };
+            self.insert(format!("journal/{}", policy), r.avg_update_ns);
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let metrics = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+        Ok(Metrics(metrics))
+    }
+
+    /// Écrit le fichier, en créant son dossier au besoin.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &self.0)?;
+        writeln!(writer)?;
+        writer.flush()
+    }
+
+    /// Compare ce run à `baseline` : régression quand une métrique est plus
+    /// lente de plus de `max_regression_pct` % (et d'au moins `NOISE_FLOOR_NS`).
+    pub fn compare(&self, baseline: &Metrics, max_regression_pct: f64) -> Comparison {
+        let mut names: Vec<&String> = self.0.keys().chain(baseline.0.keys()).collect();
+        names.sort();
+        names.dedup();
+        let diffs = names
+            .into_iter()
+            .map(|name| {
+                let (baseline, current) = (baseline.0.get(name).copied(), self.0.get(name).copied());
+                let pair = baseline.zip(current);
+                let change_pct = pair.map(|(before, now)| (now / before - 1.0) * 100.0);
+                let above_noise = pair.is_some_and(|(before, now)| now - before >= NOISE_FLOOR_NS);
+                MetricDiff {
+                    name: name.clone(),
+                    baseline,
+                    current,
+                    change_pct,
+                    regressed: above_noise && change_pct.is_some_and(|change| change > max_regression_pct),
+                }
+            })
+            .collect();
+        Comparison { diffs, max_regression_pct }
+    }
+}
+
+/// Une ligne du tableau ; une métrique absente d'un côté n'est jamais une régression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricDiff {
+    pub name: String,
+    pub baseline: Option<f64>,
+    pub current: Option<f64>,
+    /// Positif = plus lent que la référence.
+    pub change_pct: Option<f64>,
+    pub regressed: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comparison {
+    pub diffs: Vec<MetricDiff>,
+    pub max_regression_pct: f64,
+}
+
+impl Comparison {
+    pub fn regressions(&self) -> usize {
+        self.diffs.iter().filter(|d| d.regressed).count()
+    }
+
+    pub fn print(&self, baseline_path: &Path) {
+        let ns = |value: Option<f64>| value.map_or("-".to_string(), |ns| format!("{:.2}", ns));
+        println!("{}", "=".repeat(80));
+        println!("  BASELINE {} (max regression {:.1}%)", baseline_path.display(), self.max_regression_pct);
+        println!("{}", "=".repeat(80));
+        println!("  {:<44} {:>9} {:>9} {:>8}", "ns/op", "baseline", "current", "change");
+        for d in &self.diffs {
+            let change = match (d.change_pct, d.baseline) {
+                (Some(change), _) => format!("{:+.1}%", change),
+                (None, None) => "new".to_string(),
+                (None, Some(_)) => "missing".to_string(),
+            };
+            let flag = if d.regressed { "  REGRESSED" } else { "" };
+            println!("  {:<44} {:>9} {:>9} {:>8}{}", d.name, ns(d.baseline), ns(d.current), change, flag);
+        }
+        println!("  {} metric(s) regressed", self.regressions());
+        println!("{}\n", "=".repeat(80));
+    }
+}
+
+/// `10%` ou `10`.
+pub fn parse_percent(value: &str) -> Result<f64, String> {
+    let number = value.trim().trim_end_matches('%');
+    match number.parse::<f64>() {
+        Ok(pct) if pct >= 0.0 => Ok(pct),
+        _ => Err(format!("expected a percentage like 10%, got `{}`", value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn slower_metrics_beyond_the_threshold_are_regressions() {
+        let mut baseline = Metrics::default();
+        baseline.insert("default/OrderBook/update", 10.0);
+        baseline.insert("default/OrderBook/spread", 2.0);
+        baseline.insert("default/OrderBook/best_bid", 0.01);
+        baseline.insert("l3/L3 Box/add", 40.0);
+        let mut current = Metrics::default();
+        current.insert("default/OrderBook/update", 10.9);
+        current.insert("default/OrderBook/spread", 2.5);
+        current.insert("default/OrderBook/best_bid", 0.02);
+        current.insert("journal/never", 30.0);
+
+        let comparison = current.compare(&baseline, 10.0);
+        assert_eq!(comparison.regressions(), 1);
+        let regressed: Vec<&str> = comparison.diffs.iter().filter(|d| d.regressed).map(|d| d.name.as_str()).collect();
+        assert_eq!(regressed, ["default/OrderBook/spread"]);
+        // absente d'un côté : affichée, pas une régression
+        assert_eq!(comparison.diffs.len(), 5);
+
+        let path = std::env::temp_dir().join(format!("orderbook-baseline-{}", std::process::id())).join("b.json");
+        current.save(&path).unwrap();
+        assert_eq!(Metrics::load(&path).unwrap(), current);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+
+        assert_eq!(parse_percent("10%"), Ok(10.0));
+        assert_eq!(parse_percent("2.5"), Ok(2.5));
+        assert!(parse_percent("ten").is_err());
+        assert!(parse_percent("-5%").is_err());
+    }
+}
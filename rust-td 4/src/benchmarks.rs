@@ -1,14 +1,104 @@
-use crate::interfaces::{OrderBook, Side, Update};
-use std::time::Instant;
+use crate::concurrent::ConcurrentOrderBook;
+use crate::interfaces::{ChurnStats, OrderBook, Price, Quantity, Side, Update};
+use crate::journal::{FsyncPolicy, JournaledBook};
+use crate::l3::{L3Book, Order, OrderId, OrderStore};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 // Mesure en batch pour éviter la limite de résolution de `Instant` (sous Windows ~100ns). Pour perf !!!
 const BATCH_SIZE: usize = 10_000;
 const UPDATE_BATCH_SIZE: usize = 100_000;
+/// Levels per batch of the update breakdown, at most the side's depth.
+const KIND_BATCH_SIZE: usize = 64;
+/// Depth of the `get_top_levels` snapshots measured.
+pub const TOP_LEVELS_DEPTH: usize = 10;
 
 // ============================================================================
 // BENCHMARKING & TESTING FRAMEWORK
 // ============================================================================
 
+/// Mid of the warmup book: bids from `BASE_PRICE` down, asks from
+/// `BASE_PRICE + 10` up.
+const BASE_PRICE: Price = 100000;
+
+/// Quantities of the warmup levels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QuantityDistribution {
+    Uniform { min: Quantity, max: Quantity },
+    /// `exp(N(mu, sigma))`, at least 1: many small levels, a few big ones.
+    LogNormal { mu: f64, sigma: f64 },
+}
+
+/// Book the benchmark starts from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookShape {
+    /// Levels per side.
+    pub depth: usize,
+    /// Price gap between two levels of a side.
+    pub spacing: Price,
+    pub quantities: QuantityDistribution,
+}
+
+impl Default for BookShape {
+    fn default() -> Self {
+        BookShape {
+            depth: 100,
+            spacing: 10,
+            quantities: QuantityDistribution::Uniform { min: 100, max: 100 },
+        }
+    }
+}
+
+impl fmt::Display for BookShape {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} levels/side, spacing {}, ", self.depth, self.spacing)?;
+        match self.quantities {
+            QuantityDistribution::Uniform { min, max } => write!(f, "uniform {}..={}", min, max),
+            QuantityDistribution::LogNormal { mu, sigma } => write!(f, "lognormal(mu {}, sigma {})", mu, sigma),
+        }
+    }
+}
+
+/// Deterministic generator (LCG), so every implementation sees the same book.
+struct Lcg(u64);
+
+impl Lcg {
+    fn next_f64(&mut self) -> f64 {
+        self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    fn quantity(&mut self, distribution: QuantityDistribution) -> Quantity {
+        match distribution {
+            QuantityDistribution::Uniform { min, max } => {
+                min + (self.next_f64() * (max.saturating_sub(min) + 1) as f64) as Quantity
+            }
+            QuantityDistribution::LogNormal { mu, sigma } => {
+                // Box-Muller
+                let (u1, u2) = (1.0 - self.next_f64(), self.next_f64());
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                ((mu + sigma * z).exp().round() as Quantity).max(1)
+            }
+        }
+    }
+}
+
+/// Average ns of `apply_update` by kind of update; implementations trade
+/// these off very differently (shifting a sorted array, scanning for the
+/// next best...).
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UpdateBreakdown {
+    /// `Set` of a price that has no level.
+    pub insert_ns: f64,
+    /// `Set` of an existing level to another quantity.
+    pub modify_ns: f64,
+    /// `Remove` of the best level.
+    pub remove_best_ns: f64,
+    /// `Remove` of a level behind the best.
+    pub remove_deep_ns: f64,
+}
+
 #[derive(Debug, Clone)]
 pub struct BenchmarkResult {
     pub name: String,
@@ -17,10 +107,22 @@ pub struct BenchmarkResult {
     pub avg_best_bid_ns: f64,
     pub avg_best_ask_ns: f64,
     pub avg_random_read_ns: f64,
+    pub avg_top_levels_ns: f64,
     pub p50_update_ns: f64,
     pub p95_update_ns: f64,
     pub p99_update_ns: f64,
+    pub breakdown: UpdateBreakdown,
     pub total_operations: usize,
+    /// Warmup book.
+    pub shape: BookShape,
+    /// Book shape after the timed updates.
+    pub bid_levels: usize,
+    pub ask_levels: usize,
+    /// Level churn of the timed updates.
+    pub churn: ChurnStats,
+    /// `size_of` the book, and the heap it owns after the run.
+    pub struct_bytes: usize,
+    pub heap_bytes: usize,
 }
 
 pub struct OrderBookBenchmark;
@@ -28,13 +130,20 @@ pub struct OrderBookBenchmark;
 impl OrderBookBenchmark {
     /// Run comprehensive benchmark suite
     pub fn run<T: OrderBook>(name: &str, iterations: usize) -> BenchmarkResult {
+        Self::run_with_shape::<T>(name, iterations, BookShape::default())
+    }
+
+    /// Same, from a warmup book of the given depth and shape.
+    pub fn run_with_shape<T: OrderBook>(name: &str, iterations: usize, shape: BookShape) -> BenchmarkResult {
         let mut ob = T::new();
 
         // Warm up
-        Self::warmup(&mut ob);
+        Self::warmup(&mut ob, shape);
+        ob.reset_stats();
 
         // Benchmark updates
         let update_timings = Self::benchmark_updates(&mut ob, iterations);
+        let churn = ob.churn_stats();
 
         // Benchmark spread calculations
         let spread_timings = Self::benchmark_spread(&ob, iterations / 10);
@@ -44,7 +153,13 @@ impl OrderBookBenchmark {
         let best_ask_timings = Self::benchmark_best_ask(&ob, iterations / 10);
 
         // Benchmark random reads
-        let read_timings = Self::benchmark_random_reads(&ob, iterations / 10);
+        let read_timings = Self::benchmark_random_reads(&ob, iterations / 10, shape);
+
+        // Benchmark depth snapshots
+        let top_levels_timings = Self::benchmark_top_levels(&ob, iterations / 10);
+
+        // Benchmark each kind of update
+        let breakdown = Self::benchmark_update_kinds(&mut ob, iterations / 10);
 
         let avg_update = Self::average(&update_timings);
         let avg_spread = Self::average(&spread_timings);
@@ -62,34 +177,42 @@ impl OrderBookBenchmark {
             avg_best_bid_ns: avg_best_bid,
             avg_best_ask_ns: avg_best_ask,
             avg_random_read_ns: avg_read,
+            avg_top_levels_ns: Self::average(&top_levels_timings),
             p50_update_ns: sorted_updates[sorted_updates.len() / 2],
             p95_update_ns: sorted_updates[sorted_updates.len() * 95 / 100],
             p99_update_ns: sorted_updates[sorted_updates.len() * 99 / 100],
+            breakdown,
             total_operations: iterations,
+            shape,
+            bid_levels: ob.level_count(Side::Bid),
+            ask_levels: ob.level_count(Side::Ask),
+            churn,
+            struct_bytes: std::mem::size_of::<T>(),
+            heap_bytes: ob.memory_usage(),
         }
     }
 
-    fn warmup<T: OrderBook>(ob: &mut T) {
+    fn warmup<T: OrderBook>(ob: &mut T, shape: BookShape) {
         // Add some initial levels
-        for i in 0..100 {
+        let mut rng = Lcg(42);
+        for i in 0..shape.depth as Price {
             ob.apply_update(Update::Set {
-                price: 100000 + i * 10,
-                quantity: 100,
+                price: BASE_PRICE - i * shape.spacing,
+                quantity: rng.quantity(shape.quantities),
                 side: Side::Bid,
             });
             ob.apply_update(Update::Set {
-                price: 100100 + i * 10,
-                quantity: 100,
+                price: BASE_PRICE + 10 + i * shape.spacing,
+                quantity: rng.quantity(shape.quantities),
                 side: Side::Ask,
             });
         }
     }
 
     fn benchmark_updates<T: OrderBook>(ob: &mut T, iterations: usize) -> Vec<f64> {
-        let mut timings = Vec::with_capacity((iterations + UPDATE_BATCH_SIZE - 1) / UPDATE_BATCH_SIZE);
-        let base_price = 100000;
-        let bid_update = Update::Set { price: base_price, quantity: 100, side: Side::Bid };
-        let ask_update = Update::Set { price: base_price + 10, quantity: 120, side: Side::Ask };
+        let mut timings = Vec::with_capacity(iterations.div_ceil(UPDATE_BATCH_SIZE));
+        let bid_update = Update::Set { price: BASE_PRICE, quantity: 100, side: Side::Bid };
+        let ask_update = Update::Set { price: BASE_PRICE + 10, quantity: 120, side: Side::Ask };
         let mut i = 0;
 
         while i < iterations {
@@ -112,7 +235,7 @@ impl OrderBookBenchmark {
     }
 
     fn benchmark_spread<T: OrderBook>(ob: &T, iterations: usize) -> Vec<f64> {
-        let mut timings = Vec::with_capacity((iterations + BATCH_SIZE - 1) / BATCH_SIZE);
+        let mut timings = Vec::with_capacity(iterations.div_ceil(BATCH_SIZE));
         let mut i = 0;
         while i < iterations {
             let end = (i + BATCH_SIZE).min(iterations);
@@ -129,7 +252,7 @@ impl OrderBookBenchmark {
     }
 
     fn benchmark_best_bid<T: OrderBook>(ob: &T, iterations: usize) -> Vec<f64> {
-        let mut timings = Vec::with_capacity((iterations + BATCH_SIZE - 1) / BATCH_SIZE);
+        let mut timings = Vec::with_capacity(iterations.div_ceil(BATCH_SIZE));
         let mut i = 0;
         while i < iterations {
             let end = (i + BATCH_SIZE).min(iterations);
@@ -146,7 +269,7 @@ impl OrderBookBenchmark {
     }
 
     fn benchmark_best_ask<T: OrderBook>(ob: &T, iterations: usize) -> Vec<f64> {
-        let mut timings = Vec::with_capacity((iterations + BATCH_SIZE - 1) / BATCH_SIZE);
+        let mut timings = Vec::with_capacity(iterations.div_ceil(BATCH_SIZE));
         let mut i = 0;
         while i < iterations {
             let end = (i + BATCH_SIZE).min(iterations);
@@ -162,17 +285,22 @@ impl OrderBookBenchmark {
         timings
     }
 
-    fn benchmark_random_reads<T: OrderBook>(ob: &T, iterations: usize) -> Vec<f64> {
-        let mut timings = Vec::with_capacity((iterations + BATCH_SIZE - 1) / BATCH_SIZE);
-        let base_price = 100000;
+    fn benchmark_random_reads<T: OrderBook>(ob: &T, iterations: usize, shape: BookShape) -> Vec<f64> {
+        let mut timings = Vec::with_capacity(iterations.div_ceil(BATCH_SIZE));
+        // Over twice the depth: about half the reads miss.
+        let span = 2 * shape.depth.max(1);
         let mut i = 0;
         while i < iterations {
             let end = (i + BATCH_SIZE).min(iterations);
             let count = end - i;
             let start = Instant::now();
             for j in i..end {
-                let price = base_price + (j as i64 % 500) * 10;
-                let side = if j % 2 == 0 { Side::Bid } else { Side::Ask };
+                let offset = (j % span) as Price * shape.spacing;
+                let (price, side) = if j % 2 == 0 {
+                    (BASE_PRICE - offset, Side::Bid)
+                } else {
+                    (BASE_PRICE + 10 + offset, Side::Ask)
+                };
                 let _ = ob.get_quantity_at(price, side);
             }
             let elapsed = start.elapsed().as_nanos() as f64;
@@ -182,6 +310,80 @@ impl OrderBookBenchmark {
         timings
     }
 
+    fn benchmark_top_levels<T: OrderBook>(ob: &T, iterations: usize) -> Vec<f64> {
+        let mut timings = Vec::with_capacity(iterations.div_ceil(BATCH_SIZE));
+        let mut i = 0;
+        while i < iterations {
+            let end = (i + BATCH_SIZE).min(iterations);
+            let count = end - i;
+            let start = Instant::now();
+            for j in i..end {
+                let side = if j % 2 == 0 { Side::Bid } else { Side::Ask };
+                // black_box: otherwise the unused Vec (and the copy) can be optimized out
+                std::hint::black_box(ob.get_top_levels(side, TOP_LEVELS_DEPTH));
+            }
+            let elapsed = start.elapsed().as_nanos() as f64;
+            timings.push(elapsed / count as f64);
+            i = end;
+        }
+        timings
+    }
+
+    /// Rounds of one batch per kind on alternate sides, over levels spread
+    /// across the whole book; each round leaves the book as it found it.
+    fn benchmark_update_kinds<T: OrderBook>(ob: &mut T, operations: usize) -> UpdateBreakdown {
+        let (mut insert, mut modify, mut remove_best, mut remove_deep) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+        let mut rng = Lcg(7);
+        let mut done = 0;
+        let mut round = 0;
+        while done < operations {
+            let side = if round & 1 == 0 { Side::Bid } else { Side::Ask };
+            round += 1;
+            let levels = ob.get_top_levels(side, ob.level_count(side));
+            if levels.len() < 2 {
+                break;
+            }
+            // Distinct levels behind the best, from a random start with a stride
+            // coprime with their count.
+            let behind = levels.len() - 1;
+            let batch = KIND_BATCH_SIZE.min(behind);
+            let start = (rng.next_f64() * behind as f64) as usize;
+            let stride = (behind / 2 + 1..).find(|s| gcd(*s, behind) == 1).unwrap();
+            let deep: Vec<(Price, Quantity)> = (0..batch).map(|k| levels[1 + (start + k * stride) % behind]).collect();
+
+            let updates = |f: &dyn Fn(Price, Quantity) -> Update, levels: &[(Price, Quantity)]| -> Vec<Update> {
+                levels.iter().map(|&(price, quantity)| f(price, quantity)).collect()
+            };
+            let set = |price, quantity| Update::Set { price, quantity, side };
+            let remove = |price, _| Update::Remove { price, side };
+            modify.push(Self::time_batch(ob, &updates(&|price, quantity| set(price, quantity + 1), &deep)));
+            remove_deep.push(Self::time_batch(ob, &updates(&remove, &deep)));
+            insert.push(Self::time_batch(ob, &updates(&set, &deep)));
+            // in order, each one is the best when it goes
+            let best = &levels[..batch];
+            remove_best.push(Self::time_batch(ob, &updates(&remove, best)));
+            for &(price, quantity) in best.iter().rev() {
+                ob.apply_update(set(price, quantity));
+            }
+            done += 4 * batch;
+        }
+        UpdateBreakdown {
+            insert_ns: Self::average(&insert),
+            modify_ns: Self::average(&modify),
+            remove_best_ns: Self::average(&remove_best),
+            remove_deep_ns: Self::average(&remove_deep),
+        }
+    }
+
+    /// ns per update of the batch.
+    fn time_batch<T: OrderBook>(ob: &mut T, updates: &[Update]) -> f64 {
+        let start = Instant::now();
+        for update in updates {
+            ob.apply_update(update.clone());
+        }
+        start.elapsed().as_nanos() as f64 / updates.len() as f64
+    }
+
     fn average(timings: &[f64]) -> f64 {
         timings.iter().sum::<f64>() / timings.len() as f64
     }
@@ -192,12 +394,28 @@ impl OrderBookBenchmark {
         println!("  BENCHMARK RESULTS: {}", result.name);
         println!("{}", "=".repeat(60));
         println!("  Total Operations: {}", result.total_operations);
+        println!("  Warmup Book: {}", result.shape);
+        println!("  Book Shape: {} bid / {} ask levels", result.bid_levels, result.ask_levels);
+        println!(
+            "  Churn: {} inserts, {} removes, {} updates",
+            result.churn.inserts, result.churn.removes, result.churn.updates
+        );
+        println!(
+            "  Memory: {} struct + {} heap = {}",
+            format_bytes(result.struct_bytes),
+            format_bytes(result.heap_bytes),
+            format_bytes(result.struct_bytes + result.heap_bytes)
+        );
         println!("  ---");
         println!("  Update Operations:");
         println!("    Average: {:.2} ns", result.avg_update_ns);
         println!("    P50:     {:.2} ns", result.p50_update_ns);
         println!("    P95:     {:.2} ns", result.p95_update_ns);
         println!("    P99:     {:.2} ns", result.p99_update_ns);
+        println!("    Insert:      {:.2} ns", result.breakdown.insert_ns);
+        println!("    Modify:      {:.2} ns", result.breakdown.modify_ns);
+        println!("    Remove best: {:.2} ns", result.breakdown.remove_best_ns);
+        println!("    Remove deep: {:.2} ns", result.breakdown.remove_deep_ns);
         println!("  ---");
         println!("  Get Best Bid:");
         println!("    Average: {:.2} ns", result.avg_best_bid_ns);
@@ -210,6 +428,423 @@ impl OrderBookBenchmark {
         println!("  ---");
         println!("  Random Reads:");
         println!("    Average: {:.2} ns", result.avg_random_read_ns);
+        println!("  ---");
+        println!("  Top {} Levels:", TOP_LEVELS_DEPTH);
+        println!("    Average: {:.2} ns", result.avg_top_levels_ns);
+        println!("{}\n", "=".repeat(60));
+    }
+
+    /// Ranks the implementations by average update time.
+    pub fn print_comparison(results: &[BenchmarkResult]) {
+        let mut ranked: Vec<&BenchmarkResult> = results.iter().collect();
+        ranked.sort_by(|a, b| a.avg_update_ns.partial_cmp(&b.avg_update_ns).unwrap());
+        println!("{}", "=".repeat(60));
+        println!("  COMPARISON (ns, sorted by update)");
+        if let Some(first) = results.first() {
+            println!("  Book: {}", first.shape);
+        }
+        println!("{}", "=".repeat(60));
+        let top = format!("top{}", TOP_LEVELS_DEPTH);
+        println!(
+            "  {:<24} {:>8} {:>8} {:>8} {:>8} {:>8} {:>10}",
+            "", "update", "p99", "spread", "read", top, "memory"
+        );
+        for r in &ranked {
+            println!(
+                "  {:<24} {:>8.2} {:>8.2} {:>8.2} {:>8.2} {:>8.2} {:>10}",
+                r.name,
+                r.avg_update_ns,
+                r.p99_update_ns,
+                r.avg_spread_ns,
+                r.avg_random_read_ns,
+                r.avg_top_levels_ns,
+                format_bytes(r.struct_bytes + r.heap_bytes)
+            );
+        }
+        println!("  ---");
+        println!("  {:<24} {:>8} {:>8} {:>8} {:>8}", "", "insert", "modify", "rm best", "rm deep");
+        for r in &ranked {
+            let b = &r.breakdown;
+            println!(
+                "  {:<24} {:>8.2} {:>8.2} {:>8.2} {:>8.2}",
+                r.name, b.insert_ns, b.modify_ns, b.remove_best_ns, b.remove_deep_ns
+            );
+        }
         println!("{}\n", "=".repeat(60));
     }
 }
+
+// ============================================================================
+// L3 BENCHMARK (order storage)
+// ============================================================================
+
+/// Resting orders per side kept through the run, so levels never empty out
+/// and only the order nodes churn.
+const L3_RESTING_LEVELS: i64 = 100;
+
+#[derive(Debug, Clone)]
+pub struct L3BenchmarkResult {
+    pub name: String,
+    pub avg_add_ns: f64,
+    pub avg_cancel_ns: f64,
+    pub p99_add_ns: f64,
+    pub p99_cancel_ns: f64,
+    pub total_operations: usize,
+}
+
+pub struct L3Benchmark;
+
+impl L3Benchmark {
+    /// Adds batches of orders at pseudo-random prices, then cancels them in a
+    /// scrambled order; the book only differs by how it stores the nodes.
+    pub fn run<S: OrderStore>(name: &str, iterations: usize) -> L3BenchmarkResult {
+        let mut book = L3Book::<S>::with_capacity(BATCH_SIZE + 2 * L3_RESTING_LEVELS as usize);
+        let base_price = 100000;
+        for i in 0..L3_RESTING_LEVELS {
+            book.add(Order { id: i as OrderId, side: Side::Bid, price: base_price - i * 10, quantity: 100 });
+            book.add(Order {
+                id: (L3_RESTING_LEVELS + i) as OrderId,
+                side: Side::Ask,
+                price: base_price + 10 + i * 10,
+                quantity: 100,
+            });
+        }
+
+        let mut add_timings = Vec::with_capacity(iterations.div_ceil(BATCH_SIZE));
+        let mut cancel_timings = Vec::with_capacity(iterations.div_ceil(BATCH_SIZE));
+        let mut next_id = (2 * L3_RESTING_LEVELS) as OrderId;
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let mut i = 0;
+        while i < iterations {
+            let count = BATCH_SIZE.min(iterations - i);
+            let orders: Vec<Order> = (0..count)
+                .map(|_| {
+                    seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    let level = ((seed >> 33) % L3_RESTING_LEVELS as u64) as i64;
+                    next_id += 1;
+                    if seed >> 63 == 0 {
+                        Order { id: next_id, side: Side::Bid, price: base_price - level * 10, quantity: 10 }
+                    } else {
+                        Order { id: next_id, side: Side::Ask, price: base_price + 10 + level * 10, quantity: 10 }
+                    }
+                })
+                .collect();
+            // Coprime with `count`: every order is cancelled once, far from where it was added.
+            let stride = (count / 2 + 1..).find(|s| gcd(*s, count) == 1).unwrap();
+
+            let start = Instant::now();
+            for order in &orders {
+                book.add(*order);
+            }
+            add_timings.push(start.elapsed().as_nanos() as f64 / count as f64);
+
+            let start = Instant::now();
+            for k in 0..count {
+                book.cancel(orders[k * stride % count].id);
+            }
+            cancel_timings.push(start.elapsed().as_nanos() as f64 / count as f64);
+            i += count;
+        }
+
+        let p99 = |timings: &[f64]| {
+            let mut sorted = timings.to_vec();
+            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            sorted[sorted.len() * 99 / 100]
+        };
+        L3BenchmarkResult {
+            name: name.to_string(),
+            avg_add_ns: OrderBookBenchmark::average(&add_timings),
+            avg_cancel_ns: OrderBookBenchmark::average(&cancel_timings),
+            p99_add_ns: p99(&add_timings),
+            p99_cancel_ns: p99(&cancel_timings),
+            total_operations: 2 * iterations,
+        }
+    }
+
+    pub fn print_results(result: &L3BenchmarkResult) {
+        println!("\n{}", "=".repeat(60));
+        println!("  L3 BENCHMARK RESULTS: {}", result.name);
+        println!("{}", "=".repeat(60));
+        println!("  Total Operations: {}", result.total_operations);
+        println!("  ---");
+        println!("  Add Order:");
+        println!("    Average: {:.2} ns", result.avg_add_ns);
+        println!("    P99:     {:.2} ns", result.p99_add_ns);
+        println!("  ---");
+        println!("  Cancel Order:");
+        println!("    Average: {:.2} ns", result.avg_cancel_ns);
+        println!("    P99:     {:.2} ns", result.p99_cancel_ns);
+        println!("{}\n", "=".repeat(60));
+    }
+
+    /// Add + cancel time of `candidate` relative to `baseline`.
+    pub fn print_comparison(baseline: &L3BenchmarkResult, candidate: &L3BenchmarkResult) {
+        let round_trip = |r: &L3BenchmarkResult| r.avg_add_ns + r.avg_cancel_ns;
+        println!(
+            "  {} vs {}: add {:.2}x, cancel {:.2}x, add+cancel {:.2}x",
+            candidate.name,
+            baseline.name,
+            baseline.avg_add_ns / candidate.avg_add_ns,
+            baseline.avg_cancel_ns / candidate.avg_cancel_ns,
+            round_trip(baseline) / round_trip(candidate),
+        );
+    }
+}
+
+// ============================================================================
+// CONTENTION BENCHMARK (one writer, R readers)
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub struct ContentionResult {
+    pub name: String,
+    pub readers: usize,
+    /// Reads per second, all readers together, without then with the writer.
+    pub idle_reads_per_sec: f64,
+    pub loaded_reads_per_sec: f64,
+    pub writes_per_sec: f64,
+}
+
+impl ContentionResult {
+    /// Share of the reader throughput lost to the writer, in percent.
+    pub fn degradation_pct(&self) -> f64 {
+        (1.0 - self.loaded_reads_per_sec / self.idle_reads_per_sec) * 100.0
+    }
+}
+
+pub struct ContentionBenchmark;
+
+impl ContentionBenchmark {
+    /// `readers` threads alternate `get_spread` and `get_top_levels` for
+    /// `duration`, alone and then while one thread applies updates non-stop.
+    pub fn run<C: ConcurrentOrderBook>(name: &str, readers: usize, duration: Duration) -> ContentionResult {
+        let book = C::new();
+        for i in 0..100 {
+            book.apply_update(Update::Set { price: 100000 - i * 10, quantity: 100, side: Side::Bid });
+            book.apply_update(Update::Set { price: 100010 + i * 10, quantity: 100, side: Side::Ask });
+        }
+        let (idle_reads, _) = Self::phase(&book, readers, duration, false);
+        let (loaded_reads, writes) = Self::phase(&book, readers, duration, true);
+        let secs = duration.as_secs_f64();
+        ContentionResult {
+            name: name.to_string(),
+            readers,
+            idle_reads_per_sec: idle_reads as f64 / secs,
+            loaded_reads_per_sec: loaded_reads as f64 / secs,
+            writes_per_sec: writes as f64 / secs,
+        }
+    }
+
+    /// (reads, writes) done in `duration`.
+    fn phase<C: ConcurrentOrderBook>(book: &C, readers: usize, duration: Duration, write: bool) -> (u64, u64) {
+        let stop = AtomicBool::new(false);
+        let reads = AtomicU64::new(0);
+        let mut writes = 0;
+        std::thread::scope(|scope| {
+            for r in 0..readers {
+                let (stop, reads) = (&stop, &reads);
+                scope.spawn(move || {
+                    let mut count = 0u64;
+                    while !stop.load(Ordering::Relaxed) {
+                        if (count + r as u64) & 1 == 0 {
+                            std::hint::black_box(book.get_spread());
+                        } else {
+                            std::hint::black_box(book.get_top_levels(Side::Bid, TOP_LEVELS_DEPTH));
+                        }
+                        count += 1;
+                    }
+                    reads.fetch_add(count, Ordering::Relaxed);
+                });
+            }
+            let writer = write.then(|| {
+                let stop = &stop;
+                scope.spawn(move || {
+                    // inserts, updates and removes around the top of the book
+                    let mut count = 0u64;
+                    while !stop.load(Ordering::Relaxed) {
+                        let level = (count % 40) as i64;
+                        let quantity = (count / 40 % 3) * 50;
+                        let side = if count & 1 == 0 { Side::Bid } else { Side::Ask };
+                        let price = if side == Side::Bid { 100000 - level * 5 } else { 100010 + level * 5 };
+                        book.apply_update(Update::Set { price, quantity, side });
+                        count += 1;
+                    }
+                    count
+                })
+            });
+            std::thread::sleep(duration);
+            stop.store(true, Ordering::Relaxed);
+            if let Some(writer) = writer {
+                writes = writer.join().unwrap();
+            }
+        });
+        (reads.into_inner(), writes)
+    }
+
+    pub fn print_results(results: &[ContentionResult]) {
+        println!("{}", "=".repeat(60));
+        println!("  CONTENTION (1 writer, reads/s in millions)");
+        println!("{}", "=".repeat(60));
+        println!("  {:<24} {:>7} {:>8} {:>8} {:>8} {:>8}", "", "readers", "idle", "loaded", "lost", "writes");
+        for r in results {
+            println!(
+                "  {:<24} {:>7} {:>8.2} {:>8.2} {:>7.1}% {:>8.2}",
+                r.name,
+                r.readers,
+                r.idle_reads_per_sec / 1e6,
+                r.loaded_reads_per_sec / 1e6,
+                r.degradation_pct(),
+                r.writes_per_sec / 1e6
+            );
+        }
+        println!("{}\n", "=".repeat(60));
+    }
+}
+
+// ============================================================================
+// JOURNAL BENCHMARK (write-ahead journal overhead)
+// ============================================================================
+
+#[derive(Debug, Clone)]
+pub struct JournalResult {
+    pub name: String,
+    /// `None`: the book alone, the baseline of the others.
+    pub policy: Option<FsyncPolicy>,
+    pub updates: usize,
+    pub avg_update_ns: f64,
+}
+
+pub struct JournalBenchmark;
+
+impl JournalBenchmark {
+    /// Applies the same stream of inserts, updates and removes to the book
+    /// alone, then through a journal in the temp dir under each policy;
+    /// `Always` runs 1/100 of the updates (one fsync each).
+    pub fn run<B: OrderBook>(name: &str, iterations: usize, policies: &[FsyncPolicy]) -> Vec<JournalResult> {
+        let mut book = B::new();
+        let start = Instant::now();
+        for i in 0..iterations {
+            book.apply_update(Self::update(i));
+        }
+        let mut results = vec![JournalResult {
+            name: name.to_string(),
+            policy: None,
+            updates: iterations,
+            avg_update_ns: start.elapsed().as_nanos() as f64 / iterations as f64,
+        }];
+
+        let path = std::env::temp_dir().join(format!("orderbook-bench-{}.wal", std::process::id()));
+        for &policy in policies {
+            let updates = if policy == FsyncPolicy::Always { (iterations / 100).max(100) } else { iterations };
+            let mut journaled = JournaledBook::<B>::create(&path, policy).expect("creating the benchmark journal");
+            let start = Instant::now();
+            for i in 0..updates {
+                journaled.apply_update(Self::update(i)).expect("writing the benchmark journal");
+            }
+            journaled.sync().expect("syncing the benchmark journal");
+            results.push(JournalResult {
+                name: format!("{} + journal", name),
+                policy: Some(policy),
+                updates,
+                avg_update_ns: start.elapsed().as_nanos() as f64 / updates as f64,
+            });
+        }
+        let _ = std::fs::remove_file(path);
+        results
+    }
+
+    /// Around the top of the book: one update in three empties its level.
+    fn update(i: usize) -> Update {
+        let level = (i % 40) as Price;
+        let quantity = (i / 40 % 3) as Quantity * 50;
+        if i & 1 == 0 {
+            Update::Set { price: BASE_PRICE - level * 5, quantity, side: Side::Bid }
+        } else {
+            Update::Set { price: BASE_PRICE + 10 + level * 5, quantity, side: Side::Ask }
+        }
+    }
+
+    pub fn print_results(results: &[JournalResult]) {
+        let Some(baseline) = results.iter().find(|r| r.policy.is_none()) else { return };
+        println!("{}", "=".repeat(60));
+        println!("  JOURNAL (write-ahead, file in {})", std::env::temp_dir().display());
+        println!("{}", "=".repeat(60));
+        println!("  {:<24} {:<10} {:>9} {:>10} {:>9}", "", "fsync", "updates", "ns/update", "overhead");
+        for r in results {
+            let policy = match r.policy {
+                None => "-".to_string(),
+                Some(FsyncPolicy::Never) => "never".to_string(),
+                Some(FsyncPolicy::Always) => "always".to_string(),
+                Some(FsyncPolicy::Every(n)) => format!("every {}", n),
+            };
+            println!(
+                "  {:<24} {:<10} {:>9} {:>10.2} {:>8.1}x",
+                r.name,
+                policy,
+                r.updates,
+                r.avg_update_ns,
+                r.avg_update_ns / baseline.avg_update_ns
+            );
+        }
+        println!("{}\n", "=".repeat(60));
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    match bytes {
+        b if b >= 1 << 20 => format!("{:.1} MiB", b as f64 / (1 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.1} KiB", b as f64 / (1 << 10) as f64),
+        b => format!("{} B", b),
+    }
+}
+
+fn gcd(a: usize, b: usize) -> usize {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dense::DenseOrderBook;
+    use crate::orderbook::OrderBookImpl;
+
+    #[test]
+    fn the_warmup_follows_the_shape() {
+        let shape = BookShape {
+            depth: 2_000,
+            spacing: 2,
+            quantities: QuantityDistribution::LogNormal { mu: 4.5, sigma: 1.0 },
+        };
+        let mut dense = DenseOrderBook::new();
+        OrderBookBenchmark::warmup(&mut dense, shape);
+        assert_eq!((dense.level_count(Side::Bid), dense.level_count(Side::Ask)), (2_000, 2_000));
+        assert_eq!(dense.get_spread(), Some(10));
+        assert_eq!(dense.get_top_levels(Side::Ask, 2).iter().map(|l| l.0).collect::<Vec<_>>(), [100010, 100012]);
+
+        // 1024 niveaux au plus : les plus mauvais prix sont ignorés
+        let mut sorted = OrderBookImpl::new();
+        OrderBookBenchmark::warmup(&mut sorted, shape);
+        assert_eq!(sorted.level_count(Side::Bid), 1024);
+
+        // médiane d'une lognormale = exp(mu) ~ 90
+        let mut rng = Lcg(1);
+        let mut quantities: Vec<Quantity> = (0..10_001).map(|_| rng.quantity(shape.quantities)).collect();
+        quantities.sort_unstable();
+        assert!((80..100).contains(&quantities[5_000]), "median {}", quantities[5_000]);
+    }
+
+    #[test]
+    fn the_update_breakdown_leaves_the_book_unchanged() {
+        let shape = BookShape { depth: 300, ..BookShape::default() };
+        let mut book = OrderBookImpl::new();
+        OrderBookBenchmark::warmup(&mut book, shape);
+        let (bids, asks) = (book.get_top_levels(Side::Bid, 300), book.get_top_levels(Side::Ask, 300));
+
+        let breakdown = OrderBookBenchmark::benchmark_update_kinds(&mut book, 2_000);
+        assert_eq!(book.get_top_levels(Side::Bid, 300), bids);
+        assert_eq!(book.get_top_levels(Side::Ask, 300), asks);
+        for ns in [breakdown.insert_ns, breakdown.modify_ns, breakdown.remove_best_ns, breakdown.remove_deep_ns] {
+            assert!(ns.is_finite() && ns >= 0.0, "{:?}", breakdown);
+        }
+    }
+}
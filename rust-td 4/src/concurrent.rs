@@ -0,0 +1,94 @@
+use crate::interfaces::{OrderBook, Price, Quantity, Side, Update};
+use std::sync::{Mutex, RwLock};
+
+// Carnets partagés entre threads : un écrivain applique le flux, plusieurs
+// lecteurs consultent spread et profondeur. Deux designs de verrouillage
+// autour de n'importe quel `OrderBook`, comparés par `ContentionBenchmark`.
+
+/// Accès concurrent à un carnet, tout par `&self`.
+pub trait ConcurrentOrderBook: Send + Sync {
+    fn new() -> Self
+    where
+        Self: Sized;
+
+    fn apply_update(&self, update: Update);
+
+    fn get_spread(&self) -> Option<Price>;
+
+    fn get_top_levels(&self, side: Side, n: usize) -> Vec<(Price, Quantity)>;
+}
+
+/// Un seul verrou pour tout le monde : les lecteurs se sérialisent aussi entre eux.
+pub struct MutexBook<B: OrderBook>(Mutex<B>);
+
+impl<B: OrderBook> ConcurrentOrderBook for MutexBook<B> {
+    fn new() -> Self {
+        MutexBook(Mutex::new(B::new()))
+    }
+
+    fn apply_update(&self, update: Update) {
+        self.0.lock().unwrap().apply_update(update);
+    }
+
+    fn get_spread(&self) -> Option<Price> {
+        self.0.lock().unwrap().get_spread()
+    }
+
+    fn get_top_levels(&self, side: Side, n: usize) -> Vec<(Price, Quantity)> {
+        self.0.lock().unwrap().get_top_levels(side, n)
+    }
+}
+
+/// Lecteurs en parallèle, l'écrivain attend qu'ils sortent.
+pub struct RwLockBook<B: OrderBook>(RwLock<B>);
+
+impl<B: OrderBook> ConcurrentOrderBook for RwLockBook<B> {
+    fn new() -> Self {
+        RwLockBook(RwLock::new(B::new()))
+    }
+
+    fn apply_update(&self, update: Update) {
+        self.0.write().unwrap().apply_update(update);
+    }
+
+    fn get_spread(&self) -> Option<Price> {
+        self.0.read().unwrap().get_spread()
+    }
+
+    fn get_top_levels(&self, side: Side, n: usize) -> Vec<(Price, Quantity)> {
+        self.0.read().unwrap().get_top_levels(side, n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::OrderBookImpl;
+
+    fn shared_between_threads<C: ConcurrentOrderBook>() {
+        let book = C::new();
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                for i in 0..1_000 {
+                    book.apply_update(Update::Set { price: 10_000 - i % 10, quantity: 5, side: Side::Bid });
+                    book.apply_update(Update::Set { price: 10_010 + i % 10, quantity: 5, side: Side::Ask });
+                }
+            });
+            scope.spawn(|| {
+                for _ in 0..1_000 {
+                    // jamais de carnet croisé, même en pleine écriture
+                    assert!(book.get_spread().is_none_or(|spread| spread > 0));
+                    assert!(book.get_top_levels(Side::Bid, 5).len() <= 5);
+                }
+            });
+        });
+        assert_eq!(book.get_spread(), Some(10));
+        assert_eq!(book.get_top_levels(Side::Ask, 20).len(), 10);
+    }
+
+    #[test]
+    fn both_wrappers_share_a_book() {
+        shared_between_threads::<MutexBook<OrderBookImpl>>();
+        shared_between_threads::<RwLockBook<OrderBookImpl>>();
+    }
+}
@@ -0,0 +1,412 @@
+use crate::interfaces::{ChurnStats, OrderBook, Price, Quantity, Side, Update};
+
+// Carnet "HFT" pour des prix dans une bande étroite : un prix est directement
+// un index de tableau ((prix - min) / tick). Chaque côté a un tableau de
+// quantités et un bitmap à deux étages (mots de 64 niveaux + un résumé des mots
+// non vides), le meilleur prix se retrouve par scan de bits.
+
+/// Plage de prix couverte : `levels` prix à partir de `min_price`, espacés de
+/// `tick`. Les prix hors bande ou hors pas sont ignorés (voir `rejected`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DenseBand {
+    pub min_price: Price,
+    pub tick: Price,
+    pub levels: usize,
+}
+
+impl Default for DenseBand {
+    /// Prix 0 à 131_071 au pas de 1 (13,1071 en prix réel), ce qui couvre les
+    /// tests et le benchmark ; ~1 Mo de quantités par côté.
+    fn default() -> Self {
+        DenseBand {
+            min_price: 0,
+            tick: 1,
+            levels: 1 << 17,
+        }
+    }
+}
+
+impl DenseBand {
+    #[inline(always)]
+    fn index(&self, price: Price) -> Option<usize> {
+        let offset = price.checked_sub(self.min_price)?;
+        if offset < 0 || offset % self.tick != 0 {
+            return None;
+        }
+        let idx = (offset / self.tick) as usize;
+        (idx < self.levels).then_some(idx)
+    }
+
+    #[inline(always)]
+    fn price(&self, idx: usize) -> Price {
+        self.min_price + idx as Price * self.tick
+    }
+
+    /// Premier et dernier index de la bande entre `low` et `high` compris.
+    fn index_range(&self, low: Price, high: Price) -> Option<(usize, usize)> {
+        let first = low.saturating_sub(self.min_price).max(0);
+        let first = (first / self.tick + (first % self.tick != 0) as Price) as usize;
+        let last = high.saturating_sub(self.min_price);
+        if last < 0 {
+            return None;
+        }
+        let last = ((last / self.tick) as usize).min(self.levels - 1);
+        (first <= last).then_some((first, last))
+    }
+}
+
+struct DenseSide {
+    quantities: Vec<Quantity>,
+    /// Bit `i` : le niveau `i` existe.
+    words: Vec<u64>,
+    /// Bit `w` : `words[w]` n'est pas vide.
+    summary: Vec<u64>,
+    best: Option<usize>,
+    total: Quantity,
+    count: usize,
+}
+
+impl DenseSide {
+    fn new(levels: usize) -> Self {
+        let words = levels.div_ceil(64);
+        DenseSide {
+            quantities: vec![0; levels],
+            words: vec![0; words],
+            summary: vec![0; words.div_ceil(64)],
+            best: None,
+            total: 0,
+            count: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn mark(&mut self, idx: usize) {
+        let w = idx >> 6;
+        self.words[w] |= 1 << (idx & 63);
+        self.summary[w >> 6] |= 1 << (w & 63);
+    }
+
+    #[inline(always)]
+    fn unmark(&mut self, idx: usize) {
+        let w = idx >> 6;
+        self.words[w] &= !(1 << (idx & 63));
+        if self.words[w] == 0 {
+            self.summary[w >> 6] &= !(1 << (w & 63));
+        }
+    }
+
+    fn heap_bytes(&self) -> usize {
+        self.quantities.capacity() * std::mem::size_of::<Quantity>()
+            + (self.words.capacity() + self.summary.capacity()) * std::mem::size_of::<u64>()
+    }
+
+    /// Plus haut niveau existant `<= idx`.
+    fn highest_at_or_below(&self, idx: usize) -> Option<usize> {
+        let w = idx >> 6;
+        let bits = self.words[w] & (u64::MAX >> (63 - (idx & 63)));
+        if bits != 0 {
+            return Some((w << 6) + 63 - bits.leading_zeros() as usize);
+        }
+        // mots non vides strictement sous `w`
+        let mut s = w >> 6;
+        let mut mask = (1u64 << (w & 63)) - 1;
+        loop {
+            let candidates = self.summary[s] & mask;
+            if candidates != 0 {
+                let word = (s << 6) + 63 - candidates.leading_zeros() as usize;
+                return Some((word << 6) + 63 - self.words[word].leading_zeros() as usize);
+            }
+            if s == 0 {
+                return None;
+            }
+            s -= 1;
+            mask = u64::MAX;
+        }
+    }
+
+    /// Plus bas niveau existant `>= idx`.
+    fn lowest_at_or_above(&self, idx: usize) -> Option<usize> {
+        let w = idx >> 6;
+        let bits = self.words[w] & (u64::MAX << (idx & 63));
+        if bits != 0 {
+            return Some((w << 6) + bits.trailing_zeros() as usize);
+        }
+        let mut s = w >> 6;
+        let mut mask = u64::MAX.checked_shl((w & 63) as u32 + 1).unwrap_or(0);
+        while s < self.summary.len() {
+            let candidates = self.summary[s] & mask;
+            if candidates != 0 {
+                let word = (s << 6) + candidates.trailing_zeros() as usize;
+                return Some((word << 6) + self.words[word].trailing_zeros() as usize);
+            }
+            s += 1;
+            mask = u64::MAX;
+        }
+        None
+    }
+}
+
+pub struct DenseOrderBook {
+    band: DenseBand,
+    bids: DenseSide,
+    asks: DenseSide,
+    rejected: u64,
+    update_count: u64,
+    churn: ChurnStats,
+}
+
+impl DenseOrderBook {
+    pub fn with_band(band: DenseBand) -> Self {
+        assert!(band.tick > 0 && band.levels > 0, "empty price band");
+        DenseOrderBook {
+            band,
+            bids: DenseSide::new(band.levels),
+            asks: DenseSide::new(band.levels),
+            rejected: 0,
+            update_count: 0,
+            churn: ChurnStats::default(),
+        }
+    }
+
+    pub fn band(&self) -> DenseBand {
+        self.band
+    }
+
+    /// Mises à jour ignorées car hors bande ou hors pas.
+    pub fn rejected(&self) -> u64 {
+        self.rejected
+    }
+
+    #[inline(always)]
+    fn set(&mut self, side: Side, idx: usize, quantity: Quantity) {
+        let book = match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        let prev = book.quantities[idx];
+        book.quantities[idx] = quantity;
+        book.total = book.total - prev + quantity;
+        if prev != 0 {
+            self.churn.updates += 1;
+        } else {
+            self.churn.inserts += 1;
+            book.count += 1;
+            book.mark(idx);
+            let better = match (side, book.best) {
+                (_, None) => true,
+                (Side::Bid, Some(best)) => idx > best,
+                (Side::Ask, Some(best)) => idx < best,
+            };
+            if better {
+                book.best = Some(idx);
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn remove(&mut self, side: Side, idx: usize) {
+        let book = match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        let prev = book.quantities[idx];
+        if prev == 0 {
+            return;
+        }
+        book.quantities[idx] = 0;
+        book.total -= prev;
+        book.count -= 1;
+        self.churn.removes += 1;
+        book.unmark(idx);
+        if book.best == Some(idx) {
+            book.best = match side {
+                Side::Bid => idx.checked_sub(1).and_then(|i| book.highest_at_or_below(i)),
+                Side::Ask if idx + 1 < self.band.levels => book.lowest_at_or_above(idx + 1),
+                Side::Ask => None,
+            };
+        }
+    }
+}
+
+impl OrderBook for DenseOrderBook {
+    fn new() -> Self {
+        Self::with_band(DenseBand::default())
+    }
+
+    #[inline(always)]
+    fn apply_update(&mut self, update: Update) {
+        self.update_count += 1;
+        let (price, quantity, side) = match update {
+            Update::Set { price, quantity, side } => (price, quantity, side),
+            Update::Remove { price, side } => (price, 0, side),
+        };
+        let Some(idx) = self.band.index(price) else {
+            self.rejected += 1;
+            return;
+        };
+        if quantity == 0 {
+            self.remove(side, idx);
+        } else {
+            self.set(side, idx, quantity);
+        }
+    }
+
+    #[inline(always)]
+    fn get_spread(&self) -> Option<Price> {
+        Some(self.get_best_ask()? - self.get_best_bid()?)
+    }
+
+    #[inline(always)]
+    fn get_best_bid(&self) -> Option<Price> {
+        self.bids.best.map(|idx| self.band.price(idx))
+    }
+
+    #[inline(always)]
+    fn get_best_ask(&self) -> Option<Price> {
+        self.asks.best.map(|idx| self.band.price(idx))
+    }
+
+    #[inline(always)]
+    fn get_quantity_at(&self, price: Price, side: Side) -> Option<Quantity> {
+        let idx = self.band.index(price)?;
+        let quantity = match side {
+            Side::Bid => self.bids.quantities[idx],
+            Side::Ask => self.asks.quantities[idx],
+        };
+        (quantity != 0).then_some(quantity)
+    }
+
+    fn get_top_levels(&self, side: Side, n: usize) -> Vec<(Price, Quantity)> {
+        let mut levels = Vec::with_capacity(n);
+        match side {
+            Side::Bid => {
+                let mut next = self.bids.best;
+                while let Some(idx) = next.filter(|_| levels.len() < n) {
+                    levels.push((self.band.price(idx), self.bids.quantities[idx]));
+                    next = idx.checked_sub(1).and_then(|i| self.bids.highest_at_or_below(i));
+                }
+            }
+            Side::Ask => {
+                let mut next = self.asks.best;
+                while let Some(idx) = next.filter(|_| levels.len() < n) {
+                    levels.push((self.band.price(idx), self.asks.quantities[idx]));
+                    next = (idx + 1 < self.band.levels)
+                        .then(|| self.asks.lowest_at_or_above(idx + 1))
+                        .flatten();
+                }
+            }
+        }
+        levels
+    }
+
+    fn levels_in_range(&self, side: Side, from_price: Price, to_price: Price) -> impl Iterator<Item = (Price, Quantity)> {
+        let range = self.band.index_range(from_price.min(to_price), from_price.max(to_price));
+        let book = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        // niveaux suivants par scan de bits, du meilleur au pire
+        let next = move |idx: usize| {
+            let (first, last) = range?;
+            match side {
+                Side::Bid => book.highest_at_or_below(idx).filter(|&i| i >= first),
+                Side::Ask => book.lowest_at_or_above(idx).filter(|&i| i <= last),
+            }
+        };
+        let start = range.and_then(|(first, last)| match side {
+            Side::Bid => next(last),
+            Side::Ask => next(first),
+        });
+        std::iter::successors(start, move |&idx| match side {
+            Side::Bid => idx.checked_sub(1).and_then(next),
+            Side::Ask => (idx + 1 < self.band.levels).then(|| next(idx + 1)).flatten(),
+        })
+        .map(move |idx| (self.band.price(idx), book.quantities[idx]))
+    }
+
+    #[inline(always)]
+    fn get_total_quantity(&self, side: Side) -> Quantity {
+        match side {
+            Side::Bid => self.bids.total,
+            Side::Ask => self.asks.total,
+        }
+    }
+
+    #[inline(always)]
+    fn level_count(&self, side: Side) -> usize {
+        match side {
+            Side::Bid => self.bids.count,
+            Side::Ask => self.asks.count,
+        }
+    }
+
+    fn update_count(&self) -> u64 {
+        self.update_count
+    }
+
+    fn churn_stats(&self) -> ChurnStats {
+        self.churn
+    }
+
+    fn reset_stats(&mut self) {
+        self.update_count = 0;
+        self.churn = ChurnStats::default();
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.bids.heap_bytes() + self.asks.heap_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(book: &mut DenseOrderBook, price: Price, quantity: Quantity, side: Side) {
+        book.apply_update(Update::Set { price, quantity, side });
+    }
+
+    #[test]
+    fn best_prices_are_found_across_words() {
+        let mut book = DenseOrderBook::with_band(DenseBand {
+            min_price: 1000,
+            tick: 5,
+            levels: 10_000,
+        });
+        set(&mut book, 1000, 1, Side::Bid);
+        set(&mut book, 21000, 2, Side::Bid);
+        set(&mut book, 30000, 3, Side::Bid);
+        set(&mut book, 30005, 4, Side::Ask);
+        set(&mut book, 45000, 5, Side::Ask);
+        assert_eq!(book.get_spread(), Some(5));
+
+        // le best disparaît : on redescend de plusieurs mots de bitmap
+        book.apply_update(Update::Remove { price: 30000, side: Side::Bid });
+        assert_eq!(book.get_best_bid(), Some(21000));
+        set(&mut book, 30005, 0, Side::Ask);
+        assert_eq!(book.get_best_ask(), Some(45000));
+        assert_eq!(book.get_top_levels(Side::Bid, 5), [(21000, 2), (1000, 1)]);
+        assert_eq!(book.get_total_quantity(Side::Bid), 3);
+        // bornes hors pas ou hors bande : ramenées aux prix de la bande
+        let band: Vec<_> = book.levels_in_range(Side::Bid, -50, 21003).collect();
+        assert_eq!(band, [(21000, 2), (1000, 1)]);
+        assert_eq!(book.quantity_in_range(Side::Bid, 1001, 1_000_000), 2);
+        assert_eq!(book.levels_in_range(Side::Ask, 0, 999).count(), 0);
+
+        // hors bande ou hors pas : ignoré
+        set(&mut book, 999, 1, Side::Bid);
+        set(&mut book, 1001, 1, Side::Bid);
+        set(&mut book, 1000 + 5 * 10_000, 1, Side::Ask);
+        assert_eq!(book.rejected(), 3);
+        assert_eq!(book.level_count(Side::Bid), 2);
+        assert_eq!(book.update_count(), 10);
+        assert_eq!(book.churn_stats(), ChurnStats { inserts: 5, removes: 2, updates: 0 });
+        // 10_000 quantités + 157 mots + 3 mots de résumé, par côté
+        assert_eq!(book.memory_usage(), 2 * (10_000 + 157 + 3) * 8);
+        assert_eq!(book.get_quantity_at(1001, Side::Bid), None);
+
+        book.apply_update(Update::Remove { price: 45000, side: Side::Ask });
+        assert_eq!((book.get_best_ask(), book.get_spread()), (None, None));
+        assert!(book.get_top_levels(Side::Ask, 3).is_empty());
+    }
+}
@@ -0,0 +1,191 @@
+use crate::interfaces::{OrderBook, Price, Quantity, Side, Update};
+use std::time::{Duration, Instant};
+
+// Flux de profondeur incrémental, à la façon des flux des bourses : au lieu
+// de republier le carnet, on publie les niveaux qui ont changé (côté, prix,
+// nouvelle quantité, 0 quand le niveau disparaît). Les updates d'un
+// intervalle sont fusionnées : on compare les `depth` meilleurs niveaux de
+// chaque côté à ceux déjà publiés, donc un niveau modifié puis remis à sa
+// quantité ne produit rien, et un niveau qui sort des `depth` premiers (ou
+// que le carnet abandonne quand il est plein) est publié à 0.
+
+/// Changement d'un niveau tel que publié aux clients.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelChange {
+    pub side: Side,
+    pub price: Price,
+    /// Nouvelle quantité, 0 = niveau supprimé.
+    pub quantity: Quantity,
+}
+
+impl LevelChange {
+    /// `{"side":"bid","price":10000,"qty":70}`, pour le canal de profondeur du serveur WS.
+    pub fn to_json(&self) -> String {
+        let side = match self.side {
+            Side::Bid => "bid",
+            Side::Ask => "ask",
+        };
+        format!(r#"{{"side":"{}","price":{},"qty":{}}}"#, side, self.price, self.quantity)
+    }
+}
+
+/// Carnet qui produit les changements de niveaux à publier, au plus une
+/// fois par `interval`.
+pub struct DepthPublisher<B: OrderBook> {
+    book: B,
+    depth: usize,
+    interval: Duration,
+    /// Niveaux tels que les clients les connaissent, meilleurs d'abord.
+    bids: Vec<(Price, Quantity)>,
+    asks: Vec<(Price, Quantity)>,
+    last_publish: Option<Instant>,
+    /// Updates appliquées depuis la dernière publication.
+    pending: bool,
+}
+
+impl<B: OrderBook> DepthPublisher<B> {
+    /// `interval` nul : une publication par update.
+    pub fn new(book: B, depth: usize, interval: Duration) -> Self {
+        let mut publisher = DepthPublisher {
+            book,
+            depth,
+            interval,
+            bids: Vec::new(),
+            asks: Vec::new(),
+            last_publish: None,
+            pending: false,
+        };
+        publisher.bids = publisher.book.get_top_levels(Side::Bid, depth);
+        publisher.asks = publisher.book.get_top_levels(Side::Ask, depth);
+        publisher
+    }
+
+    /// Applique l'update ; renvoie les changements à publier si l'intervalle
+    /// est écoulé, rien sinon (ils partiront avec une prochaine publication).
+    pub fn apply_update(&mut self, update: Update, now: Instant) -> Vec<LevelChange> {
+        self.book.apply_update(update);
+        self.pending = true;
+        self.poll(now)
+    }
+
+    /// À appeler à chaque `interval` quand le flux se tait, pour que les
+    /// derniers changements ne restent pas en attente.
+    pub fn poll(&mut self, now: Instant) -> Vec<LevelChange> {
+        let due = self.last_publish.is_none_or(|last| now.duration_since(last) >= self.interval);
+        if self.pending && due {
+            self.flush(now)
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Publie tout de suite les changements en attente.
+    pub fn flush(&mut self, now: Instant) -> Vec<LevelChange> {
+        let mut changes = Vec::new();
+        let bids = self.book.get_top_levels(Side::Bid, self.depth);
+        let asks = self.book.get_top_levels(Side::Ask, self.depth);
+        diff(Side::Bid, &self.bids, &bids, &mut changes);
+        diff(Side::Ask, &self.asks, &asks, &mut changes);
+        self.bids = bids;
+        self.asks = asks;
+        self.last_publish = Some(now);
+        self.pending = false;
+        changes
+    }
+
+    /// Les niveaux publiés d'un côté : l'instantané à envoyer à un nouveau
+    /// client avant les changements suivants.
+    pub fn published(&self, side: Side) -> &[(Price, Quantity)] {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    pub fn book(&self) -> &B {
+        &self.book
+    }
+}
+
+/// Changements pour passer de `old` à `new`, tous deux triés meilleurs d'abord.
+fn diff(side: Side, old: &[(Price, Quantity)], new: &[(Price, Quantity)], changes: &mut Vec<LevelChange>) {
+    let better = |a: Price, b: Price| match side {
+        Side::Bid => a > b,
+        Side::Ask => a < b,
+    };
+    let mut change = |price, quantity| changes.push(LevelChange { side, price, quantity });
+    let (mut i, mut j) = (0, 0);
+    loop {
+        match (old.get(i), new.get(j)) {
+            (Some(&(old_price, old_qty)), Some(&(price, quantity))) if old_price == price => {
+                if old_qty != quantity {
+                    change(price, quantity);
+                }
+                i += 1;
+                j += 1;
+            }
+            (Some(&(old_price, _)), Some(&(price, quantity))) if better(price, old_price) => {
+                change(price, quantity);
+                j += 1;
+            }
+            (Some(&(old_price, _)), _) => {
+                change(old_price, 0);
+                i += 1;
+            }
+            (None, Some(&(price, quantity))) => {
+                change(price, quantity);
+                j += 1;
+            }
+            (None, None) => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::OrderBookImpl;
+
+    fn set(price: Price, quantity: Quantity, side: Side) -> Update {
+        Update::Set { price, quantity, side }
+    }
+
+    #[test]
+    fn updates_are_coalesced_into_level_changes() {
+        let start = Instant::now();
+        let mut publisher = DepthPublisher::new(OrderBookImpl::new(), 2, Duration::from_millis(100));
+        let first = publisher.apply_update(set(10_000, 100, Side::Bid), start);
+        assert_eq!(first, [LevelChange { side: Side::Bid, price: 10_000, quantity: 100 }]);
+
+        // dans l'intervalle : rien ne part, les updates s'accumulent
+        let at = |ms| start + Duration::from_millis(ms);
+        assert!(publisher.apply_update(set(10_000, 50, Side::Bid), at(10)).is_empty());
+        assert!(publisher.apply_update(set(10_000, 100, Side::Bid), at(20)).is_empty());
+        assert!(publisher.apply_update(set(10_010, 30, Side::Ask), at(30)).is_empty());
+        assert!(publisher.apply_update(set(9_990, 10, Side::Bid), at(40)).is_empty());
+        assert!(publisher.apply_update(set(10_005, 5, Side::Bid), at(50)).is_empty());
+        assert!(publisher.poll(at(60)).is_empty());
+
+        // 10_000 est revenu à 100 ; 9_990 est sorti des 2 meilleurs niveaux
+        let changes = publisher.poll(at(100));
+        assert_eq!(
+            changes,
+            [
+                LevelChange { side: Side::Bid, price: 10_005, quantity: 5 },
+                LevelChange { side: Side::Ask, price: 10_010, quantity: 30 },
+            ]
+        );
+        assert!(publisher.poll(at(300)).is_empty());
+
+        let changes = publisher.apply_update(Update::Remove { price: 10_005, side: Side::Bid }, at(400));
+        assert_eq!(
+            changes,
+            [
+                LevelChange { side: Side::Bid, price: 10_005, quantity: 0 },
+                LevelChange { side: Side::Bid, price: 9_990, quantity: 10 },
+            ]
+        );
+        assert_eq!(publisher.published(Side::Bid), [(10_000, 100), (9_990, 10)]);
+        assert_eq!(changes[0].to_json(), r#"{"side":"bid","price":10005,"qty":0}"#);
+    }
+}
@@ -5,6 +5,8 @@
 // The fastest implementation wins!
 // Target: Sub-nanosecond operations where possible
 
+use crate::liquidity::{sweep_cost_curve, SweepPoint};
+
 /// Price is represented as an integer where 1 unit = 10^-4
 /// Example: 12345 represents a price of 1.2345
 pub type Price = i64;
@@ -34,6 +36,17 @@ pub enum Update {
     Remove { price: Price, side: Side },
 }
 
+/// Level churn since the last `reset_stats`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ChurnStats {
+    /// New price levels
+    pub inserts: u64,
+    /// Levels removed (explicitly, by a zero quantity, or dropped by the book)
+    pub removes: u64,
+    /// Quantity changes of existing levels
+    pub updates: u64,
+}
+
 /// The main trait that students must implement
 pub trait OrderBook: Send + Sync {
     /// Create a new orderbook instance
@@ -66,4 +79,35 @@ pub trait OrderBook: Send + Sync {
 
     /// Get total quantity across all levels for a side
     fn get_total_quantity(&self, side: Side) -> Quantity;
+
+    /// Levels of a side priced between `from_price` and `to_price`
+    /// (inclusive, in either order), best prices first, without allocating
+    fn levels_in_range(&self, side: Side, from_price: Price, to_price: Price) -> impl Iterator<Item = (Price, Quantity)>;
+
+    /// Total quantity of a side between two prices (see `levels_in_range`)
+    fn quantity_in_range(&self, side: Side, from_price: Price, to_price: Price) -> Quantity {
+        self.levels_in_range(side, from_price, to_price).map(|(_, quantity)| quantity).sum()
+    }
+
+    /// Number of price levels on a side
+    fn level_count(&self, side: Side) -> usize;
+
+    /// Updates applied since the last reset, no-ops included
+    fn update_count(&self) -> u64;
+
+    /// Inserts/removes/updates of levels since the last reset
+    fn churn_stats(&self) -> ChurnStats;
+
+    /// Zero `update_count` and `churn_stats`
+    fn reset_stats(&mut self);
+
+    /// Heap bytes owned by the book (capacity, not just live levels);
+    /// `size_of::<Self>()` gives the inline part
+    fn memory_usage(&self) -> usize;
+
+    /// Cost of sweeping `side` for `steps` increasing quantities up to
+    /// `max_qty` (see `liquidity::sweep_cost_curve`)
+    fn sweep_cost_curve(&self, side: Side, max_qty: Quantity, steps: usize) -> Vec<SweepPoint> {
+        sweep_cost_curve(self, side, max_qty, steps)
+    }
 }
@@ -0,0 +1,219 @@
+use crate::interfaces::{OrderBook, Price, Quantity, Side, Update};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// Journal d'écriture anticipée : chaque `Update` est ajoutée à un fichier
+// avant d'être appliquée au carnet, qui se reconstruit après un crash en
+// rejouant le journal. Un enregistrement fait 21 octets : type (côté et
+// Set/Remove), prix et quantité en little-endian, puis une somme FNV-1a des
+// 17 premiers. Un crash peut laisser un dernier enregistrement incomplet :
+// la relecture s'arrête au premier enregistrement tronqué ou invalide.
+
+const RECORD_LEN: usize = 21;
+
+const SET_BID: u8 = 0;
+const SET_ASK: u8 = 1;
+const REMOVE_BID: u8 = 2;
+const REMOVE_ASK: u8 = 3;
+
+/// Quand le journal est forcé sur disque (`fsync`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FsyncPolicy {
+    /// Jamais : le tampon part quand il est plein et le système l'écrit sur
+    /// disque quand il veut ; un crash perd les dernières updates.
+    Never,
+    /// Après chaque update : rien n'est perdu, au prix d'un `fsync` par update.
+    Always,
+    /// Toutes les N updates : au plus N updates perdues.
+    Every(u32),
+}
+
+/// Carnet dont chaque update passe d'abord par le journal.
+pub struct JournaledBook<B: OrderBook> {
+    book: B,
+    writer: BufWriter<File>,
+    policy: FsyncPolicy,
+    /// Updates écrites depuis le dernier `fsync`.
+    unsynced: u32,
+}
+
+/// Bilan d'une relecture.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Recovery {
+    pub applied: u64,
+    /// Octets ignorés à la fin du journal (enregistrement tronqué ou invalide).
+    pub discarded_bytes: u64,
+}
+
+impl<B: OrderBook> JournaledBook<B> {
+    /// Carnet vide, journal neuf (un journal existant est écrasé).
+    pub fn create(path: impl AsRef<Path>, policy: FsyncPolicy) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self::with_file(B::new(), file, policy))
+    }
+
+    /// Carnet rejoué depuis le journal, qui continue d'être écrit ; la fin
+    /// invalide laissée par un crash est coupée. Crée le journal s'il n'existe pas.
+    pub fn recover(path: impl AsRef<Path>, policy: FsyncPolicy) -> io::Result<(Self, Recovery)> {
+        let path = path.as_ref();
+        let (book, recovery) = match File::open(path) {
+            Ok(file) => replay::<B>(file)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (B::new(), Recovery::default()),
+            Err(e) => return Err(e),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        file.set_len(recovery.applied * RECORD_LEN as u64)?;
+        Ok((Self::with_file(book, file, policy), recovery))
+    }
+
+    fn with_file(book: B, file: File, policy: FsyncPolicy) -> Self {
+        JournaledBook {
+            book,
+            writer: BufWriter::new(file),
+            policy,
+            unsynced: 0,
+        }
+    }
+
+    /// Journalise puis applique ; après une erreur le carnet n'a pas bougé,
+    /// mais l'update peut déjà être dans le journal.
+    pub fn apply_update(&mut self, update: Update) -> io::Result<()> {
+        self.writer.write_all(&encode(&update))?;
+        self.unsynced += 1;
+        match self.policy {
+            FsyncPolicy::Never => {}
+            FsyncPolicy::Always => self.sync()?,
+            FsyncPolicy::Every(n) => {
+                if self.unsynced >= n {
+                    self.sync()?;
+                }
+            }
+        }
+        self.book.apply_update(update);
+        Ok(())
+    }
+
+    /// Vide le tampon et force le journal sur disque.
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        self.writer.get_ref().sync_data()?;
+        self.unsynced = 0;
+        Ok(())
+    }
+
+    pub fn book(&self) -> &B {
+        &self.book
+    }
+}
+
+impl<B: OrderBook> Drop for JournaledBook<B> {
+    fn drop(&mut self) {
+        // arrêt propre : rien ne reste dans le tampon
+        let _ = self.writer.flush();
+    }
+}
+
+/// Reconstruit un carnet en rejouant le journal `path`.
+pub fn recover_from_journal<B: OrderBook>(path: impl AsRef<Path>) -> io::Result<(B, Recovery)> {
+    replay(File::open(path)?)
+}
+
+fn replay<B: OrderBook>(file: File) -> io::Result<(B, Recovery)> {
+    let len = file.metadata()?.len();
+    let mut reader = BufReader::new(file);
+    let mut book = B::new();
+    let mut applied = 0;
+    let mut record = [0; RECORD_LEN];
+    loop {
+        match reader.read_exact(&mut record) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+        let Some(update) = decode(&record) else { break };
+        book.apply_update(update);
+        applied += 1;
+    }
+    let discarded_bytes = len - applied * RECORD_LEN as u64;
+    Ok((book, Recovery { applied, discarded_bytes }))
+}
+
+fn encode(update: &Update) -> [u8; RECORD_LEN] {
+    let (kind, price, quantity) = match *update {
+        Update::Set { price, quantity, side: Side::Bid } => (SET_BID, price, quantity),
+        Update::Set { price, quantity, side: Side::Ask } => (SET_ASK, price, quantity),
+        Update::Remove { price, side: Side::Bid } => (REMOVE_BID, price, 0),
+        Update::Remove { price, side: Side::Ask } => (REMOVE_ASK, price, 0),
+    };
+    let mut record = [0; RECORD_LEN];
+    record[0] = kind;
+    record[1..9].copy_from_slice(&price.to_le_bytes());
+    record[9..17].copy_from_slice(&quantity.to_le_bytes());
+    let checksum = fnv1a(&record[..17]);
+    record[17..].copy_from_slice(&checksum.to_le_bytes());
+    record
+}
+
+fn decode(record: &[u8; RECORD_LEN]) -> Option<Update> {
+    if record[17..] != fnv1a(&record[..17]).to_le_bytes() {
+        return None;
+    }
+    let price = Price::from_le_bytes(record[1..9].try_into().ok()?);
+    let quantity = Quantity::from_le_bytes(record[9..17].try_into().ok()?);
+    Some(match record[0] {
+        SET_BID => Update::Set { price, quantity, side: Side::Bid },
+        SET_ASK => Update::Set { price, quantity, side: Side::Ask },
+        REMOVE_BID => Update::Remove { price, side: Side::Bid },
+        REMOVE_ASK => Update::Remove { price, side: Side::Ask },
+        _ => return None,
+    })
+}
+
+fn fnv1a(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, &b| (hash ^ b as u32).wrapping_mul(0x0100_0193))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::orderbook::OrderBookImpl;
+
+    #[test]
+    fn the_book_is_rebuilt_after_a_crash() {
+        let path = std::env::temp_dir().join(format!("orderbook-journal-{}.wal", std::process::id()));
+        {
+            let mut journaled = JournaledBook::<OrderBookImpl>::create(&path, FsyncPolicy::Every(2)).unwrap();
+            for (price, quantity) in [(10_000, 100), (9_990, 50), (10_000, 70)] {
+                journaled.apply_update(Update::Set { price, quantity, side: Side::Bid }).unwrap();
+            }
+            journaled.apply_update(Update::Set { price: 10_010, quantity: 30, side: Side::Ask }).unwrap();
+            journaled.apply_update(Update::Remove { price: 9_990, side: Side::Bid }).unwrap();
+        }
+        // crash au milieu de l'écriture d'un enregistrement
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&encode(&Update::Set { price: 1, quantity: 1, side: Side::Ask })[..10]).unwrap();
+        drop(file);
+
+        let (book, recovery) = recover_from_journal::<OrderBookImpl>(&path).unwrap();
+        assert_eq!(recovery, Recovery { applied: 5, discarded_bytes: 10 });
+        assert_eq!(book.get_top_levels(Side::Bid, 5), [(10_000, 70)]);
+        assert_eq!(book.get_spread(), Some(10));
+
+        // la reprise coupe la fin invalide et continue le journal
+        let (mut journaled, _) = JournaledBook::<OrderBookImpl>::recover(&path, FsyncPolicy::Always).unwrap();
+        journaled.apply_update(Update::Set { price: 10_005, quantity: 5, side: Side::Ask }).unwrap();
+        drop(journaled);
+        let (book, recovery) = recover_from_journal::<OrderBookImpl>(&path).unwrap();
+        assert_eq!(recovery, Recovery { applied: 6, discarded_bytes: 0 });
+        assert_eq!(book.get_best_ask(), Some(10_005));
+
+        // un octet corrompu arrête la relecture à cet enregistrement
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[2 * RECORD_LEN + 3] ^= 0xff;
+        std::fs::write(&path, bytes).unwrap();
+        let (_, recovery) = recover_from_journal::<OrderBookImpl>(&path).unwrap();
+        assert_eq!(recovery, Recovery { applied: 2, discarded_bytes: 4 * RECORD_LEN as u64 });
+        std::fs::remove_file(path).unwrap();
+    }
+}
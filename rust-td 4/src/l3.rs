@@ -0,0 +1,340 @@
+use crate::interfaces::{Price, Quantity, Side};
+use rustc_hash::FxHashMap;
+use std::collections::BTreeMap;
+
+// Carnet L3 : chaque ordre est un nœud d'une file FIFO par niveau de prix
+// (liste doublement chaînée par handles `u32`). Le stockage des nœuds est
+// interchangeable pour comparer un `Box` par ordre à un pool réutilisé.
+
+pub type OrderId = u64;
+
+/// Fin de liste / pas de nœud.
+const NIL: u32 = u32::MAX;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Order {
+    pub id: OrderId,
+    pub side: Side,
+    pub price: Price,
+    pub quantity: Quantity,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OrderNode {
+    pub order: Order,
+    prev: u32,
+    next: u32,
+}
+
+/// Stockage des nœuds d'ordres, adressés par un handle `u32`.
+pub trait OrderStore: Send + Sync {
+    fn with_capacity(capacity: usize) -> Self
+    where
+        Self: Sized;
+
+    fn insert(&mut self, node: OrderNode) -> u32;
+
+    fn remove(&mut self, handle: u32) -> OrderNode;
+
+    fn get(&self, handle: u32) -> &OrderNode;
+
+    fn get_mut(&mut self, handle: u32) -> &mut OrderNode;
+}
+
+/// Référence : une allocation `Box` par ordre, libérée à l'annulation.
+pub struct BoxedOrders {
+    slots: Vec<Option<Box<OrderNode>>>,
+    free: Vec<u32>,
+}
+
+impl OrderStore for BoxedOrders {
+    fn with_capacity(capacity: usize) -> Self {
+        BoxedOrders {
+            slots: Vec::with_capacity(capacity),
+            free: Vec::with_capacity(capacity),
+        }
+    }
+
+    #[inline(always)]
+    fn insert(&mut self, node: OrderNode) -> u32 {
+        let boxed = Some(Box::new(node));
+        match self.free.pop() {
+            Some(handle) => {
+                self.slots[handle as usize] = boxed;
+                handle
+            }
+            None => {
+                self.slots.push(boxed);
+                (self.slots.len() - 1) as u32
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn remove(&mut self, handle: u32) -> OrderNode {
+        let node = self.slots[handle as usize].take().expect("freed order node");
+        self.free.push(handle);
+        *node
+    }
+
+    #[inline(always)]
+    fn get(&self, handle: u32) -> &OrderNode {
+        self.slots[handle as usize].as_deref().expect("freed order node")
+    }
+
+    #[inline(always)]
+    fn get_mut(&mut self, handle: u32) -> &mut OrderNode {
+        self.slots[handle as usize].as_deref_mut().expect("freed order node")
+    }
+}
+
+/// Pool contigu : les nœuds libérés sont chaînés (via `next`) et réutilisés,
+/// aucune allocation une fois le pool à sa taille de croisière.
+pub struct OrderArena {
+    nodes: Vec<OrderNode>,
+    free: u32,
+}
+
+impl OrderStore for OrderArena {
+    fn with_capacity(capacity: usize) -> Self {
+        OrderArena {
+            nodes: Vec::with_capacity(capacity),
+            free: NIL,
+        }
+    }
+
+    #[inline(always)]
+    fn insert(&mut self, node: OrderNode) -> u32 {
+        if self.free == NIL {
+            self.nodes.push(node);
+            return (self.nodes.len() - 1) as u32;
+        }
+        let handle = self.free;
+        self.free = self.nodes[handle as usize].next;
+        self.nodes[handle as usize] = node;
+        handle
+    }
+
+    #[inline(always)]
+    fn remove(&mut self, handle: u32) -> OrderNode {
+        let slot = &mut self.nodes[handle as usize];
+        let node = *slot;
+        slot.next = self.free;
+        self.free = handle;
+        node
+    }
+
+    #[inline(always)]
+    fn get(&self, handle: u32) -> &OrderNode {
+        &self.nodes[handle as usize]
+    }
+
+    #[inline(always)]
+    fn get_mut(&mut self, handle: u32) -> &mut OrderNode {
+        &mut self.nodes[handle as usize]
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Level {
+    head: u32,
+    tail: u32,
+    quantity: Quantity,
+    orders: u32,
+}
+
+/// Carnet par ordre, priorité prix puis temps.
+pub struct L3Book<S: OrderStore> {
+    store: S,
+    ids: FxHashMap<OrderId, u32>,
+    bids: BTreeMap<Price, Level>,
+    asks: BTreeMap<Price, Level>,
+}
+
+impl<S: OrderStore> Default for L3Book<S> {
+    fn default() -> Self {
+        Self::with_capacity(1024)
+    }
+}
+
+impl<S: OrderStore> L3Book<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// `capacity` ordres vivants sans réallocation.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut ids = FxHashMap::default();
+        ids.reserve(capacity);
+        L3Book {
+            store: S::with_capacity(capacity),
+            ids,
+            bids: BTreeMap::new(),
+            asks: BTreeMap::new(),
+        }
+    }
+
+    fn levels(&mut self, side: Side) -> &mut BTreeMap<Price, Level> {
+        match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        }
+    }
+
+    /// Ajoute l'ordre en queue de son niveau ; refusé si l'id existe déjà ou
+    /// si la quantité est nulle.
+    pub fn add(&mut self, order: Order) -> bool {
+        if order.quantity == 0 || self.ids.contains_key(&order.id) {
+            return false;
+        }
+        let tail = self.levels(order.side).get(&order.price).map_or(NIL, |level| level.tail);
+        let handle = self.store.insert(OrderNode { order, prev: tail, next: NIL });
+        if tail != NIL {
+            self.store.get_mut(tail).next = handle;
+        }
+        let level = self.levels(order.side).entry(order.price).or_insert(Level {
+            head: handle,
+            tail: handle,
+            quantity: 0,
+            orders: 0,
+        });
+        level.tail = handle;
+        level.quantity += order.quantity;
+        level.orders += 1;
+        self.ids.insert(order.id, handle);
+        true
+    }
+
+    /// Retire l'ordre et le renvoie.
+    pub fn cancel(&mut self, id: OrderId) -> Option<Order> {
+        let handle = self.ids.remove(&id)?;
+        let node = self.store.remove(handle);
+        if node.prev != NIL {
+            self.store.get_mut(node.prev).next = node.next;
+        }
+        if node.next != NIL {
+            self.store.get_mut(node.next).prev = node.prev;
+        }
+        let order = node.order;
+        let levels = self.levels(order.side);
+        let level = levels.get_mut(&order.price).expect("level of a live order");
+        if level.orders == 1 {
+            levels.remove(&order.price);
+        } else {
+            if level.head == handle {
+                level.head = node.next;
+            }
+            if level.tail == handle {
+                level.tail = node.prev;
+            }
+            level.quantity -= order.quantity;
+            level.orders -= 1;
+        }
+        Some(order)
+    }
+
+    /// Exécution partielle : retire `quantity` à l'ordre sans lui faire perdre
+    /// sa place, et l'annule s'il n'en reste rien. Renvoie la quantité restante.
+    pub fn reduce(&mut self, id: OrderId, quantity: Quantity) -> Option<Quantity> {
+        let handle = *self.ids.get(&id)?;
+        let order = self.store.get(handle).order;
+        if quantity >= order.quantity {
+            self.cancel(id);
+            return Some(0);
+        }
+        self.store.get_mut(handle).order.quantity -= quantity;
+        self.levels(order.side).get_mut(&order.price).expect("level of a live order").quantity -= quantity;
+        Some(order.quantity - quantity)
+    }
+
+    pub fn order(&self, id: OrderId) -> Option<Order> {
+        self.ids.get(&id).map(|&handle| self.store.get(handle).order)
+    }
+
+    /// Nombre d'ordres vivants.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn best_bid(&self) -> Option<Price> {
+        self.bids.keys().next_back().copied()
+    }
+
+    pub fn best_ask(&self) -> Option<Price> {
+        self.asks.keys().next().copied()
+    }
+
+    pub fn quantity_at(&self, price: Price, side: Side) -> Option<Quantity> {
+        let levels = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        levels.get(&price).map(|level| level.quantity)
+    }
+
+    /// Ordres d'un niveau, dans leur ordre de priorité.
+    pub fn orders_at(&self, price: Price, side: Side) -> Vec<Order> {
+        let levels = match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        };
+        let mut orders = Vec::new();
+        let mut handle = levels.get(&price).map_or(NIL, |level| level.head);
+        while handle != NIL {
+            let node = self.store.get(handle);
+            orders.push(node.order);
+            handle = node.next;
+        }
+        orders
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(id: OrderId, side: Side, price: Price, quantity: Quantity) -> Order {
+        Order { id, side, price, quantity }
+    }
+
+    fn fifo_and_cancels<S: OrderStore>() {
+        let mut book = L3Book::<S>::new();
+        assert!(book.add(order(1, Side::Bid, 100, 10)));
+        assert!(book.add(order(2, Side::Bid, 100, 20)));
+        assert!(book.add(order(3, Side::Bid, 100, 30)));
+        assert!(book.add(order(4, Side::Ask, 105, 5)));
+        assert!(!book.add(order(1, Side::Ask, 110, 5)));
+        assert_eq!((book.best_bid(), book.best_ask()), (Some(100), Some(105)));
+        assert_eq!(book.quantity_at(100, Side::Bid), Some(60));
+
+        // annulation au milieu, puis en tête : la file reste chaînée
+        assert_eq!(book.cancel(2), Some(order(2, Side::Bid, 100, 20)));
+        assert_eq!(book.reduce(1, 4), Some(6));
+        let ids: Vec<OrderId> = book.orders_at(100, Side::Bid).iter().map(|o| o.id).collect();
+        assert_eq!(ids, [1, 3]);
+        assert_eq!(book.reduce(1, 6), Some(0));
+        assert_eq!(book.orders_at(100, Side::Bid), [order(3, Side::Bid, 100, 30)]);
+
+        // les nœuds libérés sont réutilisés sans casser les listes
+        assert!(book.add(order(5, Side::Bid, 100, 1)));
+        assert!(book.add(order(6, Side::Bid, 99, 2)));
+        let ids: Vec<OrderId> = book.orders_at(100, Side::Bid).iter().map(|o| o.id).collect();
+        assert_eq!(ids, [3, 5]);
+        assert_eq!(book.quantity_at(100, Side::Bid), Some(31));
+
+        assert!(book.cancel(3).is_some() && book.cancel(5).is_some());
+        assert_eq!(book.best_bid(), Some(99));
+        assert_eq!(book.cancel(3), None);
+        assert_eq!(book.len(), 2);
+    }
+
+    #[test]
+    fn both_stores_keep_time_priority() {
+        fifo_and_cancels::<BoxedOrders>();
+        fifo_and_cancels::<OrderArena>();
+    }
+}
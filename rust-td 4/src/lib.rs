@@ -0,0 +1,20 @@
+//! L2 order book of the competition (`OrderBook` trait, its contiguous and
+//! dense-array implementations, a top-K depth cache over either) and its
+//! benchmark, also used by the TD 1 backtest to simulate fills; lock-based
+//! wrappers to share a book between threads; a write-ahead journal to
+//! rebuild a book after a crash; coalesced level changes to publish the
+//! book's depth; the cost curve of sweeping a side; plus an L3 (per-order)
+//! book whose node storage is benchmarked separately. Benchmark runs can be
+//! saved and compared to a baseline to catch regressions.
+
+pub mod baseline;
+pub mod benchmarks;
+pub mod concurrent;
+pub mod dense;
+pub mod depth;
+pub mod interfaces;
+pub mod journal;
+pub mod l3;
+pub mod liquidity;
+pub mod orderbook;
+pub mod top_cache;
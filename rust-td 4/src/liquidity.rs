@@ -0,0 +1,86 @@
+use crate::interfaces::{OrderBook, Price, Quantity, Side};
+
+// Courbe de coût d'un balayage du carnet : ce que coûte un ordre au marché
+// de quantité croissante qui consomme les niveaux d'un côté du meilleur au
+// pire. Sert à tracer la liquidité dans l'interface de démo et aux
+// stratégies du backtest (TD 1) pour estimer l'impact d'un ordre.
+
+/// Un point de la courbe.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SweepPoint {
+    /// Quantité exécutée ; moins que demandé quand le côté est épuisé.
+    pub quantity: Quantity,
+    /// Somme prix × quantité des niveaux consommés.
+    pub cost: i128,
+    pub average_price: f64,
+    /// Dernier niveau touché.
+    pub worst_price: Price,
+}
+
+/// Balaye `side` (`Side::Ask` pour un achat, `Side::Bid` pour une vente)
+/// pour `steps` quantités régulières jusqu'à `max_qty`. La courbe s'arrête
+/// au premier point que le carnet ne peut pas remplir entièrement.
+pub fn sweep_cost_curve<B: OrderBook + ?Sized>(
+    book: &B,
+    side: Side,
+    max_qty: Quantity,
+    steps: usize,
+) -> Vec<SweepPoint> {
+    let mut levels = book.get_top_levels(side, book.level_count(side)).into_iter();
+    let mut curve = Vec::with_capacity(steps);
+    let (mut filled, mut cost) = (0, 0i128);
+    // niveau entamé : prix et quantité restante
+    let mut current: Option<(Price, Quantity)> = None;
+    let mut worst_price = 0;
+    for step in 1..=steps {
+        let target = (max_qty as u128 * step as u128 / steps as u128) as Quantity;
+        while filled < target {
+            let Some((price, left)) = current.filter(|&(_, left)| left > 0).or_else(|| levels.next()) else {
+                break;
+            };
+            let take = left.min(target - filled);
+            filled += take;
+            cost += price as i128 * take as i128;
+            worst_price = price;
+            current = Some((price, left - take));
+        }
+        if filled == 0 || curve.last().is_some_and(|p: &SweepPoint| p.quantity == filled) {
+            // rien de plus à ce pas (`max_qty < steps`, côté vide)
+            continue;
+        }
+        curve.push(SweepPoint {
+            quantity: filled,
+            cost,
+            average_price: cost as f64 / filled as f64,
+            worst_price,
+        });
+        if filled < target {
+            break;
+        }
+    }
+    curve
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interfaces::Update;
+    use crate::orderbook::OrderBookImpl;
+
+    #[test]
+    fn the_curve_walks_the_levels() {
+        let mut book = OrderBookImpl::new();
+        for (price, quantity) in [(10_010, 100), (10_020, 100), (10_050, 50)] {
+            book.apply_update(Update::Set { price, quantity, side: Side::Ask });
+        }
+        let curve = sweep_cost_curve(&book, Side::Ask, 300, 3);
+        let points: Vec<(Quantity, i128, Price)> = curve.iter().map(|p| (p.quantity, p.cost, p.worst_price)).collect();
+        // 300 ne tient pas dans les 250 du carnet : dernier point partiel
+        assert_eq!(points, [(100, 1_001_000, 10_010), (200, 2_003_000, 10_020), (250, 2_505_500, 10_050)]);
+        assert!((curve[1].average_price - 10_015.0).abs() < 1e-9);
+        assert_eq!(book.sweep_cost_curve(Side::Ask, 150, 1)[0].cost, 1_001_000 + 50 * 10_020);
+
+        assert!(sweep_cost_curve(&book, Side::Bid, 100, 4).is_empty());
+        assert_eq!(sweep_cost_curve(&book, Side::Ask, 2, 4).len(), 2);
+    }
+}
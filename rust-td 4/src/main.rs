@@ -1,12 +1,15 @@
-use crate::{
-    benchmarks::OrderBookBenchmark,
+use rust_3::{
+    baseline::{parse_percent, Metrics},
+    benchmarks::{BookShape, ContentionBenchmark, JournalBenchmark, L3Benchmark, OrderBookBenchmark, QuantityDistribution},
+    concurrent::{MutexBook, RwLockBook},
+    dense::DenseOrderBook,
     orderbook::OrderBookImpl,
     interfaces::{OrderBook, Side, Update},
+    journal::FsyncPolicy,
+    l3::{BoxedOrders, OrderArena},
+    top_cache::TopLevelsCache,
 };
-
-mod benchmarks;
-mod interfaces;
-mod orderbook;
+use std::path::PathBuf;
 
 // Objective: Complete the orderbook implementation at ./orderbook.rs and run this file to see how fast it is. Faster implementation wins !
 
@@ -14,11 +17,102 @@ mod orderbook;
 // MAIN
 // ============================================================================
 
+/// `--baseline FILE [--max-regression 10%]` : échoue si un temps régresse
+/// par rapport au fichier ; `--save-baseline FILE` enregistre ce run.
+#[derive(Debug, Default, PartialEq)]
+struct Options {
+    baseline: Option<PathBuf>,
+    max_regression_pct: f64,
+    save_baseline: Option<PathBuf>,
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options { max_regression_pct: 10.0, ..Options::default() };
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--baseline" => options.baseline = Some(value()?.into()),
+            "--max-regression" => options.max_regression_pct = parse_percent(&value()?)?,
+            "--save-baseline" => options.save_baseline = Some(value()?.into()),
+            other => return Err(format!("unknown argument `{}`", other)),
+        }
+    }
+    Ok(options)
+}
+
 fn main() {
+    let options = parse_args(std::env::args().skip(1)).unwrap_or_else(|e| {
+        eprintln!("{}\nusage: rust-3 [--baseline FILE] [--max-regression 10%] [--save-baseline FILE]", e);
+        std::process::exit(2);
+    });
+    // chargée avant les benchmarks : un mauvais chemin échoue tout de suite
+    let baseline = options.baseline.as_ref().map(|path| {
+        Metrics::load(path).unwrap_or_else(|e| {
+            eprintln!("reading baseline {}: {}", path.display(), e);
+            std::process::exit(2);
+        })
+    });
+    let mut metrics = Metrics::default();
+
     println!("Running Naive OrderBook Benchmark...\n");
 
-    let result = OrderBookBenchmark::run::<OrderBookImpl>("OrderBook", 100_000);
-    OrderBookBenchmark::print_results(&result);
+    let results = [
+        OrderBookBenchmark::run::<OrderBookImpl>("OrderBook", 100_000),
+        OrderBookBenchmark::run::<DenseOrderBook>("DenseOrderBook", 100_000),
+        OrderBookBenchmark::run::<TopLevelsCache<OrderBookImpl, 10>>("OrderBook + top10", 100_000),
+        OrderBookBenchmark::run::<TopLevelsCache<DenseOrderBook, 10>>("DenseOrderBook + top10", 100_000),
+    ];
+    for result in &results {
+        OrderBookBenchmark::print_results(result);
+    }
+    OrderBookBenchmark::print_comparison(&results);
+    for result in &results {
+        metrics.add_orderbook("default", result);
+    }
+
+    // Carnet de 10_000 niveaux par côté, quantités lognormales. `OrderBookImpl`
+    // plafonne à 1024 niveaux : il en mesurerait un tronqué, d'où son absence.
+    let deep = BookShape {
+        depth: 10_000,
+        spacing: 1,
+        quantities: QuantityDistribution::LogNormal { mu: 4.5, sigma: 1.0 },
+    };
+    let deep_results = [
+        OrderBookBenchmark::run_with_shape::<DenseOrderBook>("DenseOrderBook", 100_000, deep),
+        OrderBookBenchmark::run_with_shape::<TopLevelsCache<DenseOrderBook, 10>>(
+            "DenseOrderBook + top10",
+            100_000,
+            deep,
+        ),
+    ];
+    OrderBookBenchmark::print_comparison(&deep_results);
+    for result in &deep_results {
+        metrics.add_orderbook("deep", result);
+    }
+
+    println!("Running L3 order storage benchmark (Box vs arena)...\n");
+    let boxed = L3Benchmark::run::<BoxedOrders>("L3 Box", 1_000_000);
+    let arena = L3Benchmark::run::<OrderArena>("L3 Arena", 1_000_000);
+    L3Benchmark::print_results(&boxed);
+    L3Benchmark::print_results(&arena);
+    L3Benchmark::print_comparison(&boxed, &arena);
+    metrics.add_l3(&boxed);
+    metrics.add_l3(&arena);
+
+    // un coeur pour l'écrivain, le reste (4 max) pour les lecteurs
+    let readers = std::thread::available_parallelism().map_or(1, |n| n.get().saturating_sub(1)).clamp(1, 4);
+    println!("\nRunning contention benchmark (1 writer, {} readers)...\n", readers);
+    let window = std::time::Duration::from_millis(300);
+    ContentionBenchmark::print_results(&[
+        ContentionBenchmark::run::<MutexBook<OrderBookImpl>>("Mutex<OrderBook>", readers, window),
+        ContentionBenchmark::run::<RwLockBook<OrderBookImpl>>("RwLock<OrderBook>", readers, window),
+    ]);
+
+    println!("Running write-ahead journal benchmark...\n");
+    let policies = [FsyncPolicy::Never, FsyncPolicy::Every(1_000), FsyncPolicy::Always];
+    let journal = JournalBenchmark::run::<OrderBookImpl>("OrderBook", 1_000_000, &policies);
+    JournalBenchmark::print_results(&journal);
+    metrics.add_journal(&journal);
 
     // Sanity-use of the full API surface to avoid dead_code warnings and ensure coverage.
     let mut sanity = OrderBookImpl::new();
@@ -43,6 +137,22 @@ fn main() {
     println!("   - Pre-allocate where possible");
     println!("   - Profile with 'cargo flamegraph'");
     println!("   - Use 'cargo bench' for micro-benchmarks");
+
+    if let Some(path) = &options.save_baseline {
+        if let Err(e) = metrics.save(path) {
+            eprintln!("writing baseline {}: {}", path.display(), e);
+            std::process::exit(2);
+        }
+        println!("\nBaseline saved to {}", path.display());
+    }
+    if let (Some(path), Some(baseline)) = (&options.baseline, &baseline) {
+        println!();
+        let comparison = metrics.compare(baseline, options.max_regression_pct);
+        comparison.print(path);
+        if comparison.regressions() > 0 {
+            std::process::exit(1);
+        }
+    }
 }
 
 // ============================================================================
@@ -51,8 +161,9 @@ fn main() {
 
 #[cfg(test)]
 mod tests {
-    use crate::{
-        interfaces::{OrderBook, Side, Update},
+    use rust_3::{
+        dense::DenseOrderBook,
+        interfaces::{ChurnStats, OrderBook, Side, Update},
         orderbook::OrderBookImpl,
     };
 
@@ -128,9 +239,49 @@ mod tests {
         assert_eq!(ob.get_quantity_at(10000, Side::Bid), None);
     }
 
+    fn test_stats<T: OrderBook>() {
+        let mut ob = T::new();
+        for (price, quantity) in [(10000, 100), (9950, 150), (10000, 120), (9900, 0)] {
+            ob.apply_update(Update::Set { price, quantity, side: Side::Bid });
+        }
+        ob.apply_update(Update::Set { price: 10050, quantity: 80, side: Side::Ask });
+        ob.apply_update(Update::Remove { price: 9950, side: Side::Bid });
+        assert_eq!((ob.level_count(Side::Bid), ob.level_count(Side::Ask)), (1, 1));
+        assert_eq!(ob.update_count(), 6);
+        assert_eq!(ob.churn_stats(), ChurnStats { inserts: 3, removes: 1, updates: 1 });
+
+        ob.reset_stats();
+        assert_eq!((ob.update_count(), ob.churn_stats()), (0, ChurnStats::default()));
+        assert_eq!(ob.level_count(Side::Bid), 1);
+    }
+
+    fn test_levels_in_range<T: OrderBook>() {
+        let mut ob = T::new();
+        for price in [9900, 9950, 10000] {
+            ob.apply_update(Update::Set { price, quantity: 10, side: Side::Bid });
+            ob.apply_update(Update::Set { price: price + 150, quantity: 20, side: Side::Ask });
+        }
+        let bids: Vec<_> = ob.levels_in_range(Side::Bid, 9920, 10000).collect();
+        assert_eq!(bids, [(10000, 10), (9950, 10)]);
+        let asks: Vec<_> = ob.levels_in_range(Side::Ask, 10120, 10050).collect();
+        assert_eq!(asks, [(10050, 20), (10100, 20)]);
+        assert_eq!(ob.quantity_in_range(Side::Ask, 0, 20000), 60);
+        assert_eq!(ob.levels_in_range(Side::Bid, 9951, 9999).count(), 0);
+    }
+
     #[test]
     fn test_naive_implementation() {
         test_basic_operations::<OrderBookImpl>();
         test_updates_and_removes::<OrderBookImpl>();
+        test_stats::<OrderBookImpl>();
+        test_levels_in_range::<OrderBookImpl>();
+    }
+
+    #[test]
+    fn test_dense_implementation() {
+        test_basic_operations::<DenseOrderBook>();
+        test_updates_and_removes::<DenseOrderBook>();
+        test_stats::<DenseOrderBook>();
+        test_levels_in_range::<DenseOrderBook>();
     }
 }
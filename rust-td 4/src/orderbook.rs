@@ -1,4 +1,4 @@
-use crate::interfaces::{OrderBook, Price, Quantity, Side, Update};
+use crate::interfaces::{ChurnStats, OrderBook, Price, Quantity, Side, Update};
 use arrayvec::ArrayVec;
 
 // Tableau trié contigu (ArrayVec) + caches best/second-best pour limiter les scans.
@@ -15,6 +15,8 @@ pub struct OrderBookImpl {
     second_best_ask: Option<Price>,
     total_bid_qty: Quantity,
     total_ask_qty: Quantity,
+    update_count: u64,
+    churn: ChurnStats,
 }
 
 impl OrderBookImpl {
@@ -156,11 +158,14 @@ impl OrderBook for OrderBookImpl {
             second_best_ask: None,
             total_bid_qty: 0,
             total_ask_qty: 0,
+            update_count: 0,
+            churn: ChurnStats::default(),
         }
     }
 
     #[inline(always)]
     fn apply_update(&mut self, update: Update) {
+        self.update_count += 1;
         match update {
             Update::Set { price, quantity, side } => match side {
                 Side::Bid => {
@@ -169,6 +174,7 @@ impl OrderBook for OrderBookImpl {
                         let prev = self.bids[idx].1;
                         if quantity == 0 {
                             let removed = Self::remove_at(&mut self.bids, idx).1;
+                            self.churn.removes += 1;
                             self.total_bid_qty -= removed;
                             let removed_best = self.best_bid.map(|b| b == price).unwrap_or(false);
                             if removed_best {
@@ -186,6 +192,7 @@ impl OrderBook for OrderBookImpl {
                             }
                         } else {
                             self.bids[idx].1 = quantity;
+                            self.churn.updates += 1;
                             if quantity >= prev {
                                 self.total_bid_qty += quantity - prev;
                             } else {
@@ -198,15 +205,17 @@ impl OrderBook for OrderBookImpl {
                         }
                         if self.bids.is_full() {
                             // Si plein, on ignore les prix plus mauvais que le pire pour éviter un panic.
-                            if self.bids.len() > 0 && idx >= self.bids.len() {
+                            if !self.bids.is_empty() && idx >= self.bids.len() {
                                 return;
                             }
                             let dropped = self.bids.last().unwrap().1;
                             self.total_bid_qty -= dropped;
                             self.bids.pop();
+                            self.churn.removes += 1;
                             // best/second resteront valides si on n'a pas touché idx==0
                         }
                         Self::insert_at(&mut self.bids, idx, (price, quantity));
+                        self.churn.inserts += 1;
                         self.total_bid_qty += quantity;
                         match self.best_bid {
                             None => {
@@ -230,6 +239,7 @@ impl OrderBook for OrderBookImpl {
                         let prev = self.asks[idx].1;
                         if quantity == 0 {
                             let removed = Self::remove_at(&mut self.asks, idx).1;
+                            self.churn.removes += 1;
                             self.total_ask_qty -= removed;
                             let removed_best = self.best_ask.map(|b| b == price).unwrap_or(false);
                             if removed_best {
@@ -247,6 +257,7 @@ impl OrderBook for OrderBookImpl {
                             }
                         } else {
                             self.asks[idx].1 = quantity;
+                            self.churn.updates += 1;
                             if quantity >= prev {
                                 self.total_ask_qty += quantity - prev;
                             } else {
@@ -258,14 +269,16 @@ impl OrderBook for OrderBookImpl {
                             return;
                         }
                         if self.asks.is_full() {
-                            if self.asks.len() > 0 && idx >= self.asks.len() {
+                            if !self.asks.is_empty() && idx >= self.asks.len() {
                                 return;
                             }
                             let dropped = self.asks.last().unwrap().1;
                             self.total_ask_qty -= dropped;
                             self.asks.pop();
+                            self.churn.removes += 1;
                         }
                         Self::insert_at(&mut self.asks, idx, (price, quantity));
+                        self.churn.inserts += 1;
                         self.total_ask_qty += quantity;
                         match self.best_ask {
                             None => {
@@ -289,6 +302,7 @@ impl OrderBook for OrderBookImpl {
                     let (found, idx) = Self::locate_bid(self.bids.as_slice(), price);
                     if found {
                         let removed = Self::remove_at(&mut self.bids, idx).1;
+                        self.churn.removes += 1;
                         self.total_bid_qty -= removed;
                         let removed_best = self.best_bid.map(|b| b == price).unwrap_or(false);
                         if removed_best {
@@ -310,6 +324,7 @@ impl OrderBook for OrderBookImpl {
                     let (found, idx) = Self::locate_ask(self.asks.as_slice(), price);
                     if found {
                         let removed = Self::remove_at(&mut self.asks, idx).1;
+                        self.churn.removes += 1;
                         self.total_ask_qty -= removed;
                         let removed_best = self.best_ask.map(|b| b == price).unwrap_or(false);
                         if removed_best {
@@ -378,6 +393,24 @@ impl OrderBook for OrderBookImpl {
         }
     }
 
+    fn levels_in_range(&self, side: Side, from_price: Price, to_price: Price) -> impl Iterator<Item = (Price, Quantity)> {
+        let (low, high) = (from_price.min(to_price), from_price.max(to_price));
+        // les deux côtés sont triés meilleur d'abord : la bande est une tranche
+        let levels = match side {
+            Side::Bid => {
+                let start = self.bids.partition_point(|&(p, _)| p > high);
+                let end = self.bids.partition_point(|&(p, _)| p >= low);
+                &self.bids[start..end.max(start)]
+            }
+            Side::Ask => {
+                let start = self.asks.partition_point(|&(p, _)| p < low);
+                let end = self.asks.partition_point(|&(p, _)| p <= high);
+                &self.asks[start..end.max(start)]
+            }
+        };
+        levels.iter().copied()
+    }
+
     #[inline(always)]
     fn get_total_quantity(&self, side: Side) -> Quantity {
         match side {
@@ -385,4 +418,30 @@ impl OrderBook for OrderBookImpl {
             Side::Ask => self.total_ask_qty,
         }
     }
+
+    #[inline(always)]
+    fn level_count(&self, side: Side) -> usize {
+        match side {
+            Side::Bid => self.bids.len(),
+            Side::Ask => self.asks.len(),
+        }
+    }
+
+    fn update_count(&self) -> u64 {
+        self.update_count
+    }
+
+    fn churn_stats(&self) -> ChurnStats {
+        self.churn
+    }
+
+    fn reset_stats(&mut self) {
+        self.update_count = 0;
+        self.churn = ChurnStats::default();
+    }
+
+    fn memory_usage(&self) -> usize {
+        // tout est inline dans les ArrayVec
+        0
+    }
 }
@@ -0,0 +1,187 @@
+use crate::interfaces::{ChurnStats, OrderBook, Price, Quantity, Side, Update};
+use arrayvec::ArrayVec;
+
+// Enveloppe optionnelle autour de n'importe quel carnet : les `K` meilleurs
+// niveaux de chaque côté sont tenus à jour pendant `apply_update`, ce qui rend
+// `get_top_levels(n <= K)` indépendant de la profondeur du carnet. En échange,
+// chaque update paie une lecture du carnet interne et un décalage dans le cache,
+// et la suppression d'un niveau du top-K relit le carnet pour le remplacer.
+
+pub struct TopLevelsCache<B: OrderBook, const K: usize> {
+    inner: B,
+    bids: ArrayVec<(Price, Quantity), K>, // tri décroissant
+    asks: ArrayVec<(Price, Quantity), K>, // tri croissant
+}
+
+impl<B: OrderBook, const K: usize> TopLevelsCache<B, K> {
+    pub fn wrap(inner: B) -> Self {
+        let mut cache = TopLevelsCache {
+            inner,
+            bids: ArrayVec::new(),
+            asks: ArrayVec::new(),
+        };
+        cache.bids.extend(cache.inner.get_top_levels(Side::Bid, K));
+        cache.asks.extend(cache.inner.get_top_levels(Side::Ask, K));
+        cache
+    }
+
+    pub fn inner(&self) -> &B {
+        &self.inner
+    }
+
+    /// Les `K` (ou moins) meilleurs niveaux, sans copie.
+    #[inline(always)]
+    pub fn top_levels(&self, side: Side) -> &[(Price, Quantity)] {
+        match side {
+            Side::Bid => &self.bids,
+            Side::Ask => &self.asks,
+        }
+    }
+
+    #[inline(always)]
+    fn refresh(&mut self, price: Price, side: Side) {
+        // le carnet interne fait foi : il peut ignorer un prix (hors bande, plein...)
+        let quantity = self.inner.get_quantity_at(price, side);
+        let cache = match side {
+            Side::Bid => &mut self.bids,
+            Side::Ask => &mut self.asks,
+        };
+        let better = |a: Price, b: Price| match side {
+            Side::Bid => a > b,
+            Side::Ask => a < b,
+        };
+        let pos = cache.iter().position(|(p, _)| !better(*p, price));
+        let found = pos.is_some_and(|i| cache[i].0 == price);
+        match (quantity, found) {
+            (Some(q), true) => cache[pos.unwrap()].1 = q,
+            (Some(q), false) => {
+                let idx = pos.unwrap_or(cache.len());
+                if idx == K {
+                    return;
+                }
+                if cache.is_full() {
+                    cache.pop();
+                }
+                cache.insert(idx, (price, q));
+            }
+            (None, true) => {
+                let was_full = cache.is_full();
+                cache.remove(pos.unwrap());
+                if was_full {
+                    // le K+1-ième niveau remonte dans le cache
+                    if let Some(next) = self.inner.get_top_levels(side, K).get(K - 1) {
+                        cache.push(*next);
+                    }
+                }
+            }
+            (None, false) => {}
+        }
+    }
+}
+
+impl<B: OrderBook, const K: usize> OrderBook for TopLevelsCache<B, K> {
+    fn new() -> Self {
+        Self::wrap(B::new())
+    }
+
+    #[inline(always)]
+    fn apply_update(&mut self, update: Update) {
+        let (price, side) = match update {
+            Update::Set { price, side, .. } | Update::Remove { price, side } => (price, side),
+        };
+        self.inner.apply_update(update);
+        self.refresh(price, side);
+    }
+
+    #[inline(always)]
+    fn get_spread(&self) -> Option<Price> {
+        Some(self.asks.first()?.0 - self.bids.first()?.0)
+    }
+
+    #[inline(always)]
+    fn get_best_bid(&self) -> Option<Price> {
+        self.bids.first().map(|(p, _)| *p)
+    }
+
+    #[inline(always)]
+    fn get_best_ask(&self) -> Option<Price> {
+        self.asks.first().map(|(p, _)| *p)
+    }
+
+    #[inline(always)]
+    fn get_quantity_at(&self, price: Price, side: Side) -> Option<Quantity> {
+        self.inner.get_quantity_at(price, side)
+    }
+
+    fn get_top_levels(&self, side: Side, n: usize) -> Vec<(Price, Quantity)> {
+        if n <= K {
+            let cache = self.top_levels(side);
+            cache[..n.min(cache.len())].to_vec()
+        } else {
+            self.inner.get_top_levels(side, n)
+        }
+    }
+
+    fn levels_in_range(&self, side: Side, from_price: Price, to_price: Price) -> impl Iterator<Item = (Price, Quantity)> {
+        self.inner.levels_in_range(side, from_price, to_price)
+    }
+
+    #[inline(always)]
+    fn get_total_quantity(&self, side: Side) -> Quantity {
+        self.inner.get_total_quantity(side)
+    }
+
+    #[inline(always)]
+    fn level_count(&self, side: Side) -> usize {
+        self.inner.level_count(side)
+    }
+
+    fn update_count(&self) -> u64 {
+        self.inner.update_count()
+    }
+
+    fn churn_stats(&self) -> ChurnStats {
+        self.inner.churn_stats()
+    }
+
+    fn reset_stats(&mut self) {
+        self.inner.reset_stats()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.inner.memory_usage()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dense::DenseOrderBook;
+    use crate::orderbook::OrderBookImpl;
+
+    fn follows_the_inner_book<B: OrderBook>() {
+        let mut cached = TopLevelsCache::<B, 3>::new();
+        let mut seed = 7u64;
+        for _ in 0..5_000 {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let side = if seed >> 63 == 0 { Side::Bid } else { Side::Ask };
+            let price = 10_000 + ((seed >> 40) % 12) as Price * 5;
+            let quantity = (seed >> 20) % 4 * 10;
+            cached.apply_update(if seed & 1 == 0 {
+                Update::Set { price, quantity, side }
+            } else {
+                Update::Remove { price, side }
+            });
+            for side in [Side::Bid, Side::Ask] {
+                assert_eq!(cached.top_levels(side), cached.inner().get_top_levels(side, 3));
+            }
+        }
+        assert_eq!(cached.get_top_levels(Side::Ask, 10), cached.inner().get_top_levels(Side::Ask, 10));
+    }
+
+    #[test]
+    fn the_cache_matches_the_book() {
+        follows_the_inner_book::<OrderBookImpl>();
+        follows_the_inner_book::<DenseOrderBook>();
+    }
+}
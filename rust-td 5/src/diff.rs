@@ -0,0 +1,124 @@
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+
+/// A word whose share of the text changed between the two documents.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FrequencyShift {
+    pub word: String,
+    pub count_a: usize,
+    pub count_b: usize,
+    /// Occurrences per 1000 words in each document.
+    pub per_mille_a: f64,
+    pub per_mille_b: f64,
+}
+
+impl FrequencyShift {
+    /// Change from `a` to `b`, in occurrences per 1000 words.
+    pub fn delta(&self) -> f64 {
+        self.per_mille_b - self.per_mille_a
+    }
+}
+
+/// How the vocabularies of two documents differ.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct VocabularyDiff {
+    pub words_a: usize,
+    pub words_b: usize,
+    pub unique_in_a: usize,
+    pub unique_in_b: usize,
+    pub shared: usize,
+    /// Most frequent words found only in `a` (resp. `b`).
+    pub only_in_a: Vec<(String, usize)>,
+    pub only_in_b: Vec<(String, usize)>,
+    /// Shared words with the largest change of relative frequency.
+    pub shifts: Vec<FrequencyShift>,
+    /// Shared words over all distinct words of both documents (1.0 = same vocabulary).
+    pub jaccard: f64,
+}
+
+fn top_only_in(
+    freq: &FxHashMap<String, usize>,
+    other: &FxHashMap<String, usize>,
+    limit: usize,
+) -> (usize, Vec<(String, usize)>) {
+    let mut only: Vec<(String, usize)> = freq
+        .iter()
+        .filter(|(w, _)| !other.contains_key(*w))
+        .map(|(w, c)| (w.clone(), *c))
+        .collect();
+    let count = only.len();
+    only.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    only.truncate(limit);
+    (count, only)
+}
+
+/// Compares two word frequency maps (see `TextAnalyzer::into_frequencies`),
+/// keeping `limit` words per list.
+pub fn diff_vocabularies(a: &FxHashMap<String, usize>, b: &FxHashMap<String, usize>, limit: usize) -> VocabularyDiff {
+    let total_a: usize = a.values().sum();
+    let total_b: usize = b.values().sum();
+    let per_mille = |count: usize, total: usize| if total == 0 { 0.0 } else { count as f64 * 1000.0 / total as f64 };
+
+    let (unique_in_a, only_in_a) = top_only_in(a, b, limit);
+    let (unique_in_b, only_in_b) = top_only_in(b, a, limit);
+
+    let mut shifts: Vec<FrequencyShift> = a
+        .iter()
+        .filter_map(|(word, &count_a)| {
+            let count_b = *b.get(word)?;
+            Some(FrequencyShift {
+                word: word.clone(),
+                count_a,
+                count_b,
+                per_mille_a: per_mille(count_a, total_a),
+                per_mille_b: per_mille(count_b, total_b),
+            })
+        })
+        .collect();
+    let shared = shifts.len();
+    shifts.sort_unstable_by(|x, y| y.delta().abs().total_cmp(&x.delta().abs()).then(x.word.cmp(&y.word)));
+    shifts.truncate(limit);
+
+    let union = unique_in_a + unique_in_b + shared;
+    VocabularyDiff {
+        words_a: total_a,
+        words_b: total_b,
+        unique_in_a,
+        unique_in_b,
+        shared,
+        only_in_a,
+        only_in_b,
+        shifts,
+        jaccard: if union == 0 { 1.0 } else { shared as f64 / union as f64 },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextAnalyzer;
+
+    fn frequencies(text: &str) -> FxHashMap<String, usize> {
+        let mut analyzer = TextAnalyzer::new();
+        analyzer.feed(text);
+        analyzer.into_frequencies()
+    }
+
+    #[test]
+    fn diff_reports_unique_words_shifts_and_jaccard() {
+        let a = frequencies("rust speed speed memory memory memory draft");
+        let b = frequencies("Rust rust speed memory final final");
+        let diff = diff_vocabularies(&a, &b, 10);
+
+        assert_eq!((diff.words_a, diff.words_b), (7, 6));
+        assert_eq!(diff.only_in_a, vec![("draft".to_string(), 1)]);
+        assert_eq!(diff.only_in_b, vec![("final".to_string(), 2)]);
+        // rust, speed, memory shared out of 5 distinct words
+        assert_eq!(diff.shared, 3);
+        assert!((diff.jaccard - 0.6).abs() < 1e-9);
+        // memory: 3/7 -> 1/6 is the largest move
+        assert_eq!(diff.shifts[0].word, "memory");
+        assert!(diff.shifts[0].delta() < 0.0);
+        assert_eq!(diff.shifts[1].word, "rust");
+    }
+}
@@ -0,0 +1,109 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Realistic test text: a synthetic vocabulary sampled with Zipfian
+/// frequencies (rank `k` has weight `1 / k^exponent`), plus punctuation.
+#[derive(Debug, Clone)]
+pub struct ZipfTextGenerator {
+    pub vocab_size: usize,
+    pub exponent: f64,
+    /// Probability that a word is followed by a punctuation mark.
+    pub punctuation_rate: f64,
+    pub seed: u64,
+}
+
+impl Default for ZipfTextGenerator {
+    fn default() -> Self {
+        ZipfTextGenerator {
+            vocab_size: 10_000,
+            exponent: 1.07,
+            punctuation_rate: 0.1,
+            seed: 42,
+        }
+    }
+}
+
+const SYLLABLES: [&str; 24] = [
+    "ra", "be", "ti", "lo", "mu", "sen", "da", "ko", "vi", "ne", "sta", "pol",
+    "gri", "fa", "que", "zo", "lin", "ter", "ma", "chu", "dre", "op", "wy", "xe",
+];
+const PUNCTUATION: [&str; 6] = [",", ".", ";", ":", "!", "?"];
+
+impl ZipfTextGenerator {
+    /// Generate `size` words separated by spaces.
+    pub fn generate(&self, size: usize) -> String {
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let vocab = build_vocabulary(self.vocab_size.max(1));
+        let cdf = zipf_cdf(vocab.len(), self.exponent);
+
+        let mut output = String::with_capacity(size * 8);
+        for i in 0..size {
+            if i > 0 {
+                output.push(' ');
+            }
+            let u: f64 = rng.gen();
+            let rank = cdf.partition_point(|&c| c < u).min(vocab.len() - 1);
+            output.push_str(&vocab[rank]);
+            if rng.gen_bool(self.punctuation_rate.clamp(0.0, 1.0)) {
+                output.push_str(PUNCTUATION[rng.gen_range(0..PUNCTUATION.len())]);
+            }
+        }
+        output
+    }
+}
+
+/// Distinct words built from syllables (rank 0 is the shortest word).
+fn build_vocabulary(size: usize) -> Vec<String> {
+    (0..size)
+        .map(|mut n| {
+            let mut word = String::new();
+            loop {
+                word.push_str(SYLLABLES[n % SYLLABLES.len()]);
+                n /= SYLLABLES.len();
+                if n == 0 {
+                    break;
+                }
+                n -= 1;
+            }
+            word
+        })
+        .collect()
+}
+
+fn zipf_cdf(n: usize, exponent: f64) -> Vec<f64> {
+    let mut cdf: Vec<f64> = Vec::with_capacity(n);
+    let mut total = 0.0;
+    for k in 1..=n {
+        total += 1.0 / (k as f64).powf(exponent);
+        cdf.push(total);
+    }
+    for c in &mut cdf {
+        *c /= total;
+    }
+    cdf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analyze_text_fast;
+
+    #[test]
+    fn zipf_text_is_reproducible_and_skewed() {
+        let generator = ZipfTextGenerator { vocab_size: 500, ..Default::default() };
+        let a = generator.generate(20_000);
+        assert_eq!(a, generator.generate(20_000));
+
+        let stats = analyze_text_fast(&a);
+        assert!(stats.word_count > 100 && stats.word_count <= 500);
+        // Rank 1 should be far more frequent than rank 10.
+        assert!(stats.top_words[0].1 > 5 * stats.top_words[9].1);
+    }
+
+    #[test]
+    fn vocabulary_words_are_distinct() {
+        let vocab = build_vocabulary(5_000);
+        let unique: std::collections::HashSet<_> = vocab.iter().collect();
+        assert_eq!(unique.len(), vocab.len());
+    }
+}
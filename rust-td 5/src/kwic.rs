@@ -0,0 +1,79 @@
+/// One occurrence of the looked-up word with the words around it
+/// (keyword in context).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContextLine {
+    /// Byte offset of the word in the text.
+    pub offset: usize,
+    pub left: String,
+    pub word: String,
+    pub right: String,
+}
+
+/// Lines of context for each `(start, end)` byte range recorded by the
+/// analyzer: `window` whitespace-separated words on each side, as written in
+/// the text (punctuation glued to the word stays with it).
+pub fn concordance(text: &[u8], occurrences: &[(usize, usize)], window: usize) -> Vec<ContextLine> {
+    occurrences
+        .iter()
+        .filter(|&&(start, end)| start <= end && end <= text.len())
+        .map(|&(start, end)| ContextLine {
+            offset: start,
+            left: collapse_whitespace(&text[left_bound(text, start, window)..start]),
+            word: String::from_utf8_lossy(&text[start..end]).into_owned(),
+            right: collapse_whitespace(&text[end..right_bound(text, end, window)]),
+        })
+        .collect()
+}
+
+fn left_bound(text: &[u8], start: usize, window: usize) -> usize {
+    let mut i = start;
+    let skip = |i: &mut usize, ws: bool| {
+        while *i > 0 && text[*i - 1].is_ascii_whitespace() == ws {
+            *i -= 1;
+        }
+    };
+    // What is glued to the word ("(" in "(word") comes for free.
+    skip(&mut i, false);
+    for _ in 0..window {
+        skip(&mut i, true);
+        skip(&mut i, false);
+    }
+    i
+}
+
+fn right_bound(text: &[u8], end: usize, window: usize) -> usize {
+    let mut i = end;
+    let skip = |i: &mut usize, ws: bool| {
+        while *i < text.len() && text[*i].is_ascii_whitespace() == ws {
+            *i += 1;
+        }
+    };
+    skip(&mut i, false);
+    for _ in 0..window {
+        skip(&mut i, true);
+        skip(&mut i, false);
+    }
+    i
+}
+
+/// Newlines and runs of spaces become one space, so each line of context
+/// stays on one line.
+fn collapse_whitespace(bytes: &[u8]) -> String {
+    let text = String::from_utf8_lossy(bytes);
+    let mut out = String::with_capacity(text.len());
+    let mut words = text.split_ascii_whitespace();
+    if text.starts_with(|c: char| c.is_ascii_whitespace()) {
+        out.push(' ');
+    }
+    if let Some(first) = words.next() {
+        out.push_str(first);
+        for word in words {
+            out.push(' ');
+            out.push_str(word);
+        }
+        if text.ends_with(|c: char| c.is_ascii_whitespace()) {
+            out.push(' ');
+        }
+    }
+    out
+}
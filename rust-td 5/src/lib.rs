@@ -0,0 +1,568 @@
+use std::time::{Duration, Instant};
+use rustc_hash::FxHashMap;
+use serde::{Serialize, Serializer};
+
+pub mod diff;
+pub mod generator;
+pub mod kwic;
+pub mod memory;
+pub mod ngrams;
+
+pub use diff::{diff_vocabularies, FrequencyShift, VocabularyDiff};
+pub use generator::ZipfTextGenerator;
+pub use kwic::{concordance, ContextLine};
+pub use ngrams::{CharNgrams, NgramStats, MAX_NGRAM};
+
+#[derive(Debug, Serialize)]
+pub struct TextStats {
+    pub word_count: usize,
+    pub char_count: usize,
+    pub top_words: Vec<(String, usize)>,
+    pub longest_words: Vec<String>,
+    pub digit_count: usize,
+    pub punctuation_count: usize,
+    pub whitespace_count: usize,
+    pub non_ascii_bytes: usize,
+    /// Malformed or truncated UTF-8 sequences (only possible via `feed_bytes`).
+    pub invalid_utf8_sequences: usize,
+    /// Total words seen (not unique), used for words/s.
+    pub total_words: usize,
+    pub bytes_processed: usize,
+    /// Wall-clock time from the first `feed` to `finish`.
+    #[serde(rename = "elapsed_us", serialize_with = "serialize_micros")]
+    pub elapsed: Duration,
+    pub mb_per_sec: f64,
+    pub words_per_sec: f64,
+    /// Estimated heap footprint of the word frequency map.
+    pub map_memory_bytes: usize,
+    /// Peak RSS of the process when `finish` ran (None if unsupported).
+    pub peak_rss_bytes: Option<u64>,
+    /// `(start, end)` byte ranges of the word given to
+    /// `TextAnalyzerBuilder::context_word`, for [`concordance`].
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub occurrences: Vec<(usize, usize)>,
+    /// Set by `TextAnalyzerBuilder::char_ngrams`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub char_ngrams: Option<NgramStats>,
+}
+
+fn serialize_micros<S: Serializer>(d: &Duration, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_u128(d.as_micros())
+}
+
+/// Per-second rate, 0.0 when nothing was measured.
+fn rate(amount: f64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        amount / secs
+    } else {
+        0.0
+    }
+}
+
+/// Tokenization options. Defaults match the original scanner: lowercase,
+/// words broken at every non-ASCII-letter byte.
+#[derive(Debug, Clone, Copy)]
+pub struct TokenizerOptions {
+    pub keep_case: bool,
+    /// Keep `'` between two letters ("don't" stays one word).
+    pub keep_apostrophes: bool,
+    /// Break "state-of-the-art" into four words (when false, `-` between
+    /// two letters is kept).
+    pub split_hyphens: bool,
+}
+
+impl Default for TokenizerOptions {
+    fn default() -> Self {
+        TokenizerOptions {
+            keep_case: false,
+            keep_apostrophes: false,
+            split_hyphens: true,
+        }
+    }
+}
+
+/// Builder for a [`TextAnalyzer`] (how many top/longest words to report,
+/// tokenization options).
+#[derive(Debug, Clone)]
+pub struct TextAnalyzerBuilder {
+    top_n: usize,
+    longest_n: usize,
+    capacity: usize,
+    options: TokenizerOptions,
+    context_word: Option<String>,
+    char_ngrams: Option<usize>,
+    top_char_ngrams: usize,
+}
+
+impl Default for TextAnalyzerBuilder {
+    fn default() -> Self {
+        TextAnalyzerBuilder {
+            top_n: 10,
+            longest_n: 5,
+            capacity: 1024,
+            options: TokenizerOptions::default(),
+            context_word: None,
+            char_ngrams: None,
+            top_char_ngrams: 20,
+        }
+    }
+}
+
+impl TextAnalyzerBuilder {
+    pub fn top_words(mut self, n: usize) -> Self {
+        self.top_n = n;
+        self
+    }
+
+    pub fn longest_words(mut self, n: usize) -> Self {
+        self.longest_n = n;
+        self
+    }
+
+    /// Initial capacity of the frequency map.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    pub fn keep_case(mut self, yes: bool) -> Self {
+        self.options.keep_case = yes;
+        self
+    }
+
+    pub fn keep_apostrophes(mut self, yes: bool) -> Self {
+        self.options.keep_apostrophes = yes;
+        self
+    }
+
+    pub fn split_hyphens(mut self, yes: bool) -> Self {
+        self.options.split_hyphens = yes;
+        self
+    }
+
+    pub fn options(mut self, options: TokenizerOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Record where this word occurs (compared after tokenization, so
+    /// lowercased unless `keep_case`).
+    pub fn context_word(mut self, word: impl Into<String>) -> Self {
+        self.context_word = Some(word.into());
+        self
+    }
+
+    /// Also count character n-grams of `n` characters (1 to [`MAX_NGRAM`])
+    /// in the same pass, e.g. trigrams to fingerprint a language or author.
+    pub fn char_ngrams(mut self, n: usize) -> Self {
+        self.char_ngrams = Some(n);
+        self
+    }
+
+    /// How many n-grams `char_ngrams` reports.
+    pub fn top_char_ngrams(mut self, n: usize) -> Self {
+        self.top_char_ngrams = n;
+        self
+    }
+
+    pub fn build(self) -> TextAnalyzer {
+        let keep_case = self.options.keep_case;
+        TextAnalyzer {
+            word_freq: FxHashMap::with_capacity_and_hasher(self.capacity, Default::default()),
+            char_count: 0,
+            digit_count: 0,
+            punctuation_count: 0,
+            whitespace_count: 0,
+            non_ascii_bytes: 0,
+            utf8: Utf8Validator::default(),
+            buf: String::with_capacity(32),
+            pending_joiner: None,
+            options: self.options,
+            total_words: 0,
+            bytes_processed: 0,
+            top_n: self.top_n,
+            longest_n: self.longest_n,
+            start: None,
+            context_word: self.context_word.map(|w| if keep_case { w } else { w.to_ascii_lowercase() }),
+            word_start: 0,
+            occurrences: Vec::new(),
+            char_ngrams: self.char_ngrams.map(|n| CharNgrams::new(n, keep_case)),
+            top_char_ngrams: self.top_char_ngrams,
+        }
+    }
+}
+
+/// Incremental analyzer: call `feed` with successive chunks (a word may be
+/// split across two chunks), then `finish` to get the stats.
+pub struct TextAnalyzer {
+    word_freq: FxHashMap<String, usize>,
+    char_count: usize,
+    digit_count: usize,
+    punctuation_count: usize,
+    whitespace_count: usize,
+    non_ascii_bytes: usize,
+    utf8: Utf8Validator,
+    buf: String,
+    /// `'` or `-` seen right after a letter; kept only if a letter follows.
+    pending_joiner: Option<u8>,
+    options: TokenizerOptions,
+    total_words: usize,
+    bytes_processed: usize,
+    top_n: usize,
+    longest_n: usize,
+    start: Option<Instant>,
+    context_word: Option<String>,
+    /// Byte offset of the first letter of the word in `buf`.
+    word_start: usize,
+    occurrences: Vec<(usize, usize)>,
+    char_ngrams: Option<CharNgrams>,
+    top_char_ngrams: usize,
+}
+
+impl Default for TextAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextAnalyzer {
+    pub fn new() -> Self {
+        Self::builder().build()
+    }
+
+    pub fn builder() -> TextAnalyzerBuilder {
+        TextAnalyzerBuilder::default()
+    }
+
+    pub fn feed(&mut self, text: &str) {
+        self.feed_bytes(text.as_bytes());
+    }
+
+    /// Same as `feed` for raw bytes (e.g. a file read without decoding);
+    /// invalid UTF-8 is counted, a sequence may span two chunks.
+    pub fn feed_bytes(&mut self, bytes: &[u8]) {
+        self.start.get_or_insert_with(Instant::now);
+        let base = self.bytes_processed;
+        self.bytes_processed += bytes.len();
+        for (i, &b) in bytes.iter().enumerate() {
+            if let Some(ngrams) = &mut self.char_ngrams {
+                ngrams.push_byte(b);
+            }
+            if b >= 0x80 {
+                self.non_ascii_bytes += 1;
+                self.utf8.push(b);
+            } else {
+                if self.utf8.in_sequence() {
+                    self.utf8.push(b);
+                }
+                if b.is_ascii_digit() {
+                    self.digit_count += 1;
+                } else if b.is_ascii_whitespace() || b == 0x0b {
+                    self.whitespace_count += 1;
+                } else if b.is_ascii_punctuation() {
+                    self.punctuation_count += 1;
+                }
+            }
+            match b {
+                b'a'..=b'z' => self.push_letter(b, base + i),
+                b'A'..=b'Z' => {
+                    if self.options.keep_case {
+                        self.push_letter(b, base + i);
+                    } else {
+                        self.push_letter(b + 32, base + i); // to lowercase
+                    }
+                }
+                b'\'' if self.options.keep_apostrophes => self.push_joiner(b),
+                b'-' if !self.options.split_hyphens => self.push_joiner(b),
+                _ => self.end_word(),
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn push_letter(&mut self, b: u8, offset: usize) {
+        if self.buf.is_empty() {
+            self.word_start = offset;
+        }
+        if let Some(j) = self.pending_joiner.take() {
+            self.buf.push(j as char);
+        }
+        self.buf.push(b as char);
+        self.char_count += 1;
+    }
+
+    #[inline(always)]
+    fn push_joiner(&mut self, b: u8) {
+        // A joiner only counts inside a word, and never twice in a row.
+        if self.buf.is_empty() || self.pending_joiner.is_some() {
+            self.end_word();
+        } else {
+            self.pending_joiner = Some(b);
+        }
+    }
+
+    #[inline(always)]
+    fn end_word(&mut self) {
+        self.pending_joiner = None;
+        if !self.buf.is_empty() {
+            if self.context_word.as_deref() == Some(self.buf.as_str()) {
+                // Words are ASCII: one byte per char of `buf`.
+                self.occurrences.push((self.word_start, self.word_start + self.buf.len()));
+            }
+            process_word(&mut self.buf, &mut self.word_freq);
+            self.total_words += 1;
+        }
+    }
+
+    /// The word counts instead of the stats, e.g. for `diff_vocabularies`.
+    pub fn into_frequencies(mut self) -> FxHashMap<String, usize> {
+        self.end_word();
+        self.word_freq
+    }
+
+    pub fn finish(mut self) -> TextStats {
+        // Flush the word left pending at the end of the last chunk.
+        self.end_word();
+        self.utf8.finish();
+
+        let unique_words = self.word_freq.len();
+
+        // Top N via sort (fast for map sizes).
+        let mut top_words: Vec<(String, usize)> = self
+            .word_freq
+            .iter()
+            .map(|(w, c)| (w.clone(), *c))
+            .collect();
+        top_words.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        top_words.truncate(self.top_n);
+
+        // Longest N words.
+        let mut longest_words: Vec<(usize, String)> = self
+            .word_freq
+            .keys()
+            .map(|w| (w.len(), w.clone()))
+            .collect();
+        longest_words.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        longest_words.truncate(self.longest_n);
+        let longest_words: Vec<String> = longest_words.into_iter().map(|(_, w)| w).collect();
+
+        let elapsed = self.start.map(|s| s.elapsed()).unwrap_or_default();
+        let map_memory_bytes = memory::estimate_map_bytes(&self.word_freq);
+
+        TextStats {
+            word_count: unique_words,
+            char_count: self.char_count,
+            top_words,
+            longest_words,
+            digit_count: self.digit_count,
+            punctuation_count: self.punctuation_count,
+            whitespace_count: self.whitespace_count,
+            non_ascii_bytes: self.non_ascii_bytes,
+            invalid_utf8_sequences: self.utf8.invalid,
+            total_words: self.total_words,
+            bytes_processed: self.bytes_processed,
+            elapsed,
+            mb_per_sec: rate(self.bytes_processed as f64 / 1_000_000.0, elapsed),
+            words_per_sec: rate(self.total_words as f64, elapsed),
+            map_memory_bytes,
+            peak_rss_bytes: memory::peak_rss_bytes(),
+            occurrences: self.occurrences,
+            char_ngrams: self.char_ngrams.map(|ngrams| ngrams.finish(self.top_char_ngrams)),
+        }
+    }
+}
+
+/// Streaming UTF-8 checker (continuation-byte ranges per RFC 3629, so
+/// overlong forms and surrogates are rejected).
+#[derive(Debug, Default)]
+struct Utf8Validator {
+    /// Continuation bytes still expected for the current sequence.
+    remaining: u8,
+    next_lo: u8,
+    next_hi: u8,
+    invalid: usize,
+}
+
+impl Utf8Validator {
+    #[inline(always)]
+    fn in_sequence(&self) -> bool {
+        self.remaining > 0
+    }
+
+    fn push(&mut self, b: u8) {
+        if self.remaining > 0 {
+            if (self.next_lo..=self.next_hi).contains(&b) {
+                self.remaining -= 1;
+                self.next_lo = 0x80;
+                self.next_hi = 0xBF;
+                return;
+            }
+            // Truncated sequence: count it and treat `b` as a new lead byte.
+            self.invalid += 1;
+            self.remaining = 0;
+        }
+        let (remaining, lo, hi) = match b {
+            0x00..=0x7F => return,
+            0xC2..=0xDF => (1, 0x80, 0xBF),
+            0xE0 => (2, 0xA0, 0xBF),
+            0xE1..=0xEC | 0xEE..=0xEF => (2, 0x80, 0xBF),
+            0xED => (2, 0x80, 0x9F),
+            0xF0 => (3, 0x90, 0xBF),
+            0xF1..=0xF3 => (3, 0x80, 0xBF),
+            0xF4 => (3, 0x80, 0x8F),
+            _ => {
+                self.invalid += 1;
+                return;
+            }
+        };
+        self.remaining = remaining;
+        self.next_lo = lo;
+        self.next_hi = hi;
+    }
+
+    fn finish(&mut self) {
+        if self.remaining > 0 {
+            self.invalid += 1;
+            self.remaining = 0;
+        }
+    }
+}
+
+pub fn analyze_text_fast(text: &str) -> TextStats {
+    let mut analyzer = TextAnalyzer::new();
+    analyzer.feed(text);
+    analyzer.finish()
+}
+
+pub fn generate_test_text(size: usize) -> String {
+    const WORDS: [&str; 10] = [
+        "rust",
+        "performance",
+        "optimization",
+        "memory",
+        "speed",
+        "efficiency",
+        "benchmark",
+        "algorithm",
+        "data",
+        "structure",
+    ];
+
+    let mut output = String::with_capacity(size * 9);
+    for i in 0..size {
+        if i > 0 {
+            output.push(' ');
+        }
+        output.push_str(WORDS[i % WORDS.len()]);
+    }
+    output
+}
+
+#[inline(always)]
+fn process_word(
+    buf: &mut String,
+    word_freq: &mut FxHashMap<String, usize>,
+) {
+    let word = buf.clone();
+    buf.clear();
+    word_freq
+        .entry(word)
+        .and_modify(|c| *c += 1)
+        .or_insert(1);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunked_feed_matches_single_pass() {
+        let text = "Rust rust RUST speed, memory; rust-speed";
+        let whole = analyze_text_fast(text);
+
+        let mut analyzer = TextAnalyzer::new();
+        // Split in the middle of words on purpose.
+        for chunk in ["Ru", "st ru", "st RUST sp", "eed, memory; rust-sp", "eed"] {
+            analyzer.feed(chunk);
+        }
+        let chunked = analyzer.finish();
+
+        assert_eq!(chunked.word_count, whole.word_count);
+        assert_eq!(chunked.char_count, whole.char_count);
+        assert_eq!(chunked.top_words, whole.top_words);
+        assert_eq!(chunked.top_words[0], ("rust".to_string(), 4));
+        assert_eq!(chunked.total_words, 7);
+        assert_eq!(chunked.bytes_processed, text.len());
+    }
+
+    #[test]
+    fn builder_limits_reported_words() {
+        let mut analyzer = TextAnalyzer::builder().top_words(2).longest_words(1).build();
+        analyzer.feed(&generate_test_text(100));
+        let stats = analyzer.finish();
+        assert_eq!(stats.top_words.len(), 2);
+        assert_eq!(stats.longest_words, vec!["optimization".to_string()]);
+        // 10 keys at least, each with its own bucket.
+        assert!(stats.map_memory_bytes >= 10 * std::mem::size_of::<(String, usize)>());
+    }
+
+    #[test]
+    fn tokenizer_options_keep_apostrophes_and_hyphens() {
+        let text = "Don't stop: state-of-the-art -- rock'n'roll' end-";
+
+        let default = analyze_text_fast(text);
+        assert_eq!(default.total_words, 11);
+
+        let mut analyzer = TextAnalyzer::builder()
+            .keep_case(true)
+            .keep_apostrophes(true)
+            .split_hyphens(false)
+            .build();
+        // Split right after a joiner to check it survives chunk boundaries.
+        analyzer.feed("Don'");
+        analyzer.feed(&text[4..]);
+        let stats = analyzer.finish();
+        let words: Vec<&str> = stats.top_words.iter().map(|(w, _)| w.as_str()).collect();
+        assert_eq!(stats.total_words, 5);
+        assert!(words.contains(&"Don't"));
+        assert!(words.contains(&"state-of-the-art"));
+        assert!(words.contains(&"rock'n'roll"));
+        assert!(words.contains(&"end"));
+    }
+
+    #[test]
+    fn context_word_occurrences_give_a_concordance() {
+        let text = "Profile first.\nThen (Optimization) helps;   premature optimization hurts";
+        let mut analyzer = TextAnalyzer::builder().context_word("OPTIMIZATION").build();
+        // Split inside the second occurrence to check offsets across chunks.
+        analyzer.feed(&text[..60]);
+        analyzer.feed(&text[60..]);
+        let stats = analyzer.finish();
+        assert_eq!(stats.occurrences, vec![(21, 33), (54, 66)]);
+
+        let lines = concordance(text.as_bytes(), &stats.occurrences, 2);
+        assert_eq!(lines[0].left, "first. Then (");
+        assert_eq!(lines[0].word, "Optimization");
+        assert_eq!(lines[0].right, ") helps; premature");
+        assert_eq!((lines[1].left.as_str(), lines[1].right.as_str()), ("helps; premature ", " hurts"));
+    }
+
+    #[test]
+    fn character_classes_and_utf8_validation() {
+        let stats = analyze_text_fast("Été 2024, ok!\n");
+        assert_eq!(stats.digit_count, 4);
+        assert_eq!(stats.punctuation_count, 2);
+        assert_eq!(stats.whitespace_count, 3);
+        assert_eq!(stats.non_ascii_bytes, 4);
+        assert_eq!(stats.invalid_utf8_sequences, 0);
+
+        let mut analyzer = TextAnalyzer::new();
+        // "é" split across chunks is fine; a lone continuation byte, an
+        // overlong lead and a truncated tail are not.
+        analyzer.feed_bytes(b"caf\xC3");
+        analyzer.feed_bytes(b"\xA9 \x80 \xC0\xAF x\xE2\x82");
+        let stats = analyzer.finish();
+        assert_eq!(stats.invalid_utf8_sequences, 4);
+    }
+}
@@ -1,123 +1,229 @@
-use std::time::Instant;
-use rustc_hash::FxHashMap;
-
-#[derive(Debug)]
-struct TextStats {
-    word_count: usize,
-    char_count: usize,
-    top_words: Vec<(String, usize)>,
-    longest_words: Vec<String>,
-    time_ms: u128,
+use clap::builder::BoolishValueParser;
+use clap::{ArgAction, Parser, Subcommand};
+use rust_td_5::{
+    concordance, diff_vocabularies, generate_test_text, TextAnalyzer, TextAnalyzerBuilder, ZipfTextGenerator, MAX_NGRAM,
+};
+use std::path::PathBuf;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Fast text analyzer", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Text file to analyze (generated test text when omitted)
+    #[arg(value_name = "FILE")]
+    input: Option<PathBuf>,
+
+    /// Number of words in the generated test text
+    #[arg(long, default_value_t = 50_000)]
+    size: usize,
+
+    /// Generate Zipf-distributed text with punctuation instead of cycling 10 words
+    #[arg(long)]
+    zipf: bool,
+
+    /// Vocabulary size of the Zipf generator
+    #[arg(long, default_value_t = 10_000)]
+    vocab: usize,
+
+    /// Zipf exponent (higher = more skewed frequencies)
+    #[arg(long, default_value_t = 1.07)]
+    zipf_exponent: f64,
+
+    /// Probability of a punctuation mark after each generated word
+    #[arg(long, default_value_t = 0.1)]
+    punctuation: f64,
+
+    /// Seed of the Zipf generator (random when omitted)
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Print the stats as JSON (for benchmarking scripts)
+    #[arg(long, global = true)]
+    json: bool,
+
+    /// Keep the original case instead of lowercasing words
+    #[arg(long, global = true)]
+    keep_case: bool,
+
+    /// Keep apostrophes inside words ("don't")
+    #[arg(long, global = true)]
+    keep_apostrophes: bool,
+
+    /// Split words at hyphens; `--split-hyphens=no` keeps "state-of-the-art" whole
+    #[arg(
+        long,
+        global = true,
+        default_value = "yes",
+        value_parser = BoolishValueParser::new(),
+        action = ArgAction::Set
+    )]
+    split_hyphens: bool,
+
+    /// Print each occurrence of this word with the words around it (keyword in context)
+    #[arg(long, value_name = "WORD")]
+    show_context: Option<String>,
+
+    /// Words shown on each side with --show-context
+    #[arg(long, default_value_t = 5, requires = "show_context")]
+    window: usize,
+
+    /// Count character n-grams of N characters (1 to 6), e.g. 3 for trigrams
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u8).range(1..=MAX_NGRAM as i64))]
+    char_ngrams: Option<u8>,
+
+    /// N-grams listed with --char-ngrams
+    #[arg(long, default_value_t = 20, requires = "char_ngrams")]
+    top_ngrams: usize,
 }
 
-fn analyze_text_fast(text: &str) -> TextStats {
-    let start = Instant::now();
-
-    let mut word_freq: FxHashMap<String, usize> =
-        FxHashMap::with_capacity_and_hasher(1024, Default::default());
-    let mut char_count = 0usize;
-    let mut buf = String::with_capacity(32);
-    for &b in text.as_bytes() {
-        match b {
-            b'a'..=b'z' => {
-                buf.push(b as char);
-                char_count += 1;
-            }
-            b'A'..=b'Z' => {
-                buf.push((b + 32) as char); // to lowercase
-                char_count += 1;
-            }
-            _ => {
-                if !buf.is_empty() {
-                    process_word(&mut buf, &mut word_freq);
-                }
-            }
-        }
-    }
-    if !buf.is_empty() {
-        process_word(&mut buf, &mut word_freq);
-    }
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Compare the vocabularies of two documents
+    Diff {
+        a: PathBuf,
+        b: PathBuf,
+
+        /// Words listed per section
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+    },
+}
 
-    let unique_words = word_freq.len();
+/// Occurrences printed by --show-context; the count of the others follows.
+const MAX_CONTEXT_LINES: usize = 100;
 
-    // Top 10 via sort (fast for map sizes).
-    let mut top_words: Vec<(String, usize)> = word_freq
-        .iter()
-        .map(|(w, c)| (w.clone(), *c))
-        .collect();
-    top_words.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
-    if top_words.len() > 10 {
-        top_words.truncate(10);
-    }
+/// Left context wider than this is cut on the left, to keep the words aligned.
+const CONTEXT_WIDTH: usize = 60;
+
+fn tokenizer(cli: &Cli) -> TextAnalyzerBuilder {
+    TextAnalyzer::builder()
+        .keep_case(cli.keep_case)
+        .keep_apostrophes(cli.keep_apostrophes)
+        .split_hyphens(cli.split_hyphens)
+}
 
-    // Longest 5 words.
-    let mut longest_words: Vec<(usize, String)> = word_freq
-        .keys()
-        .map(|w| (w.len(), w.clone()))
-        .collect();
-    longest_words.sort_unstable_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
-    if longest_words.len() > 5 {
-        longest_words.truncate(5);
+fn diff(cli: &Cli, a: &PathBuf, b: &PathBuf, top: usize) -> Result<(), Box<dyn std::error::Error>> {
+    let frequencies = |path: &PathBuf| -> Result<_, Box<dyn std::error::Error>> {
+        let mut analyzer = tokenizer(cli).build();
+        analyzer.feed_bytes(&std::fs::read(path).map_err(|e| format!("{}: {}", path.display(), e))?);
+        Ok(analyzer.into_frequencies())
+    };
+    let diff = diff_vocabularies(&frequencies(a)?, &frequencies(b)?, top);
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&diff)?);
+        return Ok(());
     }
-    let longest_words: Vec<String> = longest_words.into_iter().map(|(_, w)| w).collect();
-
-    TextStats {
-        word_count: unique_words,
-        char_count,
-        top_words,
-        longest_words,
-        time_ms: start.elapsed().as_millis(),
+
+    println!("A: {} ({} words)", a.display(), diff.words_a);
+    println!("B: {} ({} words)", b.display(), diff.words_b);
+    println!(
+        "  Distinct words: {} shared, {} only in A, {} only in B",
+        diff.shared, diff.unique_in_a, diff.unique_in_b
+    );
+    println!("  Jaccard similarity: {:.3}", diff.jaccard);
+    println!("  Only in A: {:?}", diff.only_in_a);
+    println!("  Only in B: {:?}", diff.only_in_b);
+    println!("  Largest frequency shifts (per 1000 words, A -> B):");
+    for shift in &diff.shifts {
+        println!(
+            "    {:<20} {:>8.2} -> {:>8.2} ({:+.2})",
+            shift.word,
+            shift.per_mille_a,
+            shift.per_mille_b,
+            shift.delta()
+        );
     }
+    Ok(())
 }
 
-fn generate_test_text(size: usize) -> String {
-    const WORDS: [&str; 10] = [
-        "rust",
-        "performance",
-        "optimization",
-        "memory",
-        "speed",
-        "efficiency",
-        "benchmark",
-        "algorithm",
-        "data",
-        "structure",
-    ];
-
-    let mut output = String::with_capacity(size * 9);
-    for i in 0..size {
-        if i > 0 {
-            output.push(' ');
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse();
+    if let Some(Command::Diff { a, b, top }) = &cli.command {
+        return diff(&cli, a, b, *top);
+    }
+    let text = match &cli.input {
+        // Read raw bytes so invalid UTF-8 is reported instead of aborting.
+        Some(path) => std::fs::read(path)?,
+        None if cli.zipf => {
+            let seed = cli.seed.unwrap_or_else(rand::random);
+            if !cli.json {
+                println!("Zipf text: vocab={} exponent={} seed={}", cli.vocab, cli.zipf_exponent, seed);
+            }
+            ZipfTextGenerator {
+                vocab_size: cli.vocab,
+                exponent: cli.zipf_exponent,
+                punctuation_rate: cli.punctuation,
+                seed,
+            }
+            .generate(cli.size)
+            .into_bytes()
         }
-        output.push_str(WORDS[i % WORDS.len()]);
+        None => generate_test_text(cli.size).into_bytes(),
+    };
+
+    let mut builder = tokenizer(&cli);
+    if let Some(word) = &cli.show_context {
+        builder = builder.context_word(word);
     }
-    output
-}
+    if let Some(n) = cli.char_ngrams {
+        builder = builder.char_ngrams(n as usize).top_char_ngrams(cli.top_ngrams);
+    }
+    let mut analyzer = builder.build();
+    analyzer.feed_bytes(&text);
+    let stats = analyzer.finish();
 
-fn main() {
-    let text = generate_test_text(50_000);
+    if cli.json {
+        println!("{}", serde_json::to_string_pretty(&stats)?);
+        return Ok(());
+    }
 
     println!("Analyzing {} bytes of text...", text.len());
-
-    let stats = analyze_text_fast(&text);
-
     println!("Results:");
     println!("  Unique words: {}", stats.word_count);
     println!("  Total alphabetic chars: {}", stats.char_count);
+    println!(
+        "  Digits: {}, punctuation: {}, whitespace: {}, non-ASCII bytes: {}",
+        stats.digit_count, stats.punctuation_count, stats.whitespace_count, stats.non_ascii_bytes
+    );
+    if stats.invalid_utf8_sequences > 0 {
+        println!("  WARNING: {} invalid UTF-8 sequences", stats.invalid_utf8_sequences);
+    }
     println!("  Top 10 words: {:?}", stats.top_words);
     println!("  Longest words: {:?}", stats.longest_words);
-    println!("  Time taken: {} ms", stats.time_ms);
-}
+    println!("  Time taken: {:.3} ms", stats.elapsed.as_secs_f64() * 1000.0);
+    println!("  Throughput: {:.2} MB/s, {:.0} words/s", stats.mb_per_sec, stats.words_per_sec);
+    println!("  Map memory (estimated): {:.1} KiB", stats.map_memory_bytes as f64 / 1024.0);
+    if let Some(rss) = stats.peak_rss_bytes {
+        println!("  Peak RSS: {:.1} MiB", rss as f64 / (1024.0 * 1024.0));
+    }
 
-#[inline(always)]
-fn process_word(
-    buf: &mut String,
-    word_freq: &mut FxHashMap<String, usize>,
-) {
-    let word = buf.clone();
-    buf.clear(); 
-    word_freq
-        .entry(word)
-        .and_modify(|c| *c += 1)
-        .or_insert(1);
+    if let Some(ngrams) = &stats.char_ngrams {
+        println!("\nCharacter {}-grams: {} ({} distinct), spaces shown as _:", ngrams.n, ngrams.total, ngrams.distinct);
+        for (gram, count) in &ngrams.top {
+            println!("  {:<8} {:>10} {:>7.3}%", gram.replace(' ', "_"), count, ngrams.percent(*count));
+        }
+    }
+
+    if let Some(word) = &cli.show_context {
+        let lines = concordance(&text, &stats.occurrences, cli.window);
+        println!("\nContext of \"{}\" ({} occurrences):", word, lines.len());
+        let width = lines
+            .iter()
+            .take(MAX_CONTEXT_LINES)
+            .map(|l| l.left.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(CONTEXT_WIDTH);
+        for line in lines.iter().take(MAX_CONTEXT_LINES) {
+            let skip = line.left.chars().count().saturating_sub(width);
+            let left: String = line.left.chars().skip(skip).collect();
+            println!("  {:>8}  {:>width$}[{}]{}", line.offset, left, line.word, line.right.trim_end(), width = width);
+        }
+        if lines.len() > MAX_CONTEXT_LINES {
+            println!("  ... {} more", lines.len() - MAX_CONTEXT_LINES);
+        }
+    }
+    Ok(())
 }
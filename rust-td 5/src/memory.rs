@@ -0,0 +1,26 @@
+use rustc_hash::FxHashMap;
+use std::mem::size_of;
+
+/// Estimated heap usage of the frequency map: one bucket per slot
+/// (`(String, usize)` + 1 control byte) plus the bytes of every key.
+pub fn estimate_map_bytes(map: &FxHashMap<String, usize>) -> usize {
+    let buckets = map.capacity();
+    let table = buckets * (size_of::<(String, usize)>() + 1);
+    let keys: usize = map.keys().map(|k| k.capacity()).sum();
+    table + keys
+}
+
+/// Peak resident set size of the current process, in bytes.
+/// Only implemented on Linux (`VmHWM` in `/proc/self/status`).
+#[cfg(target_os = "linux")]
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|l| l.starts_with("VmHWM:"))?;
+    let kb: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kb * 1024)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn peak_rss_bytes() -> Option<u64> {
+    None
+}
@@ -0,0 +1,190 @@
+use rustc_hash::FxHashMap;
+use serde::Serialize;
+
+/// Longest n-gram: a key packs `n` code points of 21 bits in a `u128`.
+pub const MAX_NGRAM: usize = 6;
+
+const CHAR_BITS: usize = 21;
+
+/// Most frequent character n-grams of a text.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NgramStats {
+    pub n: usize,
+    /// N-grams seen (not unique).
+    pub total: usize,
+    pub distinct: usize,
+    pub top: Vec<(String, usize)>,
+}
+
+impl NgramStats {
+    /// Share of the n-grams, in percent.
+    pub fn percent(&self, count: usize) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / self.total as f64
+        }
+    }
+}
+
+/// Character n-gram counter fed byte by byte (UTF-8 decoded on the fly, so
+/// a character may span two chunks). Letters are kept, every run of other
+/// characters becomes one space: "Hello, world" gives "hel", "ell", "llo",
+/// "lo ", "o w", " wo"...
+#[derive(Debug, Clone)]
+pub struct CharNgrams {
+    n: usize,
+    keep_case: bool,
+    counts: FxHashMap<u128, usize>,
+    /// The last `n` characters, oldest in the high bits.
+    window: u128,
+    mask: u128,
+    /// Characters in `window`, up to `n`.
+    filled: usize,
+    after_space: bool,
+    /// Code point being decoded and continuation bytes still expected.
+    code_point: u32,
+    remaining: u8,
+}
+
+impl CharNgrams {
+    pub fn new(n: usize, keep_case: bool) -> Self {
+        assert!((1..=MAX_NGRAM).contains(&n), "n-grams of 1 to {} characters", MAX_NGRAM);
+        CharNgrams {
+            n,
+            keep_case,
+            counts: FxHashMap::default(),
+            window: 0,
+            mask: (1u128 << (CHAR_BITS * n)) - 1,
+            filled: 0,
+            // No n-gram starts with the separators at the head of the text.
+            after_space: true,
+            code_point: 0,
+            remaining: 0,
+        }
+    }
+
+    #[inline(always)]
+    pub fn push_byte(&mut self, b: u8) {
+        match b {
+            0x00..=0x7F => {
+                if self.remaining > 0 {
+                    self.invalid();
+                }
+                self.push_char(b as char);
+            }
+            0x80..=0xBF if self.remaining > 0 => {
+                self.code_point = (self.code_point << 6) | (b & 0x3F) as u32;
+                self.remaining -= 1;
+                if self.remaining == 0 {
+                    match char::from_u32(self.code_point) {
+                        Some(c) => self.push_char(c),
+                        None => self.push_separator(),
+                    }
+                }
+            }
+            0xC0..=0xDF => self.start_sequence((b & 0x1F) as u32, 1),
+            0xE0..=0xEF => self.start_sequence((b & 0x0F) as u32, 2),
+            0xF0..=0xF7 => self.start_sequence((b & 0x07) as u32, 3),
+            _ => self.invalid(),
+        }
+    }
+
+    fn start_sequence(&mut self, bits: u32, remaining: u8) {
+        if self.remaining > 0 {
+            self.invalid();
+        }
+        self.code_point = bits;
+        self.remaining = remaining;
+    }
+
+    /// Malformed UTF-8 (counted by the analyzer) separates like punctuation.
+    fn invalid(&mut self) {
+        self.remaining = 0;
+        self.push_separator();
+    }
+
+    #[inline(always)]
+    fn push_char(&mut self, c: char) {
+        if !c.is_alphabetic() {
+            return self.push_separator();
+        }
+        let c = if self.keep_case {
+            c
+        } else if c.is_ascii() {
+            c.to_ascii_lowercase()
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        };
+        self.after_space = false;
+        self.shift(c);
+    }
+
+    fn push_separator(&mut self) {
+        if !self.after_space {
+            self.after_space = true;
+            self.shift(' ');
+        }
+    }
+
+    #[inline(always)]
+    fn shift(&mut self, c: char) {
+        self.window = ((self.window << CHAR_BITS) | c as u128) & self.mask;
+        if self.filled < self.n {
+            self.filled += 1;
+        }
+        if self.filled == self.n {
+            *self.counts.entry(self.window).or_insert(0) += 1;
+        }
+    }
+
+    /// The `top` most frequent n-grams, ties in alphabetical order.
+    pub fn finish(&self, top: usize) -> NgramStats {
+        let mut ranked: Vec<(u128, usize)> = self.counts.iter().map(|(&key, &count)| (key, count)).collect();
+        // Packed keys of the same length compare like their strings.
+        ranked.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        ranked.truncate(top);
+        NgramStats {
+            n: self.n,
+            total: self.counts.values().sum(),
+            distinct: self.counts.len(),
+            top: ranked.into_iter().map(|(key, count)| (self.decode(key), count)).collect(),
+        }
+    }
+
+    fn decode(&self, key: u128) -> String {
+        (0..self.n)
+            .rev()
+            .map(|i| char::from_u32((key >> (CHAR_BITS * i)) as u32 & 0x1F_FFFF).unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trigrams(chunks: &[&[u8]]) -> NgramStats {
+        let mut ngrams = CharNgrams::new(3, false);
+        for chunk in chunks {
+            chunk.iter().for_each(|&b| ngrams.push_byte(b));
+        }
+        ngrams.finish(usize::MAX)
+    }
+
+    #[test]
+    fn trigrams_span_words_and_chunks() {
+        let stats = trigrams(&[b"  The theme, th", b"e \xC3", b"\xA9t\xC3\xA9!"]);
+        let top: Vec<(&str, usize)> = stats.top.iter().map(|(g, c)| (g.as_str(), *c)).collect();
+        // "the theme the été " : separators collapse into one space
+        assert_eq!(top[..3], [("the", 3), (" th", 2), ("e t", 2)]);
+        assert!(top.contains(&("été", 1)));
+        assert_eq!(stats.total, 16);
+        assert_eq!(stats.distinct, 11);
+        assert!((stats.percent(3) - 18.75).abs() < 1e-9);
+
+        // a stray continuation byte separates words like punctuation
+        let stats = trigrams(&[b"ab\x80cd"]);
+        assert_eq!(stats.top, [(" cd".to_string(), 1), ("ab ".to_string(), 1), ("b c".to_string(), 1)]);
+    }
+}
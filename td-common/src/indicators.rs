@@ -0,0 +1,194 @@
+//! Streaming technical indicators (SMA, EMA, RSI): fed one price at a time,
+//! so the same code serves stored history and the live feed.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+/// `sma:20`, `ema:12` or `rsi:14`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IndicatorSpec {
+    Sma(usize),
+    Ema(usize),
+    Rsi(usize),
+}
+
+impl FromStr for IndicatorSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, period) = s
+            .trim()
+            .split_once(':')
+            .ok_or_else(|| format!("expected KIND:PERIOD (e.g. sma:20), got `{}`", s))?;
+        let period: usize = period
+            .trim()
+            .parse()
+            .ok()
+            .filter(|&p| p > 0)
+            .ok_or_else(|| format!("invalid period in `{}`", s))?;
+        match kind.trim().to_ascii_lowercase().as_str() {
+            "sma" => Ok(IndicatorSpec::Sma(period)),
+            "ema" => Ok(IndicatorSpec::Ema(period)),
+            "rsi" => Ok(IndicatorSpec::Rsi(period)),
+            other => Err(format!("unknown indicator `{}` (sma, ema or rsi)", other)),
+        }
+    }
+}
+
+impl fmt::Display for IndicatorSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IndicatorSpec::Sma(n) => write!(f, "sma:{}", n),
+            IndicatorSpec::Ema(n) => write!(f, "ema:{}", n),
+            IndicatorSpec::Rsi(n) => write!(f, "rsi:{}", n),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum State {
+    Sma {
+        window: VecDeque<f64>,
+        sum: f64,
+    },
+    /// Seeded with the SMA of the first `period` prices.
+    Ema {
+        seed: Vec<f64>,
+        value: Option<f64>,
+    },
+    /// Wilder's smoothing of the average gain and loss.
+    Rsi {
+        last: Option<f64>,
+        changes: Vec<f64>,
+        averages: Option<(f64, f64)>,
+    },
+}
+
+/// One indicator over one price series.
+#[derive(Debug, Clone)]
+pub struct Indicator {
+    spec: IndicatorSpec,
+    state: State,
+}
+
+impl Indicator {
+    pub fn new(spec: IndicatorSpec) -> Self {
+        let state = match spec {
+            IndicatorSpec::Sma(_) => State::Sma {
+                window: VecDeque::new(),
+                sum: 0.0,
+            },
+            IndicatorSpec::Ema(_) => State::Ema {
+                seed: Vec::new(),
+                value: None,
+            },
+            IndicatorSpec::Rsi(_) => State::Rsi {
+                last: None,
+                changes: Vec::new(),
+                averages: None,
+            },
+        };
+        Indicator { spec, state }
+    }
+
+    pub fn spec(&self) -> IndicatorSpec {
+        self.spec
+    }
+
+    /// Feeds the next price; `None` until enough prices were seen.
+    pub fn update(&mut self, price: f64) -> Option<f64> {
+        match (&mut self.state, self.spec) {
+            (State::Sma { window, sum }, IndicatorSpec::Sma(period)) => {
+                window.push_back(price);
+                *sum += price;
+                if window.len() > period {
+                    *sum -= window.pop_front().unwrap();
+                }
+                (window.len() == period).then(|| *sum / period as f64)
+            }
+            (State::Ema { seed, value }, IndicatorSpec::Ema(period)) => {
+                let alpha = 2.0 / (period as f64 + 1.0);
+                match value {
+                    Some(ema) => *ema += alpha * (price - *ema),
+                    None => {
+                        seed.push(price);
+                        if seed.len() == period {
+                            *value = Some(seed.iter().sum::<f64>() / period as f64);
+                            seed.clear();
+                        }
+                    }
+                }
+                *value
+            }
+            (State::Rsi { last, changes, averages }, IndicatorSpec::Rsi(period)) => {
+                let previous = last.replace(price)?;
+                let change = price - previous;
+                let (gain, loss) = (change.max(0.0), (-change).max(0.0));
+                match averages {
+                    Some((avg_gain, avg_loss)) => {
+                        let n = period as f64;
+                        *avg_gain = (*avg_gain * (n - 1.0) + gain) / n;
+                        *avg_loss = (*avg_loss * (n - 1.0) + loss) / n;
+                    }
+                    None => {
+                        changes.push(change);
+                        if changes.len() < period {
+                            return None;
+                        }
+                        let n = period as f64;
+                        let avg_gain = changes.iter().map(|c| c.max(0.0)).sum::<f64>() / n;
+                        let avg_loss = changes.iter().map(|c| (-c).max(0.0)).sum::<f64>() / n;
+                        *averages = Some((avg_gain, avg_loss));
+                        changes.clear();
+                    }
+                }
+                averages.map(|(avg_gain, avg_loss)| {
+                    if avg_loss == 0.0 {
+                        100.0
+                    } else {
+                        100.0 - 100.0 / (1.0 + avg_gain / avg_loss)
+                    }
+                })
+            }
+            _ => unreachable!("state built from the spec"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(spec: &str, prices: &[f64]) -> Vec<Option<f64>> {
+        let mut indicator = Indicator::new(spec.parse().unwrap());
+        prices.iter().map(|&p| indicator.update(p)).collect()
+    }
+
+    #[test]
+    fn specs_parse_and_print() {
+        assert_eq!("SMA:20".parse(), Ok(IndicatorSpec::Sma(20)));
+        assert_eq!(IndicatorSpec::Rsi(14).to_string(), "rsi:14");
+        assert!("sma".parse::<IndicatorSpec>().is_err());
+        assert!("sma:0".parse::<IndicatorSpec>().is_err());
+        assert!("macd:9".parse::<IndicatorSpec>().is_err());
+    }
+
+    #[test]
+    fn sma_and_ema_after_warm_up() {
+        assert_eq!(run("sma:3", &[1.0, 2.0, 3.0, 4.0]), vec![None, None, Some(2.0), Some(3.0)]);
+        // Seed 2.0, then alpha 0.5: 2 + 0.5 * (6 - 2).
+        assert_eq!(run("ema:3", &[1.0, 2.0, 3.0, 6.0]), vec![None, None, Some(2.0), Some(4.0)]);
+    }
+
+    #[test]
+    fn rsi_follows_wilder() {
+        let values = run("rsi:2", &[10.0, 11.0, 10.5, 11.5, 11.5]);
+        assert_eq!(&values[..2], &[None, None]);
+        // Changes +1, -0.5: averages 0.5 / 0.25.
+        assert!((values[2].unwrap() - 100.0 * 2.0 / 3.0).abs() < 1e-9);
+        // +1: averages 0.75 / 0.125.
+        assert!((values[3].unwrap() - (100.0 - 100.0 / 7.0)).abs() < 1e-9);
+        assert_eq!(run("rsi:2", &[1.0, 2.0, 3.0]), vec![None, None, Some(100.0)]);
+    }
+}
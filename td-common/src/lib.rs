@@ -0,0 +1,197 @@
+//! Shared error type for the TD binaries, the paper-trading [`portfolio`]
+//! (feature `portfolio`), the price [`indicators`] (feature `indicators`)
+//! and [`mask_key`] for logging API keys.
+//!
+//! Every failure is classified (HTTP, database, WebSocket, parsing, I/O) and
+//! can be wrapped with context describing what was being done:
+//!
+//! ```
+//! use td_common::{Context, Result};
+//!
+//! fn read_config(path: &str) -> Result<String> {
+//!     std::fs::read_to_string(path).with_context(|| format!("reading config {path}"))
+//! }
+//! ```
+
+#[cfg(feature = "indicators")]
+pub mod indicators;
+#[cfg(feature = "portfolio")]
+pub mod portfolio;
+
+pub type BoxError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("HTTP error: {0}")]
+    Http(#[source] BoxError),
+
+    #[error("database error: {0}")]
+    Db(#[source] BoxError),
+
+    #[error("WebSocket error: {0}")]
+    Ws(#[source] BoxError),
+
+    #[error("parse error: {0}")]
+    Parse(String),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The remote API asked us to slow down; don't call it again before
+    /// `retry_after` has elapsed.
+    #[error("rate limited by {provider}, retry in {}s", retry_after.as_secs())]
+    RateLimited {
+        provider: String,
+        retry_after: std::time::Duration,
+    },
+
+    /// A provider answered with a body of an unexpected shape, most likely
+    /// an API change. `snippet` is the start of the raw body.
+    #[error("unexpected {provider} response: {message} (body: {snippet})")]
+    Schema {
+        provider: String,
+        message: String,
+        snippet: String,
+    },
+
+    /// An error annotated with what was being done when it happened. Its
+    /// message already ends with `inner`'s, which is therefore not exposed
+    /// as `source()` too: chain printers would show it twice.
+    #[error("{context}: {inner}")]
+    Context { context: String, inner: Box<Error> },
+}
+
+impl Error {
+    pub fn http(e: impl Into<BoxError>) -> Self {
+        Error::Http(e.into())
+    }
+
+    pub fn db(e: impl Into<BoxError>) -> Self {
+        Error::Db(e.into())
+    }
+
+    pub fn ws(e: impl Into<BoxError>) -> Self {
+        Error::Ws(e.into())
+    }
+
+    pub fn parse(msg: impl std::fmt::Display) -> Self {
+        Error::Parse(msg.to_string())
+    }
+
+    pub fn context(self, context: impl Into<String>) -> Self {
+        Error::Context {
+            context: context.into(),
+            inner: Box::new(self),
+        }
+    }
+
+    /// The underlying classified error, without the context layers.
+    pub fn root(&self) -> &Error {
+        match self {
+            Error::Context { inner, .. } => inner.root(),
+            other => other,
+        }
+    }
+}
+
+impl From<std::num::ParseFloatError> for Error {
+    fn from(e: std::num::ParseFloatError) -> Self {
+        Error::parse(e)
+    }
+}
+
+impl From<std::num::ParseIntError> for Error {
+    fn from(e: std::num::ParseIntError) -> Self {
+        Error::parse(e)
+    }
+}
+
+#[cfg(feature = "http")]
+impl From<reqwest::Error> for Error {
+    fn from(e: reqwest::Error) -> Self {
+        Error::http(e)
+    }
+}
+
+#[cfg(feature = "db")]
+impl From<sqlx::Error> for Error {
+    fn from(e: sqlx::Error) -> Self {
+        Error::db(e)
+    }
+}
+
+#[cfg(feature = "ws")]
+impl From<tokio_tungstenite::tungstenite::Error> for Error {
+    fn from(e: tokio_tungstenite::tungstenite::Error) -> Self {
+        Error::ws(e)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::parse(e)
+    }
+}
+
+/// `anyhow`-style context for any result whose error converts into [`Error`].
+pub trait Context<T> {
+    fn context(self, context: impl Into<String>) -> Result<T>;
+
+    fn with_context<C: Into<String>>(self, f: impl FnOnce() -> C) -> Result<T>;
+}
+
+impl<T, E: Into<Error>> Context<T> for std::result::Result<T, E> {
+    fn context(self, context: impl Into<String>) -> Result<T> {
+        self.map_err(|e| e.into().context(context))
+    }
+
+    fn with_context<C: Into<String>>(self, f: impl FnOnce() -> C) -> Result<T> {
+        self.map_err(|e| e.into().context(f()))
+    }
+}
+
+/// First four characters of an API key, so logs tell keys apart without
+/// leaking them. Short keys are fully hidden.
+pub fn mask_key(key: &str) -> String {
+    match key.char_indices().nth(4) {
+        Some((end, _)) if key.len() > 8 => format!("{}...", &key[..end]),
+        _ => "****".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_is_prepended_and_root_kept() {
+        let res: Result<f64> = "abc"
+            .parse::<f64>()
+            .context("parsing price")
+            .context("AAPL from Finnhub");
+        let err = res.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "AAPL from Finnhub: parsing price: parse error: invalid float literal"
+        );
+        assert!(matches!(err.root(), Error::Parse(_)));
+
+        // each message once along the source chain
+        let mut chain = vec![err.to_string()];
+        let mut source = std::error::Error::source(&err);
+        while let Some(e) = source {
+            chain.push(e.to_string());
+            source = e.source();
+        }
+        assert_eq!(chain, ["AAPL from Finnhub: parsing price: parse error: invalid float literal"]);
+    }
+
+    #[test]
+    fn keys_are_masked() {
+        assert_eq!(mask_key("abcd-1234-efgh"), "abcd...");
+        assert_eq!(mask_key("short"), "****");
+    }
+}
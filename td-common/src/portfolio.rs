@@ -0,0 +1,152 @@
+//! Paper-trading portfolio: positions read from TOML (or built from a DB
+//! table by the caller) and valued at the latest prices.
+//!
+//! ```toml
+//! [[position]]
+//! symbol = "AAPL"
+//! quantity = 10
+//! cost_basis = 150.25   # average price paid per share
+//! ```
+
+use crate::{Context, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Position {
+    pub symbol: String,
+    pub quantity: f64,
+    /// Average price paid per share.
+    pub cost_basis: f64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Portfolio {
+    #[serde(rename = "position", default)]
+    pub positions: Vec<Position>,
+}
+
+/// One position at the latest price; the price fields are `None` until the
+/// symbol has been quoted.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PositionValue {
+    pub symbol: String,
+    pub quantity: f64,
+    pub cost_basis: f64,
+    pub price: Option<f64>,
+    pub market_value: Option<f64>,
+    pub pnl: Option<f64>,
+}
+
+/// Totals cover the quoted positions only.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PortfolioValue {
+    pub positions: Vec<PositionValue>,
+    pub cost: f64,
+    pub market_value: f64,
+    pub pnl: f64,
+    pub pnl_pct: f64,
+}
+
+impl Portfolio {
+    /// Symbols are upper-cased; a position needs a positive quantity.
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let mut portfolio: Portfolio = toml::from_str(text).map_err(Error::parse)?;
+        for position in &mut portfolio.positions {
+            position.symbol = position.symbol.trim().to_uppercase();
+            if position.symbol.is_empty() || position.quantity <= 0.0 || position.cost_basis < 0.0 {
+                return Err(Error::parse(format!("invalid position {:?}", position)));
+            }
+        }
+        Ok(portfolio)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Portfolio::from_toml(&text).with_context(|| format!("parsing portfolio {}", path.display()))
+    }
+
+    pub fn holds(&self, symbol: &str) -> bool {
+        self.positions.iter().any(|p| p.symbol.eq_ignore_ascii_case(symbol))
+    }
+
+    pub fn symbols(&self) -> Vec<String> {
+        let mut symbols: Vec<String> = self.positions.iter().map(|p| p.symbol.clone()).collect();
+        symbols.sort();
+        symbols.dedup();
+        symbols
+    }
+
+    /// Values every position at `prices` (symbol → latest price).
+    pub fn value(&self, prices: &HashMap<String, f64>) -> PortfolioValue {
+        let positions: Vec<PositionValue> = self
+            .positions
+            .iter()
+            .map(|p| {
+                let price = prices.get(&p.symbol).copied();
+                PositionValue {
+                    symbol: p.symbol.clone(),
+                    quantity: p.quantity,
+                    cost_basis: p.cost_basis,
+                    price,
+                    market_value: price.map(|price| price * p.quantity),
+                    pnl: price.map(|price| (price - p.cost_basis) * p.quantity),
+                }
+            })
+            .collect();
+
+        // Folded from 0.0: an empty f64 `sum()` is -0.0.
+        let quoted = positions.iter().filter(|p| p.price.is_some());
+        let cost = quoted.clone().fold(0.0, |total, p| total + p.cost_basis * p.quantity);
+        let market_value = quoted.filter_map(|p| p.market_value).fold(0.0, |total, v| total + v);
+        let pnl = market_value - cost;
+        PortfolioValue {
+            positions,
+            cost,
+            market_value,
+            pnl,
+            pnl_pct: if cost > 0.0 { pnl / cost * 100.0 } else { 0.0 },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE: &str = r#"
+        [[position]]
+        symbol = "aapl"
+        quantity = 10
+        cost_basis = 150.0
+
+        [[position]]
+        symbol = "MSFT"
+        quantity = 2
+        cost_basis = 400.0
+    "#;
+
+    #[test]
+    fn positions_are_read_from_toml() {
+        let portfolio = Portfolio::from_toml(FILE).unwrap();
+        assert_eq!(portfolio.symbols(), vec!["AAPL", "MSFT"]);
+        assert!(portfolio.holds("aapl"));
+        assert!(Portfolio::from_toml("[[position]]\nsymbol = \"X\"\nquantity = 0\ncost_basis = 1.0").is_err());
+    }
+
+    #[test]
+    fn totals_cover_quoted_positions() {
+        let portfolio = Portfolio::from_toml(FILE).unwrap();
+        let value = portfolio.value(&HashMap::from([("AAPL".to_string(), 165.0)]));
+
+        assert_eq!(value.positions[0].pnl, Some(150.0));
+        assert_eq!(value.positions[1].price, None);
+        assert_eq!(value.cost, 1500.0);
+        assert_eq!(value.market_value, 1650.0);
+        assert_eq!(value.pnl_pct, 10.0);
+
+        let unquoted = portfolio.value(&HashMap::new());
+        assert!(unquoted.cost == 0.0 && unquoted.cost.is_sign_positive());
+    }
+}
@@ -0,0 +1,59 @@
+use futures_util::{SinkExt, StreamExt};
+use std::time::Duration;
+use td_common::{Context, Result};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use ws_price_feed::{Envelope, ServerMessage};
+
+/// Connection attempts, to let a server started at the same time bind its port.
+const CONNECT_ATTEMPTS: u32 = 20;
+
+/// Demo client: subscribes to `symbols` (all when empty) and prints one line
+/// per quote until the server closes the connection.
+pub async fn run(url: &str, symbols: &[String]) -> Result<()> {
+    let mut attempt = 1;
+    let (mut ws, _) = loop {
+        match connect_async(url).await {
+            Ok(connected) => break connected,
+            Err(_) if attempt < CONNECT_ATTEMPTS => {
+                attempt += 1;
+                tokio::time::sleep(Duration::from_millis(500)).await;
+            }
+            Err(e) => return Err(e).with_context(|| format!("connecting to {}", url)),
+        }
+    };
+    println!("connected to {}", url);
+
+    if symbols.is_empty() {
+        ws.send(Message::text("SUB ALL")).await?;
+    }
+    for symbol in symbols {
+        ws.send(Message::text(format!("SUB {}", symbol))).await?;
+    }
+
+    while let Some(msg) = ws.next().await {
+        let text = match msg? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let Ok(envelope) = serde_json::from_str::<Envelope>(&text) else {
+            continue;
+        };
+        match envelope.message {
+            ServerMessage::Quote(quote) => println!(
+                "{:<8} {:>12.4}  {}{}",
+                quote.symbol,
+                quote.price,
+                quote.source,
+                if quote.is_mock { " (mock)" } else { "" }
+            ),
+            ServerMessage::Announcement { group, message } => {
+                println!("[{}] {}", group.as_deref().unwrap_or("announcement"), message)
+            }
+            ServerMessage::Error { message } => eprintln!("server error: {}", message),
+            _ => {}
+        }
+    }
+    println!("connection closed");
+    Ok(())
+}
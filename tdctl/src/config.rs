@@ -0,0 +1,154 @@
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use td_common::{Context, Error, Result};
+
+/// Name of the file looked up from the current directory upwards.
+pub const CONFIG_FILE: &str = "tdctl.toml";
+
+/// Settings shared by every component; everything has a default, so the
+/// file is optional.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Postgres URL given to the fetcher and the server; `DATABASE_URL`
+    /// (environment or `.env`) when not set here.
+    pub database_url: Option<String>,
+    pub fetcher: FetcherConfig,
+    pub server: ServerConfig,
+    pub client: ClientConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FetcherConfig {
+    pub interval_secs: u64,
+    /// Extra command-line arguments of `rust-td`.
+    pub args: Vec<String>,
+}
+
+impl Default for FetcherConfig {
+    fn default() -> Self {
+        FetcherConfig {
+            interval_secs: 60,
+            args: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// `--listen` spec of `ws-price-feed`.
+    pub listen: String,
+    /// Extra command-line arguments of `ws-price-feed`.
+    pub args: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen: "127.0.0.1:8080".to_string(),
+            args: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ClientConfig {
+    /// Defaults to the server's listen address.
+    pub url: Option<String>,
+    /// Symbols printed by the demo client; all when empty.
+    pub symbols: Vec<String>,
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Config> {
+        toml::from_str(text).map_err(Error::parse)
+    }
+
+    pub fn load(path: &Path) -> Result<Config> {
+        let text = std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+        Config::parse(&text).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn database_url(&self) -> Option<String> {
+        self.database_url.clone().or_else(|| std::env::var("DATABASE_URL").ok())
+    }
+
+    /// WebSocket URL of the server, from its `ADDR[,options]` listen spec.
+    pub fn client_url(&self) -> String {
+        match &self.client.url {
+            Some(url) => url.clone(),
+            None => {
+                let addr = self.server.listen.split(',').next().unwrap_or_default().trim();
+                format!("ws://{}", addr)
+            }
+        }
+    }
+}
+
+/// `tdctl.toml` in `dir` or its closest ancestor that has one.
+pub fn find_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors().map(|d| d.join(CONFIG_FILE)).find(|p| p.is_file())
+}
+
+/// The configuration in use and where it comes from: `explicit` when given,
+/// else the discovered file, else the defaults.
+pub fn discover(explicit: Option<&Path>) -> Result<(Config, Option<PathBuf>)> {
+    let path = match explicit {
+        Some(path) => Some(path.to_path_buf()),
+        None => find_config(&std::env::current_dir()?),
+    };
+    match path {
+        Some(path) => Ok((Config::load(&path)?, Some(path))),
+        None => Ok((Config::default(), None)),
+    }
+}
+
+/// Where pid files and logs go: `.tdctl` next to the config file, or in the
+/// current directory without one.
+pub fn state_dir(config_path: Option<&Path>) -> Result<PathBuf> {
+    let base = match config_path.and_then(Path::parent) {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => std::env::current_dir()?,
+    };
+    Ok(base.join(".tdctl"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_files_keep_the_defaults() {
+        let config = Config::parse(
+            r#"
+            database_url = "postgres://localhost/td"
+            [server]
+            listen = "0.0.0.0:9000,mock=no"
+            [client]
+            symbols = ["AAPL"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.fetcher, FetcherConfig::default());
+        assert_eq!(config.database_url(), Some("postgres://localhost/td".to_string()));
+        assert_eq!(config.client_url(), "ws://0.0.0.0:9000");
+        assert!(Config::parse("[server]\nport = 1").is_err());
+    }
+
+    #[test]
+    fn the_closest_config_file_wins() {
+        let root = std::env::temp_dir().join(format!("tdctl-{}", std::process::id()));
+        let nested = root.join("a").join("b");
+        std::fs::create_dir_all(&nested).unwrap();
+        std::fs::write(root.join(CONFIG_FILE), "").unwrap();
+        assert_eq!(find_config(&nested), Some(root.join(CONFIG_FILE)));
+
+        std::fs::write(root.join("a").join(CONFIG_FILE), "").unwrap();
+        assert_eq!(find_config(&nested), Some(root.join("a").join(CONFIG_FILE)));
+        assert_eq!(state_dir(find_config(&nested).as_deref()).unwrap(), root.join("a").join(".tdctl"));
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
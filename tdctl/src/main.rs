@@ -0,0 +1,128 @@
+//! Brings the TD stack up and down: the price fetcher (TD 1), the WebSocket
+//! server (TD 2) and a demo client, configured by one `tdctl.toml`.
+
+mod client;
+mod config;
+mod process;
+
+use clap::{Parser, Subcommand};
+use config::Config;
+use process::{Component, StateDir};
+use std::path::PathBuf;
+use td_common::Result;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Start and stop the TD stack", long_about = None)]
+struct Cli {
+    /// Configuration file (default: the closest tdctl.toml from the current directory up)
+    #[arg(long, global = true, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Start everything, stop it all on Ctrl-C
+    Up,
+    /// Start components in the background (all when none given)
+    Start {
+        #[arg(value_enum)]
+        components: Vec<Component>,
+    },
+    /// Stop background components (all when none given)
+    Stop {
+        #[arg(value_enum)]
+        components: Vec<Component>,
+    },
+    /// Show which components are running
+    Status,
+    /// Print the quotes of the server
+    Client {
+        /// Server URL (default: from the configuration)
+        #[arg(long)]
+        url: Option<String>,
+
+        /// Symbols to follow (default: from the configuration, else all)
+        symbols: Vec<String>,
+    },
+    /// Print the configuration in use and where it comes from
+    Config,
+}
+
+/// The given components in startup order, all of them when none given.
+fn selection(components: &[Component]) -> Vec<Component> {
+    Component::ALL
+        .into_iter()
+        .filter(|c| components.is_empty() || components.contains(c))
+        .collect()
+}
+
+fn start(state: &StateDir, config: &Config, components: &[Component]) -> Result<()> {
+    for component in components {
+        let pid = state.start(*component, config)?;
+        println!(
+            "{:<8} running (pid {}), log: {}",
+            component.name(),
+            pid,
+            state.log_file(*component).display()
+        );
+    }
+    Ok(())
+}
+
+fn stop(state: &StateDir, components: &[Component]) -> Result<()> {
+    for component in components.iter().rev() {
+        if state.stop(*component)? {
+            println!("{:<8} stopped", component.name());
+        }
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    dotenv::dotenv().ok();
+    let cli = Cli::parse();
+    let (config, config_path) = config::discover(cli.config.as_deref())?;
+    let state = StateDir(config::state_dir(config_path.as_deref())?);
+
+    match cli.command {
+        Command::Up => {
+            let all = selection(&[]);
+            if let Err(e) = start(&state, &config, &all) {
+                stop(&state, &all)?;
+                return Err(e);
+            }
+            println!("stack up, Ctrl-C to stop");
+            tokio::signal::ctrl_c().await?;
+            stop(&state, &all)?;
+        }
+        Command::Start { components } => start(&state, &config, &selection(&components))?,
+        Command::Stop { components } => stop(&state, &selection(&components))?,
+        Command::Status => {
+            for component in Component::ALL {
+                match state.running(component) {
+                    Some(pid) => println!("{:<8} running (pid {})", component.name(), pid),
+                    None => println!("{:<8} stopped", component.name()),
+                }
+            }
+        }
+        Command::Client { url, symbols } => {
+            let url = url.unwrap_or_else(|| config.client_url());
+            let symbols = if symbols.is_empty() { config.client.symbols.clone() } else { symbols };
+            client::run(&url, &symbols).await?;
+        }
+        Command::Config => {
+            match &config_path {
+                Some(path) => println!("# {}", path.display()),
+                None => println!("# no {} found, defaults", config::CONFIG_FILE),
+            }
+            println!("{:#?}", config);
+            println!("client url: {}", config.client_url());
+            println!("state dir: {}", state.0.display());
+        }
+    }
+    Ok(())
+}
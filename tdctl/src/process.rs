@@ -0,0 +1,237 @@
+use crate::config::Config;
+use clap::ValueEnum;
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+use td_common::{Context, Error, Result};
+
+/// How long `stop` waits for a process to exit.
+const STOP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A part of the TD stack `tdctl` can run in the background.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Component {
+    /// The TD 1 price fetcher (`rust-td`)
+    Fetcher,
+    /// The TD 2 WebSocket server (`ws-price-feed`)
+    Server,
+    /// The demo client (`tdctl client`)
+    Client,
+}
+
+impl Component {
+    /// Startup order; `stop` goes the other way.
+    pub const ALL: [Component; 3] = [Component::Server, Component::Fetcher, Component::Client];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Component::Fetcher => "fetcher",
+            Component::Server => "server",
+            Component::Client => "client",
+        }
+    }
+
+    /// Name of the executable the component runs as.
+    pub fn binary(self) -> &'static str {
+        match self {
+            Component::Fetcher => "rust-td",
+            Component::Server => "ws-price-feed",
+            Component::Client => "tdctl",
+        }
+    }
+
+    /// The command running this component with the settings of `config`;
+    /// the binaries are looked up next to `tdctl` (same target directory).
+    pub fn command(self, config: &Config) -> Result<Command> {
+        let bin_dir = std::env::current_exe()?
+            .parent()
+            .map(Path::to_path_buf)
+            .unwrap_or_default();
+        let binary = |name: &str| -> Result<Command> {
+            let path = bin_dir.join(format!("{}{}", name, std::env::consts::EXE_SUFFIX));
+            if !path.is_file() {
+                return Err(Error::parse(format!(
+                    "{} not found next to tdctl; run `cargo build --workspace` first",
+                    path.display()
+                )));
+            }
+            Ok(Command::new(path))
+        };
+        let mut command = match self {
+            Component::Fetcher => {
+                let mut command = binary(self.binary())?;
+                command.arg("--interval-secs").arg(config.fetcher.interval_secs.to_string());
+                command.args(&config.fetcher.args);
+                command
+            }
+            Component::Server => {
+                let mut command = binary(self.binary())?;
+                command.arg("--listen").arg(&config.server.listen);
+                command.args(&config.server.args);
+                command
+            }
+            Component::Client => {
+                let mut command = Command::new(std::env::current_exe()?);
+                command.arg("client").arg("--url").arg(config.client_url());
+                command.args(&config.client.symbols);
+                command
+            }
+        };
+        if let Some(url) = config.database_url() {
+            command.env("DATABASE_URL", url);
+        }
+        Ok(command)
+    }
+}
+
+/// Pid files and logs of the background components.
+#[derive(Debug, Clone)]
+pub struct StateDir(pub PathBuf);
+
+impl StateDir {
+    fn pid_file(&self, component: Component) -> PathBuf {
+        self.0.join(format!("{}.pid", component.name()))
+    }
+
+    pub fn log_file(&self, component: Component) -> PathBuf {
+        self.0.join(format!("{}.log", component.name()))
+    }
+
+    /// Pid of the component if it is running; a stale pid file, whose pid is
+    /// gone or was reused by another program, is removed.
+    pub fn running(&self, component: Component) -> Option<u32> {
+        let path = self.pid_file(component);
+        let pid = std::fs::read_to_string(&path).ok()?.trim().parse().ok()?;
+        if runs(pid, component) {
+            Some(pid)
+        } else {
+            let _ = std::fs::remove_file(path);
+            None
+        }
+    }
+
+    /// Starts the component in the background, its output appended to its
+    /// log file. Returns its pid, or the pid it already runs under.
+    pub fn start(&self, component: Component, config: &Config) -> Result<u32> {
+        if let Some(pid) = self.running(component) {
+            return Ok(pid);
+        }
+        std::fs::create_dir_all(&self.0).with_context(|| format!("creating {}", self.0.display()))?;
+        let log_path = self.log_file(component);
+        let log = File::options()
+            .create(true)
+            .append(true)
+            .open(&log_path)
+            .with_context(|| format!("opening {}", log_path.display()))?;
+        let child = component
+            .command(config)?
+            .stdin(Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()
+            .with_context(|| format!("starting the {}", component.name()))?;
+        std::fs::write(self.pid_file(component), child.id().to_string())?;
+        Ok(child.id())
+    }
+
+    /// Stops the component; false when it wasn't running.
+    pub fn stop(&self, component: Component) -> Result<bool> {
+        let Some(pid) = self.running(component) else {
+            return Ok(false);
+        };
+        terminate(pid).with_context(|| format!("stopping the {} (pid {})", component.name(), pid))?;
+        let deadline = Instant::now() + STOP_TIMEOUT;
+        while runs(pid, component) {
+            if Instant::now() > deadline {
+                return Err(Error::parse(format!(
+                    "the {} (pid {}) is still running after {}s",
+                    component.name(),
+                    pid,
+                    STOP_TIMEOUT.as_secs()
+                )));
+            }
+            std::thread::sleep(Duration::from_millis(100));
+        }
+        let _ = std::fs::remove_file(self.pid_file(component));
+        Ok(true)
+    }
+}
+
+fn run_quiet(command: &mut Command) -> Result<std::process::Output> {
+    Ok(command.stdin(Stdio::null()).stderr(Stdio::null()).output()?)
+}
+
+/// Whether `pid` is alive and still the component's executable, not a
+/// program that got the pid of one that exited.
+fn runs(pid: u32, component: Component) -> bool {
+    process_name(pid).is_some_and(|name| name == component.binary())
+}
+
+/// Executable name of a live process, without directory or extension.
+#[cfg(unix)]
+fn process_name(pid: u32) -> Option<String> {
+    let out = run_quiet(Command::new("ps").args(["-o", "stat=,comm=", "-p", &pid.to_string()])).ok()?;
+    let out = String::from_utf8_lossy(&out.stdout);
+    let (state, command) = out.trim().split_once(char::is_whitespace)?;
+    // A zombie (exited, not reaped) still answers to `kill -0`.
+    if state.starts_with('Z') {
+        return None;
+    }
+    // macOS prints the full path.
+    let command = command.trim();
+    Some(command.rsplit('/').next().unwrap_or(command).to_string())
+}
+
+#[cfg(unix)]
+fn terminate(pid: u32) -> Result<()> {
+    let out = run_quiet(Command::new("kill").args(["-TERM", &pid.to_string()]))?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(Error::parse("kill failed"))
+    }
+}
+
+/// Executable name of a live process, without directory or extension.
+#[cfg(windows)]
+fn process_name(pid: u32) -> Option<String> {
+    let filter = format!("PID eq {}", pid);
+    let out = run_quiet(Command::new("tasklist").args(["/FI", &filter, "/NH", "/FO", "CSV"])).ok()?;
+    // "ws-price-feed.exe","1234","Console","1","12,345 K"; an info line when no task matches
+    let out = String::from_utf8_lossy(&out.stdout);
+    let mut fields = out.trim().split("\",\"");
+    let image = fields.next()?.strip_prefix('"')?;
+    if fields.next()? != pid.to_string() {
+        return None;
+    }
+    Some(image.strip_suffix(".exe").unwrap_or(image).to_string())
+}
+
+#[cfg(windows)]
+fn terminate(pid: u32) -> Result<()> {
+    let out = run_quiet(Command::new("taskkill").args(["/PID", &pid.to_string(), "/T", "/F"]))?;
+    if out.status.success() {
+        Ok(())
+    } else {
+        Err(Error::parse("taskkill failed"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_pid_reused_by_another_program_is_stale() {
+        let state = StateDir(std::env::temp_dir().join(format!("tdctl-pid-{}", std::process::id())));
+        std::fs::create_dir_all(&state.0).unwrap();
+        // alive, but the test binary is not the server
+        let pid_file = state.pid_file(Component::Server);
+        std::fs::write(&pid_file, std::process::id().to_string()).unwrap();
+        assert_eq!(state.running(Component::Server), None);
+        assert!(!pid_file.exists());
+        assert!(!state.stop(Component::Server).unwrap());
+        std::fs::remove_dir_all(&state.0).unwrap();
+    }
+}